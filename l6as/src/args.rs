@@ -1,25 +1,582 @@
-use clap::Parser;
+use clap::{
+    builder::PossibleValuesParser, error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum,
+};
+use clap_complete::Shell;
 use std::path::PathBuf;
 
+use crate::file::DEFAULT_MAX_INCLUDE_DEPTH;
+use crate::output::{OutputFormatRegistry, PromSplit};
+use crate::project::{DEFAULT_DISK_PROFILE, DEFAULT_PROJECT_FILE};
+use crate::symbols::SymbolExportFormat;
+
 #[derive(Debug, Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    disable_help_subcommand = true
+)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Input file path
-    pub input: PathBuf,
+    #[arg(required_unless_present_any = ["dump_opcodes", "help_man"])]
+    pub input: Option<PathBuf>,
 
     /// Run preprocessor only
     #[arg(short = 'p', long, action, conflicts_with = "listing")]
     pub preprocess: bool,
 
+    /// Keep comments in preprocessor output
+    #[arg(long, action, requires = "preprocess")]
+    pub keep_comments: bool,
+
+    /// Keep blank lines in preprocessor output, collapsing consecutive ones
+    #[arg(long, action, requires = "preprocess")]
+    pub keep_blank_lines: bool,
+
+    /// Keep %define and %include lines as comments in preprocessor output
+    #[arg(long, action, requires = "preprocess")]
+    pub keep_directives: bool,
+
+    /// Write %line markers in preprocessor output, so that assembling it reports errors at the
+    /// original source lines
+    #[arg(long, action, requires = "preprocess")]
+    pub line_markers: bool,
+
     /// Produce listing
     #[arg(short = 'l', long, action, conflicts_with = "preprocess")]
     pub listing: bool,
 
+    /// Output format. -l is the same as --format listing
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "bin",
+        value_parser = PossibleValuesParser::new(OutputFormatRegistry::builtin().names()),
+        conflicts_with_all = ["preprocess", "listing", "check", "dump_ast"]
+    )]
+    pub format: String,
+
+    /// Only check for errors, without writing any output
+    #[arg(
+        long,
+        visible_alias = "fsyntax-only",
+        action,
+        conflicts_with_all = ["preprocess", "listing", "output"]
+    )]
+    pub check: bool,
+
+    /// Dump parsed statements to JSON instead of assembling
+    #[arg(long, action, conflicts_with_all = ["preprocess", "listing", "check"])]
+    pub dump_ast: bool,
+
+    /// Decode and re-encode every address syllable, checking that the encoding is consistent
+    #[arg(long, action, conflicts_with_all = ["preprocess", "check", "dump_ast"])]
+    pub paranoid: bool,
+
+    /// Reject absolute branch locations and immediate addresses, checking that the code only
+    /// uses P-relative and B-relative addressing and can be loaded anywhere
+    #[arg(long, action, conflicts_with_all = ["preprocess", "dump_ast"])]
+    pub pic: bool,
+
+    /// Pad the binary output up to a size in bytes
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_address,
+        conflicts_with_all = ["preprocess", "listing", "check", "dump_ast"]
+    )]
+    pub pad_to: Option<u64>,
+
+    /// Byte used by --pad-to and to fill the last PROMs of --split
+    #[arg(long, value_name = "BYTE", default_value = "0", value_parser = parse_byte)]
+    pub pad_byte: u8,
+
+    /// Split the binary output between PROMs: even-odd for the even and odd bytes, or
+    /// SIZExWIDTH, e.g. 2Kx8, for PROMs of SIZE locations of 8 bits (split into even and odd
+    /// bytes) or 16 bits
+    #[arg(
+        long,
+        value_name = "PROMS",
+        value_parser = parse_prom_split,
+        conflicts_with_all = ["preprocess", "listing", "check", "dump_ast"]
+    )]
+    pub split: Option<PromSplit>,
+
+    /// Write the least significant byte of each word of the binary output first
+    #[arg(long, action, conflicts_with_all = ["preprocess", "listing", "check", "dump_ast"])]
+    pub swap_bytes: bool,
+
+    /// Order of the two words of each pair in the binary output: high-low or low-high
+    #[arg(
+        long,
+        value_name = "ORDER",
+        default_value = "high-low",
+        conflicts_with_all = ["preprocess", "listing", "check", "dump_ast"]
+    )]
+    pub word_order: WordOrder,
+
+    /// Label of the entry point of loadable modules. The first word of code when not given
+    #[arg(
+        long,
+        value_name = "LABEL",
+        conflicts_with_all = ["preprocess", "listing", "check", "dump_ast"]
+    )]
+    pub entry: Option<String>,
+
+    /// Maximum size of the output in words, e.g. 2K. Each bank is limited separately
+    #[arg(long, value_name = "WORDS", value_parser = parse_size, conflicts_with_all = ["preprocess", "dump_ast"])]
+    pub max_size: Option<u64>,
+
+    /// Cycle table of the target CPU model, used to annotate the listing with cycle estimates
+    /// and to time I/O instructions set apart by .IODELAY
+    #[arg(long, value_name = "FILE")]
+    pub cycle_table: Option<PathBuf>,
+
+    /// Optional processor of the target CPU, selecting the code of .IFCPU blocks. Can be given
+    /// more than once
+    #[arg(long, value_name = "FEATURE")]
+    pub cpu_feature: Vec<CpuFeature>,
+
+    /// Execution trace with "ADDRESS COUNT" lines, used to annotate the listing with execution counts
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+
+    /// Radix of the words of the listing: hex, octal or both
+    #[arg(long, value_name = "RADIX", default_value = "hex")]
+    pub listing_radix: ListingRadix,
+
+    /// Values accepted by LDV, CMV, ADV and MLV: signed (-128 to 127), unsigned (0 to 255) or
+    /// either
+    #[arg(long, value_name = "SIGNEDNESS", default_value = "any")]
+    pub short_immediates: ShortImmediates,
+
+    /// Symbol map files whose labels can be referenced by the program
+    #[arg(long, value_name = "FILE")]
+    pub symbols: Vec<PathBuf>,
+
+    /// Write the labels defined by the program to a symbol map file
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["preprocess", "check", "dump_ast"])]
+    pub map: Option<PathBuf>,
+
+    /// Write the labels defined by the program as a C header (.h), Rust constants (.rs), a Ghidra
+    /// script (.py) or an IDA script (.idc)
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = parse_symbol_export_path,
+        conflicts_with_all = ["preprocess", "check", "dump_ast"]
+    )]
+    pub export_symbols: Option<PathBuf>,
+
+    /// Write the estimated worst case stack usage of each routine to a report file
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["preprocess", "check", "dump_ast"])]
+    pub stack_report: Option<PathBuf>,
+
+    /// Write the graph of the calls between the routines of the program
+    #[arg(long, value_name = "FORMAT", conflicts_with_all = ["preprocess", "check", "dump_ast"])]
+    pub callgraph: Option<CallGraphFormat>,
+
+    /// Call graph file path
+    #[arg(long, value_name = "FILE", requires = "callgraph")]
+    pub callgraph_output: Option<PathBuf>,
+
+    /// Write a diagram of the memory used by each source file, and of the free memory. Banks
+    /// span --max-size words if given
+    #[arg(long, value_name = "FORMAT", conflicts_with_all = ["preprocess", "check", "dump_ast"])]
+    pub memory_map: Option<MemoryMapFormat>,
+
+    /// Memory map file path
+    #[arg(long, value_name = "FILE", requires = "memory_map")]
+    pub memory_map_output: Option<PathBuf>,
+
+    /// Print the opcode table of every instruction instead of assembling
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        conflicts_with_all = ["input", "preprocess", "listing", "check", "dump_ast"]
+    )]
+    pub dump_opcodes: Option<OpcodeTableFormat>,
+
     /// Output file path
     #[arg(short = 'o', long)]
     pub output: Option<PathBuf>,
 
+    /// Put the program on a disk image, from its first sector. The binary output is only
+    /// written when --output is also given
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["preprocess", "listing", "check", "dump_ast"]
+    )]
+    pub disk: Option<PathBuf>,
+
+    /// l6disk profile of the disk image
+    #[arg(long, value_name = "PROFILE", default_value = DEFAULT_DISK_PROFILE, requires = "disk")]
+    pub disk_profile: String,
+
+    /// File with user defined l6disk profiles
+    #[arg(long, value_name = "FILE", requires = "disk")]
+    pub disk_profile_file: Option<PathBuf>,
+
+    /// Include directories
+    #[arg(short = 'I', long)]
+    pub include_dirs: Vec<PathBuf>,
+
+    /// Maximum %include nesting depth
+    #[arg(long, value_name = "DEPTH", default_value_t = DEFAULT_MAX_INCLUDE_DEPTH)]
+    pub max_include_depth: usize,
+
+    /// Stop at the first file with errors instead of going through all includes
+    #[arg(long, action)]
+    pub fail_fast: bool,
+
+    /// Report garbage at the end of lines as an error instead of a warning
+    #[arg(long, action)]
+    pub strict: bool,
+
+    /// Print the absolute path of every file included
+    #[arg(long, action)]
+    pub print_include_paths: bool,
+
+    /// Write log records as JSON lines to a file, at info level unless L6AS_LOG is set
+    #[arg(long, value_name = "FILE", global = true)]
+    pub log_json: Option<PathBuf>,
+
+    /// Print the man page, in roff format
+    #[arg(long, action, conflicts_with = "input")]
+    pub help_man: bool,
+}
+
+impl Args {
+    /// Input file path, always present when no subcommand, --dump-opcodes or --help-man is given
+    pub fn input(&self) -> &PathBuf {
+        self.input.as_ref().unwrap()
+    }
+
+    /// Rejects options of an output format given with another output format
+    pub fn check_output_options(&self) -> Result<(), clap::Error> {
+        let format_options = [
+            ("--split", "bin", self.split.is_some()),
+            ("--swap-bytes", "bin", self.swap_bytes),
+            ("--word-order", "bin", self.word_order != WordOrder::HighLow),
+            ("--pad-to", "bin", self.pad_to.is_some()),
+            ("--pad-byte", "bin", self.pad_byte != 0),
+            ("--entry", "mod", self.entry.is_some()),
+        ];
+        let conflict = format_options
+            .iter()
+            .find(|(_, format, given)| *given && *format != self.format);
+        match conflict {
+            Some((option, format, _)) => Err(Args::command().error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "{} only applies to --format {}, not {}",
+                    option, format, self.format
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OpcodeTableFormat {
+    Csv,
+    Json,
+    Sleigh,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CallGraphFormat {
+    Dot,
+    Json,
+}
+
+impl CallGraphFormat {
+    /// Extension of the files of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Dot => "dot",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum MemoryMapFormat {
+    Txt,
+    Svg,
+}
+
+impl MemoryMapFormat {
+    /// Extension of the files of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Txt => "txt",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum WordOrder {
+    HighLow,
+    LowHigh,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ListingRadix {
+    Hex,
+    Octal,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ShortImmediates {
+    Any,
+    Signed,
+    Unsigned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CpuFeature {
+    /// Scientific Instruction Processor
+    Sip,
+    /// Commercial Instruction Processor
+    Cip,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a Language Server Protocol server on stdio
+    Lsp(LspArgs),
+
+    /// Format source files in place
+    Fmt(FmtArgs),
+
+    /// Explain the encoding of a single statement
+    Explain(ExplainArgs),
+
+    /// Decode an address syllable and check that it encodes back to the same value
+    DecodeSyllable(DecodeSyllableArgs),
+
+    /// Build the programs and disk images described by a project file
+    Build(BuildArgs),
+
+    /// Print the syntax, operands and encoding of a mnemonic, or the help of a subcommand
+    Help(HelpArgs),
+
+    /// Print the shell completion script
+    Completions(CompletionsArgs),
+}
+
+impl Command {
+    /// Name of the subcommand, as written on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Lsp(_) => "lsp",
+            Command::Fmt(_) => "fmt",
+            Command::Explain(_) => "explain",
+            Command::DecodeSyllable(_) => "decode-syllable",
+            Command::Build(_) => "build",
+            Command::Help(_) => "help",
+            Command::Completions(_) => "completions",
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct LspArgs {
     /// Include directories
     #[arg(short = 'I', long)]
     pub include_dirs: Vec<PathBuf>,
 }
+
+#[derive(Debug, clap::Args)]
+pub struct FmtArgs {
+    /// Input file paths
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Only check if files are formatted, without writing them
+    #[arg(long, action)]
+    pub check: bool,
+
+    /// Column where mnemonics start
+    #[arg(long, default_value_t = 8)]
+    pub mnemonic_column: usize,
+
+    /// Column where operands start
+    #[arg(long, default_value_t = 16)]
+    pub operand_column: usize,
+
+    /// Column where trailing comments start
+    #[arg(long, default_value_t = 40)]
+    pub comment_column: usize,
+
+    /// Write mnemonics and registers in lowercase
+    #[arg(long, action)]
+    pub lowercase: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct BuildArgs {
+    /// Project file path
+    #[arg(default_value = DEFAULT_PROJECT_FILE)]
+    pub project: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CompletionsArgs {
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct HelpArgs {
+    /// Mnemonic, e.g. "LDR", or subcommand
+    pub topic: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ExplainArgs {
+    /// Statement to encode, e.g. "LDR $R1, =5". Labels can't be referenced
+    pub statement: String,
+
+    /// Address the statement is placed at
+    #[arg(short = 'a', long, default_value = "0", value_parser = parse_address)]
+    pub address: u64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DecodeSyllableArgs {
+    /// 7 bit address syllable field
+    #[arg(value_parser = parse_address_syllable_field)]
+    pub field: u8,
+
+    /// Extra words following the instruction word
+    #[arg(value_parser = parse_word)]
+    pub extra_words: Vec<u16>,
+
+    /// Address of the instruction, used by P-relative addressing
+    #[arg(short = 'a', long, default_value = "0", value_parser = parse_address)]
+    pub address: u64,
+}
+
+// Parses a decimal or 0x prefixed hexadecimal address
+fn parse_address(input: &str) -> Result<u64, String> {
+    let res = match input.strip_prefix("0x").or(input.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => input.parse(),
+    };
+
+    res.map_err(|err| err.to_string())
+}
+
+fn parse_address_syllable_field(input: &str) -> Result<u8, String> {
+    match parse_address(input)? {
+        field @ 0..=0x7F => Ok(field as u8),
+        _ => Err("address syllable field is 7 bits wide".to_owned()),
+    }
+}
+
+fn parse_word(input: &str) -> Result<u16, String> {
+    parse_address(input)?
+        .try_into()
+        .map_err(|_| "word is 16 bits wide".to_owned())
+}
+
+fn parse_byte(input: &str) -> Result<u8, String> {
+    parse_address(input)?
+        .try_into()
+        .map_err(|_| "byte is 8 bits wide".to_owned())
+}
+
+// Parses "even-odd" or SIZExWIDTH, the width being 8 or 16 bits
+fn parse_prom_split(input: &str) -> Result<PromSplit, String> {
+    if input == "even-odd" {
+        return Ok(PromSplit::ByteLanes(None));
+    }
+
+    let (size, width) = input
+        .rsplit_once(['x', 'X'])
+        .ok_or("expected even-odd or SIZExWIDTH, e.g. 2Kx8".to_owned())?;
+    let size = parse_size(size)?;
+    if size == 0 {
+        return Err("PROM size must not be 0".to_owned());
+    }
+    match width {
+        "8" => Ok(PromSplit::ByteLanes(Some(size))),
+        "16" => Ok(PromSplit::Words(size)),
+        _ => Err("PROM width is 8 or 16 bits".to_owned()),
+    }
+}
+
+// Accepts output files whose extension selects a symbol export format
+fn parse_symbol_export_path(input: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(input);
+    match SymbolExportFormat::from_path(&path) {
+        Some(_) => Ok(path),
+        None => Err("expected a .h, .rs, .py or .idc file".to_owned()),
+    }
+}
+
+// Parses an address, optionally followed by K to multiply it by 1024
+fn parse_size(input: &str) -> Result<u64, String> {
+    match input.strip_suffix(['K', 'k']) {
+        Some(kilo) => parse_address(kilo)?
+            .checked_mul(1024)
+            .ok_or("size is too large".to_owned()),
+        None => parse_address(input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_output_options() {
+        let args = Args::try_parse_from(["l6as", "--split", "even-odd", "in.l6s"]).unwrap();
+        assert!(args.check_output_options().is_ok());
+
+        let args = Args::try_parse_from(["l6as", "--format", "ihex", "--split", "2Kx8", "in.l6s"])
+            .unwrap();
+        let err = args.check_output_options().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+
+        let args = Args::try_parse_from(["l6as", "--format", "ptp", "--swap-bytes", "in.l6s"]);
+        assert!(args.unwrap().check_output_options().is_err());
+
+        let args = Args::try_parse_from([
+            "l6as",
+            "--format",
+            "mod",
+            "--word-order",
+            "low-high",
+            "in.l6s",
+        ]);
+        assert!(args.unwrap().check_output_options().is_err());
+
+        let args = Args::try_parse_from(["l6as", "--pad-to", "0x800", "in.l6s"]);
+        assert!(args.unwrap().check_output_options().is_ok());
+
+        let args =
+            Args::try_parse_from(["l6as", "--format", "ihex", "--pad-to", "0x800", "in.l6s"]);
+        assert!(args.unwrap().check_output_options().is_err());
+
+        let args =
+            Args::try_parse_from(["l6as", "--format", "ptp", "--pad-byte", "0xFF", "in.l6s"]);
+        assert!(args.unwrap().check_output_options().is_err());
+
+        let args = Args::try_parse_from(["l6as", "--format", "mod", "--entry", "START", "in.l6s"]);
+        assert!(args.unwrap().check_output_options().is_ok());
+
+        let args = Args::try_parse_from(["l6as", "--entry", "START", "in.l6s"]);
+        assert!(args.unwrap().check_output_options().is_err());
+    }
+}
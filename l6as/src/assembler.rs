@@ -1,8 +1,40 @@
 mod assemble;
+mod banks;
+mod callgraph;
 mod codegen;
+mod directives;
+mod flow;
+mod limits;
+mod lint;
+mod opcodes;
 mod parsers;
+mod pic;
+mod regions;
 mod size;
+mod stack;
 mod statements;
+mod suggestions;
+mod syntax;
 
-pub use assemble::{assemble, AssembledLine};
-pub use statements::{BaseRegister, DataRegister, Mnemonic};
+pub use assemble::{
+    analyze_stack_usage, assemble_with_labels, build_call_graph, build_program, check,
+    AssembledLine, AssemblerOptions, LabelDefinition, Program,
+};
+pub use callgraph::{generate_call_graph_dot, CallGraph};
+pub use codegen::{
+    decode_address_syllable, decode_branch_location, explain_statement,
+    get_address_syllable_field_value, ExplainedField, ExplainedWord, ShortImmediateSignedness,
+};
+pub use directives::{DirectiveHandler, DirectiveOutput, DirectiveRegistry};
+pub use opcodes::{
+    explain_sample_statement, get_opcode_table, get_opcode_table_entry, is_fixed_field,
+    OpcodeTableEntry,
+};
+pub use parsers::{
+    evaluate_constant_expression, is_branch_mnemonic, parse_label, parse_mnemonic_and_args,
+    parse_statement, MAX_VECTOR_INDEX,
+};
+pub use stack::{generate_stack_report, RoutineStackUsage};
+pub use statements::{BaseRegister, CpuFeature, DataRegister, Mnemonic};
+pub use suggestions::suggest_mnemonic;
+pub use syntax::{get_operand_specs, get_syntax, Arity};
@@ -1,121 +1,803 @@
-use super::parsers::{parse_label, parse_statement};
-use super::statements::Statement;
-use crate::assembler::codegen::codegen;
+use super::banks::find_cross_bank_branches;
+use super::callgraph::{find_call_graph, CallGraph};
+use super::directives::DirectiveRegistry;
+use super::limits::find_size_limit_violations;
+use super::lint::{find_unreachable_code, find_unused_labels};
+use super::parsers::{is_builtin_mnemonic, parse_label, parse_mnemonic_and_args, parse_statement};
+use super::pic::find_absolute_addressing;
+use super::regions::find_cross_region_p_relative;
+use super::stack::{find_stack_usage, RoutineStackUsage};
+use super::statements::{
+    AddressExpression, BranchLocation, BranchOnIndicatorsOpCode, CpuFeature, DataDefinitionSize,
+    Statement,
+};
+use crate::assembler::codegen::{
+    check_short_immediate_value, codegen, verify_statement_round_trip, ShortImmediateSignedness,
+};
 use crate::assembler::size::statement_size;
-use crate::logging::{print_assembler_error, AssemblerError, AssemblerErrorKind};
+use crate::cycles::{estimate_cycles, CycleTable};
+use crate::logging::{
+    print_assembler_error, print_assembler_warning, AssemblerError, AssemblerErrorKind,
+};
 use crate::preprocessor::{CodeLine, LineLocation};
 use nom::Err;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::path::Path;
 
-#[derive(Debug, Clone, PartialEq)]
-struct AbstractBinaryLine {
-    address: u64,
-    statement: Statement,
-    location: LineLocation,
+/// Statement placed in memory, as seen by the passes checking the program
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AbstractBinaryLine {
+    pub address: u64,
+    pub bank: Option<u64>,
+    pub statement: Statement,
+    pub size: u64,
+    pub location: LineLocation,
+    /// Mnemonic of the preprocessed code of the line, used to look up its cycles
+    #[serde(skip)]
+    pub mnemonic: Option<String>,
+}
+
+/// Statements placed in memory, with the labels pointing to them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Program {
+    #[serde(rename = "statements")]
+    abstract_binary_list: Vec<AbstractBinaryLine>,
+    #[serde(skip)]
+    label_table: HashMap<String, u64>,
+    #[serde(rename = "labels")]
+    label_definitions: Vec<LabelDefinition>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AssembledLine {
     pub address: u64,
+    pub bank: Option<u64>,
     pub data: Vec<u16>,
     pub location: LineLocation,
+    /// Mnemonic of the preprocessed code of the line, used to look up its cycles
+    pub mnemonic: Option<String>,
+}
+
+// Location of the lines built by tests
+#[cfg(test)]
+fn test_location() -> LineLocation {
+    LineLocation {
+        line_n: 1,
+        file_name: "a.l6s".into(),
+        raw_content: String::new(),
+    }
+}
+
+#[cfg(test)]
+impl AbstractBinaryLine {
+    /// Statement placed at line 1 of "a.l6s", for tests of the passes checking the program
+    pub fn new_test(address: u64, bank: Option<u64>, statement: Statement, size: u64) -> Self {
+        Self {
+            address,
+            bank,
+            statement,
+            size,
+            location: test_location(),
+            mnemonic: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl AssembledLine {
+    /// Words assembled from line 1 of "a.l6s", for tests of the outputs
+    pub fn new_test(
+        address: u64,
+        bank: Option<u64>,
+        data: Vec<u16>,
+        mnemonic: Option<&str>,
+    ) -> Self {
+        Self {
+            address,
+            bank,
+            data,
+            location: test_location(),
+            mnemonic: mnemonic.map(str::to_owned),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LabelDefinition {
+    pub label: String,
+    pub address: u64,
+    pub bank: Option<u64>,
+    pub location: LineLocation,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssemblerOptions {
+    /// Decode and re-encode every address syllable, checking that the encoding is consistent
+    pub paranoid: bool,
+    /// Labels defined outside of the program, overridden by the program's own labels
+    pub symbols: HashMap<String, u64>,
+    /// Maximum size of the image of each bank, in words
+    pub max_size: Option<u64>,
+    /// Stop at the first file with errors
+    pub fail_fast: bool,
+    /// Directives handled outside of the assembler
+    pub directives: DirectiveRegistry,
+    /// Cycle estimates of the target CPU model, used to time I/O instructions set apart by
+    /// .IODELAY
+    pub cycle_table: Option<CycleTable>,
+    /// Values accepted by Short Value Immediate instructions
+    pub short_immediates: ShortImmediateSignedness,
+    /// Reject absolute addressing, for code which must run wherever it is loaded
+    pub pic: bool,
+    /// Optional processors of the target CPU, selecting the code of .IFCPU blocks
+    pub cpu_features: Vec<CpuFeature>,
+}
+
+/// Assembles a list of `CodeLine`s to a list of `AssembledLine`s containing the raw machine code,
+/// also returning all of the labels defined in the program
+pub fn assemble_with_labels(
+    input: &[CodeLine],
+    opts: &AssemblerOptions,
+) -> (
+    Result<Vec<AssembledLine>, Vec<AssembledLine>>,
+    Vec<LabelDefinition>,
+) {
+    // Create abstract binary list
+    let (program, mut error_occurred) = match build_program(input, opts) {
+        Ok(program) => (program, false),
+        Err(program) => (program, true),
+    };
+    if opts.fail_fast && error_occurred {
+        return (Err(vec![]), program.label_definitions);
+    }
+    lint_program(&program);
+    if check_banks(&program).is_err()
+        || check_size_limits(&program, opts.max_size).is_err()
+        || (opts.pic && check_position_independence(&program).is_err())
+    {
+        error_occurred = true;
+    }
+    let Program {
+        abstract_binary_list,
+        label_table,
+        label_definitions,
+        ..
+    } = program;
+
+    // Generate machine code
+    let _span = tracing::info_span!("pass", kind = "codegen").entered();
+    let mut result: Vec<AssembledLine> = vec![];
+    for line in abstract_binary_list {
+        // Generate binary for this statement
+        let data: Vec<u16> = match codegen(&line.statement, line.address, &label_table) {
+            Ok(res) => res,
+            Err(err) => {
+                error_occurred = true;
+                print_assembler_error(AssemblerError {
+                    kind: err,
+                    location: Some(line.location),
+                });
+                continue;
+            }
+        };
+
+        // Check encoding of address syllables
+        if opts.paranoid {
+            if let Err(err) =
+                verify_statement_round_trip(&line.statement, line.address, &label_table)
+            {
+                error_occurred = true;
+                print_assembler_error(AssemblerError {
+                    kind: err,
+                    location: Some(line.location.clone()),
+                });
+            }
+        }
+
+        result.push(AssembledLine {
+            address: line.address,
+            bank: line.bank,
+            data,
+            location: line.location,
+            mnemonic: line.mnemonic,
+        })
+    }
+
+    tracing::info!(
+        "generated {} words",
+        result.iter().map(|line| line.data.len()).sum::<usize>()
+    );
+
+    // Return result based on whether an error occurred or not
+    let result = match error_occurred {
+        false => Ok(result),
+        true => Err(result),
+    };
+    (result, label_definitions)
 }
 
-/// Assembles a list of `CodeLine`s to a list of `AssembledLine`s, containing the raw machine code
-pub fn assemble(input: &[CodeLine]) -> Result<Vec<AssembledLine>, Vec<AssembledLine>> {
+/// Checks a list of `CodeLine`s for errors without writing any output. Machine code is
+/// generated and discarded, so that encoding errors such as out of range values are reported
+pub fn check(input: &[CodeLine], opts: &AssemblerOptions) -> Result<(), ()> {
+    match assemble_with_labels(input, opts).0 {
+        Ok(_) => Ok(()),
+        Err(_) => Err(()),
+    }
+}
+
+/// Parses all code lines, placing statements in memory and building the label table
+pub fn build_program(input: &[CodeLine], opts: &AssemblerOptions) -> Result<Program, Program> {
+    let _span = tracing::info_span!("pass", kind = "placement").entered();
     let mut error_occurred = false;
     let mut current_address: u64 = 0;
+    let mut current_bank: Option<u64> = None;
+    let mut bank_addresses: HashMap<Option<u64>, u64> = HashMap::new();
 
-    // Create abstract binary list
     let mut abstract_binary_list: Vec<AbstractBinaryLine> = vec![];
     let mut label_table: HashMap<String, u64> = HashMap::new();
+    let mut label_definitions: Vec<LabelDefinition> = vec![];
+    let mut equates: Vec<(LabelDefinition, Option<String>, i128)> = vec![];
+    let mut weak_labels: HashSet<String> = HashSet::new();
+    // Minimum cycles between I/O instructions, and cycles of the code since the last one
+    let mut io_delay: u64 = 0;
+    let mut cycles_since_io: Option<u64> = None;
+    let nop_cycles = opts
+        .cycle_table
+        .as_ref()
+        .and_then(|table| estimate_cycles(table, "NOP", 1))
+        .filter(|cycles| *cycles > 0);
+    // Entries of the vector table being built, placed once all of them are known
+    let mut vector_table: Vec<(u64, String, LineLocation)> = vec![];
+    // Open .IFCPU blocks, with whether their current branch is assembled
+    let mut conditions: Vec<(bool, LineLocation)> = vec![];
+    let mut first_error_file: Option<&Path> = None;
+    let mut previous_file: Option<&Path> = None;
     for line in input {
+        // Errors are reported while handling a line, so the first one was on the previous line
+        if error_occurred && first_error_file.is_none() {
+            first_error_file = previous_file;
+        }
+        previous_file = Some(&line.location.file_name);
+
+        // Stop once past the first file with errors
+        if opts.fail_fast {
+            if let Some(file_name) = first_error_file {
+                if file_name != line.location.file_name {
+                    break;
+                }
+            }
+        }
+
+        // Lines of branches which aren't assembled only matter for nesting
+        if conditions.iter().any(|(active, _)| !active) {
+            match get_code_mnemonic(&line.body).map(|mnemonic| mnemonic.to_uppercase()) {
+                Some(mnemonic) if mnemonic == ".IFCPU" => {
+                    conditions.push((true, line.location.clone()))
+                }
+                Some(mnemonic) if mnemonic == ".ELSE" => {
+                    if let Some((active, _)) = conditions.last_mut() {
+                        *active = !*active;
+                    }
+                }
+                Some(mnemonic) if mnemonic == ".ENDIF" => {
+                    conditions.pop();
+                }
+                _ => {}
+            }
+            continue;
+        }
+
         // Parse code line
-        let (label, statement) = match parse_code_line(&line.body, &line.location) {
+        let (mut label, statement, symbols) = match parse_code_line(
+            &line.body,
+            &line.location,
+            current_address,
+            &opts.directives,
+        ) {
             Ok(res) => res,
             Err(res) => {
                 error_occurred = true;
-                res
+                *res
             }
         };
 
-        // Handle inserting label into label table
-        if let Some(label) = label {
-            // Check if label is already defined
-            if !label_table.contains_key(&label) {
-                // Insert label into label table
-                label_table.insert(label, current_address);
-            } else {
-                // Double label definition
+        // Conditional assembly
+        let condition_error = match &statement {
+            Some(Statement::IfCpu(feature)) => {
+                let active = opts.cpu_features.contains(feature);
+                conditions.push((active, line.location.clone()));
+                None
+            }
+            Some(Statement::Else) => match conditions.last_mut() {
+                Some((active, _)) => {
+                    *active = !*active;
+                    None
+                }
+                None => Some(AssemblerErrorKind::ElseWithoutIfCpu),
+            },
+            Some(Statement::EndIf) => match conditions.pop() {
+                Some(_) => None,
+                None => Some(AssemblerErrorKind::EndIfWithoutIfCpu),
+            },
+            _ => None,
+        };
+        if let Some(kind) = condition_error {
+            print_assembler_error(AssemblerError {
+                kind,
+                location: Some(line.location.clone()),
+            });
+            error_occurred = true;
+        }
+
+        // A vector table ends at the first label or statement which isn't .VECTOR
+        let in_vector_table =
+            label.is_none() && matches!(statement, None | Some(Statement::Vector(_, _)));
+        if !in_vector_table && !vector_table.is_empty() {
+            current_address = place_vector_table(
+                &mut vector_table,
+                current_address,
+                current_bank,
+                &mut abstract_binary_list,
+            );
+        }
+
+        // Short Value Immediate values, in the interpretation chosen by the user
+        if let Some(Statement::ShortValueImmediate(_, _, value)) = &statement {
+            if let Err(kind) = check_short_immediate_value(*value, opts.short_immediates) {
                 print_assembler_error(AssemblerError {
-                    kind: AssemblerErrorKind::LabelDoubleDefinition(label),
+                    kind,
                     location: Some(line.location.clone()),
                 });
                 error_occurred = true;
             }
         }
 
+        // Symbols defined by directives are handled like labels
+        for (symbol, address) in symbols {
+            let definition = LabelDefinition {
+                label: symbol,
+                address,
+                bank: current_bank,
+                location: line.location.clone(),
+            };
+            let res = define_label(
+                &mut label_table,
+                &mut label_definitions,
+                &mut weak_labels,
+                definition,
+                false,
+            );
+            if res.is_err() {
+                error_occurred = true;
+            }
+        }
+
+        // Labels of .EQU statements get their value once all other labels are known
+        if let Some(Statement::Equ(target, offset)) = &statement {
+            match label.take() {
+                Some(label) => equates.push((
+                    LabelDefinition {
+                        label,
+                        address: 0,
+                        bank: current_bank,
+                        location: line.location.clone(),
+                    },
+                    target.clone(),
+                    *offset,
+                )),
+                None => {
+                    print_assembler_error(AssemblerError {
+                        kind: AssemblerErrorKind::EquWithoutLabel,
+                        location: Some(line.location.clone()),
+                    });
+                    error_occurred = true;
+                }
+            }
+        }
+
+        // Labels of .WEAK statements are replaced by any other definition
+        let weak = matches!(statement, Some(Statement::Weak));
+        if weak && label.is_none() {
+            print_assembler_error(AssemblerError {
+                kind: AssemblerErrorKind::WeakWithoutLabel,
+                location: Some(line.location.clone()),
+            });
+            error_occurred = true;
+        }
+
+        // Handle inserting label into label table
+        if let Some(label) = label {
+            let definition = LabelDefinition {
+                label,
+                address: current_address,
+                bank: current_bank,
+                location: line.location.clone(),
+            };
+            let res = define_label(
+                &mut label_table,
+                &mut label_definitions,
+                &mut weak_labels,
+                definition,
+                weak,
+            );
+            if res.is_err() {
+                error_occurred = true;
+            }
+        }
+
         // Handle adding statements to abstract binary list
         if let Some(statement) = statement {
             // If statement is Org, change current address
             if let Statement::Org(address) = statement {
                 current_address = address;
+                cycles_since_io = None;
+                continue;
+            }
+
+            // If statement is Bank, switch to the bank, resuming where it was left
+            if let Statement::Bank(bank) = statement {
+                bank_addresses.insert(current_bank, current_address);
+                current_bank = Some(bank);
+                current_address = bank_addresses.get(&current_bank).copied().unwrap_or(0);
+                cycles_since_io = None;
+                continue;
+            }
+
+            // Vector table entries are placed by index once the table ends
+            if let Statement::Vector(index, handler) = statement {
+                if vector_table.iter().any(|(other, _, _)| *other == index) {
+                    print_assembler_error(AssemblerError {
+                        kind: AssemblerErrorKind::VectorDoubleDefinition(index),
+                        location: Some(line.location.clone()),
+                    });
+                    error_occurred = true;
+                } else {
+                    vector_table.push((index, handler, line.location.clone()));
+                }
+                continue;
+            }
+
+            // If statement is IoDelay, pad the following I/O instructions
+            if let Statement::IoDelay(cycles) = statement {
+                if cycles > 0 && nop_cycles.is_none() {
+                    print_assembler_error(AssemblerError {
+                        kind: AssemblerErrorKind::IoDelayWithoutNopCycles,
+                        location: Some(line.location.clone()),
+                    });
+                    error_occurred = true;
+                }
+                io_delay = cycles;
                 continue;
             }
 
+            // Insert NOPs before I/O instructions following another one too closely
+            let is_io = matches!(
+                statement,
+                Statement::InputOutput(_, _, _) | Statement::InputOutputLoad(_, _, _)
+            );
+            if let (true, Some(elapsed), Some(nop_cycles)) = (is_io, cycles_since_io, nop_cycles) {
+                for _ in 0..io_delay.saturating_sub(elapsed).div_ceil(nop_cycles) {
+                    let nop = Statement::BranchOnIndicators(
+                        BranchOnIndicatorsOpCode::NOP,
+                        BranchLocation::ShortDisplacement(AddressExpression::WordDisplacement(-1)),
+                    );
+                    let size = statement_size(&nop, current_address);
+                    // Listed as inserted code, on the line of the I/O instruction
+                    let location = LineLocation {
+                        raw_content: "NOP ; .IODELAY".to_owned(),
+                        ..line.location.clone()
+                    };
+                    abstract_binary_list.push(AbstractBinaryLine {
+                        address: current_address,
+                        bank: current_bank,
+                        statement: nop,
+                        size,
+                        location,
+                        mnemonic: Some("NOP".to_owned()),
+                    });
+                    current_address += size;
+                }
+            }
+
             // Calculate statement size in words
             let size = statement_size(&statement, current_address);
 
             // Add statement to Abstract Binary List
+            let mnemonic = get_code_mnemonic(&line.body).map(str::to_owned);
+            let cycles = opts
+                .cycle_table
+                .as_ref()
+                .and_then(|table| estimate_cycles(table, mnemonic.as_deref()?, size));
             abstract_binary_list.push(AbstractBinaryLine {
                 address: current_address,
+                bank: current_bank,
                 statement,
+                size,
                 location: line.location.clone(),
+                mnemonic,
             });
 
             // Update current address with size of just processed statement
             current_address += size;
+
+            // Count cycles in straight-line code, branches aren't followed
+            cycles_since_io = match is_io {
+                true => Some(0),
+                false => cycles_since_io.map(|elapsed| elapsed + cycles.unwrap_or(0)),
+            };
         }
     }
 
-    println!("{:#?}", label_table);
-    println!("{:#?}", abstract_binary_list);
+    for (_, location) in conditions {
+        print_assembler_error(AssemblerError {
+            kind: AssemblerErrorKind::IfCpuWithoutEndIf,
+            location: Some(location),
+        });
+        error_occurred = true;
+    }
 
-    // Generate machine code
-    let mut result: Vec<AssembledLine> = vec![];
-    for line in abstract_binary_list {
-        // Generate binary for this statement
-        let data: Vec<u16> = match codegen(&line.statement, line.address, &label_table) {
-            Ok(res) => res,
-            Err(err) => {
-                error_occurred = true;
-                print_assembler_error(AssemblerError {
-                    kind: err,
-                    location: Some(line.location),
-                });
+    if !vector_table.is_empty() {
+        place_vector_table(
+            &mut vector_table,
+            current_address,
+            current_bank,
+            &mut abstract_binary_list,
+        );
+    }
+
+    // Define .EQU labels, which may refer to each other in any order. Those which can't be
+    // resolved are reported by code generation, like other undefined labels
+    let mut pending = equates;
+    loop {
+        let pending_count = pending.len();
+        let mut unresolved = vec![];
+        for (mut definition, target, offset) in pending {
+            let base = match &target {
+                Some(target) => label_table
+                    .get(target)
+                    .or_else(|| opts.symbols.get(target))
+                    .copied(),
+                None => Some(0),
+            };
+            let address = base.and_then(|base| u64::try_from(base as i128 + offset).ok());
+            let Some(address) = address else {
+                unresolved.push((definition, target, offset));
                 continue;
+            };
+
+            definition.address = address;
+            let res = define_label(
+                &mut label_table,
+                &mut label_definitions,
+                &mut weak_labels,
+                definition,
+                false,
+            );
+            if res.is_err() {
+                error_occurred = true;
             }
-        };
+        }
 
-        result.push(AssembledLine {
-            address: line.address,
-            data,
-            location: line.location,
-        })
+        pending = unresolved;
+        if pending.len() == pending_count {
+            break;
+        }
     }
 
-    // Return result based on whether an error occurred or not
+    // Add external symbols which the program doesn't define itself
+    for (label, address) in &opts.symbols {
+        label_table.entry(label.clone()).or_insert(*address);
+    }
+
+    tracing::info!(
+        "placed {} statements and {} labels",
+        abstract_binary_list.len(),
+        label_table.len()
+    );
+
+    let program = Program {
+        abstract_binary_list,
+        label_table,
+        label_definitions,
+    };
     match error_occurred {
-        false => Ok(result),
-        true => Err(result),
+        false => Ok(program),
+        true => Err(program),
     }
 }
 
+// Places the entries of a vector table starting at an address, filling unused entries with 0.
+// Returns the address following the table
+fn place_vector_table(
+    vector_table: &mut Vec<(u64, String, LineLocation)>,
+    start: u64,
+    bank: Option<u64>,
+    abstract_binary_list: &mut Vec<AbstractBinaryLine>,
+) -> u64 {
+    vector_table.sort_by_key(|(index, _, _)| *index);
+
+    let mut next_index = 0;
+    for (index, handler, location) in vector_table.drain(..) {
+        // Listed as inserted data, on the line of the following entry
+        if index > next_index {
+            let count = index - next_index;
+            abstract_binary_list.push(AbstractBinaryLine {
+                address: start + next_index,
+                bank,
+                statement: Statement::Fill(DataDefinitionSize::Word, count, 0),
+                size: count,
+                location: LineLocation {
+                    raw_content: format!(".FILL {}, 0 ; .VECTOR", count),
+                    ..location.clone()
+                },
+                mnemonic: None,
+            });
+        }
+
+        abstract_binary_list.push(AbstractBinaryLine {
+            address: start + index,
+            bank,
+            statement: Statement::Vector(index, handler),
+            size: 1,
+            location,
+            mnemonic: None,
+        });
+        next_index = index + 1;
+    }
+
+    start + next_index
+}
+
+// Adds a label to the label table. Weak definitions give way to any other definition of the
+// same label, whichever comes first, other double definitions are reported
+fn define_label(
+    label_table: &mut HashMap<String, u64>,
+    label_definitions: &mut Vec<LabelDefinition>,
+    weak_labels: &mut HashSet<String>,
+    definition: LabelDefinition,
+    weak: bool,
+) -> Result<(), ()> {
+    match label_table.entry(definition.label.clone()) {
+        Entry::Vacant(entry) => {
+            entry.insert(definition.address);
+            if weak {
+                weak_labels.insert(definition.label.clone());
+            }
+            label_definitions.push(definition);
+            Ok(())
+        }
+        Entry::Occupied(_) if weak => Ok(()),
+        Entry::Occupied(mut entry) if weak_labels.remove(&definition.label) => {
+            entry.insert(definition.address);
+            label_definitions.retain(|other| other.label != definition.label);
+            label_definitions.push(definition);
+            Ok(())
+        }
+        Entry::Occupied(_) => {
+            print_assembler_error(AssemblerError {
+                kind: AssemblerErrorKind::LabelDoubleDefinition(definition.label),
+                location: Some(definition.location),
+            });
+            Err(())
+        }
+    }
+}
+
+// Reports unused labels, unreachable code and P-relative operands across .ORG regions
+fn lint_program(program: &Program) {
+    let _span = tracing::info_span!("pass", kind = "lint").entered();
+    let mut warnings =
+        find_unused_labels(&program.label_definitions, &program.abstract_binary_list);
+    warnings.extend(find_unreachable_code(
+        &program.label_table,
+        &program.abstract_binary_list,
+    ));
+    warnings.extend(find_cross_region_p_relative(
+        &program.label_table,
+        &program.abstract_binary_list,
+    ));
+    for warning in warnings {
+        print_assembler_warning(warning);
+    }
+}
+
+// Reports direct branches between banks
+fn check_banks(program: &Program) -> Result<(), ()> {
+    let label_banks: HashMap<String, Option<u64>> = program
+        .label_definitions
+        .iter()
+        .map(|definition| (definition.label.clone(), definition.bank))
+        .collect();
+
+    report_errors(find_cross_bank_branches(
+        &label_banks,
+        &program.abstract_binary_list,
+    ))
+}
+
+// Prints the errors found by a check, failing if there are any
+fn report_errors(errors: Vec<AssemblerError>) -> Result<(), ()> {
+    let error_occurred = !errors.is_empty();
+    for error in errors {
+        print_assembler_error(error);
+    }
+
+    match error_occurred {
+        false => Ok(()),
+        true => Err(()),
+    }
+}
+
+// Reports absolute addressing, in code which must be position independent
+fn check_position_independence(program: &Program) -> Result<(), ()> {
+    report_errors(find_absolute_addressing(&program.abstract_binary_list))
+}
+
+// Reports images which grow past their maximum size
+fn check_size_limits(program: &Program, max_size: Option<u64>) -> Result<(), ()> {
+    report_errors(find_size_limit_violations(
+        max_size,
+        &program.abstract_binary_list,
+    ))
+}
+
+/// Estimates the worst case stack usage of the routines of a program
+pub fn analyze_stack_usage(program: &Program) -> Vec<RoutineStackUsage> {
+    let _span = tracing::info_span!("pass", kind = "stack").entered();
+    find_stack_usage(
+        &program.label_table,
+        &program.label_definitions,
+        &program.abstract_binary_list,
+    )
+}
+
+/// Builds the graph of the calls between the routines of a program
+pub fn build_call_graph(program: &Program) -> CallGraph {
+    let _span = tracing::info_span!("pass", kind = "callgraph").entered();
+    // Name addresses after the labels of the program first, then after external symbols
+    let mut symbols: Vec<(&String, &u64)> = program.label_table.iter().collect();
+    symbols.sort();
+    let mut label_names: HashMap<u64, String> = HashMap::new();
+    for definition in &program.label_definitions {
+        label_names
+            .entry(definition.address)
+            .or_insert(definition.label.clone());
+    }
+    for (label, address) in symbols {
+        label_names.entry(*address).or_insert(label.clone());
+    }
+
+    find_call_graph(
+        &program.label_table,
+        &label_names,
+        &program.abstract_binary_list,
+    )
+}
+
+// Mnemonic of a preprocessed code line, used to look up its cycles
+fn get_code_mnemonic(input: &str) -> Option<&str> {
+    let rest = match parse_label(input) {
+        Ok((rest, _)) => rest,
+        Err(_) => input,
+    };
+    let (_, (mnemonic, _)) = parse_mnemonic_and_args(rest.trim()).ok()?;
+    Some(mnemonic)
+}
+
+// Labels, statements and directive symbols of a code line
+type ParsedCodeLine = (Option<String>, Option<Statement>, Vec<(String, u64)>);
+
+// Statement and symbols a registered directive expands to
+type ExpandedDirective = (Statement, Vec<(String, u64)>);
+
 // Parse code line
 fn parse_code_line(
     input: &str,
     location: &LineLocation,
-) -> Result<(Option<String>, Option<Statement>), (Option<String>, Option<Statement>)> {
+    cur_addr: u64,
+    directives: &DirectiveRegistry,
+) -> Result<ParsedCodeLine, Box<ParsedCodeLine>> {
     // Parse label
     let (input, label) = match parse_label(input) {
         Ok((input, res)) => (input, Some(res.to_owned())),
@@ -123,7 +805,15 @@ fn parse_code_line(
     };
 
     // Check if there is a statement
-    if input.len() > 0 {
+    if !input.is_empty() {
+        // Directives registered by the user
+        if let Some(res) = expand_custom_directive(input.trim(), location, cur_addr, directives) {
+            return match res {
+                Ok((statement, symbols)) => Ok((label, Some(statement), symbols)),
+                Err(()) => Err(Box::new((label, None, vec![]))),
+            };
+        }
+
         let (_input, statement) = match parse_statement(input.trim()) {
             Ok((input, res)) => (input, Some(res)),
             Err(err) => {
@@ -138,11 +828,532 @@ fn parse_code_line(
                     }),
                     Err::Incomplete(_) => {}
                 }
-                return Err((label, None));
+                return Err(Box::new((label, None, vec![])));
             }
         };
-        Ok((label, statement))
+        Ok((label, statement, vec![]))
     } else {
-        Ok((label, None))
+        Ok((label, None, vec![]))
+    }
+}
+
+// Runs the handler of a registered directive, if the statement is one. Built-in mnemonics
+// take precedence
+fn expand_custom_directive(
+    input: &str,
+    location: &LineLocation,
+    cur_addr: u64,
+    directives: &DirectiveRegistry,
+) -> Option<Result<ExpandedDirective, ()>> {
+    let (_, (mnemonic, args)) = parse_mnemonic_and_args(input).ok()?;
+    if is_builtin_mnemonic(mnemonic) {
+        return None;
+    }
+    let handler = directives.get(mnemonic)?;
+
+    match handler.expand(&args, cur_addr) {
+        Ok(output) => Some(Ok((
+            Statement::Custom(handler.name().to_owned(), output.words),
+            output.symbols,
+        ))),
+        Err(msg) => {
+            print_assembler_error(AssemblerError {
+                kind: AssemblerErrorKind::DirectiveFailed(handler.name().to_owned(), msg),
+                location: Some(location.clone()),
+            });
+            Some(Err(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycles::CycleEstimate;
+    use crate::logging::capture_diagnostics;
+
+    fn code_lines(lines: &[&str]) -> Vec<CodeLine> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| CodeLine {
+                body: line.to_string(),
+                comment: String::new(),
+                location: LineLocation {
+                    line_n: i + 1,
+                    file_name: "test.l6s".into(),
+                    raw_content: line.to_string(),
+                },
+            })
+            .collect()
+    }
+
+    fn label_address(labels: &[LabelDefinition], label: &str) -> Option<u64> {
+        labels
+            .iter()
+            .find(|definition| definition.label == label)
+            .map(|definition| definition.address)
+    }
+
+    #[test]
+    fn test_equ() {
+        // Aliases can refer to labels and other aliases defined later
+        let input = code_lines(&[
+            ".ORG 0x100",
+            "END_ALIAS: .EQU BUF_END - 1",
+            "BUF_END: .EQU BUFFER + 0x10",
+            "BUFFER: .DW 0",
+            "PORT: .EQU 0x20 * 2",
+            "LDR $R1, <BUF_END",
+        ]);
+        let (res, labels) = assemble_with_labels(&input, &AssemblerOptions::default());
+        let lines = res.unwrap();
+        assert_eq!(lines.last().unwrap().data[1], 0x110);
+        assert_eq!(label_address(&labels, "BUFFER"), Some(0x100));
+        assert_eq!(label_address(&labels, "BUF_END"), Some(0x110));
+        assert_eq!(label_address(&labels, "END_ALIAS"), Some(0x10F));
+        assert_eq!(label_address(&labels, "PORT"), Some(0x40));
+
+        // External symbols can be aliased
+        let mut opts = AssemblerOptions::default();
+        opts.symbols.insert("ROM_ENTRY".to_owned(), 0x8000);
+        let input = code_lines(&["ENTRY: .EQU ROM_ENTRY + 2", "JMP <ENTRY"]);
+        let (res, labels) = assemble_with_labels(&input, &opts);
+        assert!(res.is_ok());
+        assert_eq!(label_address(&labels, "ENTRY"), Some(0x8002));
+    }
+
+    #[test]
+    fn test_weak() {
+        // Strong definitions replace weak ones, before or after them
+        let input = code_lines(&[
+            "PRINT: .WEAK",
+            "HLT",
+            "EXIT: HLT",
+            "PRINT: HLT",
+            "EXIT: .WEAK",
+            "HLT",
+            "PUTC: .WEAK",
+            "PUTC: .WEAK",
+            "HLT",
+            "PUTC: .EQU 0x100",
+        ]);
+        let (res, labels) = assemble_with_labels(&input, &AssemblerOptions::default());
+        assert!(res.is_ok());
+        assert_eq!(label_address(&labels, "PRINT"), Some(2));
+        assert_eq!(label_address(&labels, "EXIT"), Some(1));
+        assert_eq!(label_address(&labels, "PUTC"), Some(0x100));
+        assert_eq!(labels.len(), 3);
+
+        // The first of several weak definitions is used
+        let input = code_lines(&["A: .WEAK", "HLT", "A: .WEAK", "HLT"]);
+        let (res, labels) = assemble_with_labels(&input, &AssemblerOptions::default());
+        assert!(res.is_ok());
+        assert_eq!(label_address(&labels, "A"), Some(0));
+    }
+
+    #[test]
+    fn test_equ_weak_err() {
+        let tests: [&[&str]; 6] = [
+            // Missing label for .WEAK
+            &[".WEAK"],
+            // Missing label
+            &[".EQU 0x100"],
+            // Undefined label
+            &["A: .EQU MISSING"],
+            // Cycle
+            &["A: .EQU B", "B: .EQU A + 1"],
+            // Negative address
+            &["A: .EQU B - 2", "B: HLT"],
+            // Double definition
+            &["A: HLT", "A: .EQU 0x10"],
+        ];
+        for input in tests {
+            let (res, _) = assemble_with_labels(&code_lines(input), &AssemblerOptions::default());
+            assert!(res.is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_assert_at() {
+        // Assertions can come before the label definition
+        let input = code_lines(&[
+            ".ASSERT_AT VECTOR, 0x102",
+            ".ORG 0x100",
+            "LDR $R1, =1",
+            "VECTOR: HLT",
+        ]);
+        let (res, _) = assemble_with_labels(&input, &AssemblerOptions::default());
+        assert!(res.is_ok());
+
+        let tests: [&[&str]; 2] = [
+            // Label moved
+            &["LDR $R1, =1", "VECTOR: HLT", ".ASSERT_AT VECTOR, 0x1"],
+            // Undefined label
+            &[".ASSERT_AT VECTOR, 0x0"],
+        ];
+        for input in tests {
+            let (res, _) = assemble_with_labels(&code_lines(input), &AssemblerOptions::default());
+            assert!(res.is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(&code_lines(&["VECTOR: HLT"]), &AssemblerOptions::default()).is_ok());
+
+        // Errors found while generating code are reported too
+        let tests: [&[&str]; 2] = [
+            &["LDR $R1, =1", "VECTOR: HLT", ".ASSERT_AT VECTOR, 0x1"],
+            &["B MISSING"],
+        ];
+        for input in tests {
+            let res = check(&code_lines(input), &AssemblerOptions::default());
+            assert!(res.is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_vector() {
+        // Entries are placed by index, unused ones are 0
+        let input = code_lines(&[
+            ".ORG 0x80",
+            ".VECTOR 3, TIMER",
+            ".VECTOR 0, RESET",
+            "RESET: HLT",
+            "TIMER: HLT",
+        ]);
+        let (res, labels) = assemble_with_labels(&input, &AssemblerOptions::default());
+        let words: Vec<(u64, Vec<u16>)> = res
+            .unwrap()
+            .into_iter()
+            .map(|line| (line.address, line.data))
+            .collect();
+        assert_eq!(
+            words,
+            vec![
+                (0x80, vec![0x84]),
+                (0x81, vec![0, 0]),
+                (0x83, vec![0x85]),
+                (0x84, vec![0]),
+                (0x85, vec![0]),
+            ]
+        );
+        assert_eq!(label_address(&labels, "RESET"), Some(0x84));
+
+        let tests: [&[&str]; 3] = [
+            // Collision
+            &[".VECTOR 1, A", ".VECTOR 1, A", "A: HLT"],
+            // Index out of range
+            &[".VECTOR 64, A", "A: HLT"],
+            // Undefined handler
+            &[".VECTOR 0, A"],
+        ];
+        for input in tests {
+            let (res, _) = assemble_with_labels(&code_lines(input), &AssemblerOptions::default());
+            assert!(res.is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_series() {
+        let input = code_lines(&[
+            ".SERIES 0x100, 0x10, 3",
+            ".SERIESB 10, -2, 4",
+            ".SERIES 0, 1, 0",
+        ]);
+        let (res, _) = assemble_with_labels(&input, &AssemblerOptions::default());
+        let words: Vec<(u64, Vec<u16>)> = res
+            .unwrap()
+            .into_iter()
+            .map(|line| (line.address, line.data))
+            .collect();
+        assert_eq!(
+            words,
+            vec![
+                (0, vec![0x100, 0x110, 0x120]),
+                (3, vec![0x0A08, 0x0604]),
+                (5, vec![]),
+            ]
+        );
+
+        let tests: [&[&str]; 4] = [
+            // Negative count
+            &[".SERIES 0, 1, -1"],
+            // Element does not fit in a byte
+            &[".SERIESB 0xF0, 0x10, 2"],
+            // Element does not fit in a word
+            &[".SERIES 0xFFFF, 1, 2"],
+            // Missing count
+            &[".SERIES 0, 1"],
+        ];
+        for input in tests {
+            let (res, _) = assemble_with_labels(&code_lines(input), &AssemblerOptions::default());
+            assert!(res.is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        let input = code_lines(&[".RANDOM 4, 1", ".RANDOM 2, 1"]);
+        let (res, _) = assemble_with_labels(&input, &AssemblerOptions::default());
+        let words: Vec<(u64, Vec<u16>)> = res
+            .unwrap()
+            .into_iter()
+            .map(|line| (line.address, line.data))
+            .collect();
+        // The same seed always gives the same words
+        assert_eq!(
+            words,
+            vec![
+                (0, vec![0xB400, 0x5A00, 0x2D00, 0x1680]),
+                (4, vec![0xB400, 0x5A00]),
+            ]
+        );
+
+        let tests: [&[&str]; 3] = [
+            // Negative count
+            &[".RANDOM -1, 1"],
+            // Zero seed
+            &[".RANDOM 4, 0"],
+            // Seed larger than a word
+            &[".RANDOM 4, 0x10000"],
+        ];
+        for input in tests {
+            let (res, _) = assemble_with_labels(&code_lines(input), &AssemblerOptions::default());
+            assert!(res.is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_if_cpu() {
+        let input = code_lines(&[
+            ".IFCPU SIP",
+            "    .DW 0x1111",
+            "    .IFCPU CIP",
+            "    .DW 0x2222",
+            "    .ELSE",
+            "    .DW 0x3333",
+            "    .ENDIF",
+            ".ELSE",
+            "    NOTANINSTRUCTION",
+            ".ENDIF",
+            ".DW 0x4444",
+        ]);
+        let words = |cpu_features: Vec<CpuFeature>| -> Vec<u16> {
+            let opts = AssemblerOptions {
+                cpu_features,
+                ..Default::default()
+            };
+            let (res, _) = assemble_with_labels(&input, &opts);
+            res.unwrap()
+                .into_iter()
+                .flat_map(|line| line.data)
+                .collect()
+        };
+        assert_eq!(words(vec![CpuFeature::Sip]), vec![0x1111, 0x3333, 0x4444]);
+        assert_eq!(
+            words(vec![CpuFeature::Sip, CpuFeature::Cip]),
+            vec![0x1111, 0x2222, 0x4444]
+        );
+
+        let tests: [&[&str]; 4] = [
+            &[".IFCPU FPU", ".ENDIF"],
+            &[".ELSE"],
+            &[".ENDIF"],
+            &[".IFCPU SIP", "HLT"],
+        ];
+        for input in tests {
+            let (res, _) = assemble_with_labels(&code_lines(input), &AssemblerOptions::default());
+            assert!(res.is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_stack_usage() {
+        let input = code_lines(&[
+            "START: LNJ $B5, OUTER",
+            "LNJ $B5, REC",
+            "LNJ $B5, IND",
+            "LNJ $B5, GROW",
+            "HLT",
+            "OUTER: STR $R1, -$B7",
+            "SAVE <AREA, 0x0003",
+            "LNJ $B4, INNER",
+            "RSTR <AREA",
+            "LDR $R1, +$B7",
+            "JMP $B5",
+            "INNER: STR $R2, -$B7",
+            "LDR $R2, +$B7",
+            "JMP $B4",
+            "REC: LNJ $B5, REC",
+            "JMP $B5",
+            "IND: JMP *$B6.-2",
+            "GROW: STR $R1, -$B7",
+            "B <GROW",
+            "AREA: .DW 0",
+        ]);
+        let program = build_program(&input, &AssemblerOptions::default()).unwrap();
+        let usages: Vec<(String, u64, bool, bool, usize)> = analyze_stack_usage(&program)
+            .into_iter()
+            .map(|usage| {
+                (
+                    usage.name,
+                    usage.max_depth,
+                    usage.recursive,
+                    usage.unbounded,
+                    usage.indirect_branches.len(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            usages,
+            vec![
+                ("START".to_owned(), 4, true, true, 0),
+                ("OUTER".to_owned(), 4, false, false, 0),
+                ("INNER".to_owned(), 1, false, false, 0),
+                ("REC".to_owned(), 0, true, false, 0),
+                ("IND".to_owned(), 0, false, false, 1),
+                ("GROW".to_owned(), 1, false, true, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_call_graph() {
+        let input = code_lines(&[
+            "START: LNJ $B5, SUB",
+            "LNJ $B5, ROM_ENTRY",
+            "HLT",
+            "SUB: BEZ $R1, DONE",
+            "B TAIL",
+            "DONE: JMP $B5",
+            "TAIL: LNJ $B4, LEAF",
+            "JMP *$B6.-2",
+            "LEAF: B LEAF_LOOP",
+            "LEAF_LOOP: JMP $B4",
+        ]);
+        let mut opts = AssemblerOptions::default();
+        opts.symbols.insert("ROM_ENTRY".to_owned(), 0x8000);
+        let program = build_program(&input, &opts).unwrap();
+        let graph = build_call_graph(&program);
+
+        let nodes: Vec<(&str, bool, usize)> = graph
+            .nodes
+            .iter()
+            .map(|node| (node.name.as_str(), node.external, node.indirect_branches))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("START", false, 0),
+                ("SUB", false, 1),
+                ("LEAF", false, 0),
+                ("ROM_ENTRY", true, 0),
+            ]
+        );
+
+        let edges: Vec<(&str, &str)> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+        assert_eq!(
+            edges,
+            vec![("START", "SUB"), ("START", "ROM_ENTRY"), ("SUB", "LEAF")]
+        );
+    }
+
+    #[test]
+    fn test_fail_fast_after_first_file() {
+        let mut input = code_lines(&["HLT", ".IODELAY 10"]);
+        input.extend(code_lines(&["BAD"]).into_iter().map(|mut line| {
+            line.location.file_name = "b.l6s".into();
+            line
+        }));
+        let opts = AssemblerOptions {
+            fail_fast: true,
+            ..Default::default()
+        };
+
+        // The .IODELAY error ends the first file, so the second one isn't parsed
+        let (res, diagnostics) = capture_diagnostics(|| build_program(&input, &opts));
+        assert!(res.is_err());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].location.as_ref().unwrap().file_name,
+            Path::new("test.l6s")
+        );
+    }
+
+    #[test]
+    fn test_io_delay() {
+        let input = code_lines(&[
+            ".IODELAY 10",
+            "IO <0x100, =0x100",
+            "LDV $R1, =1",
+            "WAIT: IO <0x100, =0x100",
+            ".IODELAY 0",
+            "IO <0x100, =0x100",
+        ]);
+        let mut opts = AssemblerOptions::default();
+        let estimate = |base| CycleEstimate {
+            base,
+            per_extra_word: 0,
+        };
+        opts.cycle_table = Some(HashMap::from([
+            ("NOP".to_owned(), estimate(2)),
+            ("LDV".to_owned(), estimate(3)),
+        ]));
+        let program = build_program(&input, &opts).unwrap();
+
+        // 7 more cycles are needed after LDV, taken by 4 NOPs the label points to
+        let nops: Vec<u64> = program
+            .abstract_binary_list
+            .iter()
+            .filter(|line| {
+                matches!(
+                    line.statement,
+                    Statement::BranchOnIndicators(BranchOnIndicatorsOpCode::NOP, _)
+                )
+            })
+            .map(|line| line.address)
+            .collect();
+        let wait = label_address(&program.label_definitions, "WAIT").unwrap();
+        assert_eq!(nops, (wait..wait + 4).collect::<Vec<u64>>());
+
+        // Listings look up cycles by the same mnemonics, inserted NOPs included
+        let mnemonics: Vec<Option<String>> = assemble_with_labels(&input, &opts)
+            .0
+            .unwrap()
+            .into_iter()
+            .map(|line| line.mnemonic)
+            .collect();
+        let exp = ["IO", "LDV", "NOP", "NOP", "NOP", "NOP", "IO", "IO"];
+        assert_eq!(
+            mnemonics,
+            exp.map(|mnemonic| Some(mnemonic.to_owned())).to_vec()
+        );
+
+        // The delay needs the cycles of NOP
+        assert!(build_program(&input, &AssemblerOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_short_immediates() {
+        let input = code_lines(&["LDV $R1, =200", "CMV $R2, =-1"]);
+        let mut opts = AssemblerOptions::default();
+        assert!(build_program(&input, &opts).is_ok());
+
+        for signedness in [
+            ShortImmediateSignedness::Signed,
+            ShortImmediateSignedness::Unsigned,
+        ] {
+            opts.short_immediates = signedness;
+            let (res, diagnostics) = capture_diagnostics(|| build_program(&input, &opts));
+            assert!(res.is_err());
+            assert_eq!(diagnostics.len(), 1);
+        }
     }
 }
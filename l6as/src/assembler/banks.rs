@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+
+use super::assemble::AbstractBinaryLine;
+use super::lint::get_statement_label_references;
+use super::statements::{DoubleOperandOpCode, SingleOperandOpCode, Statement};
+use crate::logging::{AssemblerError, AssemblerErrorKind};
+
+/// Reports direct branches into a different bank, which isn't guaranteed to be mapped in.
+/// Code outside of banks is common to all of them. Branches to common code and to labels
+/// declared with .TRAMPOLINE are always allowed
+pub fn find_cross_bank_branches(
+    label_banks: &HashMap<String, Option<u64>>,
+    lines: &[AbstractBinaryLine],
+) -> Vec<AssemblerError> {
+    let trampolines: HashSet<&String> = lines
+        .iter()
+        .filter_map(|line| match &line.statement {
+            Statement::Trampoline(label) => Some(label),
+            _ => None,
+        })
+        .collect();
+
+    let mut errors = vec![];
+    for line in lines {
+        for label in get_branch_targets(&line.statement) {
+            // Labels from symbol files aren't in any bank
+            let target_bank = match label_banks.get(&label) {
+                Some(Some(bank)) => *bank,
+                _ => continue,
+            };
+
+            if line.bank != Some(target_bank) && !trampolines.contains(&label) {
+                errors.push(AssemblerError {
+                    kind: AssemblerErrorKind::CrossBankBranch(label, line.bank, target_bank),
+                    location: Some(line.location.clone()),
+                });
+            }
+        }
+    }
+
+    errors
+}
+
+// Labels a statement transfers control to
+fn get_branch_targets(statement: &Statement) -> Vec<String> {
+    match statement {
+        Statement::BranchOnIndicators(_, _)
+        | Statement::BranchOnRegisters(_, _, _)
+        | Statement::SingleOperand(SingleOperandOpCode::JMP, _, _)
+        | Statement::DoubleOperand(DoubleOperandOpCode::LNJ, _, _, _) => {
+            get_statement_label_references(statement)
+        }
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::statements::{
+        AddressExpression, BranchLocation, BranchOnIndicatorsOpCode,
+    };
+
+    fn branch(label: &str) -> Statement {
+        Statement::BranchOnIndicators(
+            BranchOnIndicatorsOpCode::B,
+            BranchLocation::Absolute(AddressExpression::Label(label.to_owned())),
+        )
+    }
+
+    #[test]
+    fn find_cross_bank_branches_succ() {
+        let label_banks = HashMap::from([
+            ("COMMON".to_owned(), None),
+            ("ONE".to_owned(), Some(1)),
+            ("TWO".to_owned(), Some(2)),
+            ("ENTRY".to_owned(), Some(2)),
+        ]);
+        let statements = [
+            (Some(1), branch("ONE")),
+            (Some(1), branch("COMMON")),
+            (Some(2), branch("ONE")),
+            (None, branch("TWO")),
+            (None, branch("ENTRY")),
+            (Some(1), branch("EXTERNAL")),
+            (Some(2), Statement::Trampoline("ENTRY".to_owned())),
+        ];
+        let lines: Vec<AbstractBinaryLine> = statements
+            .into_iter()
+            .map(|(bank, statement)| AbstractBinaryLine::new_test(0, bank, statement, 0))
+            .collect();
+
+        let errors: Vec<(String, Option<u64>, u64)> =
+            find_cross_bank_branches(&label_banks, &lines)
+                .into_iter()
+                .map(|err| match err.kind {
+                    AssemblerErrorKind::CrossBankBranch(label, from, to) => (label, from, to),
+                    _ => panic!(),
+                })
+                .collect();
+
+        assert_eq!(
+            errors,
+            vec![("ONE".to_owned(), Some(2), 1), ("TWO".to_owned(), None, 2)]
+        );
+    }
+}
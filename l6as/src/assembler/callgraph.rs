@@ -0,0 +1,315 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde::Serialize;
+
+use super::assemble::AbstractBinaryLine;
+use super::flow::{find_routine_entries, get_code_addresses, get_flow, next_index, Flow};
+
+/// Routine of a call graph, or code outside of the program it branches to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CallGraphNode {
+    pub name: String,
+    pub address: u64,
+    /// Placed outside of the program, e.g. a routine from a symbol map
+    pub external: bool,
+    /// Branches and calls whose target isn't known when assembling
+    pub indirect_branches: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallKind {
+    /// LNJ, returning to the caller
+    Call,
+    /// Branch into another routine, which returns in place of the caller
+    Jump,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CallGraphEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: CallKind,
+}
+
+/// Routines of a program and the calls between them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CallGraph {
+    pub nodes: Vec<CallGraphNode>,
+    pub edges: Vec<CallGraphEdge>,
+}
+
+/// Builds the call graph of a program. The routines are the entry point and the targets of
+/// LNJ, each made of the code reachable from its entry without calling or branching into
+/// another routine
+pub fn find_call_graph(
+    label_table: &HashMap<String, u64>,
+    label_names: &HashMap<u64, String>,
+    lines: &[AbstractBinaryLine],
+) -> CallGraph {
+    let code_addresses = get_code_addresses(lines);
+    let entries = find_routine_entries(label_table, &code_addresses, lines);
+    let entry_set: HashSet<u64> = entries.iter().copied().collect();
+
+    let name = |address: u64| match label_names.get(&address) {
+        Some(name) => name.clone(),
+        None => format!("{:#06X}", address),
+    };
+
+    let mut nodes = vec![];
+    let mut edges: BTreeSet<(u64, u64, CallKind)> = BTreeSet::new();
+    let mut external: BTreeSet<u64> = BTreeSet::new();
+    for &entry in &entries {
+        let mut indirect_branches = 0;
+        let mut visited: HashSet<usize> = HashSet::new();
+        let mut pending = vec![code_addresses[&entry]];
+
+        while let Some(index) = pending.pop() {
+            if !visited.insert(index) {
+                continue;
+            }
+
+            let (next, branch) = match get_flow(&lines[index], label_table) {
+                Flow::Next => (next_index(lines, index), None),
+                Flow::Branch(target) => (None, Some((target, CallKind::Jump))),
+                Flow::ConditionalBranch(target) => {
+                    (next_index(lines, index), Some((target, CallKind::Jump)))
+                }
+                Flow::Call(target) => (next_index(lines, index), Some((target, CallKind::Call))),
+                Flow::Return | Flow::Stop => (None, None),
+            };
+            pending.extend(next);
+
+            match branch {
+                Some((None, _)) => indirect_branches += 1,
+                Some((Some(target), kind)) => match code_addresses.get(&target) {
+                    // Branches within the routine
+                    Some(target_index)
+                        if kind == CallKind::Jump && !entry_set.contains(&target) =>
+                    {
+                        pending.push(*target_index)
+                    }
+                    // Loops back to the start of the routine
+                    Some(_) if kind == CallKind::Jump && target == entry => {
+                        pending.push(code_addresses[&target])
+                    }
+                    Some(_) => {
+                        edges.insert((entry, target, kind));
+                    }
+                    None => {
+                        edges.insert((entry, target, kind));
+                        external.insert(target);
+                    }
+                },
+                None => {}
+            }
+        }
+
+        nodes.push(CallGraphNode {
+            name: name(entry),
+            address: entry,
+            external: false,
+            indirect_branches,
+        });
+    }
+
+    nodes.extend(external.into_iter().map(|address| CallGraphNode {
+        name: name(address),
+        address,
+        external: true,
+        indirect_branches: 0,
+    }));
+
+    CallGraph {
+        nodes,
+        edges: edges
+            .into_iter()
+            .map(|(from, to, kind)| CallGraphEdge {
+                from: name(from),
+                to: name(to),
+                kind,
+            })
+            .collect(),
+    }
+}
+
+/// Generates a Graphviz DOT description of a call graph. Jumps are dashed, external code is
+/// dotted and routines with indirect branches have a double border
+pub fn generate_call_graph_dot(graph: &CallGraph) -> String {
+    let mut output = String::new();
+    output.push_str("digraph callgraph {\n");
+    output.push_str("    node [shape=box];\n");
+
+    for node in &graph.nodes {
+        let mut attributes = vec![format!(
+            "label=\"{}\\n{:#06X}\"",
+            escape_dot(&node.name),
+            node.address
+        )];
+        if node.external {
+            attributes.push("style=dotted".to_owned());
+        }
+        if node.indirect_branches > 0 {
+            attributes.push("peripheries=2".to_owned());
+        }
+        output.push_str(&format!(
+            "    \"{}\" [{}];\n",
+            escape_dot(&node.name),
+            attributes.join(", ")
+        ));
+    }
+
+    for edge in &graph.edges {
+        let style = match edge.kind {
+            CallKind::Call => "",
+            CallKind::Jump => " [style=dashed]",
+        };
+        output.push_str(&format!(
+            "    \"{}\" -> \"{}\"{};\n",
+            escape_dot(&edge.from),
+            escape_dot(&edge.to),
+            style
+        ));
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+fn escape_dot(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::statements::{
+        AddressExpression, AddressSyllable, BRelativeAddress, BRelativeAddressMode, BaseRegister,
+        DoubleOperandOpCode, GenericOpCode, ImmediateAddress, ImmediateAddressMode,
+        PRelativeAddress, Register, SingleOperandOpCode, Statement,
+    };
+
+    fn lnj(addr_syl: AddressSyllable) -> Statement {
+        Statement::DoubleOperand(
+            DoubleOperandOpCode::LNJ,
+            Register::Base(BaseRegister::B5),
+            addr_syl,
+            None,
+        )
+    }
+
+    fn lnj_label(label: &str) -> Statement {
+        lnj(AddressSyllable::PRelative(PRelativeAddress::Direct(
+            AddressExpression::Label(label.to_owned()),
+        )))
+    }
+
+    fn ret() -> Statement {
+        Statement::SingleOperand(
+            SingleOperandOpCode::JMP,
+            AddressSyllable::BRelative(BRelativeAddressMode::Direct(BRelativeAddress::Simple(
+                BaseRegister::B5,
+            ))),
+            None,
+        )
+    }
+
+    fn build(program: Vec<Statement>, labels: &[(&str, u64)]) -> CallGraph {
+        let lines: Vec<AbstractBinaryLine> = program
+            .into_iter()
+            .enumerate()
+            .map(|(address, statement)| {
+                AbstractBinaryLine::new_test(address as u64, None, statement, 1)
+            })
+            .collect();
+        let label_table: HashMap<String, u64> = labels
+            .iter()
+            .map(|(label, address)| (label.to_string(), *address))
+            .collect();
+        let label_names: HashMap<u64, String> = labels
+            .iter()
+            .map(|(label, address)| (*address, label.to_string()))
+            .collect();
+
+        find_call_graph(&label_table, &label_names, &lines)
+    }
+
+    fn edge(from: &str, to: &str, kind: CallKind) -> CallGraphEdge {
+        CallGraphEdge {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn find_call_graph_indirect() {
+        let program = vec![
+            // B-relative, through a register loaded at run time
+            lnj(AddressSyllable::BRelative(BRelativeAddressMode::Direct(
+                BRelativeAddress::Simple(BaseRegister::B2),
+            ))),
+            // Through a pointer in memory
+            lnj(AddressSyllable::ImmediateAddressing(
+                ImmediateAddressMode::Indirect(ImmediateAddress::Simple(AddressExpression::Label(
+                    "TABLE".to_owned(),
+                ))),
+            )),
+            lnj_label("SUB"),
+            Statement::Generic(GenericOpCode::HLT),
+            // SUB
+            ret(),
+        ];
+
+        let graph = build(program, &[("MAIN", 0), ("SUB", 4), ("TABLE", 0x100)]);
+        assert_eq!(
+            graph.nodes,
+            vec![
+                CallGraphNode {
+                    name: "MAIN".to_owned(),
+                    address: 0,
+                    external: false,
+                    indirect_branches: 2,
+                },
+                CallGraphNode {
+                    name: "SUB".to_owned(),
+                    address: 4,
+                    external: false,
+                    indirect_branches: 0,
+                },
+            ]
+        );
+        // Calls without a known target have no edge
+        assert_eq!(graph.edges, vec![edge("MAIN", "SUB", CallKind::Call)]);
+        assert!(generate_call_graph_dot(&graph).contains("peripheries=2"));
+    }
+
+    #[test]
+    fn find_call_graph_recursive() {
+        let program = vec![
+            lnj_label("EVEN"),
+            Statement::Generic(GenericOpCode::HLT),
+            // EVEN, calling ODD which calls it back
+            lnj_label("ODD"),
+            ret(),
+            // ODD, calling itself as well
+            lnj_label("EVEN"),
+            lnj_label("ODD"),
+            ret(),
+        ];
+
+        let graph = build(program, &[("MAIN", 0), ("EVEN", 2), ("ODD", 4)]);
+        let names: Vec<&str> = graph.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, vec!["MAIN", "EVEN", "ODD"]);
+        assert!(graph.nodes.iter().all(|node| !node.external));
+        assert_eq!(
+            graph.edges,
+            vec![
+                edge("MAIN", "EVEN", CallKind::Call),
+                edge("EVEN", "ODD", CallKind::Call),
+                edge("ODD", "EVEN", CallKind::Call),
+                edge("ODD", "ODD", CallKind::Call),
+            ]
+        );
+    }
+}
@@ -4,10 +4,16 @@ mod branch_on_registers;
 mod common;
 mod data_definition;
 mod double_operand;
+mod explain;
 mod generic;
 mod input_output;
 mod shift;
 mod short_value_immediate;
 mod single_operand;
 
-pub use common::codegen;
+pub use address_syllable::{
+    decode_address_syllable, get_address_syllable_field_value, verify_statement_round_trip,
+};
+pub use common::{codegen, decode_branch_location, resolve_address_expression};
+pub use explain::{explain_statement, ExplainedField, ExplainedWord};
+pub use short_value_immediate::{check_short_immediate_value, ShortImmediateSignedness};
@@ -1,7 +1,7 @@
 use crate::{
     assembler::statements::{
-        AddressSyllable, BRelativeAddress, BRelativeAddressMode, BaseRegister, ImmediateAddress,
-        ImmediateAddressMode, IncDec, PRelativeAddress, Register,
+        AddressSyllable, BRelativeAddress, BRelativeAddressMode, BaseRegister, ChannelExpression,
+        ImmediateAddress, ImmediateAddressMode, IncDec, PRelativeAddress, Register, Statement,
     },
     logging::AssemblerErrorKind,
 };
@@ -24,6 +24,10 @@ bit_struct! {
     }
 }
 
+// Fields of AddressSyllableField, from the most significant bit
+pub const ADDRESS_SYLLABLE_FIELD_LAYOUT: &[(&str, u32)] =
+    &[("addr_mod", 3), ("ind_addr_bit", 1), ("reg", 3)];
+
 /// Encode an address syllable
 pub fn get_address_syllable_field_value(
     addr_syl: &AddressSyllable,
@@ -71,19 +75,29 @@ fn encode_addr_syl_immediate_operand(
 
 fn get_immediate_operand_extra_word(val: i128) -> Result<u16, AssemblerErrorKind> {
     if val >= 0 {
-        // Raw value into u8
+        // Raw value into u16
         match TryInto::<u16>::try_into(val) {
             Ok(val) => Ok(val),
             Err(_) => Err(AssemblerErrorKind::ImmediateValueOutOfRange(val)),
         }
     } else {
-        // Two's complemented i8 into u8
-        match TryInto::<u16>::try_into(val) {
+        // Two's complemented i16 into u16
+        match TryInto::<i16>::try_into(val) {
             Ok(val) => Ok(u16::from_be_bytes(val.to_be_bytes())),
             Err(_) => Err(AssemblerErrorKind::ImmediateValueOutOfRange(val)),
         }
     }
 }
+
+/// Extra word of an immediate operand of a byte instruction, which reads the left byte of it
+pub fn get_byte_immediate_operand_extra_word(val: i128) -> Result<u16, AssemblerErrorKind> {
+    if !(i8::MIN as i128..=u8::MAX as i128).contains(&val) {
+        return Err(AssemblerErrorKind::ByteImmediateValueOutOfRange(val));
+    }
+
+    // Two's complemented i8 into u8 if negative
+    Ok((val as u8 as u16) << 8)
+}
 fn encode_addr_syl_immediate_addressing(
     imm_addr: &ImmediateAddressMode,
     cur_addr: u64,
@@ -251,6 +265,198 @@ fn get_prelative_address_extra_word(
     }
 }
 
+/// Decode a 7 bit address syllable, returning it along with the number of extra words it uses.
+/// Registers are decoded as data registers, as the field alone doesn't tell them apart
+pub fn decode_address_syllable(
+    field: u8,
+    extra_words: &[u16],
+) -> Result<(AddressSyllable, usize), AssemblerErrorKind> {
+    if field > 0x7F {
+        return Err(AssemblerErrorKind::InvalidAddressSyllableEncoding(field));
+    }
+    let addr_mod = (field >> 4) & 0b111;
+    let indirect = (field >> 3) & 0b1 == 1;
+    let reg = field & 0b111;
+
+    let extra_word = || match extra_words.first() {
+        Some(word) => Ok(*word),
+        None => Err(AssemblerErrorKind::AddressSyllableExtraWordMissing),
+    };
+
+    Ok(match (addr_mod, indirect, reg) {
+        // Immediate addressing
+        (0..=3, _, 0) => {
+            let ae = AddressExpression::Immediate(extra_word()? as u64);
+            let imm_addr = match addr_mod {
+                0 => ImmediateAddress::Simple(ae),
+                _ => ImmediateAddress::Indexed(ae, decode_data_register(addr_mod)),
+            };
+            let imm_addr_mode = match indirect {
+                false => ImmediateAddressMode::Direct(imm_addr),
+                true => ImmediateAddressMode::Indirect(imm_addr),
+            };
+            (AddressSyllable::ImmediateAddressing(imm_addr_mode), 1)
+        }
+
+        // B-relative, simple or indexed
+        (0..=3, _, _) => {
+            let brel_addr = match addr_mod {
+                0 => BRelativeAddress::Simple(decode_base_register(reg)),
+                _ => BRelativeAddress::Indexed(
+                    decode_base_register(reg),
+                    decode_data_register(addr_mod),
+                ),
+            };
+            (decode_brelative_direct_indirect(brel_addr, indirect), 0)
+        }
+
+        // P-relative
+        (4, _, 0) => {
+            let ae = AddressExpression::WordDisplacement(extra_word()? as i16 as i128);
+            let prel_addr = match indirect {
+                false => PRelativeAddress::Direct(ae),
+                true => PRelativeAddress::Indirect(ae),
+            };
+            (AddressSyllable::PRelative(prel_addr), 1)
+        }
+
+        // B-relative with displacement
+        (4, _, _) => {
+            let brel_addr = BRelativeAddress::Displacement(
+                decode_base_register(reg),
+                extra_word()? as i16 as i128,
+            );
+            (decode_brelative_direct_indirect(brel_addr, indirect), 1)
+        }
+
+        // Register addressing
+        (5, false, 1..=7) => (
+            AddressSyllable::RegisterAddressing(Register::Data(decode_data_register(reg))),
+            0,
+        ),
+
+        // Immediate operand
+        (7, false, 0) => (AddressSyllable::ImmediateOperand(extra_word()? as i128), 1),
+
+        // Push and pop
+        (6..=7, false, 1..=7) => {
+            let incdec = match addr_mod {
+                6 => IncDec::Decrement,
+                _ => IncDec::Increment,
+            };
+            (
+                AddressSyllable::BRelative(BRelativeAddressMode::PushPop(
+                    decode_base_register(reg),
+                    incdec,
+                )),
+                0,
+            )
+        }
+
+        // Increment and decrement indexed
+        (5..=7, true, 1..=3 | 5..=7) => {
+            let incdec = match reg {
+                1..=3 => IncDec::Decrement,
+                _ => IncDec::Increment,
+            };
+            (
+                AddressSyllable::BRelative(BRelativeAddressMode::IncDecIndexed(
+                    decode_base_register(reg & 0b11),
+                    decode_data_register(addr_mod - 4),
+                    incdec,
+                )),
+                0,
+            )
+        }
+
+        _ => return Err(AssemblerErrorKind::InvalidAddressSyllableEncoding(field)),
+    })
+}
+
+fn decode_brelative_direct_indirect(
+    brel_addr: BRelativeAddress,
+    indirect: bool,
+) -> AddressSyllable {
+    AddressSyllable::BRelative(match indirect {
+        false => BRelativeAddressMode::Direct(brel_addr),
+        true => BRelativeAddressMode::Indirect(brel_addr),
+    })
+}
+
+/// Encode an address syllable, decode it and encode it again, checking that nothing changed
+pub fn verify_address_syllable_round_trip(
+    addr_syl: &AddressSyllable,
+    cur_addr: u64,
+    label_table: &HashMap<String, u64>,
+) -> Result<(), AssemblerErrorKind> {
+    let (field, extra_words) = get_address_syllable_field_value(addr_syl, cur_addr, label_table)?;
+    let (decoded, _) = decode_address_syllable(field.value(), &extra_words)?;
+    let (redecoded_field, redecoded_extra_words) =
+        get_address_syllable_field_value(&decoded, cur_addr, label_table)?;
+
+    if field == redecoded_field && extra_words == redecoded_extra_words {
+        Ok(())
+    } else {
+        Err(AssemblerErrorKind::AddressSyllableRoundTripMismatch(
+            addr_syl.to_string(),
+            decoded.to_string(),
+        ))
+    }
+}
+
+/// Verify the round trip of every address syllable in a statement
+pub fn verify_statement_round_trip(
+    statement: &Statement,
+    cur_addr: u64,
+    label_table: &HashMap<String, u64>,
+) -> Result<(), AssemblerErrorKind> {
+    let addr_syls = match statement {
+        Statement::SingleOperand(_, addr_syl, _) | Statement::DoubleOperand(_, _, addr_syl, _) => {
+            vec![addr_syl]
+        }
+        Statement::InputOutput(_, addr_syl, chan_expr) => match chan_expr {
+            ChannelExpression::AddressSyllable(chan_addr_syl) => vec![addr_syl, chan_addr_syl],
+            ChannelExpression::Immediate(_, _) => vec![addr_syl],
+        },
+        Statement::InputOutputLoad(buffer_addr_syl, chan_expr, range_addr_syl) => match chan_expr {
+            ChannelExpression::AddressSyllable(chan_addr_syl) => {
+                vec![buffer_addr_syl, chan_addr_syl, range_addr_syl]
+            }
+            ChannelExpression::Immediate(_, _) => vec![buffer_addr_syl, range_addr_syl],
+        },
+        _ => vec![],
+    };
+
+    for addr_syl in addr_syls {
+        verify_address_syllable_round_trip(addr_syl, cur_addr, label_table)?;
+    }
+    Ok(())
+}
+
+fn decode_base_register(reg: u8) -> BaseRegister {
+    match reg {
+        1 => BaseRegister::B1,
+        2 => BaseRegister::B2,
+        3 => BaseRegister::B3,
+        4 => BaseRegister::B4,
+        5 => BaseRegister::B5,
+        6 => BaseRegister::B6,
+        _ => BaseRegister::B7,
+    }
+}
+
+fn decode_data_register(reg: u8) -> DataRegister {
+    match reg {
+        1 => DataRegister::R1,
+        2 => DataRegister::R2,
+        3 => DataRegister::R3,
+        4 => DataRegister::R4,
+        5 => DataRegister::R5,
+        6 => DataRegister::R6,
+        _ => DataRegister::R7,
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -410,4 +616,59 @@ mod tests {
             assert_eq!(ew, ew_exp);
         }
     }
+
+    #[test]
+    fn decode_address_syllable_round_trip() {
+        let label_table: HashMap<String, u64> = HashMap::new();
+        let mut decoded_count = 0;
+
+        // Every valid field encodes back to itself
+        for field in 0..=0x7F {
+            let (addr_syl, size) = match decode_address_syllable(field, &[0x1234]) {
+                Ok(res) => res,
+                Err(_) => continue,
+            };
+            decoded_count += 1;
+
+            let (field_exp, ew) =
+                get_address_syllable_field_value(&addr_syl, 0x100, &label_table).unwrap();
+            assert_eq!(field_exp.value(), field, "{:?}", addr_syl);
+            assert_eq!(ew.len(), size);
+        }
+
+        // Only increment and decrement indexed with registers 0 and 4,
+        // register addressing and push with register 0 are invalid
+        assert_eq!(decoded_count, 128 - 3 * 2 - 2);
+    }
+
+    #[test]
+    fn decode_address_syllable_fail() {
+        for field in [0b1011000, 0b1010000, 0x80] {
+            assert!(matches!(
+                decode_address_syllable(field, &[]),
+                Err(AssemblerErrorKind::InvalidAddressSyllableEncoding(_))
+            ));
+        }
+
+        assert!(matches!(
+            decode_address_syllable(0b1110000, &[]),
+            Err(AssemblerErrorKind::AddressSyllableExtraWordMissing)
+        ));
+    }
+
+    #[test]
+    fn verify_statement_round_trip_succ() {
+        let label_table = HashMap::from([("TABLE".to_owned(), 0x2000)]);
+        let statement = Statement::InputOutputLoad(
+            AddressSyllable::PRelative(PRelativeAddress::Direct(AddressExpression::Label(
+                "TABLE".to_owned(),
+            ))),
+            ChannelExpression::AddressSyllable(AddressSyllable::RegisterAddressing(
+                Register::Base(BaseRegister::B3),
+            )),
+            AddressSyllable::ImmediateOperand(10),
+        );
+
+        assert!(verify_statement_round_trip(&statement, 0x100, &label_table).is_ok());
+    }
 }
@@ -16,6 +16,10 @@ bit_struct! {
     }
 }
 
+// Fields of BranchOnIndicatorsInstructionWord, from the most significant bit
+pub const BRANCH_ON_INDICATORS_WORD_LAYOUT: &[(&str, u32)] =
+    &[("header", 4), ("op", 5), ("branchloc", 7)];
+
 /// Generaete code for a Branch on Indicators instruction
 pub fn codegen_branch_on_indicators(
     op: &BranchOnIndicatorsOpCode,
@@ -20,6 +20,10 @@ bit_struct! {
     }
 }
 
+// Fields of BranchOnRegistersInstructionWord, from the most significant bit
+pub const BRANCH_ON_REGISTERS_WORD_LAYOUT: &[(&str, u32)] =
+    &[("header", 1), ("reg", 3), ("op", 5), ("branchloc", 7)];
+
 /// Generaete code for a Branch on Indicators instruction
 pub fn codegen_branch_on_registers(
     op: &BranchOnRegistersOpCode,
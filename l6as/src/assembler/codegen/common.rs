@@ -1,17 +1,20 @@
 use std::collections::HashMap;
 
-use crate::{assembler::statements::ModeControlRegister, logging::AssemblerErrorKind};
+use crate::{
+    assembler::{statements::ModeControlRegister, suggestions::suggest},
+    logging::AssemblerErrorKind,
+};
 
 use bit_struct::*;
 
 use crate::assembler::statements::{
-    AddressExpression, BaseRegister, BranchLocation, DataRegister, Register, Statement,
+    AddressExpression, BaseRegister, BranchLocation, DataRegister, DataValue, Register, Statement,
 };
 
 use super::{
     branch_on_indicators::codegen_branch_on_indicators,
     branch_on_registers::codegen_branch_on_registers,
-    data_definition::codegen_data_definition,
+    data_definition::{codegen_data_definition, codegen_fill},
     double_operand::codegen_double_operand,
     generic::codegen_generic,
     input_output::{codegen_input_output, codegen_input_output_load},
@@ -28,8 +31,70 @@ pub fn codegen(
 ) -> Result<Vec<u16>, AssemblerErrorKind> {
     // Compute different size depending on the kind of statement
     match statement {
-        Statement::Org(_) => Ok(vec![]),
-        Statement::DataDefinition(size, values) => codegen_data_definition(size, values),
+        Statement::Org(_)
+        | Statement::Bank(_)
+        | Statement::Limit(_)
+        | Statement::IoDelay(_)
+        | Statement::Weak
+        | Statement::IfCpu(_)
+        | Statement::Else
+        | Statement::EndIf => Ok(vec![]),
+        Statement::Trampoline(label) => {
+            // Only check that the label exists
+            resolve_address_expression(&AddressExpression::Label(label.clone()), 0, label_table)?;
+            Ok(vec![])
+        }
+        Statement::AssertAt(label, expected) => {
+            let address = resolve_address_expression(
+                &AddressExpression::Label(label.clone()),
+                0,
+                label_table,
+            )?;
+            match address == *expected {
+                true => Ok(vec![]),
+                false => Err(AssemblerErrorKind::LabelAddressAssertionFailed(
+                    label.clone(),
+                    address,
+                    *expected,
+                )),
+            }
+        }
+        Statement::Vector(_, handler) => {
+            let address = resolve_address_expression(
+                &AddressExpression::Label(handler.clone()),
+                0,
+                label_table,
+            )?;
+            match u16::try_from(address) {
+                Ok(word) => Ok(vec![word]),
+                Err(_) => Err(AssemblerErrorKind::BranchAddressOutOfRange(address)),
+            }
+        }
+        Statement::Equ(label, offset) => {
+            // Only check that the value is a valid address
+            let base = match label {
+                Some(label) => resolve_address_expression(
+                    &AddressExpression::Label(label.clone()),
+                    0,
+                    label_table,
+                )?,
+                None => 0,
+            };
+            match u64::try_from(base as i128 + offset) {
+                Ok(_) => Ok(vec![]),
+                Err(_) => Err(AssemblerErrorKind::EquValueOutOfRange(
+                    base as i128 + offset,
+                )),
+            }
+        }
+        Statement::DataDefinition(size, values) => {
+            let values = values
+                .iter()
+                .map(|value| resolve_data_value(value, label_table))
+                .collect::<Result<Vec<i128>, AssemblerErrorKind>>()?;
+            codegen_data_definition(size, &values)
+        }
+        Statement::Fill(size, count, value) => codegen_fill(size, *count, *value),
         Statement::BranchOnIndicators(op, branchloc) => {
             codegen_branch_on_indicators(op, branchloc, cur_addr, label_table)
         }
@@ -60,6 +125,7 @@ pub fn codegen(
                 label_table,
             )
         }
+        Statement::Custom(_name, words) => Ok(words.clone()),
     }
 }
 
@@ -146,6 +212,22 @@ pub fn get_branch_location_field_value(
     Ok((value, extra_words))
 }
 
+/// Decodes the target of a branch from its instruction words, along with the displacement
+/// from the branch when the location is relative
+pub fn decode_branch_location(words: &[u16], cur_addr: u64) -> Option<(u64, Option<i128>)> {
+    let field = (words.first()? & 0x7F) as u8;
+    let displacement = match field {
+        // Absolute address in the extra word
+        0 => return Some((*words.get(1)? as u64, None)),
+        1 => *words.get(1)? as i16 as i128,
+        // Sign extend the 7 bit field
+        _ => (((field << 1) as i8) >> 1) as i128,
+    };
+
+    let target = (cur_addr as i128 + displacement).try_into().ok()?;
+    Some((target, Some(displacement)))
+}
+
 /// Resolve address expression to an absolute address in the u64 address space
 pub fn resolve_address_expression(
     addr_exp: &AddressExpression,
@@ -167,8 +249,33 @@ pub fn resolve_address_expression(
             // Find label
             match label_table.get(label) {
                 Some(addr) => Ok(*addr),
-                None => Err(AssemblerErrorKind::UndefinedLabel(label.to_owned())),
+                None => Err(AssemblerErrorKind::UndefinedLabel(
+                    label.to_owned(),
+                    suggest(label, label_table.keys().map(|l| l.as_str())),
+                )),
+            }
+        }
+    }
+}
+
+// Resolves the labels of a data definition value
+fn resolve_data_value(
+    value: &DataValue,
+    label_table: &HashMap<String, u64>,
+) -> Result<i128, AssemblerErrorKind> {
+    match value {
+        DataValue::Constant(value) => Ok(*value),
+        DataValue::Labels(labels, offset) => {
+            let mut value = *offset;
+            for (sign, label) in labels {
+                let address = resolve_address_expression(
+                    &AddressExpression::Label(label.clone()),
+                    0,
+                    label_table,
+                )?;
+                value += sign * address as i128;
             }
+            Ok(value)
         }
     }
 }
@@ -290,6 +397,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn decode_branch_location_succ() {
+        let tests = [
+            (vec![0x0000, 0x1234], (0x1234, None)),
+            (vec![0x0001, 0xFFFC], (0x000C, Some(-4))),
+            (vec![0x0005], (0x0015, Some(5))),
+            (vec![0x007E], (0x000E, Some(-2))),
+        ];
+
+        for (words, exp) in tests {
+            assert_eq!(decode_branch_location(&words, 0x10), Some(exp));
+        }
+        assert_eq!(decode_branch_location(&[0x0000], 0x10), None);
+    }
+
     #[test]
     fn twos_complement_u16_succ() {
         let tests = [
@@ -15,6 +15,15 @@ pub fn codegen_data_definition(
     Ok(words)
 }
 
+/// Generate data for a Fill directive
+pub fn codegen_fill(
+    size: &DataDefinitionSize,
+    count: u64,
+    value: i128,
+) -> Result<Vec<u16>, AssemblerErrorKind> {
+    codegen_data_definition(size, &vec![value; count as usize])
+}
+
 pub fn encode_values_to_byte(values: &[i128]) -> Result<Vec<u16>, AssemblerErrorKind> {
     let mut res = vec![];
 
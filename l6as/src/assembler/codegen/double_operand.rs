@@ -8,7 +8,7 @@ use crate::{
 use bit_struct::*;
 
 use super::{
-    address_syllable::get_address_syllable_field_value,
+    address_syllable::{get_address_syllable_field_value, get_byte_immediate_operand_extra_word},
     common::{get_generic_register_value, get_maskword_value},
 };
 
@@ -21,6 +21,10 @@ bit_struct! {
     }
 }
 
+// Fields of DoubleOperandInstructionWord, from the most significant bit
+pub const DOUBLE_OPERAND_WORD_LAYOUT: &[(&str, u32)] =
+    &[("header", 1), ("reg", 3), ("op", 5), ("addr_syl", 7)];
+
 /// Generaete code for a Double Operand  instruction
 pub fn codegen_double_operand(
     op: &DoubleOperandOpCode,
@@ -42,6 +46,13 @@ pub fn codegen_double_operand(
         addr_syl_field,
     );
 
+    // Immediate operands of byte instructions only have their left byte read
+    if let AddressSyllable::ImmediateOperand(val) = addr_syl {
+        if is_byte_instruction(op) {
+            addr_syl_extra_words = vec![get_byte_immediate_operand_extra_word(*val)?];
+        }
+    }
+
     // Concatenate words
     let mut words = vec![inst_word.raw()];
     words.append(&mut addr_syl_extra_words);
@@ -54,6 +65,19 @@ pub fn codegen_double_operand(
     Ok(words)
 }
 
+fn is_byte_instruction(op: &DoubleOperandOpCode) -> bool {
+    matches!(
+        op,
+        DoubleOperandOpCode::LDH
+            | DoubleOperandOpCode::STH
+            | DoubleOperandOpCode::CMH
+            | DoubleOperandOpCode::ORH
+            | DoubleOperandOpCode::XOH
+            | DoubleOperandOpCode::ANH
+            | DoubleOperandOpCode::LLH
+    )
+}
+
 fn get_double_operand_op_value(op: &DoubleOperandOpCode) -> u5 {
     match op {
         DoubleOperandOpCode::LDR => u5!(0b10000),
@@ -373,6 +397,36 @@ mod tests {
                 ),
                 vec![0b1001001110000000, 0x0000],
             ),
+            (
+                (
+                    DoubleOperandOpCode::LDR,
+                    Register::Data(DataRegister::R1),
+                    AddressSyllable::ImmediateOperand(-2),
+                    None,
+                    100,
+                ),
+                vec![0b1001100001110000, 0xFFFE],
+            ),
+            (
+                (
+                    DoubleOperandOpCode::LDH,
+                    Register::Data(DataRegister::R1),
+                    AddressSyllable::ImmediateOperand(0x41),
+                    None,
+                    100,
+                ),
+                vec![0b1001000011110000, 0x4100],
+            ),
+            (
+                (
+                    DoubleOperandOpCode::CMH,
+                    Register::Data(DataRegister::R1),
+                    AddressSyllable::ImmediateOperand(-1),
+                    None,
+                    100,
+                ),
+                vec![0b1001000111110000, 0xFF00],
+            ),
         ];
 
         let label_table: HashMap<String, u64> = HashMap::new();
@@ -385,4 +439,23 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn codegen_byte_immediate_operand_err() {
+        let label_table: HashMap<String, u64> = HashMap::new();
+        for val in [256, -129] {
+            let res = codegen_double_operand(
+                &DoubleOperandOpCode::LDH,
+                &Register::Data(DataRegister::R1),
+                &AddressSyllable::ImmediateOperand(val),
+                &None,
+                100,
+                &label_table,
+            );
+            assert!(matches!(
+                res,
+                Err(AssemblerErrorKind::ByteImmediateValueOutOfRange(err_val)) if err_val == val
+            ));
+        }
+    }
 }
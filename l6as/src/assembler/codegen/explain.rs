@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use crate::{assembler::statements::Statement, logging::AssemblerErrorKind};
+
+use super::{
+    address_syllable::ADDRESS_SYLLABLE_FIELD_LAYOUT,
+    branch_on_indicators::BRANCH_ON_INDICATORS_WORD_LAYOUT,
+    branch_on_registers::BRANCH_ON_REGISTERS_WORD_LAYOUT,
+    common::codegen,
+    double_operand::DOUBLE_OPERAND_WORD_LAYOUT,
+    generic::GENERIC_WORD_LAYOUT,
+    input_output::INPUT_OUTPUT_WORD_LAYOUT,
+    shift::{SHIFT_LONG_WORD_LAYOUT, SHIFT_SHORT_WORD_LAYOUT},
+    short_value_immediate::SHORT_VALUE_IMMEDIATE_WORD_LAYOUT,
+    single_operand::SINGLE_OPERAND_WORD_LAYOUT,
+};
+
+// Field names and widths, from the most significant bit
+type Layout = &'static [(&'static str, u32)];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplainedWord {
+    pub word: u16,
+    pub fields: Vec<ExplainedField>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplainedField {
+    pub name: &'static str,
+    pub width: u32,
+    pub value: u16,
+    pub subfields: Vec<ExplainedField>,
+}
+
+/// Generate raw words for one statement, breaking down the fields of its instruction word
+pub fn explain_statement(
+    statement: &Statement,
+    cur_addr: u64,
+) -> Result<Vec<ExplainedWord>, AssemblerErrorKind> {
+    let words = codegen(statement, cur_addr, &HashMap::new())?;
+
+    Ok(words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            // Only the first word of an instruction has a layout, the rest are extra words
+            let fields = match get_instruction_word_layout(statement) {
+                Some(layout) if i == 0 => split_fields(*word, 16, layout),
+                _ => vec![],
+            };
+
+            ExplainedWord {
+                word: *word,
+                fields,
+            }
+        })
+        .collect())
+}
+
+fn get_instruction_word_layout(statement: &Statement) -> Option<Layout> {
+    match statement {
+        Statement::Org(_)
+        | Statement::DataDefinition(_, _)
+        | Statement::Fill(_, _, _)
+        | Statement::Bank(_)
+        | Statement::Limit(_)
+        | Statement::IoDelay(_)
+        | Statement::AssertAt(_, _)
+        | Statement::Vector(_, _)
+        | Statement::Equ(_, _)
+        | Statement::Weak
+        | Statement::IfCpu(_)
+        | Statement::Else
+        | Statement::EndIf
+        | Statement::Trampoline(_)
+        | Statement::Custom(_, _) => None,
+        Statement::BranchOnIndicators(_, _) => Some(BRANCH_ON_INDICATORS_WORD_LAYOUT),
+        Statement::BranchOnRegisters(_, _, _) => Some(BRANCH_ON_REGISTERS_WORD_LAYOUT),
+        Statement::ShortValueImmediate(_, _, _) => Some(SHORT_VALUE_IMMEDIATE_WORD_LAYOUT),
+        Statement::SingleOperand(_, _, _) => Some(SINGLE_OPERAND_WORD_LAYOUT),
+        Statement::DoubleOperand(_, _, _, _) => Some(DOUBLE_OPERAND_WORD_LAYOUT),
+        Statement::Generic(_) => Some(GENERIC_WORD_LAYOUT),
+        Statement::ShiftShort(_, _, _) => Some(SHIFT_SHORT_WORD_LAYOUT),
+        Statement::ShiftLong(_, _, _) => Some(SHIFT_LONG_WORD_LAYOUT),
+        Statement::InputOutput(_, _, _) | Statement::InputOutputLoad(_, _, _) => {
+            Some(INPUT_OUTPUT_WORD_LAYOUT)
+        }
+    }
+}
+
+// Splits a value into fields, the first field being the most significant one
+fn split_fields(value: u16, width: u32, layout: Layout) -> Vec<ExplainedField> {
+    let mut shift = width;
+
+    layout
+        .iter()
+        .map(|&(name, field_width)| {
+            shift -= field_width;
+            let field_value = (value >> shift) & ((1 << field_width) - 1);
+
+            // Address syllables have fields of their own
+            let subfields = match name {
+                "addr_syl" => split_fields(field_value, field_width, ADDRESS_SYLLABLE_FIELD_LAYOUT),
+                _ => vec![],
+            };
+
+            ExplainedField {
+                name,
+                width: field_width,
+                value: field_value,
+                subfields,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bit_struct::*;
+
+    use super::*;
+    use crate::assembler::codegen::{
+        address_syllable::AddressSyllableField,
+        branch_on_indicators::BranchOnIndicatorsInstructionWord,
+        branch_on_registers::BranchOnRegistersInstructionWord,
+        double_operand::DoubleOperandInstructionWord,
+        generic::GenericInstructionWord,
+        input_output::InputOutputInstructionWord,
+        shift::{ShiftLongInstructionWord, ShiftShortInstructionWord},
+        short_value_immediate::ShortValueImmediateInstructionWord,
+        single_operand::SingleOperandInstructionWord,
+    };
+    use crate::assembler::statements::{
+        AddressSyllable, DataRegister, DoubleOperandOpCode, GenericOpCode, Register,
+    };
+
+    fn field_values(fields: &[ExplainedField]) -> Vec<u16> {
+        fields.iter().map(|field| field.value).collect()
+    }
+
+    #[test]
+    fn layouts_match_bit_structs() {
+        // Words built with distinct values in every field, decoded with the layout tables
+        let tests: [(u16, Layout, Vec<u16>); 9] = [
+            (
+                BranchOnIndicatorsInstructionWord::new(u4!(0b0001), u5!(0b10110), i7!(-3)).raw(),
+                BRANCH_ON_INDICATORS_WORD_LAYOUT,
+                vec![0b0001, 0b10110, 0b1111101],
+            ),
+            (
+                BranchOnRegistersInstructionWord::new(u1!(1), u3!(5), u5!(0b01101), i7!(5)).raw(),
+                BRANCH_ON_REGISTERS_WORD_LAYOUT,
+                vec![1, 5, 0b01101, 5],
+            ),
+            (
+                DoubleOperandInstructionWord::new(u1!(1), u3!(3), u5!(0b10010), u7!(0x55)).raw(),
+                DOUBLE_OPERAND_WORD_LAYOUT,
+                vec![1, 3, 0b10010, 0x55],
+            ),
+            (
+                GenericInstructionWord::new(0x12, 0x34).raw(),
+                GENERIC_WORD_LAYOUT,
+                vec![0x12, 0x34],
+            ),
+            (
+                InputOutputInstructionWord::new(u4!(0b0000), u5!(0b11010), u7!(0x2A)).raw(),
+                INPUT_OUTPUT_WORD_LAYOUT,
+                vec![0, 0b11010, 0x2A],
+            ),
+            (
+                ShiftShortInstructionWord::new(u1!(0), u3!(6), u4!(0), u4!(0b1010), u4!(9)).raw(),
+                SHIFT_SHORT_WORD_LAYOUT,
+                vec![0, 6, 0, 0b1010, 9],
+            ),
+            (
+                ShiftLongInstructionWord::new(u1!(0), u3!(2), u4!(1), u3!(0b101), u5!(17)).raw(),
+                SHIFT_LONG_WORD_LAYOUT,
+                vec![0, 2, 1, 0b101, 17],
+            ),
+            (
+                ShortValueImmediateInstructionWord::new(u1!(0), u3!(7), u4!(0b1001), 0xA5).raw(),
+                SHORT_VALUE_IMMEDIATE_WORD_LAYOUT,
+                vec![0, 7, 0b1001, 0xA5],
+            ),
+            (
+                SingleOperandInstructionWord::new(u4!(0b1000), u5!(0b00011), u7!(0x31)).raw(),
+                SINGLE_OPERAND_WORD_LAYOUT,
+                vec![0b1000, 0b00011, 0x31],
+            ),
+        ];
+
+        for (word, layout, exp) in tests {
+            assert_eq!(layout.iter().map(|(_, width)| width).sum::<u32>(), 16);
+            assert_eq!(field_values(&split_fields(word, 16, layout)), exp);
+        }
+
+        let addr_syl = AddressSyllableField::new(u3!(5), u1!(1), u3!(2)).raw();
+        assert_eq!(
+            field_values(&split_fields(
+                addr_syl.value() as u16,
+                7,
+                ADDRESS_SYLLABLE_FIELD_LAYOUT
+            )),
+            vec![5, 1, 2]
+        );
+    }
+
+    #[test]
+    fn explain_statement_succ() {
+        let statement = Statement::DoubleOperand(
+            DoubleOperandOpCode::LDR,
+            Register::Data(DataRegister::R1),
+            AddressSyllable::ImmediateOperand(5),
+            None,
+        );
+
+        let words = explain_statement(&statement, 0).unwrap();
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].word, 0x9870);
+        assert_eq!(
+            field_values(&words[0].fields),
+            vec![1, 1, 0b10000, 0b1110000]
+        );
+        assert_eq!(
+            field_values(&words[0].fields[3].subfields),
+            vec![0b111, 0, 0]
+        );
+        assert_eq!(
+            words[1],
+            ExplainedWord {
+                word: 5,
+                fields: vec![]
+            }
+        );
+
+        let words = explain_statement(&Statement::Generic(GenericOpCode::HLT), 0).unwrap();
+        assert_eq!(words[0].fields.len(), 2);
+    }
+}
@@ -9,6 +9,9 @@ bit_struct! {
     }
 }
 
+// Fields of GenericInstructionWord, from the most significant bit
+pub const GENERIC_WORD_LAYOUT: &[(&str, u32)] = &[("header", 8), ("op", 8)];
+
 /// Generaete code for a Generic instruction
 pub fn codegen_generic(op: &GenericOpCode) -> Result<Vec<u16>, AssemblerErrorKind> {
     // Build instruction word
@@ -30,6 +30,9 @@ bit_struct! {
     }
 }
 
+// Fields of InputOutputInstructionWord, from the most significant bit
+pub const INPUT_OUTPUT_WORD_LAYOUT: &[(&str, u32)] = &[("header", 4), ("op", 5), ("addr_syl", 7)];
+
 /// Generaete code for an Input Output instruction
 pub fn codegen_input_output(
     op: &InputOutputOpCode,
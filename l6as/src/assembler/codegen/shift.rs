@@ -23,6 +23,24 @@ bit_struct! {
     }
 }
 
+// Fields of ShiftShortInstructionWord, from the most significant bit
+pub const SHIFT_SHORT_WORD_LAYOUT: &[(&str, u32)] = &[
+    ("header", 1),
+    ("reg", 3),
+    ("pad", 4),
+    ("op", 4),
+    ("dist", 4),
+];
+
+// Fields of ShiftLongInstructionWord, from the most significant bit
+pub const SHIFT_LONG_WORD_LAYOUT: &[(&str, u32)] = &[
+    ("header", 1),
+    ("reg", 3),
+    ("pad", 4),
+    ("op", 3),
+    ("dist", 5),
+];
+
 /// Generaete code for a Shift Short instruction
 pub fn codegen_shift_short(
     op: &ShiftShortOpCode,
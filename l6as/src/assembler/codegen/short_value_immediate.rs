@@ -16,6 +16,45 @@ bit_struct! {
     }
 }
 
+// Fields of ShortValueImmediateInstructionWord, from the most significant bit
+pub const SHORT_VALUE_IMMEDIATE_WORD_LAYOUT: &[(&str, u32)] =
+    &[("header", 1), ("reg", 3), ("op", 4), ("val", 8)];
+
+/// How the 8 bit value field of Short Value Immediate instructions is interpreted
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ShortImmediateSignedness {
+    /// Either signed or unsigned
+    #[default]
+    Any,
+    Signed,
+    Unsigned,
+}
+
+impl ShortImmediateSignedness {
+    /// Smallest and largest value allowed
+    pub fn range(&self) -> (i128, i128) {
+        match self {
+            Self::Any => (i8::MIN as i128, u8::MAX as i128),
+            Self::Signed => (i8::MIN as i128, i8::MAX as i128),
+            Self::Unsigned => (0, u8::MAX as i128),
+        }
+    }
+}
+
+/// Checks that a value fits the value field of Short Value Immediate instructions
+pub fn check_short_immediate_value(
+    val: i128,
+    signedness: ShortImmediateSignedness,
+) -> Result<(), AssemblerErrorKind> {
+    let (min, max) = signedness.range();
+    match (min..=max).contains(&val) {
+        true => Ok(()),
+        false => Err(AssemblerErrorKind::ShortImmediateValueOutOfRange(
+            val, min, max,
+        )),
+    }
+}
+
 /// Generaete code for a Short Value Immediate  instruction
 pub fn codegen_short_value_immediate(
     op: &ShortValueImmediateOpCode,
@@ -43,19 +82,10 @@ fn get_short_value_immediate_op_value(op: &ShortValueImmediateOpCode) -> u4 {
 }
 
 fn get_short_immediate_value_field(val: i128) -> Result<u8, AssemblerErrorKind> {
-    if val >= 0 {
-        // Raw value into u8
-        match TryInto::<u8>::try_into(val) {
-            Ok(val) => Ok(val),
-            Err(_) => Err(AssemblerErrorKind::ShortImmediateValueOutOfRange(val)),
-        }
-    } else {
-        // Two's complemented i8 into u8
-        match TryInto::<i8>::try_into(val) {
-            Ok(val) => Ok(u8::from_be_bytes(val.to_be_bytes())),
-            Err(_) => Err(AssemblerErrorKind::ShortImmediateValueOutOfRange(val)),
-        }
-    }
+    check_short_immediate_value(val, ShortImmediateSignedness::Any)?;
+
+    // Two's complemented i8 into u8 if negative
+    Ok(val as u8)
 }
 
 #[cfg(test)]
@@ -90,4 +120,27 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn check_short_immediate_value_range() {
+        let tests = [
+            (ShortImmediateSignedness::Any, -128, true),
+            (ShortImmediateSignedness::Any, 255, true),
+            (ShortImmediateSignedness::Any, 256, false),
+            (ShortImmediateSignedness::Any, -129, false),
+            (ShortImmediateSignedness::Signed, 127, true),
+            (ShortImmediateSignedness::Signed, 128, false),
+            (ShortImmediateSignedness::Unsigned, 255, true),
+            (ShortImmediateSignedness::Unsigned, -1, false),
+        ];
+        for (signedness, val, exp) in tests {
+            assert_eq!(
+                check_short_immediate_value(val, signedness).is_ok(),
+                exp,
+                "{:?} {}",
+                signedness,
+                val
+            );
+        }
+    }
 }
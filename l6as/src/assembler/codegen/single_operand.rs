@@ -7,7 +7,10 @@ use crate::{
 
 use bit_struct::*;
 
-use super::{address_syllable::get_address_syllable_field_value, common::get_maskword_value};
+use super::{
+    address_syllable::{get_address_syllable_field_value, get_byte_immediate_operand_extra_word},
+    common::get_maskword_value,
+};
 
 bit_struct! {
     pub struct SingleOperandInstructionWord(u16) {
@@ -17,6 +20,9 @@ bit_struct! {
     }
 }
 
+// Fields of SingleOperandInstructionWord, from the most significant bit
+pub const SINGLE_OPERAND_WORD_LAYOUT: &[(&str, u32)] = &[("header", 4), ("op", 5), ("addr_syl", 7)];
+
 /// Generaete code for a Single Operand  instruction
 pub fn codegen_single_operand(
     op: &SingleOperandOpCode,
@@ -36,6 +42,11 @@ pub fn codegen_single_operand(
         addr_syl_field,
     );
 
+    // Immediate operands of byte instructions only have their left byte read
+    if let (SingleOperandOpCode::CLH, AddressSyllable::ImmediateOperand(val)) = (op, addr_syl) {
+        addr_syl_extra_words = vec![get_byte_immediate_operand_extra_word(*val)?];
+    }
+
     // Concatenate words
     let mut words = vec![inst_word.raw()];
     words.append(&mut addr_syl_extra_words);
@@ -0,0 +1,153 @@
+use std::{fmt, sync::Arc};
+
+/// Words and symbols emitted by a directive
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DirectiveOutput {
+    /// Words placed at the address of the directive
+    pub words: Vec<u16>,
+    /// Symbols defined by the directive, which the program can reference like labels
+    pub symbols: Vec<(String, u64)>,
+}
+
+/// Handler of a directive which isn't built into the assembler, registered by a crate using
+/// l6as as a library. Directives are processed while statements are placed in memory,
+/// so handlers know the address of the directive but not the labels of the program
+pub trait DirectiveHandler {
+    /// Name of the directive, including the leading dot, e.g. ".CRC"
+    fn name(&self) -> &str;
+
+    /// Processes a directive, given its arguments as written, after %define substitution
+    fn expand(&self, args: &[String], address: u64) -> Result<DirectiveOutput, String>;
+}
+
+/// Directive handlers, by name
+#[derive(Clone, Default)]
+pub struct DirectiveRegistry {
+    handlers: Vec<Arc<dyn DirectiveHandler>>,
+}
+
+impl DirectiveRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler, replacing any handler of a directive with the same name.
+    /// Built-in directives and instructions can't be replaced
+    pub fn register(&mut self, handler: impl DirectiveHandler + 'static) {
+        self.handlers
+            .retain(|other| !other.name().eq_ignore_ascii_case(handler.name()));
+        self.handlers.push(Arc::new(handler));
+    }
+
+    /// Handler of a directive, names are case insensitive
+    pub fn get(&self, name: &str) -> Option<&dyn DirectiveHandler> {
+        self.handlers
+            .iter()
+            .find(|handler| handler.name().eq_ignore_ascii_case(name))
+            .map(|handler| handler.as_ref())
+    }
+
+    /// Names of the registered directives
+    pub fn names(&self) -> Vec<&str> {
+        self.handlers.iter().map(|handler| handler.name()).collect()
+    }
+}
+
+impl fmt::Debug for DirectiveRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+// Registries with the same directives are considered equal
+impl PartialEq for DirectiveRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        self.names() == other.names()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::{assemble_with_labels, AssemblerOptions};
+    use crate::preprocessor::{CodeLine, LineLocation};
+
+    // Emits its arguments as words, followed by their sum
+    struct SumDirective;
+
+    impl DirectiveHandler for SumDirective {
+        fn name(&self) -> &str {
+            ".SUM"
+        }
+
+        fn expand(&self, args: &[String], address: u64) -> Result<DirectiveOutput, String> {
+            let mut words = vec![];
+            for arg in args {
+                words.push(arg.trim().parse::<u16>().map_err(|err| err.to_string())?);
+            }
+            words.push(
+                words
+                    .iter()
+                    .fold(0, |sum: u16, word| sum.wrapping_add(*word)),
+            );
+
+            Ok(DirectiveOutput {
+                words,
+                symbols: vec![("SUM_AT".to_owned(), address)],
+            })
+        }
+    }
+
+    fn code_lines(lines: &[&str]) -> Vec<CodeLine> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| CodeLine {
+                body: line.to_string(),
+                comment: String::new(),
+                location: LineLocation {
+                    line_n: i + 1,
+                    file_name: "test.l6s".into(),
+                    raw_content: line.to_string(),
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_directive_registry() {
+        let mut directives = DirectiveRegistry::new();
+        assert!(directives.get(".sum").is_none());
+
+        directives.register(SumDirective);
+        directives.register(SumDirective);
+        assert_eq!(directives.names(), vec![".SUM"]);
+        assert_eq!(directives.get(".sum").unwrap().name(), ".SUM");
+    }
+
+    #[test]
+    fn test_custom_directive() {
+        let mut opts = AssemblerOptions::default();
+        opts.directives.register(SumDirective);
+
+        let input = code_lines(&[".ORG 0x10", "START: .sum 1, 2, 3", "JMP SUM_AT"]);
+        let (res, labels) = assemble_with_labels(&input, &opts);
+        let lines = res.unwrap();
+        assert_eq!(lines[0].address, 0x10);
+        assert_eq!(lines[0].data, vec![1, 2, 3, 6]);
+        assert_eq!(lines[1].address, 0x14);
+        assert!(labels
+            .iter()
+            .any(|label| label.label == "SUM_AT" && label.address == 0x10));
+
+        // Handler errors are reported
+        let input = code_lines(&[".SUM 1, x"]);
+        assert!(assemble_with_labels(&input, &opts).0.is_err());
+
+        // Unregistered directives are unknown
+        let input = code_lines(&[".SUM 1"]);
+        assert!(assemble_with_labels(&input, &AssemblerOptions::default())
+            .0
+            .is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use super::assemble::AbstractBinaryLine;
+use super::codegen::resolve_address_expression;
+use super::statements::{
+    AddressSyllable, BRelativeAddress, BRelativeAddressMode, BranchLocation,
+    BranchOnIndicatorsOpCode, DoubleOperandOpCode, GenericOpCode, ImmediateAddress,
+    ImmediateAddressMode, PRelativeAddress, SingleOperandOpCode, Statement,
+};
+
+/// Where control goes after a statement, with the targets known when assembling
+pub enum Flow {
+    Next,
+    Branch(Option<u64>),
+    ConditionalBranch(Option<u64>),
+    Call(Option<u64>),
+    Return,
+    Stop,
+}
+
+/// Index of the statement placed at each address
+pub fn get_code_addresses(lines: &[AbstractBinaryLine]) -> HashMap<u64, usize> {
+    let mut code_addresses = HashMap::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.size > 0 {
+            code_addresses.entry(line.address).or_insert(i);
+        }
+    }
+    code_addresses
+}
+
+/// Addresses of the routines of a program, sorted: the entry point, which is the first
+/// statement, and the targets of LNJ placed in the program
+pub fn find_routine_entries(
+    label_table: &HashMap<String, u64>,
+    code_addresses: &HashMap<u64, usize>,
+    lines: &[AbstractBinaryLine],
+) -> Vec<u64> {
+    let mut entries: Vec<u64> = lines
+        .iter()
+        .filter_map(|line| match get_flow(line, label_table) {
+            Flow::Call(target) => target,
+            _ => None,
+        })
+        .filter(|address| code_addresses.contains_key(address))
+        .collect();
+    if let Some(line) = lines.iter().find(|line| line.size > 0) {
+        if !matches!(
+            &line.statement,
+            Statement::DataDefinition(_, _) | Statement::Fill(_, _, _)
+        ) {
+            entries.push(line.address);
+        }
+    }
+    entries.sort();
+    entries.dedup();
+    entries
+}
+
+/// Statement following another in memory, if execution can continue to it
+pub fn next_index(lines: &[AbstractBinaryLine], index: usize) -> Option<usize> {
+    let line = &lines[index];
+    let next = lines.get(index + 1)?;
+    match next.address == line.address + line.size {
+        true => Some(index + 1),
+        // Placed elsewhere by .ORG
+        false => None,
+    }
+}
+
+/// Where control goes after a statement. Returns are jumps through the base register
+/// LNJ saved the link in
+pub fn get_flow(line: &AbstractBinaryLine, label_table: &HashMap<String, u64>) -> Flow {
+    let resolve = |addr_syl| get_address_syllable_target(addr_syl, line.address, label_table);
+
+    match &line.statement {
+        Statement::BranchOnIndicators(BranchOnIndicatorsOpCode::B, branchloc) => Flow::Branch(
+            get_branch_location_target(branchloc, line.address, label_table),
+        ),
+        Statement::BranchOnIndicators(_, branchloc)
+        | Statement::BranchOnRegisters(_, _, branchloc) => Flow::ConditionalBranch(
+            get_branch_location_target(branchloc, line.address, label_table),
+        ),
+        Statement::SingleOperand(
+            SingleOperandOpCode::JMP,
+            AddressSyllable::BRelative(BRelativeAddressMode::Direct(BRelativeAddress::Simple(_))),
+            _,
+        ) => Flow::Return,
+        Statement::SingleOperand(SingleOperandOpCode::JMP, addr_syl, _) => {
+            Flow::Branch(resolve(addr_syl))
+        }
+        Statement::DoubleOperand(DoubleOperandOpCode::LNJ, _, addr_syl, _) => {
+            Flow::Call(resolve(addr_syl))
+        }
+        Statement::Generic(GenericOpCode::RTT) => Flow::Return,
+        // Execution halting, or running into data
+        Statement::Generic(GenericOpCode::HLT)
+        | Statement::DataDefinition(_, _)
+        | Statement::Fill(_, _, _) => Flow::Stop,
+        _ => Flow::Next,
+    }
+}
+
+fn get_branch_location_target(
+    branchloc: &BranchLocation,
+    cur_addr: u64,
+    label_table: &HashMap<String, u64>,
+) -> Option<u64> {
+    match branchloc {
+        BranchLocation::Absolute(addr_exp)
+        | BranchLocation::LongDisplacement(addr_exp)
+        | BranchLocation::ShortDisplacement(addr_exp) => {
+            resolve_address_expression(addr_exp, cur_addr, label_table).ok()
+        }
+    }
+}
+
+// Address an operand refers to, if known when assembling
+fn get_address_syllable_target(
+    addr_syl: &AddressSyllable,
+    cur_addr: u64,
+    label_table: &HashMap<String, u64>,
+) -> Option<u64> {
+    let addr_exp = match addr_syl {
+        AddressSyllable::ImmediateAddressing(ImmediateAddressMode::Direct(
+            ImmediateAddress::Simple(addr_exp),
+        )) => addr_exp,
+        AddressSyllable::PRelative(PRelativeAddress::Direct(addr_exp)) => addr_exp,
+        _ => return None,
+    };
+
+    resolve_address_expression(addr_exp, cur_addr, label_table).ok()
+}
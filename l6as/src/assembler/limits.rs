@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+
+use super::assemble::AbstractBinaryLine;
+use super::statements::Statement;
+use crate::logging::{AssemblerError, AssemblerErrorKind};
+
+/// Reports the statement which makes an image grow past its maximum size, in words.
+/// Each bank is a separate image, limited by `max_size` and by .LIMIT directives placed in it
+pub fn find_size_limit_violations(
+    max_size: Option<u64>,
+    lines: &[AbstractBinaryLine],
+) -> Vec<AssemblerError> {
+    // Collect limits of every image, the tightest one applies
+    let mut limits: HashMap<Option<u64>, u64> = HashMap::new();
+    for line in lines {
+        if let Statement::Limit(limit) = &line.statement {
+            let current = limits.entry(line.bank).or_insert(*limit);
+            *current = (*current).min(*limit);
+        }
+    }
+
+    let mut sizes: HashMap<Option<u64>, u64> = HashMap::new();
+    let mut exceeded: HashSet<Option<u64>> = HashSet::new();
+    let mut errors = vec![];
+    for line in lines {
+        let size = sizes.entry(line.bank).or_insert(0);
+        *size += line.size;
+
+        let limit = match (limits.get(&line.bank), max_size) {
+            (Some(limit), Some(max_size)) => (*limit).min(max_size),
+            (Some(limit), None) => *limit,
+            (None, Some(max_size)) => max_size,
+            (None, None) => continue,
+        };
+
+        // Only report the first statement which doesn't fit
+        if *size > limit && exceeded.insert(line.bank) {
+            errors.push(AssemblerError {
+                kind: AssemblerErrorKind::ImageSizeExceeded(line.bank, *size, limit),
+                location: Some(line.location.clone()),
+            });
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::statements::GenericOpCode;
+
+    #[test]
+    fn find_size_limit_violations_succ() {
+        let hlt = Statement::Generic(GenericOpCode::HLT);
+        let limit = Statement::Limit(3);
+
+        // Bank, size, statement
+        let statements = [
+            (None, 2, &hlt),
+            (None, 2, &hlt),
+            (None, 2, &hlt),
+            (Some(1), 0, &limit),
+            (Some(1), 2, &hlt),
+            (Some(1), 2, &hlt),
+            (Some(2), 2, &hlt),
+        ];
+        let lines: Vec<AbstractBinaryLine> = statements
+            .into_iter()
+            .map(|(bank, size, statement)| {
+                AbstractBinaryLine::new_test(0, bank, statement.clone(), size)
+            })
+            .collect();
+
+        let tests = [
+            (None, vec![(Some(1), 4, 3)]),
+            (Some(4), vec![(None, 6, 4), (Some(1), 4, 3)]),
+            (
+                Some(1),
+                vec![(None, 2, 1), (Some(1), 2, 1), (Some(2), 2, 1)],
+            ),
+        ];
+
+        for (max_size, exp) in tests {
+            let errors: Vec<(Option<u64>, u64, u64)> = find_size_limit_violations(max_size, &lines)
+                .into_iter()
+                .map(|err| match err.kind {
+                    AssemblerErrorKind::ImageSizeExceeded(bank, size, limit) => (bank, size, limit),
+                    _ => panic!(),
+                })
+                .collect();
+            assert_eq!(errors, exp);
+        }
+    }
+}
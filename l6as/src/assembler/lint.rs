@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+
+use super::assemble::{AbstractBinaryLine, LabelDefinition};
+use super::flow::{get_flow, Flow};
+use super::statements::{
+    AddressExpression, AddressSyllable, BranchLocation, ChannelExpression, DataValue,
+    ImmediateAddress, ImmediateAddressMode, PRelativeAddress, Statement,
+};
+use crate::logging::{AssemblerWarning, AssemblerWarningKind};
+
+/// Reports labels which are defined but never referenced by any statement
+pub fn find_unused_labels(
+    label_definitions: &[LabelDefinition],
+    lines: &[AbstractBinaryLine],
+) -> Vec<AssemblerWarning> {
+    // Collect every label referenced in the program
+    let mut referenced: HashSet<String> = HashSet::new();
+    for line in lines {
+        referenced.extend(get_statement_label_references(&line.statement));
+    }
+
+    label_definitions
+        .iter()
+        .filter(|definition| !referenced.contains(&definition.label))
+        .map(|definition| AssemblerWarning {
+            kind: AssemblerWarningKind::UnusedLabel(definition.label.clone()),
+            location: Some(definition.location.clone()),
+        })
+        .collect()
+}
+
+/// Reports code following an unconditional branch, a return or a halt which can't be
+/// reached because no label points to it
+pub fn find_unreachable_code(
+    label_table: &HashMap<String, u64>,
+    lines: &[AbstractBinaryLine],
+) -> Vec<AssemblerWarning> {
+    let labeled_addresses: HashSet<u64> = label_table.values().copied().collect();
+
+    let mut warnings = vec![];
+    let mut unreachable = false;
+    let mut next_address: Option<u64> = None;
+    for line in lines {
+        // A labeled statement, or one placed by .ORG, may be reached from elsewhere
+        if labeled_addresses.contains(&line.address) || next_address != Some(line.address) {
+            unreachable = false;
+        } else if unreachable
+            && line.size > 0
+            && !matches!(
+                &line.statement,
+                Statement::DataDefinition(_, _) | Statement::Fill(_, _, _)
+            )
+        {
+            // Only report the first statement of an unreachable region
+            warnings.push(AssemblerWarning {
+                kind: AssemblerWarningKind::UnreachableCode,
+                location: Some(line.location.clone()),
+            });
+            unreachable = false;
+        }
+
+        // Control never continues to the next statement
+        if matches!(
+            get_flow(line, label_table),
+            Flow::Branch(_) | Flow::Return | Flow::Stop
+        ) {
+            unreachable = true;
+        }
+
+        next_address = Some(line.address + line.size);
+    }
+
+    warnings
+}
+
+/// Lists all labels referenced by a statement
+pub fn get_statement_label_references(statement: &Statement) -> Vec<String> {
+    let mut labels = vec![];
+
+    match statement {
+        Statement::BranchOnIndicators(_, branchloc) => {
+            add_branch_location_label(branchloc, &mut labels)
+        }
+        Statement::BranchOnRegisters(_, _, branchloc) => {
+            add_branch_location_label(branchloc, &mut labels)
+        }
+        Statement::SingleOperand(_, addr_syl, _) => {
+            add_address_syllable_label(addr_syl, &mut labels)
+        }
+        Statement::DoubleOperand(_, _, addr_syl, _) => {
+            add_address_syllable_label(addr_syl, &mut labels)
+        }
+        Statement::InputOutput(_, addr_syl, chan_expr) => {
+            add_address_syllable_label(addr_syl, &mut labels);
+            add_channel_expression_label(chan_expr, &mut labels);
+        }
+        Statement::InputOutputLoad(buffer_addr_syl, chan_expr, range_addr_syl) => {
+            add_address_syllable_label(buffer_addr_syl, &mut labels);
+            add_channel_expression_label(chan_expr, &mut labels);
+            add_address_syllable_label(range_addr_syl, &mut labels);
+        }
+        Statement::Trampoline(label)
+        | Statement::Equ(Some(label), _)
+        | Statement::AssertAt(label, _)
+        | Statement::Vector(_, label) => labels.push(label.clone()),
+        Statement::DataDefinition(_, values) => {
+            for value in values {
+                if let DataValue::Labels(value_labels, _) = value {
+                    labels.extend(value_labels.iter().map(|(_, label)| label.clone()));
+                }
+            }
+        }
+        Statement::Org(_)
+        | Statement::Fill(_, _, _)
+        | Statement::Bank(_)
+        | Statement::Limit(_)
+        | Statement::IoDelay(_)
+        | Statement::Equ(None, _)
+        | Statement::Weak
+        | Statement::IfCpu(_)
+        | Statement::Else
+        | Statement::EndIf
+        | Statement::ShortValueImmediate(_, _, _)
+        | Statement::Generic(_)
+        | Statement::ShiftShort(_, _, _)
+        | Statement::ShiftLong(_, _, _)
+        | Statement::Custom(_, _) => {}
+    }
+
+    labels
+}
+
+fn add_branch_location_label(branchloc: &BranchLocation, labels: &mut Vec<String>) {
+    match branchloc {
+        BranchLocation::Absolute(addr_exp)
+        | BranchLocation::LongDisplacement(addr_exp)
+        | BranchLocation::ShortDisplacement(addr_exp) => {
+            add_address_expression_label(addr_exp, labels)
+        }
+    }
+}
+
+fn add_address_syllable_label(addr_syl: &AddressSyllable, labels: &mut Vec<String>) {
+    match addr_syl {
+        AddressSyllable::ImmediateAddressing(
+            ImmediateAddressMode::Direct(imm_addr) | ImmediateAddressMode::Indirect(imm_addr),
+        ) => match imm_addr {
+            ImmediateAddress::Simple(addr_exp) | ImmediateAddress::Indexed(addr_exp, _) => {
+                add_address_expression_label(addr_exp, labels)
+            }
+        },
+        AddressSyllable::PRelative(
+            PRelativeAddress::Direct(addr_exp) | PRelativeAddress::Indirect(addr_exp),
+        ) => add_address_expression_label(addr_exp, labels),
+        AddressSyllable::RegisterAddressing(_)
+        | AddressSyllable::ImmediateOperand(_)
+        | AddressSyllable::BRelative(_) => {}
+    }
+}
+
+fn add_channel_expression_label(chan_expr: &ChannelExpression, labels: &mut Vec<String>) {
+    if let ChannelExpression::AddressSyllable(addr_syl) = chan_expr {
+        add_address_syllable_label(addr_syl, labels);
+    }
+}
+
+fn add_address_expression_label(addr_exp: &AddressExpression, labels: &mut Vec<String>) {
+    if let AddressExpression::Label(label) = addr_exp {
+        labels.push(label.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::assembler::statements::{
+        BRelativeAddress, BRelativeAddressMode, BaseRegister, BranchOnIndicatorsOpCode,
+        DataDefinitionSize, DataRegister, GenericOpCode, SingleOperandOpCode,
+    };
+    use crate::preprocessor::LineLocation;
+
+    fn location(line_n: usize) -> LineLocation {
+        LineLocation {
+            line_n,
+            file_name: PathBuf::from("test.l6s"),
+            raw_content: "".to_owned(),
+        }
+    }
+
+    fn line(address: u64, statement: &Statement, line_n: usize) -> AbstractBinaryLine {
+        AbstractBinaryLine {
+            location: location(line_n),
+            ..AbstractBinaryLine::new_test(address, None, statement.clone(), 1)
+        }
+    }
+
+    #[test]
+    fn get_statement_label_references_succ() {
+        let tests = [
+            (
+                Statement::BranchOnIndicators(
+                    BranchOnIndicatorsOpCode::B,
+                    BranchLocation::ShortDisplacement(AddressExpression::Label("LOOP".to_owned())),
+                ),
+                vec!["LOOP"],
+            ),
+            (
+                Statement::SingleOperand(
+                    SingleOperandOpCode::JMP,
+                    AddressSyllable::ImmediateAddressing(ImmediateAddressMode::Indirect(
+                        ImmediateAddress::Indexed(
+                            AddressExpression::Label("TABLE".to_owned()),
+                            DataRegister::R1,
+                        ),
+                    )),
+                    None,
+                ),
+                vec!["TABLE"],
+            ),
+            (
+                Statement::InputOutputLoad(
+                    AddressSyllable::PRelative(PRelativeAddress::Direct(AddressExpression::Label(
+                        "BUF".to_owned(),
+                    ))),
+                    ChannelExpression::AddressSyllable(AddressSyllable::PRelative(
+                        PRelativeAddress::Indirect(AddressExpression::Label("CHAN".to_owned())),
+                    )),
+                    AddressSyllable::ImmediateOperand(10),
+                ),
+                vec!["BUF", "CHAN"],
+            ),
+            (Statement::Generic(GenericOpCode::HLT), vec![]),
+        ];
+
+        for (input, exp) in tests {
+            assert_eq!(get_statement_label_references(&input), exp);
+        }
+    }
+
+    #[test]
+    fn find_unused_labels_succ() {
+        let used = Statement::BranchOnIndicators(
+            BranchOnIndicatorsOpCode::B,
+            BranchLocation::LongDisplacement(AddressExpression::Label("USED".to_owned())),
+        );
+        let lines = [line(0, &used, 1)];
+        let definitions = [
+            LabelDefinition {
+                label: "USED".to_owned(),
+                address: 0,
+                bank: None,
+                location: location(1),
+            },
+            LabelDefinition {
+                label: "UNUSED".to_owned(),
+                address: 2,
+                bank: None,
+                location: location(2),
+            },
+        ];
+
+        let warnings = find_unused_labels(&definitions, &lines);
+        assert_eq!(
+            warnings,
+            vec![AssemblerWarning {
+                kind: AssemblerWarningKind::UnusedLabel("UNUSED".to_owned()),
+                location: Some(location(2)),
+            }]
+        );
+    }
+
+    #[test]
+    fn find_unreachable_code_succ() {
+        let branch = Statement::BranchOnIndicators(
+            BranchOnIndicatorsOpCode::B,
+            BranchLocation::ShortDisplacement(AddressExpression::WordDisplacement(-2)),
+        );
+        let halt = Statement::Generic(GenericOpCode::HLT);
+        let data =
+            Statement::DataDefinition(DataDefinitionSize::Word, vec![DataValue::Constant(0)]);
+
+        let lines = [
+            // Branch away
+            line(0, &branch, 1),
+            // Data after branch is fine
+            line(1, &data, 2),
+            // Unreachable
+            line(2, &halt, 3),
+            // Still unreachable, but only reported once
+            line(3, &branch, 4),
+            // Labeled, reachable
+            line(4, &branch, 5),
+            // New .ORG region, reachable
+            line(0x100, &branch, 6),
+            // Unreachable
+            line(0x101, &halt, 7),
+        ];
+        let label_table = HashMap::from([("LABELED".to_owned(), 4)]);
+
+        let warnings = find_unreachable_code(&label_table, &lines);
+        let lines_n: Vec<usize> = warnings
+            .iter()
+            .map(|w| w.location.as_ref().unwrap().line_n)
+            .collect();
+        assert_eq!(lines_n, vec![3, 7]);
+    }
+
+    #[test]
+    fn find_unreachable_code_flow() {
+        let halt = Statement::Generic(GenericOpCode::HLT);
+        let tests = [
+            (halt.clone(), true),
+            (Statement::Generic(GenericOpCode::RTT), true),
+            // Return through the base register LNJ saved the link in
+            (
+                Statement::SingleOperand(
+                    SingleOperandOpCode::JMP,
+                    AddressSyllable::BRelative(BRelativeAddressMode::Direct(
+                        BRelativeAddress::Simple(BaseRegister::B5),
+                    )),
+                    None,
+                ),
+                true,
+            ),
+            (
+                Statement::SingleOperand(
+                    SingleOperandOpCode::JMP,
+                    AddressSyllable::PRelative(PRelativeAddress::Direct(AddressExpression::Label(
+                        "LABELED".to_owned(),
+                    ))),
+                    None,
+                ),
+                true,
+            ),
+            // Running into data
+            (
+                Statement::DataDefinition(DataDefinitionSize::Word, vec![DataValue::Constant(0)]),
+                true,
+            ),
+            (
+                Statement::BranchOnIndicators(
+                    BranchOnIndicatorsOpCode::BE,
+                    BranchLocation::ShortDisplacement(AddressExpression::WordDisplacement(-2)),
+                ),
+                false,
+            ),
+            (Statement::Generic(GenericOpCode::MCL), false),
+        ];
+        let label_table = HashMap::from([("LABELED".to_owned(), 0x100)]);
+
+        for (statement, exp) in tests {
+            let lines = [line(0, &statement, 1), line(1, &halt, 2)];
+            let warnings = find_unreachable_code(&label_table, &lines);
+            assert_eq!(!warnings.is_empty(), exp, "{:?}", statement);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use serde::Serialize;
+use strum::IntoEnumIterator;
+
+use super::{
+    codegen::{explain_statement, ExplainedWord},
+    parsers::parse_statement,
+    statements::{Mnemonic, StatementKind},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpcodeTableEntry {
+    pub mnemonic: String,
+    pub kind: String,
+    pub header: String,
+    pub opcode: String,
+    pub pattern: String,
+}
+
+// Address samples are encoded at, away from 0 so that relative branches fit
+const SAMPLE_ADDRESS: u64 = 0x100;
+
+// Fields of the instruction word which only depend on the mnemonic
+const FIXED_FIELDS: &[&str] = &["header", "op", "pad"];
+
+/// Builds the opcode table of every instruction, by encoding a sample of each one
+pub fn get_opcode_table() -> Vec<OpcodeTableEntry> {
+    Mnemonic::iter()
+        .filter_map(|mnemonic| get_opcode_table_entry(&mnemonic))
+        .collect()
+}
+
+/// Builds the opcode table entry of an instruction, None for directives
+pub fn get_opcode_table_entry(mnemonic: &Mnemonic) -> Option<OpcodeTableEntry> {
+    let words = explain_sample_statement(mnemonic)?;
+
+    let mut entry = OpcodeTableEntry {
+        mnemonic: mnemonic.to_string(),
+        kind: format!("{:?}", mnemonic.get_kind()),
+        header: "".to_owned(),
+        opcode: "".to_owned(),
+        pattern: "".to_owned(),
+    };
+
+    // Operand fields are marked with x in the pattern
+    for field in &words.first()?.fields {
+        let bits = format!("{:0width$b}", field.value, width = field.width as usize);
+        match field.name {
+            "header" => entry.header = bits.clone(),
+            "op" => entry.opcode = bits.clone(),
+            _ => {}
+        }
+        match FIXED_FIELDS.contains(&field.name) {
+            true => entry.pattern.push_str(&bits),
+            false => entry.pattern.push_str(&"x".repeat(field.width as usize)),
+        }
+    }
+
+    Some(entry)
+}
+
+/// Encodes a sample of an instruction, None for directives
+pub fn explain_sample_statement(mnemonic: &Mnemonic) -> Option<Vec<ExplainedWord>> {
+    let args = get_sample_args(&mnemonic.get_kind())?;
+
+    let source = format!("{} {}", mnemonic, args);
+    let (_, statement) = parse_statement(source.trim()).ok()?;
+    explain_statement(&statement, SAMPLE_ADDRESS).ok()
+}
+
+/// Whether a field of the instruction word only depends on the mnemonic
+pub fn is_fixed_field(name: &str) -> bool {
+    FIXED_FIELDS.contains(&name)
+}
+
+// Arguments valid for every mnemonic of a kind, None for directives
+fn get_sample_args(kind: &StatementKind) -> Option<&'static str> {
+    match kind {
+        StatementKind::Org
+        | StatementKind::DataDefinition
+        | StatementKind::Fill
+        | StatementKind::Series
+        | StatementKind::Random
+        | StatementKind::Bank
+        | StatementKind::Limit
+        | StatementKind::IoDelay
+        | StatementKind::AssertAt
+        | StatementKind::Vector
+        | StatementKind::IfCpu
+        | StatementKind::Else
+        | StatementKind::EndIf
+        | StatementKind::Equ
+        | StatementKind::Weak
+        | StatementKind::Trampoline => None,
+        StatementKind::BranchOnIndicators => Some("<0x100"),
+        StatementKind::NoOp | StatementKind::Generic => Some(""),
+        StatementKind::BranchOnRegisters => Some("$R1, <0x100"),
+        StatementKind::ShortValueImmediate => Some("$R1, =1"),
+        StatementKind::SingleOperandData => Some("=$R1"),
+        StatementKind::SingleOperandBase => Some("=$B1"),
+        StatementKind::SingleOperandMemonly => Some("<0x100"),
+        StatementKind::SingleOperandDataMasked => Some("=$R1, 1"),
+        StatementKind::SingleOperandMemonlyMasked => Some("<0x100, 1"),
+        StatementKind::DoubleOperandData => Some("$R1, =$R2"),
+        StatementKind::DoubleOperandDataMasked => Some("$R1, <0x100, 1"),
+        StatementKind::DoubleOperandBase => Some("$B1, =$B2"),
+        StatementKind::DoubleOperandNoreg => Some("$B1, $B2"),
+        StatementKind::DoubleOperandMemonly => Some("$B1, $B2"),
+        StatementKind::DoubleOperandMode => Some("$M1, <0x100"),
+        StatementKind::ShiftShort | StatementKind::ShiftLong => Some("$R1, 1"),
+        StatementKind::InputOutput => Some("<0x100, =0x100"),
+        StatementKind::InputOutputLoad => Some("<0x100, =0x100, <0x200"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_opcode_table_succ() {
+        let table = get_opcode_table();
+
+        // Every instruction must be encodable from its sample arguments
+        let instructions: Vec<String> = Mnemonic::iter()
+            .filter(|mnemonic| get_sample_args(&mnemonic.get_kind()).is_some())
+            .map(|mnemonic| mnemonic.to_string())
+            .collect();
+        let mnemonics: Vec<String> = table.iter().map(|entry| entry.mnemonic.clone()).collect();
+        assert_eq!(mnemonics, instructions);
+
+        let ldr = table.iter().find(|entry| entry.mnemonic == "LDR").unwrap();
+        assert_eq!(ldr.kind, "DoubleOperandData");
+        assert_eq!(ldr.header, "1");
+        assert_eq!(ldr.opcode, "10000");
+        assert_eq!(ldr.pattern, "1xxx10000xxxxxxx");
+    }
+}
@@ -1,10 +1,15 @@
+use super::codegen::{check_short_immediate_value, ShortImmediateSignedness};
 use super::statements::{
     AddressExpression, AddressSyllable, BRelativeAddress, BRelativeAddressMode, BaseRegister,
     BranchLocation, BranchOnIndicatorsOpCode, BranchOnRegistersOpCode, ChannelExpression,
-    DataDefinitionSize, DataRegister, DoubleOperandOpCode, GenericOpCode, ImmediateAddress,
-    ImmediateAddressMode, IncDec, InputOutputOpCode, Mnemonic, ModeControlRegister,
-    PRelativeAddress, Register, ShiftLongOpCode, ShiftShortOpCode, ShortValueImmediateOpCode,
-    SingleOperandOpCode, Statement,
+    CpuFeature, DataDefinitionSize, DataRegister, DataValue, DoubleOperandOpCode, GenericOpCode,
+    ImmediateAddress, ImmediateAddressMode, IncDec, InputOutputOpCode, Mnemonic,
+    ModeControlRegister, PRelativeAddress, Register, ShiftLongOpCode, ShiftShortOpCode,
+    ShortValueImmediateOpCode, SingleOperandOpCode, Statement,
+};
+use super::suggestions::suggest_mnemonic;
+use super::syntax::{
+    get_argument_count_range, get_operand_specs, OperandSpec, OperandType, RegisterType,
 };
 use crate::{assembler::statements::StatementKind, logging::AssemblerErrorKind};
 use nom::{
@@ -13,11 +18,19 @@ use nom::{
     character::complete::{digit1, hex_digit1, space0},
     combinator::{map, map_res, opt, value},
     error::{ErrorKind, ParseError},
-    multi::{fold_many0, fold_many1, separated_list0},
+    multi::{fold_many0, fold_many1, many0, separated_list0},
     sequence::{delimited, preceded, separated_pair, terminated, tuple},
     Err, IResult,
 };
 
+// Largest number of values a fill directive can emit
+const MAX_FILL_COUNT: u64 = 0x100000;
+
+// Feedback taps of the maximal length 16 bit LFSR used by .RANDOM
+const RANDOM_LFSR_TAPS: u16 = 0xB400;
+// Highest entry of a vector table, one for each of the 64 interrupt levels
+pub const MAX_VECTOR_INDEX: u64 = 63;
+
 #[derive(Debug)]
 pub struct AssemblerParseError<'a> {
     _input: &'a str,
@@ -76,7 +89,9 @@ pub fn parse_statement(input: &str) -> IResult<&str, Statement, AssemblerParseEr
     Ok(("", statement))
 }
 
-fn parse_mnemonic_and_args(input: &str) -> IResult<&str, (&str, Vec<String>), AssemblerParseError> {
+pub fn parse_mnemonic_and_args(
+    input: &str,
+) -> IResult<&str, (&str, Vec<String>), AssemblerParseError<'_>> {
     // Store intermediary result for type annotations
     let mnemo_res: IResult<&str, &str> = is_not(" ,")(input);
 
@@ -127,52 +142,51 @@ fn encapsulate_statement(
     // Match mnemonic to list of mnemonics
     let mnemo = match match_mnemonic(&mnemonic_str.to_uppercase()) {
         Ok(mnemonic) => mnemonic,
-        Err(()) => return Err(AssemblerErrorKind::UnkownMnemonic(mnemonic_str.to_owned())),
+        Err(()) => {
+            return Err(AssemblerErrorKind::UnkownMnemonic(
+                mnemonic_str.to_owned(),
+                suggest_mnemonic(mnemonic_str),
+            ))
+        }
     };
+    let kind = mnemo.get_kind();
 
-    match mnemo.get_kind() {
-        StatementKind::Org => encapsulate_org_statement(args),
-        StatementKind::DataDefinition => encapsulate_data_definition_statement(mnemo, args),
-        StatementKind::BranchOnIndicators => {
-            encapsulate_branch_on_indicators_statement(mnemo, args)
-        }
-        StatementKind::BranchOnRegisters => encapsulate_branch_on_registers_statement(mnemo, args),
-        StatementKind::ShortValueImmediate => {
-            encapsulate_short_value_immediate_statement(mnemo, args)
-        }
-        StatementKind::SingleOperandData => encapsulate_single_operand_data_statement(mnemo, args),
-        StatementKind::SingleOperandBase => encapsulate_single_operand_base_statement(mnemo, args),
-        StatementKind::SingleOperandMemonly => {
-            encapsulate_single_operand_memonly_statement(mnemo, args)
-        }
-        StatementKind::SingleOperandDataMasked => {
-            encapsulate_single_operand_data_masked_statement(mnemo, args)
-        }
-        StatementKind::SingleOperandMemonlyMasked => {
-            encapsulate_single_operand_memonly_masked_statement(mnemo, args)
-        }
-        StatementKind::NoOp => encapsulate_noop_statement(args),
-        StatementKind::Generic => encapsulate_generic_statement(mnemo, args),
-        StatementKind::DoubleOperandData => encapsulate_double_operand_data_statement(mnemo, args),
-        StatementKind::DoubleOperandDataMasked => {
-            encapsulate_double_operand_data_masked_statement(mnemo, args)
-        }
-        StatementKind::DoubleOperandBase => encapsulate_double_operand_base_statement(mnemo, args),
-        StatementKind::DoubleOperandNoreg => {
-            encapsulate_double_operand_noreg_statement(mnemo, args)
-        }
-        StatementKind::DoubleOperandMemonly => {
-            encapsulate_double_operand_memonly_statement(mnemo, args)
-        }
-        StatementKind::DoubleOperandMode => encapsulate_double_operand_mode_statement(mnemo, args),
-        StatementKind::ShiftShort => encapsulate_shift_short_statement(mnemo, args),
-        StatementKind::ShiftLong => encapsulate_shift_long_statement(mnemo, args),
-        StatementKind::InputOutput => encapsulate_input_output_statement(mnemo, args),
-        StatementKind::InputOutputLoad => encapsulate_input_output_load_statement(mnemo, args),
+    // Check number of arguments
+    let (min, max) = get_argument_count_range(&kind);
+    if args.len() < min || max.is_some_and(|max| args.len() > max) {
+        return Err(AssemblerErrorKind::WrongNumberOfArguments(
+            mnemo,
+            min,
+            max,
+            args.len(),
+        ));
+    }
+
+    // Parse arguments according to the operand table, the last operand is reused by
+    // repeated arguments
+    let specs = get_operand_specs(&kind);
+    let mut operands = vec![];
+    for (i, arg) in args.iter().enumerate() {
+        operands.push(parse_operand(&mnemo, &specs[i.min(specs.len() - 1)], arg)?);
     }
+
+    build_statement(mnemo, kind, Operands(operands.into_iter()), args)
 }
 
 /// Matches a mnemonic string to its enum type
+/// Checks if a mnemonic is an instruction or a built-in directive
+pub fn is_builtin_mnemonic(mnemonic: &str) -> bool {
+    match_mnemonic(&mnemonic.to_uppercase()).is_ok()
+}
+
+/// Checks if a mnemonic is a Branch on Indicators or Branch on Registers instruction
+pub fn is_branch_mnemonic(mnemonic: &str) -> bool {
+    matches!(
+        match_mnemonic(&mnemonic.to_uppercase()).map(|mnemo| mnemo.get_kind()),
+        Ok(StatementKind::BranchOnIndicators | StatementKind::BranchOnRegisters)
+    )
+}
+
 fn match_mnemonic(input: &str) -> Result<Mnemonic, ()> {
     match input {
         // Assembler directives
@@ -181,6 +195,22 @@ fn match_mnemonic(input: &str) -> Result<Mnemonic, ()> {
         ".DW" => Ok(Mnemonic::DotDW),
         ".DD" => Ok(Mnemonic::DotDD),
         ".DQ" => Ok(Mnemonic::DotDQ),
+        ".BANK" => Ok(Mnemonic::DotBANK),
+        ".TRAMPOLINE" => Ok(Mnemonic::DotTRAMPOLINE),
+        ".FILL" => Ok(Mnemonic::DotFILL),
+        ".FILLB" => Ok(Mnemonic::DotFILLB),
+        ".SERIES" => Ok(Mnemonic::DotSERIES),
+        ".SERIESB" => Ok(Mnemonic::DotSERIESB),
+        ".RANDOM" => Ok(Mnemonic::DotRANDOM),
+        ".LIMIT" => Ok(Mnemonic::DotLIMIT),
+        ".IODELAY" => Ok(Mnemonic::DotIODELAY),
+        ".ASSERT_AT" => Ok(Mnemonic::DotASSERTAT),
+        ".VECTOR" => Ok(Mnemonic::DotVECTOR),
+        ".IFCPU" => Ok(Mnemonic::DotIFCPU),
+        ".ELSE" => Ok(Mnemonic::DotELSE),
+        ".ENDIF" => Ok(Mnemonic::DotENDIF),
+        ".EQU" => Ok(Mnemonic::DotEQU),
+        ".WEAK" => Ok(Mnemonic::DotWEAK),
 
         // Branch on Indicators instructions
         "BL" => Ok(Mnemonic::BL),
@@ -323,37 +353,412 @@ fn match_mnemonic(input: &str) -> Result<Mnemonic, ()> {
     }
 }
 
-fn encapsulate_org_statement(args: &[String]) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 1 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            Mnemonic::DotORG,
-            1,
-            args.len(),
-        ));
+// Value of a parsed operand
+enum Operand {
+    Number(u64),
+    Value(i128),
+    Values(Vec<DataValue>),
+    Label(String),
+    Symbol(Option<String>, i128),
+    BranchLocation(BranchLocation),
+    DataRegister(DataRegister),
+    BaseRegister(BaseRegister),
+    ModeControlRegister(ModeControlRegister),
+    ChannelExpression(ChannelExpression),
+    AddressSyllable(AddressSyllable),
+}
+
+fn parse_operand(
+    mnemo: &Mnemonic,
+    spec: &OperandSpec,
+    arg: &str,
+) -> Result<Operand, AssemblerErrorKind> {
+    Ok(match spec.operand_type {
+        OperandType::HexAddress => Operand::Number(parse_hex_address_arg(arg)?),
+        OperandType::BankNumber => Operand::Number(parse_bank_number_arg(arg)?),
+        OperandType::ShiftValue(_) => Operand::Number(parse_shift_value_arg(arg)?),
+        OperandType::Label => Operand::Label(parse_label_arg(arg)?),
+        OperandType::ConstantExpression => Operand::Value(parse_constant_expression_arg(arg)?),
+        OperandType::SymbolExpression => {
+            let (label, offset) = parse_symbol_expression_arg(arg)?;
+            Operand::Symbol(label, offset)
+        }
+        OperandType::ImmediateValue => Operand::Value(parse_immediate_value_arg(arg)?),
+        OperandType::MaskWord => Operand::Value(parse_maskword_arg(arg)?),
+        OperandType::DefinitionChunk => Operand::Values(parse_definition_chunk_arg(arg)?),
+        OperandType::BranchLocation => Operand::BranchLocation(parse_branch_location_arg(arg)?),
+        OperandType::DataRegister => Operand::DataRegister(parse_data_register_arg(arg)?),
+        OperandType::BaseRegister => Operand::BaseRegister(parse_base_register_arg(arg)?),
+        OperandType::ModeControlRegister => {
+            Operand::ModeControlRegister(parse_mode_control_register_arg(arg)?)
+        }
+        OperandType::ChannelExpression => {
+            Operand::ChannelExpression(parse_channel_expression_arg(arg)?)
+        }
+        OperandType::AddressSyllable {
+            register,
+            immediate,
+        } => {
+            let addr_syl = parse_address_syllable_arg(arg)?;
+            check_addressing(mnemo, arg, &addr_syl, register, immediate)?;
+            Operand::AddressSyllable(addr_syl)
+        }
+    })
+}
+
+// Checks the addressing mode of an address syllable against the restrictions of its operand
+fn check_addressing(
+    mnemo: &Mnemonic,
+    arg: &str,
+    addr_syl: &AddressSyllable,
+    register: Option<RegisterType>,
+    immediate: bool,
+) -> Result<(), AssemblerErrorKind> {
+    match (addr_syl, register) {
+        (AddressSyllable::RegisterAddressing(_), None) => {
+            Err(AssemblerErrorKind::RegisterAddressingInvalid(mnemo.clone()))
+        }
+        (AddressSyllable::RegisterAddressing(Register::Base(_)), Some(RegisterType::Data))
+        | (AddressSyllable::RegisterAddressing(Register::Data(_)), Some(RegisterType::Base)) => {
+            Err(AssemblerErrorKind::WrongRegisterType(
+                arg.to_owned(),
+                mnemo.clone(),
+            ))
+        }
+        (AddressSyllable::ImmediateAddressing(_), _) if !immediate => Err(
+            AssemblerErrorKind::ImmediateAddressingInvalid(mnemo.clone()),
+        ),
+        (AddressSyllable::ImmediateOperand(_), _) if mnemo.writes_operand() => Err(
+            AssemblerErrorKind::ImmediateOperandWrite(mnemo.clone(), arg.to_owned()),
+        ),
+        _ => Ok(()),
     }
+}
 
-    // Parse address
-    let address = parse_hex_address_arg(&args[0])?;
+// Parsed operands of a statement, taken in the order of its operand table
+struct Operands(std::vec::IntoIter<Operand>);
+
+impl Operands {
+    fn number(&mut self) -> u64 {
+        match self.0.next() {
+            Some(Operand::Number(value)) => value,
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn value(&mut self) -> i128 {
+        match self.0.next() {
+            Some(Operand::Value(value)) => value,
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn optional_value(&mut self) -> Option<i128> {
+        match self.0.next() {
+            Some(Operand::Value(value)) => Some(value),
+            None => None,
+            _ => panic!("operand table mismatch"),
+        }
+    }
 
-    Ok(Statement::Org(address))
+    // Values of all the remaining operands
+    fn values(&mut self) -> Vec<DataValue> {
+        let mut values = vec![];
+        for operand in self.0.by_ref() {
+            match operand {
+                Operand::Values(mut chunk) => values.append(&mut chunk),
+                _ => panic!("operand table mismatch"),
+            }
+        }
+        values
+    }
+
+    fn label(&mut self) -> String {
+        match self.0.next() {
+            Some(Operand::Label(label)) => label,
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn symbol(&mut self) -> (Option<String>, i128) {
+        match self.0.next() {
+            Some(Operand::Symbol(label, offset)) => (label, offset),
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn branch_location(&mut self) -> BranchLocation {
+        match self.0.next() {
+            Some(Operand::BranchLocation(branchloc)) => branchloc,
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn data_register(&mut self) -> DataRegister {
+        match self.0.next() {
+            Some(Operand::DataRegister(reg)) => reg,
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn base_register(&mut self) -> BaseRegister {
+        match self.0.next() {
+            Some(Operand::BaseRegister(reg)) => reg,
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn mode_control_register(&mut self) -> ModeControlRegister {
+        match self.0.next() {
+            Some(Operand::ModeControlRegister(reg)) => reg,
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn channel_expression(&mut self) -> ChannelExpression {
+        match self.0.next() {
+            Some(Operand::ChannelExpression(chan_expr)) => chan_expr,
+            _ => panic!("operand table mismatch"),
+        }
+    }
+
+    fn address_syllable(&mut self) -> AddressSyllable {
+        match self.0.next() {
+            Some(Operand::AddressSyllable(addr_syl)) => addr_syl,
+            _ => panic!("operand table mismatch"),
+        }
+    }
 }
 
-fn encapsulate_branch_on_indicators_statement(
+// Mask word of a masked instruction, 0 if omitted. Bit instructions with an indexed address
+// syllable take the bit number from the index register and have no mask word
+fn get_mask(
+    mnemo: &Mnemonic,
+    addr_syl: &AddressSyllable,
+    mask: Option<i128>,
+) -> Result<Option<i128>, AssemblerErrorKind> {
+    let bit_instruction = matches!(
+        mnemo,
+        Mnemonic::LB | Mnemonic::LBF | Mnemonic::LBT | Mnemonic::LBC | Mnemonic::LBS
+    );
+    match (
+        bit_instruction && is_indexed_address_syllable(addr_syl),
+        mask,
+    ) {
+        (true, Some(_)) => Err(AssemblerErrorKind::MaskWithIndexedAddressing(mnemo.clone())),
+        (true, None) => Ok(None),
+        (false, mask) => Ok(Some(mask.unwrap_or(0))),
+    }
+}
+
+fn is_indexed_address_syllable(addr_syl: &AddressSyllable) -> bool {
+    matches!(
+        addr_syl,
+        AddressSyllable::ImmediateAddressing(
+            ImmediateAddressMode::Direct(ImmediateAddress::Indexed(_, _))
+                | ImmediateAddressMode::Indirect(ImmediateAddress::Indexed(_, _))
+        ) | AddressSyllable::BRelative(
+            BRelativeAddressMode::Direct(BRelativeAddress::Indexed(_, _))
+                | BRelativeAddressMode::Indirect(BRelativeAddress::Indexed(_, _))
+                | BRelativeAddressMode::IncDecIndexed(_, _, _)
+        )
+    )
+}
+
+// Builds a statement from operands parsed according to the operand table of its kind
+fn build_statement(
     mnemo: Mnemonic,
+    kind: StatementKind,
+    mut ops: Operands,
     args: &[String],
 ) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 1 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            1,
-            args.len(),
-        ));
+    Ok(match kind {
+        StatementKind::Org => Statement::Org(ops.number()),
+        StatementKind::DataDefinition => {
+            Statement::DataDefinition(match_data_size(&mnemo), ops.values())
+        }
+        StatementKind::Bank => Statement::Bank(ops.number()),
+        StatementKind::Trampoline => Statement::Trampoline(ops.label()),
+        StatementKind::Fill => {
+            let count = ops.value();
+            if !(0..=MAX_FILL_COUNT as i128).contains(&count) {
+                return Err(AssemblerErrorKind::FillCountOutOfRange(count));
+            }
+            Statement::Fill(match_data_size(&mnemo), count as u64, ops.value())
+        }
+        // Data definition of the values of an arithmetic series
+        StatementKind::Series => {
+            let (start, step, count) = (ops.value(), ops.value(), ops.value());
+            if !(0..=MAX_FILL_COUNT as i128).contains(&count) {
+                return Err(AssemblerErrorKind::SeriesCountOutOfRange(count));
+            }
+            let values: Option<Vec<DataValue>> = (0..count)
+                .map(|i| {
+                    let value = step.checked_mul(i)?.checked_add(start)?;
+                    Some(DataValue::Constant(value))
+                })
+                .collect();
+            match values {
+                Some(values) => Statement::DataDefinition(match_data_size(&mnemo), values),
+                None => {
+                    return Err(AssemblerErrorKind::InvalidConstantExpression(
+                        args.join(", "),
+                    ))
+                }
+            }
+        }
+        // Words of a 16 bit Galois LFSR, the same for a given seed on every run
+        StatementKind::Random => {
+            let (count, seed) = (ops.value(), ops.value());
+            if !(0..=MAX_FILL_COUNT as i128).contains(&count) {
+                return Err(AssemblerErrorKind::RandomCountOutOfRange(count));
+            }
+            if !(1..=0xFFFF).contains(&seed) {
+                return Err(AssemblerErrorKind::RandomSeedOutOfRange(seed));
+            }
+            let mut state = seed as u16;
+            let values = (0..count)
+                .map(|_| {
+                    state = (state >> 1) ^ (0u16.wrapping_sub(state & 1) & RANDOM_LFSR_TAPS);
+                    DataValue::Constant(state as i128)
+                })
+                .collect();
+            Statement::DataDefinition(DataDefinitionSize::Word, values)
+        }
+        StatementKind::Weak => Statement::Weak,
+        StatementKind::Equ => {
+            let (label, offset) = ops.symbol();
+            Statement::Equ(label, offset)
+        }
+        StatementKind::Limit => match TryInto::<u64>::try_into(ops.value()) {
+            Ok(size) => Statement::Limit(size),
+            Err(_) => {
+                return Err(AssemblerErrorKind::InvalidConstantExpression(
+                    args[0].to_owned(),
+                ))
+            }
+        },
+        StatementKind::IoDelay => match TryInto::<u64>::try_into(ops.value()) {
+            Ok(cycles) => Statement::IoDelay(cycles),
+            Err(_) => {
+                return Err(AssemblerErrorKind::InvalidConstantExpression(
+                    args[0].to_owned(),
+                ))
+            }
+        },
+        StatementKind::AssertAt => {
+            let label = ops.label();
+            Statement::AssertAt(label, ops.number())
+        }
+        StatementKind::Vector => {
+            let index = ops.value();
+            if !(0..=MAX_VECTOR_INDEX as i128).contains(&index) {
+                return Err(AssemblerErrorKind::VectorIndexOutOfRange(index));
+            }
+            Statement::Vector(index as u64, ops.label())
+        }
+        StatementKind::IfCpu => {
+            let name = ops.label();
+            match CpuFeature::from_name(&name) {
+                Some(feature) => Statement::IfCpu(feature),
+                None => return Err(AssemblerErrorKind::UnknownCpuFeature(name)),
+            }
+        }
+        StatementKind::Else => Statement::Else,
+        StatementKind::EndIf => Statement::EndIf,
+        StatementKind::BranchOnIndicators => Statement::BranchOnIndicators(
+            match_branch_on_indicators_opcode(&mnemo),
+            ops.branch_location(),
+        ),
+        StatementKind::NoOp => Statement::BranchOnIndicators(
+            BranchOnIndicatorsOpCode::NOP,
+            BranchLocation::ShortDisplacement(AddressExpression::WordDisplacement(-1)),
+        ),
+        StatementKind::BranchOnRegisters => Statement::BranchOnRegisters(
+            match_branch_on_registers_opcode(&mnemo),
+            ops.data_register(),
+            ops.branch_location(),
+        ),
+        StatementKind::ShortValueImmediate => {
+            let reg = ops.data_register();
+            let value = ops.value();
+            check_short_immediate_value(value, ShortImmediateSignedness::Any)?;
+            Statement::ShortValueImmediate(match_short_value_immediate_opcode(&mnemo), reg, value)
+        }
+        StatementKind::SingleOperandData
+        | StatementKind::SingleOperandBase
+        | StatementKind::SingleOperandMemonly => Statement::SingleOperand(
+            match_single_operand_opcode(&mnemo),
+            ops.address_syllable(),
+            None,
+        ),
+        StatementKind::SingleOperandDataMasked | StatementKind::SingleOperandMemonlyMasked => {
+            let addr_syl = ops.address_syllable();
+            let mask = get_mask(&mnemo, &addr_syl, ops.optional_value())?;
+            Statement::SingleOperand(match_single_operand_opcode(&mnemo), addr_syl, mask)
+        }
+        StatementKind::Generic => Statement::Generic(match_generic_opcode(&mnemo)),
+        StatementKind::DoubleOperandData => Statement::DoubleOperand(
+            match_double_operand_opcode(&mnemo),
+            Register::Data(ops.data_register()),
+            ops.address_syllable(),
+            None,
+        ),
+        StatementKind::DoubleOperandDataMasked => Statement::DoubleOperand(
+            match_double_operand_opcode(&mnemo),
+            Register::Data(ops.data_register()),
+            ops.address_syllable(),
+            Some(ops.optional_value().unwrap_or(0)),
+        ),
+        StatementKind::DoubleOperandBase
+        | StatementKind::DoubleOperandNoreg
+        | StatementKind::DoubleOperandMemonly => Statement::DoubleOperand(
+            match_double_operand_opcode(&mnemo),
+            Register::Base(ops.base_register()),
+            ops.address_syllable(),
+            None,
+        ),
+        StatementKind::DoubleOperandMode => Statement::DoubleOperand(
+            match_double_operand_opcode(&mnemo),
+            Register::ModeControl(ops.mode_control_register()),
+            ops.address_syllable(),
+            None,
+        ),
+        StatementKind::ShiftShort => Statement::ShiftShort(
+            match_shift_short_opcode(&mnemo),
+            ops.data_register(),
+            ops.number(),
+        ),
+        StatementKind::ShiftLong => Statement::ShiftLong(
+            match_shift_long_opcode(&mnemo),
+            ops.data_register(),
+            ops.number(),
+        ),
+        StatementKind::InputOutput => Statement::InputOutput(
+            match_input_output_opcode(&mnemo),
+            ops.address_syllable(),
+            ops.channel_expression(),
+        ),
+        StatementKind::InputOutputLoad => Statement::InputOutputLoad(
+            ops.address_syllable(),
+            ops.channel_expression(),
+            ops.address_syllable(),
+        ),
+    })
+}
+
+fn match_data_size(mnemo: &Mnemonic) -> DataDefinitionSize {
+    match mnemo {
+        Mnemonic::DotDB | Mnemonic::DotFILLB | Mnemonic::DotSERIESB => DataDefinitionSize::Byte,
+        Mnemonic::DotDW | Mnemonic::DotFILL | Mnemonic::DotSERIES => DataDefinitionSize::Word,
+        Mnemonic::DotDD => DataDefinitionSize::DoubleWord,
+        Mnemonic::DotDQ => DataDefinitionSize::QuadWord,
+        _ => panic!("invalid mnemonic for DataDefinition"),
     }
+}
 
-    // Get op code
-    let op = match mnemo {
+fn match_branch_on_indicators_opcode(mnemo: &Mnemonic) -> BranchOnIndicatorsOpCode {
+    match mnemo {
         Mnemonic::BL => BranchOnIndicatorsOpCode::BL,
         Mnemonic::BGE => BranchOnIndicatorsOpCode::BGE,
         Mnemonic::BG => BranchOnIndicatorsOpCode::BG,
@@ -376,29 +781,11 @@ fn encapsulate_branch_on_indicators_statement(
         Mnemonic::BSE => BranchOnIndicatorsOpCode::BSE,
         Mnemonic::B => BranchOnIndicatorsOpCode::B,
         _ => panic!("invalid OpCode for BranchOnIndicators"),
-    };
-
-    // Parse branch location
-    let branchloc = parse_branch_location_arg(&args[0])?;
-
-    Ok(Statement::BranchOnIndicators(op, branchloc))
-}
-
-fn encapsulate_branch_on_registers_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
     }
+}
 
-    // Get op code
-    let op = match mnemo {
+fn match_branch_on_registers_opcode(mnemo: &Mnemonic) -> BranchOnRegistersOpCode {
+    match mnemo {
         Mnemonic::BLZ => BranchOnRegistersOpCode::BLZ,
         Mnemonic::BGEZ => BranchOnRegistersOpCode::BGEZ,
         Mnemonic::BEZ => BranchOnRegistersOpCode::BEZ,
@@ -410,243 +797,39 @@ fn encapsulate_branch_on_registers_statement(
         Mnemonic::BINC => BranchOnRegistersOpCode::BINC,
         Mnemonic::BDEC => BranchOnRegistersOpCode::BDEC,
         _ => panic!("invalid OpCode for BranchOnRegisters"),
-    };
-
-    // Parse data register
-    let reg = parse_data_register_arg(&args[0])?;
-
-    // Parse branch location
-    let branchloc = parse_branch_location_arg(&args[1])?;
-
-    Ok(Statement::BranchOnRegisters(op, reg, branchloc))
-}
-
-fn encapsulate_short_value_immediate_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
     }
+}
 
-    // Get op code
-    let op = match mnemo {
+fn match_short_value_immediate_opcode(mnemo: &Mnemonic) -> ShortValueImmediateOpCode {
+    match mnemo {
         Mnemonic::LDV => ShortValueImmediateOpCode::LDV,
         Mnemonic::CMV => ShortValueImmediateOpCode::CMV,
         Mnemonic::ADV => ShortValueImmediateOpCode::ADV,
         Mnemonic::MLV => ShortValueImmediateOpCode::MLV,
         _ => panic!("invalid OpCode for ShortValueImmediate"),
-    };
-
-    // Parse data register
-    let reg = parse_data_register_arg(&args[0])?;
-
-    // Parse branch location
-    let val = parse_immediate_value_arg(&args[1])?;
-
-    Ok(Statement::ShortValueImmediate(op, reg, val))
-}
-
-fn encapsulate_data_definition_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() == 0 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            0,
-            args.len(),
-        ));
-    }
-
-    // Get data size
-    let size = match mnemo {
-        Mnemonic::DotDB => DataDefinitionSize::Byte,
-        Mnemonic::DotDW => DataDefinitionSize::Word,
-        Mnemonic::DotDD => DataDefinitionSize::DoubleWord,
-        Mnemonic::DotDQ => DataDefinitionSize::QuadWord,
-        _ => panic!("invalid mnemonic for DataDefinition"),
-    };
-
-    // Parse definition chunks
-    let mut values: Vec<i128> = vec![];
-    for arg in args {
-        values.append(&mut parse_definition_chunk_arg(&arg)?)
-    }
-
-    Ok(Statement::DataDefinition(size, values))
-}
-
-fn encapsulate_single_operand_data_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 1 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            1,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match_single_operand_opcode(&mnemo);
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[0])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &addr_syl {
-        if let Register::Base(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
-    }
-
-    Ok(Statement::SingleOperand(op, addr_syl, None))
-}
-
-fn encapsulate_single_operand_base_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 1 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            1,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match_single_operand_opcode(&mnemo);
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[0])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &addr_syl {
-        if let Register::Data(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
     }
-
-    Ok(Statement::SingleOperand(op, addr_syl, None))
 }
 
-fn encapsulate_single_operand_memonly_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 1 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            1,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match_single_operand_opcode(&mnemo);
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[0])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(_) = &addr_syl {
-        return Err(AssemblerErrorKind::RegisterAddressingInvalid(mnemo));
-    }
-
-    Ok(Statement::SingleOperand(op, addr_syl, None))
-}
-
-fn encapsulate_single_operand_data_masked_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() < 1 && args.len() > 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            1,
-            args.len(),
-        ));
-    }
-
-    // Check if mask needs to be parsed
-    let mask = if args.len() == 2 {
-        parse_maskword_arg(&args[1])?
-    } else {
-        0
-    };
-
-    // Potential TODO: Only add mask when addressing is not indexed
-
-    // Get op code
-    let op = match_single_operand_opcode(&mnemo);
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[0])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &addr_syl {
-        if let Register::Base(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
-    }
-
-    Ok(Statement::SingleOperand(op, addr_syl, Some(mask)))
-}
-
-fn encapsulate_single_operand_memonly_masked_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() < 1 && args.len() > 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            1,
-            args.len(),
-        ));
-    }
-
-    // Check if mask needs to be parsed
-    let mask = if args.len() == 2 {
-        parse_maskword_arg(&args[1])?
-    } else {
-        0
-    };
-
-    // Get op code
-    let op = match_single_operand_opcode(&mnemo);
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[0])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(_) = &addr_syl {
-        return Err(AssemblerErrorKind::RegisterAddressingInvalid(mnemo));
+fn match_generic_opcode(mnemo: &Mnemonic) -> GenericOpCode {
+    match mnemo {
+        Mnemonic::HLT => GenericOpCode::HLT,
+        Mnemonic::MCL => GenericOpCode::MCL,
+        Mnemonic::RTT => GenericOpCode::RTT,
+        Mnemonic::RTCN => GenericOpCode::RTCN,
+        Mnemonic::RTCF => GenericOpCode::RTCF,
+        Mnemonic::WDTN => GenericOpCode::WDTN,
+        Mnemonic::WDTF => GenericOpCode::WDTF,
+        Mnemonic::BRK => GenericOpCode::BRK,
+        Mnemonic::MMM => GenericOpCode::MMM,
+        Mnemonic::ASD => GenericOpCode::ASD,
+        Mnemonic::VLD => GenericOpCode::VLD,
+        Mnemonic::QOH => GenericOpCode::QOH,
+        Mnemonic::QOT => GenericOpCode::QOT,
+        Mnemonic::DQH => GenericOpCode::DQH,
+        Mnemonic::DQA => GenericOpCode::DQA,
+        Mnemonic::RSC => GenericOpCode::RSC,
+        _ => panic!("invalid OpCode for GenericOpCode"),
     }
-
-    Ok(Statement::SingleOperand(op, addr_syl, Some(mask)))
 }
 
 fn match_single_operand_opcode(mnemo: &Mnemonic) -> SingleOperandOpCode {
@@ -679,299 +862,6 @@ fn match_single_operand_opcode(mnemo: &Mnemonic) -> SingleOperandOpCode {
     }
 }
 
-fn encapsulate_noop_statement(args: &[String]) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 0 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            Mnemonic::NOP,
-            0,
-            args.len(),
-        ));
-    }
-
-    Ok(Statement::BranchOnIndicators(
-        BranchOnIndicatorsOpCode::NOP,
-        BranchLocation::ShortDisplacement(AddressExpression::WordDisplacement(-1)),
-    ))
-}
-
-fn encapsulate_generic_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 0 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            0,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match mnemo {
-        Mnemonic::HLT => GenericOpCode::HLT,
-        Mnemonic::MCL => GenericOpCode::MCL,
-        Mnemonic::RTT => GenericOpCode::RTT,
-        Mnemonic::RTCN => GenericOpCode::RTCN,
-        Mnemonic::RTCF => GenericOpCode::RTCF,
-        Mnemonic::WDTN => GenericOpCode::WDTN,
-        Mnemonic::WDTF => GenericOpCode::WDTF,
-        Mnemonic::BRK => GenericOpCode::BRK,
-        Mnemonic::MMM => GenericOpCode::MMM,
-        Mnemonic::ASD => GenericOpCode::ASD,
-        Mnemonic::VLD => GenericOpCode::VLD,
-        Mnemonic::QOH => GenericOpCode::QOH,
-        Mnemonic::QOT => GenericOpCode::QOT,
-        Mnemonic::DQH => GenericOpCode::DQH,
-        Mnemonic::DQA => GenericOpCode::DQA,
-        Mnemonic::RSC => GenericOpCode::RSC,
-        _ => panic!("invalid OpCode for GenericOpCode"),
-    };
-
-    Ok(Statement::Generic(op))
-}
-
-fn encapsulate_double_operand_data_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match_double_operand_opcode(&mnemo);
-
-    // Parse register
-    let reg = parse_data_register_arg(&args[0])?;
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[1])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &addr_syl {
-        if let Register::Base(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
-    }
-
-    Ok(Statement::DoubleOperand(
-        op,
-        Register::Data(reg),
-        addr_syl,
-        None,
-    ))
-}
-
-fn encapsulate_double_operand_data_masked_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 && args.len() != 3 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
-    }
-
-    // Check if mask needs to be parsed
-    let mask = if args.len() == 3 {
-        parse_maskword_arg(&args[2])?
-    } else {
-        0
-    };
-
-    // Get op code
-    let op = match_double_operand_opcode(&mnemo);
-
-    // Parse register
-    let reg = parse_data_register_arg(&args[0])?;
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[1])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &addr_syl {
-        if let Register::Base(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
-    }
-
-    Ok(Statement::DoubleOperand(
-        op,
-        Register::Data(reg),
-        addr_syl,
-        Some(mask),
-    ))
-}
-
-fn encapsulate_double_operand_base_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match_double_operand_opcode(&mnemo);
-
-    // Parse register
-    let reg = parse_base_register_arg(&args[0])?;
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[1])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &addr_syl {
-        if let Register::Data(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
-    }
-
-    Ok(Statement::DoubleOperand(
-        op,
-        Register::Base(reg),
-        addr_syl,
-        None,
-    ))
-}
-
-fn encapsulate_double_operand_mode_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match_double_operand_opcode(&mnemo);
-
-    // Parse register
-    let reg = parse_mode_control_register_arg(&args[0])?;
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[1])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &addr_syl {
-        if let Register::Data(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
-    }
-
-    Ok(Statement::DoubleOperand(
-        op,
-        Register::ModeControl(reg),
-        addr_syl,
-        None,
-    ))
-}
-
-fn encapsulate_double_operand_noreg_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match_double_operand_opcode(&mnemo);
-
-    // Parse register
-    let reg = parse_base_register_arg(&args[0])?;
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[1])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(_reg) = &addr_syl {
-        return Err(AssemblerErrorKind::RegisterAddressingInvalid(mnemo));
-    }
-
-    Ok(Statement::DoubleOperand(
-        op,
-        Register::Base(reg),
-        addr_syl,
-        None,
-    ))
-}
-
-fn encapsulate_double_operand_memonly_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match_double_operand_opcode(&mnemo);
-
-    // Parse register
-    let reg = parse_base_register_arg(&args[0])?;
-
-    // Parse address syllable
-    let addr_syl = parse_address_syllable_arg(&args[1])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(_reg) = &addr_syl {
-        return Err(AssemblerErrorKind::RegisterAddressingInvalid(mnemo));
-    }
-    if let AddressSyllable::ImmediateAddressing(_val) = &addr_syl {
-        return Err(AssemblerErrorKind::ImmediateAddressingInvalid(mnemo));
-    }
-
-    Ok(Statement::DoubleOperand(
-        op,
-        Register::Base(reg),
-        addr_syl,
-        None,
-    ))
-}
-
 fn match_double_operand_opcode(mnemo: &Mnemonic) -> DoubleOperandOpCode {
     match mnemo {
         Mnemonic::LDR => DoubleOperandOpCode::LDR,
@@ -1005,21 +895,8 @@ fn match_double_operand_opcode(mnemo: &Mnemonic) -> DoubleOperandOpCode {
     }
 }
 
-fn encapsulate_shift_short_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
-    }
-
-    // Get op code
-    let op = match mnemo {
+fn match_shift_short_opcode(mnemo: &Mnemonic) -> ShiftShortOpCode {
+    match mnemo {
         Mnemonic::SOL => ShiftShortOpCode::SOL,
         Mnemonic::SCL => ShiftShortOpCode::SCL,
         Mnemonic::SAL => ShiftShortOpCode::SAL,
@@ -1029,134 +906,25 @@ fn encapsulate_shift_short_statement(
         Mnemonic::SAR => ShiftShortOpCode::SAR,
         Mnemonic::DCR => ShiftShortOpCode::DCR,
         _ => panic!("invalid OpCode for ShiftShort"),
-    };
-
-    // Parse data register
-    let reg = parse_data_register_arg(&args[0])?;
-
-    // Parse shift value
-    let val = parse_shift_value_arg(&args[1])?;
-
-    Ok(Statement::ShiftShort(op, reg, val))
-}
-
-fn encapsulate_shift_long_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
     }
+}
 
-    // Get op code
-    let op = match mnemo {
+fn match_shift_long_opcode(mnemo: &Mnemonic) -> ShiftLongOpCode {
+    match mnemo {
         Mnemonic::DOL => ShiftLongOpCode::DOL,
         Mnemonic::DAL => ShiftLongOpCode::DAL,
         Mnemonic::DOR => ShiftLongOpCode::DOR,
         Mnemonic::DAR => ShiftLongOpCode::DAR,
         _ => panic!("invalid OpCode for ShiftLong"),
-    };
-
-    // Parse data register
-    let reg = parse_data_register_arg(&args[0])?;
-
-    // Parse shift value
-    let val = parse_shift_value_arg(&args[1])?;
-
-    Ok(Statement::ShiftLong(op, reg, val))
-}
-
-fn encapsulate_input_output_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 2 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            2,
-            args.len(),
-        ));
     }
+}
 
-    // Get op code
-    let op = match mnemo {
+fn match_input_output_opcode(mnemo: &Mnemonic) -> InputOutputOpCode {
+    match mnemo {
         Mnemonic::IO => InputOutputOpCode::IO,
         Mnemonic::IOH => InputOutputOpCode::IOH,
         _ => panic!("invalid OpCode for InputOutput"),
-    };
-
-    // Parse address syllable
-    let data_addr_syl = parse_address_syllable_arg(&args[0])?;
-
-    // Parse channel expression
-    let chan_expr = parse_channel_expression_arg(&args[1])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &data_addr_syl {
-        if let Register::Base(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
-    }
-
-    Ok(Statement::InputOutput(op, data_addr_syl, chan_expr))
-}
-
-fn encapsulate_input_output_load_statement(
-    mnemo: Mnemonic,
-    args: &[String],
-) -> Result<Statement, AssemblerErrorKind> {
-    // Check number of arguments
-    if args.len() != 3 {
-        return Err(AssemblerErrorKind::WrongNumberOfArguments(
-            mnemo,
-            3,
-            args.len(),
-        ));
-    }
-
-    // Parse data address syllable
-    let buffer_addr_syl = parse_address_syllable_arg(&args[0])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &buffer_addr_syl {
-        if let Register::Base(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
     }
-
-    // Parse channel expression
-    let chan_expr = parse_channel_expression_arg(&args[1])?;
-
-    // Parse data address syllable
-    let range_addr_syl = parse_address_syllable_arg(&args[2])?;
-
-    // Check Register Addressing
-    if let AddressSyllable::RegisterAddressing(reg) = &range_addr_syl {
-        if let Register::Data(_) = reg {
-            return Err(AssemblerErrorKind::WrongRegisterType(
-                args[0].clone(),
-                mnemo,
-            ));
-        }
-    }
-
-    Ok(Statement::InputOutputLoad(
-        buffer_addr_syl,
-        chan_expr,
-        range_addr_syl,
-    ))
 }
 
 fn parse_hex_address_arg(input: &str) -> Result<u64, AssemblerErrorKind> {
@@ -1305,6 +1073,148 @@ fn parse_shift_value_arg(input: &str) -> Result<u64, AssemblerErrorKind> {
     Ok(value)
 }
 
+fn parse_bank_number_arg(input: &str) -> Result<u64, AssemblerErrorKind> {
+    // Parse bank number
+    let (input, value) = match parse_dec_u64(input) {
+        Ok(bank) => bank,
+        Err(_) => return Err(AssemblerErrorKind::InvalidBankNumber(input.to_owned())),
+    };
+
+    // Check for extra characters
+    if !input.is_empty() {
+        return Err(AssemblerErrorKind::UnexpectedCharactersAtEndOfArgument(
+            input.to_owned(),
+        ));
+    }
+
+    Ok(value)
+}
+
+fn parse_label_arg(input: &str) -> Result<String, AssemblerErrorKind> {
+    // Parse label
+    let (input, label) = match parse_label_identifier(input) {
+        Ok(label) => label,
+        Err(_) => return Err(AssemblerErrorKind::InvalidLabel(input.to_owned())),
+    };
+
+    // Check for extra characters
+    if !input.is_empty() {
+        return Err(AssemblerErrorKind::UnexpectedCharactersAtEndOfArgument(
+            input.to_owned(),
+        ));
+    }
+
+    Ok(label.to_uppercase())
+}
+
+// Label, optionally followed by a constant added to or subtracted from it, or a constant alone
+fn parse_symbol_expression_arg(input: &str) -> Result<(Option<String>, i128), AssemblerErrorKind> {
+    if let Ok(value) = parse_constant_expression_arg(input) {
+        return Ok((None, value));
+    }
+
+    // Parse label
+    let (rest, label) = match parse_label_identifier(input.trim()) {
+        Ok(res) => res,
+        Err(_) => {
+            return Err(AssemblerErrorKind::InvalidSymbolExpression(
+                input.to_owned(),
+            ))
+        }
+    };
+    let rest = rest.trim_start();
+    if rest.is_empty() {
+        return Ok((Some(label.to_uppercase()), 0));
+    }
+
+    // Parse offset
+    let (sign, offset) = match (rest.strip_prefix('+'), rest.strip_prefix('-')) {
+        (Some(offset), _) => (1, offset),
+        (_, Some(offset)) => (-1, offset),
+        _ => {
+            return Err(AssemblerErrorKind::UnexpectedCharactersAtEndOfArgument(
+                rest.to_owned(),
+            ))
+        }
+    };
+    match parse_constant_expression_arg(offset) {
+        Ok(offset) => Ok((Some(label.to_uppercase()), sign * offset)),
+        Err(_) => Err(AssemblerErrorKind::InvalidSymbolExpression(
+            input.to_owned(),
+        )),
+    }
+}
+
+fn parse_constant_expression_arg(input: &str) -> Result<i128, AssemblerErrorKind> {
+    // Parse expression
+    let (rest, value) = match parse_constant_expression(input.trim()) {
+        Ok(res) => res,
+        Err(_) => {
+            return Err(AssemblerErrorKind::InvalidConstantExpression(
+                input.to_owned(),
+            ))
+        }
+    };
+
+    // Check for extra characters
+    if !rest.is_empty() {
+        return Err(AssemblerErrorKind::UnexpectedCharactersAtEndOfArgument(
+            rest.to_owned(),
+        ));
+    }
+
+    match value {
+        Some(value) => Ok(value),
+        None => Err(AssemblerErrorKind::InvalidConstantExpression(
+            input.to_owned(),
+        )),
+    }
+}
+
+/// Evaluates sums and products of values, None if the input isn't one or overflows
+pub fn evaluate_constant_expression(input: &str) -> Option<i128> {
+    parse_constant_expression_arg(input).ok()
+}
+
+/// Parses sums and products of values, evaluating them. Overflow results in None
+fn parse_constant_expression(input: &str) -> IResult<&str, Option<i128>> {
+    let (input, first) = parse_constant_term(input)?;
+
+    fold_many0(
+        tuple((
+            delimited(space0, alt((tag("+"), tag("-"))), space0),
+            parse_constant_term,
+        )),
+        move || first,
+        |acc, (op, val)| match (acc, val) {
+            (Some(acc), Some(val)) if op == "+" => acc.checked_add(val),
+            (Some(acc), Some(val)) => acc.checked_sub(val),
+            _ => None,
+        },
+    )(input)
+}
+
+fn parse_constant_term(input: &str) -> IResult<&str, Option<i128>> {
+    let (input, first) = parse_constant_factor(input)?;
+
+    fold_many0(
+        preceded(delimited(space0, tag("*"), space0), parse_constant_factor),
+        move || first,
+        |acc, val| acc?.checked_mul(val?),
+    )(input)
+}
+
+fn parse_constant_factor(input: &str) -> IResult<&str, Option<i128>> {
+    alt((
+        map(parse_immediate_value_contents, Some),
+        delimited(
+            terminated(tag("("), space0),
+            parse_constant_expression,
+            preceded(space0, tag(")")),
+        ),
+    ))(input)
+}
+
 fn parse_maskword_arg(input: &str) -> Result<i128, AssemblerErrorKind> {
     // Parse address
     let (input, value) = match parse_immediate_value_contents(input) {
@@ -1323,29 +1233,82 @@ fn parse_maskword_arg(input: &str) -> Result<i128, AssemblerErrorKind> {
 }
 
 // Chunk of a Data Definition statement
-fn parse_definition_chunk_arg(input: &str) -> Result<Vec<i128>, AssemblerErrorKind> {
+fn parse_definition_chunk_arg(input: &str) -> Result<Vec<DataValue>, AssemblerErrorKind> {
     // Parse address
-    let (input, value) = match alt((
+    let (rest, value) = match alt((
         map(parse_immediate_value_contents, |imm| vec![imm]),
         parse_string_to_i128s,
     ))(input)
     {
         Ok(address) => address,
-        Err(_) => {
-            return Err(AssemblerErrorKind::InvalidDataDefinitionChunk(
-                input.to_owned(),
-            ))
-        }
+        Err(_) => match parse_label_expression_arg(input) {
+            Ok(value) => return Ok(vec![value]),
+            Err(_) => {
+                return Err(AssemblerErrorKind::InvalidDataDefinitionChunk(
+                    input.to_owned(),
+                ))
+            }
+        },
     };
 
-    // Check for extra characters
-    if input.len() > 0 {
+    // Check for extra characters, unless the value starts a label expression
+    if !rest.is_empty() {
+        return match parse_label_expression_arg(input) {
+            Ok(value) => Ok(vec![value]),
+            Err(_) => Err(AssemblerErrorKind::UnexpectedCharactersAtEndOfArgument(
+                rest.to_owned(),
+            )),
+        };
+    }
+
+    Ok(value.into_iter().map(DataValue::Constant).collect())
+}
+
+// Sum of labels and constants, e.g. HANDLER_3 - HANDLER_BASE. Products only apply to constants
+fn parse_label_expression_arg(input: &str) -> Result<DataValue, AssemblerErrorKind> {
+    let invalid = || AssemblerErrorKind::InvalidSymbolExpression(input.to_owned());
+
+    let term = |input| -> IResult<&str, (Option<String>, Option<i128>)> {
+        alt((
+            map(parse_constant_term, |value| (None, value)),
+            map(parse_label_identifier, |label| (Some(label), Some(0))),
+        ))(input)
+    };
+    let (rest, (first, terms)) = match tuple((
+        term,
+        many0(tuple((
+            delimited(space0, alt((tag("+"), tag("-"))), space0),
+            term,
+        ))),
+    ))(input.trim())
+    {
+        Ok(res) => res,
+        Err(_) => return Err(invalid()),
+    };
+    if !rest.is_empty() {
         return Err(AssemblerErrorKind::UnexpectedCharactersAtEndOfArgument(
-            input.to_owned(),
+            rest.to_owned(),
         ));
     }
 
-    Ok(value)
+    let mut labels = vec![];
+    let mut offset: i128 = 0;
+    for (sign, (label, value)) in std::iter::once(("+", first)).chain(terms) {
+        let sign = if sign == "+" { 1 } else { -1 };
+        match label {
+            Some(label) => labels.push((sign, label.to_uppercase())),
+            None => {
+                offset = value
+                    .and_then(|value| offset.checked_add(sign * value))
+                    .ok_or_else(invalid)?
+            }
+        }
+    }
+
+    match labels.is_empty() {
+        true => Ok(DataValue::Constant(offset)),
+        false => Ok(DataValue::Labels(labels, offset)),
+    }
 }
 
 // Address syllable
@@ -1844,6 +1807,23 @@ mod tests {
         let tests = [
             // Org
             (".org 0x100", Statement::Org(0x100), ""),
+            // Bank
+            (".bank 2", Statement::Bank(2), ""),
+            (
+                ".TRAMPOLINE far_call",
+                Statement::Trampoline("FAR_CALL".to_owned()),
+                "",
+            ),
+            (
+                ".ASSERT_AT vector, 0x100",
+                Statement::AssertAt("VECTOR".to_owned(), 0x100),
+                "",
+            ),
+            (
+                ".VECTOR 2 * 4, timer",
+                Statement::Vector(8, "TIMER".to_owned()),
+                "",
+            ),
             // BranchOnIndicators
             (
                 "BL <0x1234",
@@ -1910,6 +1890,40 @@ mod tests {
                 "",
             ),
             // TODO finish testing BranchOnIndicators instructions
+            // Masked
+            (
+                "LBT $B3, 4",
+                Statement::SingleOperand(
+                    SingleOperandOpCode::LBT,
+                    AddressSyllable::BRelative(BRelativeAddressMode::Direct(
+                        BRelativeAddress::Simple(BaseRegister::B3),
+                    )),
+                    Some(4),
+                ),
+                "",
+            ),
+            (
+                "LBT $B3.$R1",
+                Statement::SingleOperand(
+                    SingleOperandOpCode::LBT,
+                    AddressSyllable::BRelative(BRelativeAddressMode::Direct(
+                        BRelativeAddress::Indexed(BaseRegister::B3, DataRegister::R1),
+                    )),
+                    None,
+                ),
+                "",
+            ),
+            (
+                "SAVE $B3.$R1",
+                Statement::SingleOperand(
+                    SingleOperandOpCode::SAVE,
+                    AddressSyllable::BRelative(BRelativeAddressMode::Direct(
+                        BRelativeAddress::Indexed(BaseRegister::B3, DataRegister::R1),
+                    )),
+                    Some(0),
+                ),
+                "",
+            ),
         ];
         for (input, exp_output, exp_remaining) in tests {
             let (remaining, output) = parse_statement(input).unwrap();
@@ -1918,6 +1932,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn encapsulate_statement_fail() {
+        let tests = [
+            (
+                "LDR $R1",
+                AssemblerErrorKind::WrongNumberOfArguments(Mnemonic::LDR, 2, Some(2), 1),
+            ),
+            (
+                "SRM $R1, <0x100, 1, 2",
+                AssemblerErrorKind::WrongNumberOfArguments(Mnemonic::SRM, 2, Some(3), 4),
+            ),
+            (
+                ".DW",
+                AssemblerErrorKind::WrongNumberOfArguments(Mnemonic::DotDW, 1, None, 0),
+            ),
+            (
+                "LDR $R1, =$B2",
+                AssemblerErrorKind::WrongRegisterType("=$B2".to_owned(), Mnemonic::LDR),
+            ),
+            (
+                "JMP =$R1",
+                AssemblerErrorKind::RegisterAddressingInvalid(Mnemonic::JMP),
+            ),
+            (
+                "LNJ $B1, <0x10",
+                AssemblerErrorKind::ImmediateAddressingInvalid(Mnemonic::LNJ),
+            ),
+            (
+                "LBS $B3.$R1, 4",
+                AssemblerErrorKind::MaskWithIndexedAddressing(Mnemonic::LBS),
+            ),
+            (
+                "STR $R1, =123",
+                AssemblerErrorKind::ImmediateOperandWrite(Mnemonic::STR, "=123".to_owned()),
+            ),
+            (
+                "LDV $R1, =256",
+                AssemblerErrorKind::ShortImmediateValueOutOfRange(256, -128, 255),
+            ),
+        ];
+        for (input, exp_kind) in tests {
+            match parse_statement(input) {
+                Err(Err::Failure(err)) => {
+                    assert_eq!(format!("{:?}", err.kind), format!("{:?}", exp_kind))
+                }
+                res => panic!("{}: {:?}", input, res),
+            }
+        }
+    }
+
     #[test]
     fn parse_branch_location_arg_succ() {
         let tests = [
@@ -1961,6 +2025,10 @@ mod tests {
         for (input, exp_output) in tests {
             let output = parse_branch_location_arg(input).unwrap();
             assert_eq!(output, exp_output);
+            assert_eq!(
+                parse_branch_location_arg(&output.to_string()).unwrap(),
+                output
+            );
         }
     }
 
@@ -1997,6 +2065,64 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_constant_expression_arg_succ() {
+        let tests = [
+            ("10", 10),
+            ("0x10 + 0b11", 0x13),
+            ("2 * 3 + 4", 10),
+            ("2 * (3 + 4)", 14),
+            ("10 - -2 - 1", 11),
+            ("( 1+2 )*3", 9),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(
+                parse_constant_expression_arg(input).unwrap(),
+                exp,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parse_symbol_expression_arg_succ() {
+        let tests = [
+            ("0x100", (None, 0x100)),
+            ("2 * 3 + 1", (None, 7)),
+            ("start", (Some("START".to_owned()), 0)),
+            ("BUFFER + 4", (Some("BUFFER".to_owned()), 4)),
+            ("BUFFER-(2*3)", (Some("BUFFER".to_owned()), -6)),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(
+                parse_symbol_expression_arg(input).unwrap(),
+                exp,
+                "{}",
+                input
+            );
+        }
+
+        for input in ["", "BUFFER +", "BUFFER * 2", "BUFFER + OTHER", "$R1"] {
+            parse_symbol_expression_arg(input).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn parse_constant_expression_arg_err() {
+        let tests = [
+            "",
+            "label",
+            "1 +",
+            "(1 + 2",
+            "1 2",
+            "0x7FFFFFFFFFFFFFFF * 0x7FFFFFFFFFFFFFFF * 4",
+        ];
+        for input in tests {
+            parse_constant_expression_arg(input).unwrap_err();
+        }
+    }
+
     #[test]
     fn parse_immediate_value_succ() {
         let tests = [("=10", "", 10), ("=0x1234notnumber", "notnumber", 0x1234)];
@@ -2100,8 +2226,34 @@ mod tests {
         ];
         for (input, exp_output) in tests {
             let output = parse_definition_chunk_arg(input).unwrap();
+            let exp_output: Vec<DataValue> =
+                exp_output.into_iter().map(DataValue::Constant).collect();
             assert_eq!(output, exp_output);
         }
+
+        // Label expressions
+        let labels = |labels: &[(i128, &str)], offset: i128| {
+            let labels = labels
+                .iter()
+                .map(|(sign, label)| (*sign, label.to_string()))
+                .collect();
+            vec![DataValue::Labels(labels, offset)]
+        };
+        let tests = [
+            ("handler", labels(&[(1, "HANDLER")], 0)),
+            (
+                "HANDLER_3 - HANDLER_BASE",
+                labels(&[(1, "HANDLER_3"), (-1, "HANDLER_BASE")], 0),
+            ),
+            ("2 * 4 + TABLE - 1", labels(&[(1, "TABLE")], 7)),
+        ];
+        for (input, exp_output) in tests {
+            assert_eq!(parse_definition_chunk_arg(input).unwrap(), exp_output);
+        }
+
+        for input in ["HANDLER *", "HANDLER * 2", "$R1", "A - "] {
+            parse_definition_chunk_arg(input).unwrap_err();
+        }
     }
 
     #[test]
@@ -2225,6 +2377,7 @@ mod tests {
         for (input, exp_output) in tests {
             let output = parse_address_syllable_arg(input).unwrap();
             assert_eq!(output, exp_output);
+            assert_eq!(output.to_string(), input);
         }
     }
 
@@ -2249,6 +2402,10 @@ mod tests {
         for (input, exp_output) in tests {
             let output = parse_channel_expression_arg(input).unwrap();
             assert_eq!(output, exp_output);
+            assert_eq!(
+                parse_channel_expression_arg(&output.to_string()).unwrap(),
+                output
+            );
         }
     }
 }
@@ -0,0 +1,124 @@
+use super::assemble::AbstractBinaryLine;
+use super::statements::{AddressSyllable, BranchLocation, ChannelExpression, DataValue, Statement};
+use crate::logging::{AssemblerError, AssemblerErrorKind};
+
+/// Reports operands addressing memory absolutely, which break when the code is loaded at
+/// another address. Position independent code only uses P-relative and B-relative addressing
+pub fn find_absolute_addressing(lines: &[AbstractBinaryLine]) -> Vec<AssemblerError> {
+    let mut errors = vec![];
+    for line in lines {
+        for operand in get_absolute_operands(&line.statement) {
+            errors.push(AssemblerError {
+                kind: AssemblerErrorKind::AbsoluteAddressingInPic(operand),
+                location: Some(line.location.clone()),
+            });
+        }
+    }
+
+    errors
+}
+
+// Operands of a statement using absolute branch locations or immediate addresses
+fn get_absolute_operands(statement: &Statement) -> Vec<String> {
+    let addr_syls: Vec<&AddressSyllable> = match statement {
+        Statement::BranchOnIndicators(_, branchloc)
+        | Statement::BranchOnRegisters(_, _, branchloc) => {
+            return match branchloc {
+                BranchLocation::Absolute(_) => vec![branchloc.to_string()],
+                _ => vec![],
+            };
+        }
+        // Differences of labels don't depend on the load address, single labels do
+        Statement::DataDefinition(_, values) => {
+            return values
+                .iter()
+                .filter(|value| match value {
+                    DataValue::Labels(labels, _) => {
+                        labels.iter().map(|(sign, _)| sign).sum::<i128>() != 0
+                    }
+                    DataValue::Constant(_) => false,
+                })
+                .map(|value| value.to_string())
+                .collect();
+        }
+        Statement::SingleOperand(_, addr_syl, _) | Statement::DoubleOperand(_, _, addr_syl, _) => {
+            vec![addr_syl]
+        }
+        Statement::InputOutput(_, addr_syl, chan_expr) => match chan_expr {
+            ChannelExpression::AddressSyllable(chan_addr_syl) => vec![addr_syl, chan_addr_syl],
+            _ => vec![addr_syl],
+        },
+        Statement::InputOutputLoad(buffer_addr_syl, chan_expr, range_addr_syl) => match chan_expr {
+            ChannelExpression::AddressSyllable(chan_addr_syl) => {
+                vec![buffer_addr_syl, chan_addr_syl, range_addr_syl]
+            }
+            _ => vec![buffer_addr_syl, range_addr_syl],
+        },
+        _ => vec![],
+    };
+
+    addr_syls
+        .into_iter()
+        .filter(|addr_syl| matches!(addr_syl, AddressSyllable::ImmediateAddressing(_)))
+        .map(|addr_syl| addr_syl.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::statements::{
+        AddressExpression, BranchOnIndicatorsOpCode, DataDefinitionSize, DataRegister,
+        DoubleOperandOpCode, ImmediateAddress, ImmediateAddressMode, PRelativeAddress, Register,
+    };
+
+    #[test]
+    fn find_absolute_addressing_succ() {
+        let label = || AddressExpression::Label("BUF".to_owned());
+        let statements = [
+            Statement::BranchOnIndicators(
+                BranchOnIndicatorsOpCode::B,
+                BranchLocation::Absolute(label()),
+            ),
+            Statement::BranchOnIndicators(
+                BranchOnIndicatorsOpCode::B,
+                BranchLocation::LongDisplacement(label()),
+            ),
+            Statement::DoubleOperand(
+                DoubleOperandOpCode::LDR,
+                Register::Data(DataRegister::R1),
+                AddressSyllable::ImmediateAddressing(ImmediateAddressMode::Direct(
+                    ImmediateAddress::Simple(label()),
+                )),
+                None,
+            ),
+            Statement::DoubleOperand(
+                DoubleOperandOpCode::LDR,
+                Register::Data(DataRegister::R1),
+                AddressSyllable::PRelative(PRelativeAddress::Direct(label())),
+                None,
+            ),
+            Statement::DataDefinition(
+                DataDefinitionSize::Word,
+                vec![
+                    DataValue::Labels(vec![(1, "BUF".to_owned()), (-1, "BASE".to_owned())], 0),
+                    DataValue::Labels(vec![(1, "BUF".to_owned())], 2),
+                ],
+            ),
+        ];
+        let lines: Vec<AbstractBinaryLine> = statements
+            .into_iter()
+            .map(|statement| AbstractBinaryLine::new_test(0, None, statement, 0))
+            .collect();
+
+        let operands: Vec<String> = find_absolute_addressing(&lines)
+            .into_iter()
+            .map(|error| match error.kind {
+                AssemblerErrorKind::AbsoluteAddressingInPic(operand) => operand,
+                _ => panic!(),
+            })
+            .collect();
+
+        assert_eq!(operands, vec!["<BUF", "<BUF", "BUF + 2"]);
+    }
+}
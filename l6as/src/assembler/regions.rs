@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use super::assemble::AbstractBinaryLine;
+use super::codegen::resolve_address_expression;
+use super::statements::{
+    AddressExpression, AddressSyllable, ChannelExpression, PRelativeAddress, Statement,
+};
+use crate::logging::{AssemblerWarning, AssemblerWarningKind};
+
+// Contiguous range of memory filled by statements, started by .ORG or .BANK
+struct Region {
+    bank: Option<u64>,
+    start: u64,
+    end: u64,
+}
+
+impl Region {
+    // Labels right after the last statement still belong to the region
+    fn contains(&self, bank: Option<u64>, address: u64) -> bool {
+        self.bank == bank && (self.start..=self.end).contains(&address)
+    }
+}
+
+/// Reports P-relative operands referring to another .ORG region of the program. Their
+/// displacement only holds while both regions are loaded at their assembled addresses
+pub fn find_cross_region_p_relative(
+    label_table: &HashMap<String, u64>,
+    lines: &[AbstractBinaryLine],
+) -> Vec<AssemblerWarning> {
+    // Region of each statement
+    let mut regions: Vec<Region> = vec![];
+    let mut line_regions: Vec<usize> = vec![];
+    for line in lines {
+        match regions.last_mut() {
+            Some(region) if region.bank == line.bank && region.end == line.address => {
+                region.end += line.size;
+            }
+            _ => regions.push(Region {
+                bank: line.bank,
+                start: line.address,
+                end: line.address + line.size,
+            }),
+        }
+        line_regions.push(regions.len() - 1);
+    }
+
+    let mut warnings = vec![];
+    for (line, region) in lines.iter().zip(line_regions) {
+        for addr_exp in get_p_relative_expressions(&line.statement) {
+            let target = match resolve_address_expression(addr_exp, line.address, label_table) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+
+            // Targets outside of the program, e.g. from symbol maps, are placed by the user
+            if !regions[region].contains(line.bank, target)
+                && regions
+                    .iter()
+                    .any(|other| other.contains(line.bank, target))
+            {
+                warnings.push(AssemblerWarning {
+                    kind: AssemblerWarningKind::PRelativeAcrossRegions(target),
+                    location: Some(line.location.clone()),
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+// Address expressions of the P-relative operands of a statement
+fn get_p_relative_expressions(statement: &Statement) -> Vec<&AddressExpression> {
+    let addr_syls: Vec<&AddressSyllable> = match statement {
+        Statement::SingleOperand(_, addr_syl, _) | Statement::DoubleOperand(_, _, addr_syl, _) => {
+            vec![addr_syl]
+        }
+        Statement::InputOutput(_, addr_syl, chan_expr) => match chan_expr {
+            ChannelExpression::AddressSyllable(chan_addr_syl) => vec![addr_syl, chan_addr_syl],
+            _ => vec![addr_syl],
+        },
+        Statement::InputOutputLoad(buffer_addr_syl, chan_expr, range_addr_syl) => match chan_expr {
+            ChannelExpression::AddressSyllable(chan_addr_syl) => {
+                vec![buffer_addr_syl, chan_addr_syl, range_addr_syl]
+            }
+            _ => vec![buffer_addr_syl, range_addr_syl],
+        },
+        _ => vec![],
+    };
+
+    addr_syls
+        .into_iter()
+        .filter_map(|addr_syl| match addr_syl {
+            AddressSyllable::PRelative(
+                PRelativeAddress::Direct(addr_exp) | PRelativeAddress::Indirect(addr_exp),
+            ) => Some(addr_exp),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::statements::{
+        DataDefinitionSize, DataRegister, DataValue, DoubleOperandOpCode, Register,
+    };
+
+    fn load(label: &str) -> Statement {
+        Statement::DoubleOperand(
+            DoubleOperandOpCode::LDR,
+            Register::Data(DataRegister::R1),
+            AddressSyllable::PRelative(PRelativeAddress::Direct(AddressExpression::Label(
+                label.to_owned(),
+            ))),
+            None,
+        )
+    }
+
+    #[test]
+    fn find_cross_region_p_relative_succ() {
+        let label_table = HashMap::from([
+            ("NEAR".to_owned(), 0x104),
+            ("END".to_owned(), 0x106),
+            ("FAR".to_owned(), 0x200),
+            ("EXTERNAL".to_owned(), 0x8000),
+        ]);
+        let data = Statement::DataDefinition(
+            DataDefinitionSize::Word,
+            vec![DataValue::Constant(0), DataValue::Constant(0)],
+        );
+        let statements = [
+            (0x100, 2, load("NEAR")),
+            (0x102, 2, load("END")),
+            (0x104, 2, load("FAR")),
+            (0x106, 0, load("EXTERNAL")),
+            (0x200, 2, data),
+        ];
+        let lines: Vec<AbstractBinaryLine> = statements
+            .into_iter()
+            .map(|(address, size, statement)| {
+                AbstractBinaryLine::new_test(address, None, statement, size)
+            })
+            .collect();
+
+        let targets: Vec<u64> = find_cross_region_p_relative(&label_table, &lines)
+            .into_iter()
+            .map(|warning| match warning.kind {
+                AssemblerWarningKind::PRelativeAcrossRegions(target) => target,
+                _ => panic!(),
+            })
+            .collect();
+
+        assert_eq!(targets, vec![0x200]);
+    }
+}
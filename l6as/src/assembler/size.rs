@@ -1,15 +1,28 @@
 use super::statements::{
-    AddressSyllable, BranchLocation, ChannelExpression, DataDefinitionSize, Statement,
+    AddressSyllable, BRelativeAddress, BRelativeAddressMode, BranchLocation, ChannelExpression,
+    DataDefinitionSize, DataValue, Statement,
 };
 
 /// Computes the size of a statement in memory (in words)
 pub fn statement_size(statement: &Statement, _cur_addr: u64) -> u64 {
     // Compute different size depending on the kind of statement
     match statement {
-        Statement::Org(_) => 0,
+        Statement::Org(_)
+        | Statement::Bank(_)
+        | Statement::Trampoline(_)
+        | Statement::Limit(_)
+        | Statement::IoDelay(_)
+        | Statement::AssertAt(_, _)
+        | Statement::Equ(_, _)
+        | Statement::Weak
+        | Statement::IfCpu(_)
+        | Statement::Else
+        | Statement::EndIf => 0,
         Statement::DataDefinition(size, chunks) => data_definition_dir_size(size, chunks),
+        Statement::Fill(size, count, _value) => fill_dir_size(size, *count),
         Statement::BranchOnIndicators(_op, branchloc) => branch_inst_size(branchloc),
         Statement::BranchOnRegisters(_op, _reg, branchloc) => branch_inst_size(branchloc),
+        Statement::Vector(_, _) => 1,
         Statement::ShortValueImmediate(_op, _reg, _value) => 1,
         Statement::SingleOperand(_op, addr_syl, mask) => single_operand_inst_size(addr_syl, mask),
         Statement::Generic(_op) => 1,
@@ -24,11 +37,12 @@ pub fn statement_size(statement: &Statement, _cur_addr: u64) -> u64 {
         Statement::InputOutputLoad(buffer_addr_syl, chan_expr, range_addr_syl) => {
             input_output_load_inst_size(buffer_addr_syl, chan_expr, range_addr_syl)
         }
+        Statement::Custom(_name, words) => words.len() as u64,
     }
 }
 
 /// Computes words used by a data definition directive
-pub fn data_definition_dir_size(size: &DataDefinitionSize, chunks: &Vec<i128>) -> u64 {
+pub fn data_definition_dir_size(size: &DataDefinitionSize, chunks: &[DataValue]) -> u64 {
     let n_chunks = chunks.len() as u64;
     match size {
         DataDefinitionSize::Byte => (n_chunks + 1) / 2,
@@ -38,6 +52,16 @@ pub fn data_definition_dir_size(size: &DataDefinitionSize, chunks: &Vec<i128>) -
     }
 }
 
+/// Computes words used by a fill directive
+pub fn fill_dir_size(size: &DataDefinitionSize, count: u64) -> u64 {
+    match size {
+        DataDefinitionSize::Byte => count.div_ceil(2),
+        DataDefinitionSize::Word => count,
+        DataDefinitionSize::DoubleWord => count * 2,
+        DataDefinitionSize::QuadWord => count * 4,
+    }
+}
+
 /// Computes the size of a Single Operand instruction
 pub fn single_operand_inst_size(addr_syl: &AddressSyllable, mask: &Option<i128>) -> u64 {
     1 + address_syl_extra_words(addr_syl) + mask_extra_words(mask)
@@ -87,7 +111,11 @@ pub fn address_syl_extra_words(address_syllable: &AddressSyllable) -> u64 {
         AddressSyllable::RegisterAddressing(_) => 0,
         AddressSyllable::ImmediateAddressing(_) => 1,
         AddressSyllable::ImmediateOperand(_) => 1,
-        AddressSyllable::BRelative(_) => 0,
+        AddressSyllable::BRelative(mode) => match mode {
+            BRelativeAddressMode::Direct(BRelativeAddress::Displacement(..))
+            | BRelativeAddressMode::Indirect(BRelativeAddress::Displacement(..)) => 1,
+            _ => 0,
+        },
         AddressSyllable::PRelative(_) => 1,
     }
 }
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+
+use super::assemble::{AbstractBinaryLine, LabelDefinition};
+use super::flow::{find_routine_entries, get_code_addresses, get_flow, next_index, Flow};
+use super::statements::{
+    AddressSyllable, BRelativeAddressMode, ChannelExpression, IncDec, SingleOperandOpCode,
+    Statement,
+};
+use crate::preprocessor::LineLocation;
+
+/// Words of a frame pushed by ENT, which has no mask: one for each register a mask can select
+const FULL_FRAME_WORDS: u64 = 16;
+
+/// Estimated worst case stack usage of a routine, in words
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutineStackUsage {
+    pub name: String,
+    pub address: u64,
+    /// Deepest the stack gets while the routine runs, including the routines it calls
+    pub max_depth: u64,
+    /// The routine, or one it calls, can call itself, so the depth has no bound
+    pub recursive: bool,
+    /// The stack grows on every iteration of a loop of the routine, or of one it calls
+    pub unbounded: bool,
+    /// Branches and calls of the routine whose target isn't known when assembling
+    pub indirect_branches: Vec<LineLocation>,
+}
+
+// Change of the stack made by a statement
+enum StackEffect {
+    Words(i64),
+    PushFrame(u64),
+    PopFrame,
+}
+
+struct Analyzer<'a> {
+    lines: &'a [AbstractBinaryLine],
+    label_table: &'a HashMap<String, u64>,
+    // Index of the statement placed at each address
+    code_addresses: HashMap<u64, usize>,
+    results: HashMap<u64, RoutineStackUsage>,
+    call_stack: Vec<u64>,
+}
+
+/// Estimates the stack usage of the routines of a program, following the control flow of each
+/// routine from its entry to its returns
+pub fn find_stack_usage(
+    label_table: &HashMap<String, u64>,
+    label_definitions: &[LabelDefinition],
+    lines: &[AbstractBinaryLine],
+) -> Vec<RoutineStackUsage> {
+    let code_addresses = get_code_addresses(lines);
+    let entries = find_routine_entries(label_table, &code_addresses, lines);
+
+    let mut analyzer = Analyzer {
+        lines,
+        label_table,
+        code_addresses,
+        results: HashMap::new(),
+        call_stack: vec![],
+    };
+
+    entries
+        .into_iter()
+        .map(|address| {
+            let mut usage = analyzer.analyze_routine(address);
+            if let Some(definition) = label_definitions
+                .iter()
+                .find(|definition| definition.address == address)
+            {
+                usage.name = definition.label.clone();
+            }
+            usage
+        })
+        .collect()
+}
+
+impl Analyzer<'_> {
+    fn analyze_routine(&mut self, address: u64) -> RoutineStackUsage {
+        if let Some(usage) = self.results.get(&address) {
+            return usage.clone();
+        }
+
+        let mut usage = RoutineStackUsage {
+            name: format!("{:#06X}", address),
+            address,
+            max_depth: 0,
+            recursive: false,
+            unbounded: false,
+            indirect_branches: vec![],
+        };
+
+        // A routine calling itself, through any number of others
+        if self.call_stack.contains(&address) {
+            usage.recursive = true;
+            return usage;
+        }
+
+        self.call_stack.push(address);
+
+        // Paths still to follow: statement, stack depth and frames pushed
+        let mut pending: Vec<(usize, u64, Vec<u64>)> =
+            vec![(self.code_addresses[&address], 0, vec![])];
+        // Depth the stack had the first time each statement was reached
+        let mut visited: HashMap<usize, u64> = HashMap::new();
+
+        while let Some((mut index, mut depth, mut frames)) = pending.pop() {
+            loop {
+                let line = &self.lines[index];
+
+                // Reaching a statement again with a deeper stack means it grows in a loop
+                if let Some(first_depth) = visited.get(&index) {
+                    usage.unbounded |= depth > *first_depth;
+                    break;
+                }
+                visited.insert(index, depth);
+
+                match get_stack_effect(&line.statement) {
+                    StackEffect::Words(words) => depth = depth.saturating_add_signed(words),
+                    StackEffect::PushFrame(words) => {
+                        depth += words;
+                        frames.push(words);
+                    }
+                    StackEffect::PopFrame => {
+                        depth = depth.saturating_sub(frames.pop().unwrap_or(0));
+                    }
+                }
+                usage.max_depth = usage.max_depth.max(depth);
+
+                let next = match get_flow(line, self.label_table) {
+                    Flow::Next => next_index(self.lines, index),
+                    Flow::Branch(target) => match self.code_index(target) {
+                        Some(target) => Some(target),
+                        None => {
+                            usage.indirect_branches.push(line.location.clone());
+                            None
+                        }
+                    },
+                    Flow::ConditionalBranch(target) => {
+                        match self.code_index(target) {
+                            Some(target) => pending.push((target, depth, frames.clone())),
+                            None => usage.indirect_branches.push(line.location.clone()),
+                        }
+                        next_index(self.lines, index)
+                    }
+                    Flow::Call(target) => {
+                        match target.filter(|target| self.code_addresses.contains_key(target)) {
+                            Some(target) => {
+                                let callee = self.analyze_routine(target);
+                                usage.max_depth = usage.max_depth.max(depth + callee.max_depth);
+                                usage.recursive |= callee.recursive;
+                                usage.unbounded |= callee.unbounded;
+                            }
+                            None => usage.indirect_branches.push(line.location.clone()),
+                        }
+                        next_index(self.lines, index)
+                    }
+                    Flow::Return | Flow::Stop => None,
+                };
+
+                match next {
+                    Some(next) => index = next,
+                    None => break,
+                }
+            }
+        }
+
+        self.call_stack.pop();
+
+        // Usage found while a caller is on the call stack may be missing its recursive calls
+        if !usage.recursive {
+            self.results.insert(address, usage.clone());
+        }
+        usage
+    }
+
+    fn code_index(&self, address: Option<u64>) -> Option<usize> {
+        self.code_addresses.get(&address?).copied()
+    }
+}
+
+fn get_stack_effect(statement: &Statement) -> StackEffect {
+    match statement {
+        Statement::SingleOperand(SingleOperandOpCode::SAVE | SingleOperandOpCode::ENT, _, mask) => {
+            StackEffect::PushFrame(match mask {
+                Some(mask) => (*mask as u16).count_ones() as u64,
+                None => FULL_FRAME_WORDS,
+            })
+        }
+        Statement::SingleOperand(SingleOperandOpCode::RSTR | SingleOperandOpCode::LEV, _, _) => {
+            StackEffect::PopFrame
+        }
+        Statement::SingleOperand(_, addr_syl, _) | Statement::DoubleOperand(_, _, addr_syl, _) => {
+            StackEffect::Words(get_push_pop_words(addr_syl))
+        }
+        Statement::InputOutput(_, addr_syl, chan_expr) => {
+            let mut words = get_push_pop_words(addr_syl);
+            if let ChannelExpression::AddressSyllable(addr_syl) = chan_expr {
+                words += get_push_pop_words(addr_syl);
+            }
+            StackEffect::Words(words)
+        }
+        _ => StackEffect::Words(0),
+    }
+}
+
+// Words pushed by a "-$Bn" operand, or popped by a "+$Bn" one
+fn get_push_pop_words(addr_syl: &AddressSyllable) -> i64 {
+    match addr_syl {
+        AddressSyllable::BRelative(BRelativeAddressMode::PushPop(_, IncDec::Decrement)) => 1,
+        AddressSyllable::BRelative(BRelativeAddressMode::PushPop(_, IncDec::Increment)) => -1,
+        _ => 0,
+    }
+}
+
+/// Generates a stack usage report, with one routine per line sorted by address
+pub fn generate_stack_report(usages: &[RoutineStackUsage]) -> String {
+    let mut output = String::new();
+    output.push_str("; Estimated worst case stack usage, in words\n");
+    output.push_str(&format!(
+        "; SAVE pushes one word per register of its mask, ENT {} words\n",
+        FULL_FRAME_WORDS
+    ));
+
+    for usage in usages {
+        let mut notes = vec![];
+        if usage.recursive {
+            notes.push("recursive".to_owned());
+        }
+        if usage.unbounded {
+            notes.push("grows in a loop".to_owned());
+        }
+        if !usage.indirect_branches.is_empty() {
+            notes.push(format!(
+                "indirect branches not followed: {}",
+                usage.indirect_branches.len()
+            ));
+        }
+
+        let mut line = format!(
+            "{:<16} {:#06X} {:>6}",
+            usage.name, usage.address, usage.max_depth
+        );
+        if !notes.is_empty() {
+            line.push_str(&format!("  ; {}", notes.join(", ")));
+        }
+        output.push_str(line.trim_end());
+        output.push('\n');
+
+        for location in &usage.indirect_branches {
+            output.push_str(&format!(
+                ";   {}:{}: {}\n",
+                location.file_name.display(),
+                location.line_n,
+                location.raw_content.lines().next().unwrap_or("").trim()
+            ));
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::statements::{
+        AddressExpression, BRelativeAddress, BaseRegister, BranchLocation,
+        BranchOnIndicatorsOpCode, DataDefinitionSize, DataRegister, DataValue, DoubleOperandOpCode,
+        GenericOpCode, Register,
+    };
+
+    fn push() -> Statement {
+        Statement::DoubleOperand(
+            DoubleOperandOpCode::STR,
+            Register::Data(DataRegister::R1),
+            AddressSyllable::BRelative(BRelativeAddressMode::PushPop(
+                BaseRegister::B7,
+                IncDec::Decrement,
+            )),
+            None,
+        )
+    }
+
+    fn pop() -> Statement {
+        Statement::DoubleOperand(
+            DoubleOperandOpCode::LDR,
+            Register::Data(DataRegister::R1),
+            AddressSyllable::BRelative(BRelativeAddressMode::PushPop(
+                BaseRegister::B7,
+                IncDec::Increment,
+            )),
+            None,
+        )
+    }
+
+    fn branch(opcode: BranchOnIndicatorsOpCode, disp: i128) -> Statement {
+        Statement::BranchOnIndicators(
+            opcode,
+            BranchLocation::ShortDisplacement(AddressExpression::WordDisplacement(disp)),
+        )
+    }
+
+    fn ret() -> Statement {
+        Statement::SingleOperand(
+            SingleOperandOpCode::JMP,
+            AddressSyllable::BRelative(BRelativeAddressMode::Direct(BRelativeAddress::Simple(
+                BaseRegister::B5,
+            ))),
+            None,
+        )
+    }
+
+    // Stack usage of a program made of one routine, with a statement per word
+    fn find_routine_usage(program: Vec<Statement>) -> RoutineStackUsage {
+        let lines: Vec<AbstractBinaryLine> = program
+            .into_iter()
+            .enumerate()
+            .map(|(address, statement)| {
+                AbstractBinaryLine::new_test(address as u64, None, statement, 1)
+            })
+            .collect();
+
+        let mut usages = find_stack_usage(&HashMap::new(), &[], &lines);
+        assert_eq!(usages.len(), 1);
+        usages.remove(0)
+    }
+
+    #[test]
+    fn find_stack_usage_unbalanced_branches() {
+        let usage = find_routine_usage(vec![
+            push(),
+            // Skips two pushes, joining the other path with a shallower stack
+            branch(BranchOnIndicatorsOpCode::BE, 3),
+            push(),
+            push(),
+            pop(),
+            ret(),
+        ]);
+        assert_eq!(usage.max_depth, 3);
+        assert!(!usage.unbounded);
+        assert!(!usage.recursive);
+        assert!(usage.indirect_branches.is_empty());
+
+        // Pushing on every iteration of a loop
+        let usage = find_routine_usage(vec![
+            push(),
+            branch(BranchOnIndicatorsOpCode::BNE, -1),
+            ret(),
+        ]);
+        assert!(usage.unbounded);
+
+        // Popping more than was pushed doesn't go below an empty stack
+        let usage = find_routine_usage(vec![pop(), pop(), push(), ret()]);
+        assert_eq!(usage.max_depth, 1);
+    }
+
+    #[test]
+    fn find_stack_usage_return() {
+        // Returning through the base register LNJ saved the link in
+        let usage = find_routine_usage(vec![push(), pop(), ret(), push(), push()]);
+        assert_eq!(usage.max_depth, 1);
+        assert!(usage.indirect_branches.is_empty());
+
+        // Unconditional branches are followed
+        let usage = find_routine_usage(vec![
+            branch(BranchOnIndicatorsOpCode::B, 3),
+            push(),
+            push(),
+            push(),
+            ret(),
+        ]);
+        assert_eq!(usage.max_depth, 1);
+    }
+
+    #[test]
+    fn find_stack_usage_stop() {
+        // Data following the code isn't executed
+        let usage = find_routine_usage(vec![
+            push(),
+            Statement::DataDefinition(DataDefinitionSize::Word, vec![DataValue::Constant(0)]),
+            push(),
+        ]);
+        assert_eq!(usage.max_depth, 1);
+
+        let usage =
+            find_routine_usage(vec![push(), Statement::Generic(GenericOpCode::HLT), push()]);
+        assert_eq!(usage.max_depth, 1);
+    }
+}
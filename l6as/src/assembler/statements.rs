@@ -1,5 +1,8 @@
 use core::fmt;
-#[derive(Debug, Clone, PartialEq)]
+use serde::Serialize;
+use strum::EnumIter;
+
+#[derive(Debug, Clone, PartialEq, EnumIter)]
 pub enum Mnemonic {
     // Assembler directives
     DotORG,
@@ -7,6 +10,22 @@ pub enum Mnemonic {
     DotDW,
     DotDD,
     DotDQ,
+    DotBANK,
+    DotTRAMPOLINE,
+    DotFILL,
+    DotFILLB,
+    DotSERIES,
+    DotSERIESB,
+    DotRANDOM,
+    DotLIMIT,
+    DotEQU,
+    DotWEAK,
+    DotIODELAY,
+    DotASSERTAT,
+    DotVECTOR,
+    DotIFCPU,
+    DotELSE,
+    DotENDIF,
 
     // Branch on Registers instructions
     BL,
@@ -157,6 +176,22 @@ impl Mnemonic {
             Self::DotDW => StatementKind::DataDefinition,
             Self::DotDD => StatementKind::DataDefinition,
             Self::DotDQ => StatementKind::DataDefinition,
+            Self::DotBANK => StatementKind::Bank,
+            Self::DotTRAMPOLINE => StatementKind::Trampoline,
+            Self::DotFILL => StatementKind::Fill,
+            Self::DotFILLB => StatementKind::Fill,
+            Self::DotSERIES => StatementKind::Series,
+            Self::DotSERIESB => StatementKind::Series,
+            Self::DotRANDOM => StatementKind::Random,
+            Self::DotLIMIT => StatementKind::Limit,
+            Self::DotEQU => StatementKind::Equ,
+            Self::DotWEAK => StatementKind::Weak,
+            Self::DotIODELAY => StatementKind::IoDelay,
+            Self::DotASSERTAT => StatementKind::AssertAt,
+            Self::DotVECTOR => StatementKind::Vector,
+            Self::DotIFCPU => StatementKind::IfCpu,
+            Self::DotELSE => StatementKind::Else,
+            Self::DotENDIF => StatementKind::EndIf,
 
             // Branch on Indicators instructions
             Self::BL => StatementKind::BranchOnIndicators,
@@ -297,6 +332,34 @@ impl Mnemonic {
             Self::IOLD => StatementKind::InputOutputLoad,
         }
     }
+
+    // Whether the instruction stores into the location its address syllable refers to
+    pub fn writes_operand(&self) -> bool {
+        matches!(
+            self,
+            Self::INC
+                | Self::DEC
+                | Self::NEG
+                | Self::CPL
+                | Self::CL
+                | Self::CLH
+                | Self::STS
+                | Self::SAVE
+                | Self::LBF
+                | Self::LBT
+                | Self::LBC
+                | Self::LBS
+                | Self::SDI
+                | Self::SID
+                | Self::STR
+                | Self::SRM
+                | Self::SWR
+                | Self::STH
+                | Self::STM
+                | Self::STB
+                | Self::SWB
+        )
+    }
 }
 
 impl Mnemonic {
@@ -308,6 +371,22 @@ impl Mnemonic {
             Self::DotDW => ".DW",
             Self::DotDD => ".DD",
             Self::DotDQ => ".DQ",
+            Self::DotBANK => ".BANK",
+            Self::DotTRAMPOLINE => ".TRAMPOLINE",
+            Self::DotFILL => ".FILL",
+            Self::DotFILLB => ".FILLB",
+            Self::DotSERIES => ".SERIES",
+            Self::DotSERIESB => ".SERIESB",
+            Self::DotRANDOM => ".RANDOM",
+            Self::DotLIMIT => ".LIMIT",
+            Self::DotEQU => ".EQU",
+            Self::DotWEAK => ".WEAK",
+            Self::DotIODELAY => ".IODELAY",
+            Self::DotASSERTAT => ".ASSERT_AT",
+            Self::DotVECTOR => ".VECTOR",
+            Self::DotIFCPU => ".IFCPU",
+            Self::DotELSE => ".ELSE",
+            Self::DotENDIF => ".ENDIF",
 
             // Branch on Indicators instructions
             Self::BL => "BL",
@@ -459,6 +538,20 @@ impl fmt::Display for Mnemonic {
 pub enum StatementKind {
     Org,
     DataDefinition,
+    Bank,
+    Trampoline,
+    Fill,
+    Series,
+    Random,
+    Limit,
+    Equ,
+    Weak,
+    IoDelay,
+    AssertAt,
+    Vector,
+    IfCpu,
+    Else,
+    EndIf,
     BranchOnIndicators,
     NoOp,
     BranchOnRegisters,
@@ -481,10 +574,28 @@ pub enum StatementKind {
     InputOutputLoad,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Statement {
     Org(u64),
-    DataDefinition(DataDefinitionSize, Vec<i128>),
+    DataDefinition(DataDefinitionSize, Vec<DataValue>),
+    Bank(u64),
+    Trampoline(String),
+    Fill(DataDefinitionSize, u64, i128),
+    Limit(u64),
+    // Value of the label of the statement: a label or none, plus a constant
+    Equ(Option<String>, i128),
+    // Marks the label of the statement as replaceable by other definitions
+    Weak,
+    // Minimum cycles between I/O instructions, 0 for none
+    IoDelay(u64),
+    // Label and the address it must be defined at
+    AssertAt(String, u64),
+    // Index in the vector table and handler, whose address is the entry
+    Vector(u64, String),
+    // Start of code assembled only for CPUs with the feature, up to .ELSE or .ENDIF
+    IfCpu(CpuFeature),
+    Else,
+    EndIf,
     BranchOnIndicators(BranchOnIndicatorsOpCode, BranchLocation),
     BranchOnRegisters(BranchOnRegistersOpCode, DataRegister, BranchLocation),
     ShortValueImmediate(ShortValueImmediateOpCode, DataRegister, i128),
@@ -495,9 +606,38 @@ pub enum Statement {
     ShiftLong(ShiftLongOpCode, DataRegister, u64),
     InputOutput(InputOutputOpCode, AddressSyllable, ChannelExpression),
     InputOutputLoad(AddressSyllable, ChannelExpression, AddressSyllable),
+    // Words emitted by a registered directive handler, with the directive name
+    Custom(String, Vec<u16>),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Optional processors of CPU models, tested by .IFCPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CpuFeature {
+    /// Scientific Instruction Processor, floating point
+    Sip,
+    /// Commercial Instruction Processor, decimal arithmetic
+    Cip,
+}
+
+impl CpuFeature {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "SIP" => Some(Self::Sip),
+            "CIP" => Some(Self::Cip),
+            _ => None,
+        }
+    }
+}
+
+/// Value of a data definition
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum DataValue {
+    Constant(i128),
+    // Labels, each added (1) or subtracted (-1), plus a constant. Resolved by code generation
+    Labels(Vec<(i128, String)>, i128),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum DataDefinitionSize {
     Byte,
     Word,
@@ -505,7 +645,7 @@ pub enum DataDefinitionSize {
     QuadWord,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum BranchOnIndicatorsOpCode {
     BL,
     BGE,
@@ -531,7 +671,7 @@ pub enum BranchOnIndicatorsOpCode {
     B,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum BranchOnRegistersOpCode {
     BLZ,
     BGEZ,
@@ -545,7 +685,7 @@ pub enum BranchOnRegistersOpCode {
     BDEC,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum ShortValueImmediateOpCode {
     LDV,
     CMV,
@@ -553,7 +693,7 @@ pub enum ShortValueImmediateOpCode {
     MLV,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum SingleOperandOpCode {
     INC,
     DEC,
@@ -581,7 +721,7 @@ pub enum SingleOperandOpCode {
     SID,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum GenericOpCode {
     HLT,
     MCL,
@@ -601,7 +741,7 @@ pub enum GenericOpCode {
     RSC,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum DoubleOperandOpCode {
     LDR,
     STR,
@@ -632,7 +772,7 @@ pub enum DoubleOperandOpCode {
     LNJ,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum ShiftShortOpCode {
     SOL,
     SCL,
@@ -644,7 +784,7 @@ pub enum ShiftShortOpCode {
     DCR,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum ShiftLongOpCode {
     DOL,
     DAL,
@@ -652,27 +792,27 @@ pub enum ShiftLongOpCode {
     DAR,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum InputOutputOpCode {
     IO,
     IOH,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum BranchLocation {
     Absolute(AddressExpression),
     LongDisplacement(AddressExpression),
     ShortDisplacement(AddressExpression),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum AddressExpression {
     Immediate(u64),
     Label(String),
     WordDisplacement(i128),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum AddressSyllable {
     RegisterAddressing(Register),
     ImmediateAddressing(ImmediateAddressMode),
@@ -681,14 +821,14 @@ pub enum AddressSyllable {
     BRelative(BRelativeAddressMode),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Register {
     Data(DataRegister),
     Base(BaseRegister),
     ModeControl(ModeControlRegister),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum DataRegister {
     R1,
     R2,
@@ -699,7 +839,7 @@ pub enum DataRegister {
     R7,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum BaseRegister {
     B1,
     B2,
@@ -710,7 +850,7 @@ pub enum BaseRegister {
     B7,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum ModeControlRegister {
     M1,
     M2,
@@ -721,25 +861,25 @@ pub enum ModeControlRegister {
     M7,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum ImmediateAddressMode {
     Direct(ImmediateAddress),
     Indirect(ImmediateAddress),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum ImmediateAddress {
     Simple(AddressExpression),
     Indexed(AddressExpression, DataRegister),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum PRelativeAddress {
     Direct(AddressExpression),
     Indirect(AddressExpression),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum BRelativeAddressMode {
     Direct(BRelativeAddress),
     Indirect(BRelativeAddress),
@@ -747,21 +887,189 @@ pub enum BRelativeAddressMode {
     PushPop(BaseRegister, IncDec),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum BRelativeAddress {
     Simple(BaseRegister),
     Indexed(BaseRegister, DataRegister),
     Displacement(BaseRegister, i128),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum IncDec {
     Increment,
     Decrement,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum ChannelExpression {
     Immediate(u64, u64),
     AddressSyllable(AddressSyllable),
 }
+
+// Operands are displayed in the syntax they are written in, normalized
+
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Constant(value) => write!(f, "{}", value),
+            Self::Labels(labels, offset) => {
+                for (i, (sign, label)) in labels.iter().enumerate() {
+                    match (i, *sign < 0) {
+                        (0, false) => write!(f, "{}", label)?,
+                        (0, true) => write!(f, "-{}", label)?,
+                        (_, false) => write!(f, " + {}", label)?,
+                        (_, true) => write!(f, " - {}", label)?,
+                    }
+                }
+                match *offset {
+                    0 => Ok(()),
+                    offset if offset < 0 => write!(f, " - {}", -offset),
+                    offset => write!(f, " + {}", offset),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for AddressExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Immediate(addr) => write!(f, "{:#06X}", addr),
+            Self::Label(label) => write!(f, "{}", label),
+            Self::WordDisplacement(disp) => write!(f, "{:+}", disp),
+        }
+    }
+}
+
+impl fmt::Display for BranchLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Absolute(addr_exp) => write!(f, "<{}", addr_exp),
+            Self::LongDisplacement(addr_exp) => write!(f, "{}", addr_exp),
+            Self::ShortDisplacement(addr_exp) => write!(f, ">{}", addr_exp),
+        }
+    }
+}
+
+impl fmt::Display for AddressSyllable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RegisterAddressing(reg) => write!(f, "={}", reg),
+            Self::ImmediateAddressing(ImmediateAddressMode::Direct(imm_addr)) => {
+                write!(f, "<{}", imm_addr)
+            }
+            Self::ImmediateAddressing(ImmediateAddressMode::Indirect(imm_addr)) => {
+                write!(f, "*<{}", imm_addr)
+            }
+            Self::ImmediateOperand(val) => write!(f, "={}", val),
+            Self::PRelative(PRelativeAddress::Direct(addr_exp)) => write!(f, "{}", addr_exp),
+            Self::PRelative(PRelativeAddress::Indirect(addr_exp)) => write!(f, "*{}", addr_exp),
+            Self::BRelative(brel_addr_mode) => write!(f, "{}", brel_addr_mode),
+        }
+    }
+}
+
+impl fmt::Display for ImmediateAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Simple(addr_exp) => write!(f, "{}", addr_exp),
+            Self::Indexed(addr_exp, reg) => write!(f, "{}.{}", addr_exp, reg),
+        }
+    }
+}
+
+impl fmt::Display for BRelativeAddressMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Direct(brel_addr) => write!(f, "{}", brel_addr),
+            Self::Indirect(brel_addr) => write!(f, "*{}", brel_addr),
+            Self::IncDecIndexed(base_reg, data_reg, inc_dec) => {
+                write!(f, "{}.{}{}", base_reg, inc_dec, data_reg)
+            }
+            Self::PushPop(reg, inc_dec) => write!(f, "{}{}", inc_dec, reg),
+        }
+    }
+}
+
+impl fmt::Display for BRelativeAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Simple(reg) => write!(f, "{}", reg),
+            Self::Indexed(base_reg, data_reg) => write!(f, "{}.{}", base_reg, data_reg),
+            Self::Displacement(reg, disp) => write!(f, "{}.{:+}", reg, disp),
+        }
+    }
+}
+
+impl fmt::Display for IncDec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Increment => write!(f, "+"),
+            Self::Decrement => write!(f, "-"),
+        }
+    }
+}
+
+impl fmt::Display for ChannelExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Immediate(channel, function) => write!(f, "@{:#04X}.{:#04X}", channel, function),
+            Self::AddressSyllable(addr_syl) => write!(f, "{}", addr_syl),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Data(reg) => write!(f, "{}", reg),
+            Self::Base(reg) => write!(f, "{}", reg),
+            Self::ModeControl(reg) => write!(f, "{}", reg),
+        }
+    }
+}
+
+impl fmt::Display for DataRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = match self {
+            Self::R1 => 1,
+            Self::R2 => 2,
+            Self::R3 => 3,
+            Self::R4 => 4,
+            Self::R5 => 5,
+            Self::R6 => 6,
+            Self::R7 => 7,
+        };
+        write!(f, "$R{}", n)
+    }
+}
+
+impl fmt::Display for BaseRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = match self {
+            Self::B1 => 1,
+            Self::B2 => 2,
+            Self::B3 => 3,
+            Self::B4 => 4,
+            Self::B5 => 5,
+            Self::B6 => 6,
+            Self::B7 => 7,
+        };
+        write!(f, "$B{}", n)
+    }
+}
+
+impl fmt::Display for ModeControlRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = match self {
+            Self::M1 => 1,
+            Self::M2 => 2,
+            Self::M3 => 3,
+            Self::M4 => 4,
+            Self::M5 => 5,
+            Self::M6 => 6,
+            Self::M7 => 7,
+        };
+        write!(f, "$M{}", n)
+    }
+}
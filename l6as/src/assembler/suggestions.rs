@@ -0,0 +1,128 @@
+use strum::IntoEnumIterator;
+
+use super::statements::Mnemonic;
+
+/// Finds the closest known mnemonic to an unknown one
+pub fn suggest_mnemonic(input: &str) -> Option<String> {
+    let candidates: Vec<String> = Mnemonic::iter().map(|m| m.to_string()).collect();
+
+    suggest(&input.to_uppercase(), candidates.iter().map(|c| c.as_str()))
+}
+
+/// Finds the closest candidate to the input string, if any is close enough
+pub fn suggest<'a>(input: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    // Maximum edit distance for a candidate to be considered a typo
+    let max_distance = usize::max(1, input.chars().count() / 3);
+
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        let distance = edit_distance(input, candidate);
+
+        if distance > max_distance || distance == 0 {
+            continue;
+        }
+
+        // Keep the closest candidate, breaking ties alphabetically so that the
+        // result doesn't depend on the iteration order of the candidates
+        best = match best {
+            Some((best_distance, best_candidate))
+                if best_distance < distance
+                    || (best_distance == distance && best_candidate <= candidate) =>
+            {
+                Some((best_distance, best_candidate))
+            }
+            _ => Some((distance, candidate)),
+        };
+    }
+
+    best.map(|(_, candidate)| candidate.to_owned())
+}
+
+/// Computes the Levenshtein distance between two strings, counting the
+/// transposition of two adjacent characters as a single edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Distance matrix, (a.len() + 1) x (b.len() + 1)
+    let mut dist = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in dist[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+
+            dist[i][j] = (dist[i - 1][j] + 1) // Deletion
+                .min(dist[i][j - 1] + 1) // Insertion
+                .min(dist[i - 1][j - 1] + cost); // Substitution
+
+            // Transposition
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                dist[i][j] = dist[i][j].min(dist[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    dist[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_succ() {
+        let tests = [
+            ("LDR", "LDR", 0),
+            ("LRD", "LDR", 1),
+            ("LD", "LDR", 1),
+            ("LDRR", "LDR", 1),
+            ("LDX", "LDR", 1),
+            ("", "ABC", 3),
+            ("LOOP_START", "LOOP_STRAT", 1),
+        ];
+
+        for (a, b, exp) in tests {
+            assert_eq!(edit_distance(a, b), exp);
+        }
+    }
+
+    #[test]
+    fn suggest_mnemonic_succ() {
+        let tests = [
+            ("LRD", Some("LDR")),
+            ("ldrr", Some("LDR")),
+            (".ogr", Some(".ORG")),
+            ("SAEV", Some("SAVE")),
+            ("XYZZY", None),
+        ];
+
+        for (input, exp) in tests {
+            assert_eq!(suggest_mnemonic(input), exp.map(|s| s.to_owned()));
+        }
+    }
+
+    #[test]
+    fn suggest_succ() {
+        let candidates = ["LOOP_START", "LOOP_END", "MAIN"];
+
+        let tests = [
+            ("LOOP_STRAT", Some("LOOP_START")),
+            ("LOOP_ED", Some("LOOP_END")),
+            ("MIAN", Some("MAIN")),
+            ("SOMETHING_ELSE", None),
+        ];
+
+        for (input, exp) in tests {
+            assert_eq!(
+                suggest(input, candidates.iter().copied()),
+                exp.map(|s| s.to_owned())
+            );
+        }
+    }
+}
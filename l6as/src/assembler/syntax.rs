@@ -0,0 +1,370 @@
+use super::statements::{Mnemonic, StatementKind};
+
+/// Type of register
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegisterType {
+    Data,
+    Base,
+}
+
+/// Type of the value an operand accepts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperandType {
+    HexAddress,
+    BankNumber,
+    Label,
+    ConstantExpression,
+    /// Label plus or minus a constant expression, or a constant expression alone
+    SymbolExpression,
+    DefinitionChunk,
+    BranchLocation,
+    DataRegister,
+    BaseRegister,
+    ModeControlRegister,
+    ImmediateValue,
+    /// Shift distance, up to the given number of bits
+    ShiftValue(u64),
+    ChannelExpression,
+    MaskWord,
+    /// Address syllable, restricted in the addressing modes it can use
+    AddressSyllable {
+        /// Type of register usable with register addressing, None if register addressing
+        /// is invalid
+        register: Option<RegisterType>,
+        /// Whether immediate addressing is valid
+        immediate: bool,
+    },
+}
+
+impl OperandType {
+    /// Short description of the values accepted, with their range
+    pub fn description(&self) -> String {
+        match self {
+            Self::HexAddress => "hexadecimal address, e.g. 0x100".to_owned(),
+            Self::BankNumber => "decimal bank number".to_owned(),
+            Self::Label => "label".to_owned(),
+            Self::ConstantExpression => "constant expression".to_owned(),
+            Self::SymbolExpression => {
+                "label plus or minus a constant expression, or constant expression".to_owned()
+            }
+            Self::DefinitionChunk => "number or string literal".to_owned(),
+            Self::BranchLocation => {
+                "branch location: <address (absolute), >address (short displacement) or address (long displacement)".to_owned()
+            }
+            Self::DataRegister => "data register, $R1 to $R7".to_owned(),
+            Self::BaseRegister => "base register, $B1 to $B7".to_owned(),
+            Self::ModeControlRegister => "mode control register, $M1 to $M7".to_owned(),
+            Self::ImmediateValue => "immediate value, -128 to 255".to_owned(),
+            Self::ShiftValue(max) => format!("decimal shift distance, 0 to {}", max),
+            Self::ChannelExpression => {
+                "channel expression: @channel.function (hexadecimal) or address syllable".to_owned()
+            }
+            Self::MaskWord => "mask word, 0 to 0xFFFF, 0 if omitted, none for bit instructions with indexed addressing".to_owned(),
+            Self::AddressSyllable { .. } => "address syllable".to_owned(),
+        }
+    }
+
+    /// Addressing modes accepted by address syllable operands, as syntax and description
+    pub fn addressing_modes(&self) -> Vec<(&'static str, &'static str)> {
+        let Self::AddressSyllable {
+            register,
+            immediate,
+        } = self
+        else {
+            return vec![];
+        };
+
+        let mut modes = vec![];
+        match register {
+            Some(RegisterType::Data) => modes.push(("=$Rn", "register addressing")),
+            Some(RegisterType::Base) => modes.push(("=$Bn", "register addressing")),
+            None => {}
+        }
+        modes.push(("=value", "immediate operand"));
+        if *immediate {
+            modes.push(("<address", "immediate addressing"));
+        }
+        modes.push(("address", "P-relative addressing"));
+        modes.push(("$Bn", "B-relative addressing"));
+
+        modes
+    }
+}
+
+/// How many times an operand can appear
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Required,
+    Optional,
+    /// One or more times, only valid for the last operand
+    Repeated,
+}
+
+/// Operand of a statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperandSpec {
+    /// Placeholder shown in syntax help
+    pub name: &'static str,
+    pub operand_type: OperandType,
+    pub arity: Arity,
+}
+
+const fn required(name: &'static str, operand_type: OperandType) -> OperandSpec {
+    OperandSpec {
+        name,
+        operand_type,
+        arity: Arity::Required,
+    }
+}
+
+const fn optional(name: &'static str, operand_type: OperandType) -> OperandSpec {
+    OperandSpec {
+        name,
+        operand_type,
+        arity: Arity::Optional,
+    }
+}
+
+const fn repeated(name: &'static str, operand_type: OperandType) -> OperandSpec {
+    OperandSpec {
+        name,
+        operand_type,
+        arity: Arity::Repeated,
+    }
+}
+
+const DATA_SYLLABLE: OperandType = OperandType::AddressSyllable {
+    register: Some(RegisterType::Data),
+    immediate: true,
+};
+const BASE_SYLLABLE: OperandType = OperandType::AddressSyllable {
+    register: Some(RegisterType::Base),
+    immediate: true,
+};
+const MEMORY_SYLLABLE: OperandType = OperandType::AddressSyllable {
+    register: None,
+    immediate: true,
+};
+const MEMORY_ONLY_SYLLABLE: OperandType = OperandType::AddressSyllable {
+    register: None,
+    immediate: false,
+};
+
+// Operand tables
+const ORG: &[OperandSpec] = &[required("address", OperandType::HexAddress)];
+const DATA_DEFINITION: &[OperandSpec] = &[repeated("value", OperandType::DefinitionChunk)];
+const BANK: &[OperandSpec] = &[required("bank", OperandType::BankNumber)];
+const TRAMPOLINE: &[OperandSpec] = &[required("label", OperandType::Label)];
+const FILL: &[OperandSpec] = &[
+    required("count", OperandType::ConstantExpression),
+    required("value", OperandType::ConstantExpression),
+];
+const SERIES: &[OperandSpec] = &[
+    required("start", OperandType::ConstantExpression),
+    required("step", OperandType::ConstantExpression),
+    required("count", OperandType::ConstantExpression),
+];
+const RANDOM: &[OperandSpec] = &[
+    required("count", OperandType::ConstantExpression),
+    required("seed", OperandType::ConstantExpression),
+];
+const LIMIT: &[OperandSpec] = &[required("size", OperandType::ConstantExpression)];
+const IODELAY: &[OperandSpec] = &[required("cycles", OperandType::ConstantExpression)];
+const ASSERT_AT: &[OperandSpec] = &[
+    required("label", OperandType::Label),
+    required("address", OperandType::HexAddress),
+];
+const VECTOR: &[OperandSpec] = &[
+    required("index", OperandType::ConstantExpression),
+    required("handler", OperandType::Label),
+];
+const IFCPU: &[OperandSpec] = &[required("feature", OperandType::Label)];
+const EQU: &[OperandSpec] = &[required("value", OperandType::SymbolExpression)];
+const BRANCH_ON_INDICATORS: &[OperandSpec] = &[required("location", OperandType::BranchLocation)];
+const BRANCH_ON_REGISTERS: &[OperandSpec] = &[
+    required("$Rn", OperandType::DataRegister),
+    required("location", OperandType::BranchLocation),
+];
+const SHORT_VALUE_IMMEDIATE: &[OperandSpec] = &[
+    required("$Rn", OperandType::DataRegister),
+    required("=value", OperandType::ImmediateValue),
+];
+const SINGLE_OPERAND_DATA: &[OperandSpec] = &[required("operand", DATA_SYLLABLE)];
+const SINGLE_OPERAND_BASE: &[OperandSpec] = &[required("operand", BASE_SYLLABLE)];
+const SINGLE_OPERAND_MEMONLY: &[OperandSpec] = &[required("operand", MEMORY_SYLLABLE)];
+const SINGLE_OPERAND_DATA_MASKED: &[OperandSpec] = &[
+    required("operand", DATA_SYLLABLE),
+    optional("mask", OperandType::MaskWord),
+];
+const SINGLE_OPERAND_MEMONLY_MASKED: &[OperandSpec] = &[
+    required("operand", MEMORY_SYLLABLE),
+    optional("mask", OperandType::MaskWord),
+];
+const DOUBLE_OPERAND_DATA: &[OperandSpec] = &[
+    required("$Rn", OperandType::DataRegister),
+    required("operand", DATA_SYLLABLE),
+];
+const DOUBLE_OPERAND_DATA_MASKED: &[OperandSpec] = &[
+    required("$Rn", OperandType::DataRegister),
+    required("operand", DATA_SYLLABLE),
+    optional("mask", OperandType::MaskWord),
+];
+const DOUBLE_OPERAND_BASE: &[OperandSpec] = &[
+    required("$Bn", OperandType::BaseRegister),
+    required("operand", BASE_SYLLABLE),
+];
+const DOUBLE_OPERAND_NOREG: &[OperandSpec] = &[
+    required("$Bn", OperandType::BaseRegister),
+    required("operand", MEMORY_SYLLABLE),
+];
+const DOUBLE_OPERAND_MEMONLY: &[OperandSpec] = &[
+    required("$Bn", OperandType::BaseRegister),
+    required("operand", MEMORY_ONLY_SYLLABLE),
+];
+const DOUBLE_OPERAND_MODE: &[OperandSpec] = &[
+    required("$Mn", OperandType::ModeControlRegister),
+    required("operand", BASE_SYLLABLE),
+];
+const SHIFT_SHORT: &[OperandSpec] = &[
+    required("$Rn", OperandType::DataRegister),
+    required("count", OperandType::ShiftValue(15)),
+];
+const SHIFT_LONG: &[OperandSpec] = &[
+    required("$Rn", OperandType::DataRegister),
+    required("count", OperandType::ShiftValue(31)),
+];
+const INPUT_OUTPUT: &[OperandSpec] = &[
+    required("operand", DATA_SYLLABLE),
+    required("channel", OperandType::ChannelExpression),
+];
+const INPUT_OUTPUT_LOAD: &[OperandSpec] = &[
+    required("buffer", DATA_SYLLABLE),
+    required("channel", OperandType::ChannelExpression),
+    required("range", BASE_SYLLABLE),
+];
+
+/// Operands taken by each kind of statement, in order
+pub fn get_operand_specs(kind: &StatementKind) -> &'static [OperandSpec] {
+    match kind {
+        StatementKind::Org => ORG,
+        StatementKind::DataDefinition => DATA_DEFINITION,
+        StatementKind::Bank => BANK,
+        StatementKind::Trampoline => TRAMPOLINE,
+        StatementKind::Fill => FILL,
+        StatementKind::Series => SERIES,
+        StatementKind::Random => RANDOM,
+        StatementKind::Limit => LIMIT,
+        StatementKind::Equ => EQU,
+        StatementKind::IoDelay => IODELAY,
+        StatementKind::AssertAt => ASSERT_AT,
+        StatementKind::Vector => VECTOR,
+        StatementKind::IfCpu => IFCPU,
+        StatementKind::BranchOnIndicators => BRANCH_ON_INDICATORS,
+        StatementKind::NoOp
+        | StatementKind::Generic
+        | StatementKind::Weak
+        | StatementKind::Else
+        | StatementKind::EndIf => &[],
+        StatementKind::BranchOnRegisters => BRANCH_ON_REGISTERS,
+        StatementKind::ShortValueImmediate => SHORT_VALUE_IMMEDIATE,
+        StatementKind::SingleOperandData => SINGLE_OPERAND_DATA,
+        StatementKind::SingleOperandBase => SINGLE_OPERAND_BASE,
+        StatementKind::SingleOperandMemonly => SINGLE_OPERAND_MEMONLY,
+        StatementKind::SingleOperandDataMasked => SINGLE_OPERAND_DATA_MASKED,
+        StatementKind::SingleOperandMemonlyMasked => SINGLE_OPERAND_MEMONLY_MASKED,
+        StatementKind::DoubleOperandData => DOUBLE_OPERAND_DATA,
+        StatementKind::DoubleOperandDataMasked => DOUBLE_OPERAND_DATA_MASKED,
+        StatementKind::DoubleOperandBase => DOUBLE_OPERAND_BASE,
+        StatementKind::DoubleOperandNoreg => DOUBLE_OPERAND_NOREG,
+        StatementKind::DoubleOperandMemonly => DOUBLE_OPERAND_MEMONLY,
+        StatementKind::DoubleOperandMode => DOUBLE_OPERAND_MODE,
+        StatementKind::ShiftShort => SHIFT_SHORT,
+        StatementKind::ShiftLong => SHIFT_LONG,
+        StatementKind::InputOutput => INPUT_OUTPUT,
+        StatementKind::InputOutputLoad => INPUT_OUTPUT_LOAD,
+    }
+}
+
+/// Smallest and largest number of arguments taken by a kind of statement,
+/// None if there's no upper limit
+pub fn get_argument_count_range(kind: &StatementKind) -> (usize, Option<usize>) {
+    let specs = get_operand_specs(kind);
+    let min = specs
+        .iter()
+        .filter(|spec| spec.arity != Arity::Optional)
+        .count();
+    let max = match specs.iter().any(|spec| spec.arity == Arity::Repeated) {
+        true => None,
+        false => Some(specs.len()),
+    };
+
+    (min, max)
+}
+
+/// Syntax of a statement, e.g. "LDR $Rn, operand"
+pub fn get_syntax(mnemonic: &Mnemonic) -> String {
+    let mut syntax = mnemonic.to_string();
+
+    for (i, spec) in get_operand_specs(&mnemonic.get_kind()).iter().enumerate() {
+        let separator = match i {
+            0 => " ",
+            _ => ", ",
+        };
+        let operand = match spec.arity {
+            Arity::Required => format!("{}{}", separator, spec.name),
+            Arity::Optional => format!("[{}{}]", separator, spec.name),
+            Arity::Repeated => format!("{}{}[, {}...]", separator, spec.name, spec.name),
+        };
+        syntax.push_str(&operand);
+    }
+
+    syntax
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn get_syntax_succ() {
+        let tests = [
+            (Mnemonic::LDR, "LDR $Rn, operand"),
+            (Mnemonic::SRM, "SRM $Rn, operand[, mask]"),
+            (Mnemonic::LEV, "LEV operand[, mask]"),
+            (Mnemonic::HLT, "HLT"),
+            (Mnemonic::DotDW, ".DW value[, value...]"),
+            (Mnemonic::IOLD, "IOLD buffer, channel, range"),
+        ];
+
+        for (mnemonic, syntax) in tests {
+            assert_eq!(get_syntax(&mnemonic), syntax);
+        }
+    }
+
+    #[test]
+    fn get_argument_count_range_succ() {
+        assert_eq!(
+            get_argument_count_range(&StatementKind::DoubleOperandDataMasked),
+            (2, Some(3))
+        );
+        assert_eq!(
+            get_argument_count_range(&StatementKind::DataDefinition),
+            (1, None)
+        );
+        assert_eq!(
+            get_argument_count_range(&StatementKind::Generic),
+            (0, Some(0))
+        );
+    }
+
+    #[test]
+    fn repeated_operands_are_last() {
+        for mnemonic in Mnemonic::iter() {
+            let specs = get_operand_specs(&mnemonic.get_kind());
+            for spec in specs.iter().rev().skip(1) {
+                assert_ne!(spec.arity, Arity::Repeated, "{}", mnemonic);
+            }
+        }
+    }
+}
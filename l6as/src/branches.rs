@@ -0,0 +1,98 @@
+use crate::assembler::{
+    decode_branch_location, is_branch_mnemonic, AssembledLine, LabelDefinition,
+};
+
+/// Where an assembled branch instruction goes
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchTarget {
+    pub address: u64,
+    /// Displacement from the branch, in words, None for absolute branch locations
+    pub displacement: Option<i128>,
+    /// Label pointing to the target, in the bank of the branch or in common code
+    pub label: Option<String>,
+}
+
+/// Finds the target of an assembled line, None if it isn't a branch instruction
+pub fn get_line_branch_target(
+    line: &AssembledLine,
+    labels: &[LabelDefinition],
+) -> Option<BranchTarget> {
+    if !is_branch_mnemonic(line.mnemonic.as_deref()?) {
+        return None;
+    }
+
+    let (address, displacement) = decode_branch_location(&line.data, line.address)?;
+    let label = labels
+        .iter()
+        .find(|label| label.address == address && (label.bank.is_none() || label.bank == line.bank))
+        .map(|label| label.label.clone());
+
+    Some(BranchTarget {
+        address,
+        displacement,
+        label,
+    })
+}
+
+/// Formats a branch target for the listing, e.g. "-> LOOP 0x0012 (-4)"
+pub fn format_branch_target(target: &BranchTarget) -> String {
+    let mut output = "->".to_owned();
+    if let Some(label) = &target.label {
+        output.push_str(&format!(" {}", label));
+    }
+    output.push_str(&format!(" {:#06X}", target.address));
+    if let Some(displacement) = target.displacement {
+        output.push_str(&format!(" ({:+})", displacement));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessor::LineLocation;
+
+    #[test]
+    fn get_line_branch_target_succ() {
+        let labels = vec![LabelDefinition {
+            label: "LOOP".to_owned(),
+            address: 0x0C,
+            bank: None,
+            location: LineLocation {
+                line_n: 1,
+                file_name: "a.l6s".into(),
+                raw_content: "LOOP: INC $R1".to_owned(),
+            },
+        }];
+
+        let target = get_line_branch_target(
+            &AssembledLine::new_test(0x10, None, vec![0x00FC], Some("BNEZ")),
+            &labels,
+        )
+        .unwrap();
+        assert_eq!(
+            target,
+            BranchTarget {
+                address: 0x0C,
+                displacement: Some(-4),
+                label: Some("LOOP".to_owned()),
+            }
+        );
+        assert_eq!(format_branch_target(&target), "-> LOOP 0x000C (-4)");
+
+        let target = get_line_branch_target(
+            &AssembledLine::new_test(0x10, None, vec![0x0000, 0x0100], Some("B")),
+            &labels,
+        )
+        .unwrap();
+        assert_eq!(format_branch_target(&target), "-> 0x0100");
+
+        assert_eq!(
+            get_line_branch_target(
+                &AssembledLine::new_test(0x10, None, vec![0x0001], Some("LDR")),
+                &labels
+            ),
+            None
+        );
+    }
+}
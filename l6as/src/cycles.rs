@@ -0,0 +1,190 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    assembler::{AssembledLine, LabelDefinition},
+    logging::TableFileErrorKind,
+    table_file::{parse_table_file, read_table_file},
+};
+
+/// Cycles taken by an instruction: a base count, plus a count for each extra word
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CycleEstimate {
+    pub base: u64,
+    pub per_extra_word: u64,
+}
+
+/// Cycle estimates of a CPU model, by mnemonic
+pub type CycleTable = HashMap<String, CycleEstimate>;
+
+/// Reads a cycle table file, with one "MNEMONIC CYCLES [CYCLES_PER_EXTRA_WORD]" entry per line
+pub fn read_cycle_table(file_path: &Path) -> Result<CycleTable, ()> {
+    let table = read_table_file(
+        file_path,
+        parse_cycle_table_entry,
+        TableFileErrorKind::InvalidCycleTableEntry,
+    )?;
+    Ok(table.into_iter().collect())
+}
+
+/// Parses the contents of a cycle table file. Comments start with ';'
+pub fn parse_cycle_table(contents: &str, file_path: &Path) -> Result<CycleTable, ()> {
+    let table = parse_table_file(
+        contents,
+        file_path,
+        parse_cycle_table_entry,
+        TableFileErrorKind::InvalidCycleTableEntry,
+    )?;
+    Ok(table.into_iter().collect())
+}
+
+fn parse_cycle_table_entry(code: &str) -> Option<(String, CycleEstimate)> {
+    let parts: Vec<&str> = code.split_whitespace().collect();
+    let (mnemonic, base, per_extra_word) = match parts[..] {
+        [mnemonic, base] => (mnemonic, base, "0"),
+        [mnemonic, base, per_extra_word] => (mnemonic, base, per_extra_word),
+        _ => return None,
+    };
+
+    Some((
+        mnemonic.to_uppercase(),
+        CycleEstimate {
+            base: base.parse().ok()?,
+            per_extra_word: per_extra_word.parse().ok()?,
+        },
+    ))
+}
+
+/// Estimates the cycles taken by an assembled line, None if it isn't an
+/// instruction or its mnemonic isn't in the table
+pub fn get_line_cycles(table: &CycleTable, line: &AssembledLine) -> Option<u64> {
+    estimate_cycles(table, line.mnemonic.as_deref()?, line.data.len() as u64)
+}
+
+/// Estimates the cycles taken by an instruction of a given size in words, None if its
+/// mnemonic isn't in the table
+pub fn estimate_cycles(table: &CycleTable, mnemonic: &str, size: u64) -> Option<u64> {
+    let estimate = table.get(&mnemonic.to_uppercase())?;
+    let extra_words = size.saturating_sub(1);
+
+    Some(estimate.base + estimate.per_extra_word * extra_words)
+}
+
+/// Sums the cycles of the straight-line code following each label, up to the next label.
+/// Branches aren't followed, so loops are counted once
+pub fn get_label_cycles(
+    table: &CycleTable,
+    lines: &[AssembledLine],
+    labels: &[LabelDefinition],
+) -> Vec<(String, u64)> {
+    // Labels pointing to each address
+    let mut labels_at: HashMap<(Option<u64>, u64), Vec<&String>> = HashMap::new();
+    for label in labels {
+        labels_at
+            .entry((label.bank, label.address))
+            .or_default()
+            .push(&label.label);
+    }
+
+    let mut totals: Vec<(String, u64)> = vec![];
+    let mut current: Vec<usize> = vec![];
+    for line in lines.iter().filter(|line| !line.data.is_empty()) {
+        if let Some(line_labels) = labels_at.remove(&(line.bank, line.address)) {
+            current = (totals.len()..totals.len() + line_labels.len()).collect();
+            totals.extend(line_labels.into_iter().map(|label| (label.clone(), 0)));
+        }
+
+        let cycles = get_line_cycles(table, line).unwrap_or(0);
+        for i in &current {
+            totals[*i].1 += cycles;
+        }
+    }
+
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> CycleTable {
+        parse_cycle_table("; Test\nldr 4 2\nB 3\n", Path::new("a.cyc")).unwrap()
+    }
+
+    #[test]
+    fn parse_cycle_table_succ() {
+        assert_eq!(
+            table(),
+            HashMap::from([
+                (
+                    "LDR".to_owned(),
+                    CycleEstimate {
+                        base: 4,
+                        per_extra_word: 2
+                    }
+                ),
+                (
+                    "B".to_owned(),
+                    CycleEstimate {
+                        base: 3,
+                        per_extra_word: 0
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_cycle_table_entry_fail() {
+        let tests = ["LDR", "LDR x", "LDR 1 2 3", "LDR -1"];
+
+        for input in tests {
+            assert_eq!(parse_cycle_table_entry(input), None, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn get_line_cycles_succ() {
+        let tests = [
+            (
+                AssembledLine::new_test(0, None, vec![0x9870, 5], Some("ldr")),
+                Some(6),
+            ),
+            (
+                AssembledLine::new_test(0, None, vec![0x0F80], Some("B")),
+                Some(3),
+            ),
+            (AssembledLine::new_test(0, None, vec![0], Some("HLT")), None),
+            (
+                AssembledLine::new_test(0, None, vec![1, 2], Some(".DW")),
+                None,
+            ),
+            (AssembledLine::new_test(0, None, vec![0], None), None),
+        ];
+
+        for (line, exp) in tests {
+            assert_eq!(get_line_cycles(&table(), &line), exp);
+        }
+    }
+
+    #[test]
+    fn get_label_cycles_succ() {
+        let lines = [
+            AssembledLine::new_test(0x100, None, vec![0x9870, 5], Some("ldr")),
+            AssembledLine::new_test(0x102, None, vec![0x0F80], Some("b")),
+            AssembledLine::new_test(0x103, None, vec![0x9870, 5], Some("ldr")),
+            AssembledLine::new_test(0x105, None, vec![0x0F80], Some("b")),
+        ];
+        let label = |label: &str, address: u64| LabelDefinition {
+            label: label.to_owned(),
+            address,
+            bank: None,
+            location: lines[0].location.clone(),
+        };
+        let labels = [label("START", 0x100), label("LOOP", 0x103)];
+
+        assert_eq!(
+            get_label_cycles(&table(), &lines, &labels),
+            vec![("START".to_owned(), 9), ("LOOP".to_owned(), 9)]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use crate::{
+    args::{DecodeSyllableArgs, ExplainArgs},
+    assembler::{
+        decode_address_syllable, explain_statement, get_address_syllable_field_value,
+        parse_statement, ExplainedField, ExplainedWord,
+    },
+    logging::{print_assembler_error, AssemblerError, AssemblerErrorKind},
+};
+use nom::Err;
+
+/// Prints the encoding of a single statement, with a breakdown of its fields
+pub fn run_explain(args: &ExplainArgs) -> Result<(), ()> {
+    // Parse statement
+    let statement = match parse_statement(args.statement.trim()) {
+        Ok((_, statement)) => statement,
+        Err(Err::Failure(err) | Err::Error(err)) => {
+            print_assembler_error(AssemblerError {
+                kind: err.kind,
+                location: None,
+            });
+            return Err(());
+        }
+        Err(Err::Incomplete(_)) => return Err(()),
+    };
+
+    // Encode it
+    let words = match explain_statement(&statement, args.address) {
+        Ok(words) => words,
+        Err(kind) => {
+            print_assembler_error(AssemblerError {
+                kind,
+                location: None,
+            });
+            return Err(());
+        }
+    };
+
+    print!("{}", format_explanation(&words));
+    Ok(())
+}
+
+/// Decodes an address syllable, checking that it encodes back to the same field and extra words
+pub fn run_decode_syllable(args: &DecodeSyllableArgs) -> Result<(), ()> {
+    let res =
+        decode_address_syllable(args.field, &args.extra_words).and_then(|(addr_syl, size)| {
+            let (field, extra_words) =
+                get_address_syllable_field_value(&addr_syl, args.address, &HashMap::new())?;
+
+            if field.value() == args.field && extra_words == args.extra_words[..size] {
+                Ok((addr_syl, size))
+            } else {
+                let (redecoded, _) = decode_address_syllable(field.value(), &extra_words)?;
+                Err(AssemblerErrorKind::AddressSyllableRoundTripMismatch(
+                    addr_syl.to_string(),
+                    redecoded.to_string(),
+                ))
+            }
+        });
+
+    match res {
+        Ok((addr_syl, size)) => {
+            println!("{}", addr_syl);
+            println!("extra words: {}", size);
+            Ok(())
+        }
+        Err(kind) => {
+            print_assembler_error(AssemblerError {
+                kind,
+                location: None,
+            });
+            Err(())
+        }
+    }
+}
+
+fn format_explanation(words: &[ExplainedWord]) -> String {
+    let mut output = String::new();
+
+    for (i, word) in words.iter().enumerate() {
+        let description = match (i, word.fields.is_empty()) {
+            (0, false) => "instruction word",
+            (0, true) => "data word",
+            _ => "extra word",
+        };
+        output.push_str(&format!(
+            "{:0>4X}  {:016b}  {}\n",
+            word.word, word.word, description
+        ));
+        format_fields(&word.fields, 1, &mut output);
+    }
+
+    output
+}
+
+// Formats fields as "name  bits = value", indenting subfields
+fn format_fields(fields: &[ExplainedField], depth: usize, output: &mut String) {
+    for field in fields {
+        let name = format!("{}{}", "  ".repeat(depth), field.name);
+        let bits = format!("{:0width$b}", field.value, width = field.width as usize);
+        output.push_str(&format!("{:<16}{:>16} = {}\n", name, bits, field.value));
+
+        format_fields(&field.subfields, depth + 1, output);
+    }
+}
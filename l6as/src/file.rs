@@ -1,12 +1,27 @@
+use std::collections::HashMap;
 use std::env::current_dir;
 use std::fs;
 use std::io;
 use std::io::Write;
 use std::path::PathBuf;
+use std::thread;
+
+/// Default maximum %include nesting depth
+pub const DEFAULT_MAX_INCLUDE_DEPTH: usize = 32;
 
 pub struct FileInclusionCoordinator {
     include_dirs: Vec<PathBuf>,
     already_included: Vec<PathBuf>,
+    overrides: HashMap<PathBuf, String>,
+    // Files read ahead of their inclusion, from the include directory they would be read from
+    prefetched: HashMap<PathBuf, String>,
+    // Files currently being processed, from the outermost one
+    inclusion_chain: Vec<PathBuf>,
+    max_depth: usize,
+    fail_fast: bool,
+    strict: bool,
+    // %defines given from outside of the program, e.g. by a project file
+    definitions: Vec<(String, String)>,
 }
 
 impl FileInclusionCoordinator {
@@ -14,9 +29,48 @@ impl FileInclusionCoordinator {
         Self {
             include_dirs: vec![],
             already_included: vec![],
+            overrides: HashMap::new(),
+            prefetched: HashMap::new(),
+            inclusion_chain: vec![],
+            max_depth: DEFAULT_MAX_INCLUDE_DEPTH,
+            fail_fast: false,
+            strict: false,
+            definitions: vec![],
         }
     }
 
+    /// Stops including further files once a file has errors
+    pub fn set_fail_fast(&mut self, fail_fast: bool) {
+        self.fail_fast = fail_fast;
+    }
+
+    pub fn fail_fast(&self) -> bool {
+        self.fail_fast
+    }
+
+    /// Reports lines parsed only in part as errors instead of warnings
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Sets how deeply %includes can be nested
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Defines identifiers as if by %define, taking precedence over the program's own
+    pub fn add_definitions(&mut self, definitions: &[(String, String)]) {
+        self.definitions.extend_from_slice(definitions);
+    }
+
+    pub fn definitions(&self) -> &[(String, String)] {
+        &self.definitions
+    }
+
     fn add_include_dir(&mut self, dir: PathBuf) -> io::Result<()> {
         let mut absolute_path = current_dir()?;
         absolute_path.push(dir);
@@ -37,6 +91,73 @@ impl FileInclusionCoordinator {
         Ok(())
     }
 
+    /// Uses `contents` instead of reading the file at `file_path` from disk
+    pub fn add_override(&mut self, file_path: PathBuf, contents: String) {
+        self.overrides.insert(file_path, contents);
+    }
+
+    /// Reads and parses files which are about to be included concurrently, so that a file
+    /// including many others doesn't wait for each of them in turn. The parsed files are
+    /// returned by absolute path, to be merged in inclusion order. Inclusion checks are left to
+    /// `read_file`, which takes the contents read here
+    pub fn prefetch<T: Send>(
+        &mut self,
+        file_paths: &[PathBuf],
+        parse: impl Fn(&PathBuf, &str) -> T + Sync,
+    ) -> HashMap<PathBuf, T> {
+        if file_paths.is_empty() {
+            return HashMap::new();
+        }
+
+        // Split the files between a few worker threads
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = file_paths.len().div_ceil(threads);
+        let files: Vec<(PathBuf, String, T)> = thread::scope(|scope| {
+            let workers: Vec<_> = file_paths
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(|| {
+                        chunk
+                            .iter()
+                            .filter_map(|file_path| self.find_first(file_path))
+                            .map(|(abs_path, contents)| {
+                                let parsed = parse(&abs_path, &contents);
+                                (abs_path, contents, parsed)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_default())
+                .collect()
+        });
+
+        let mut parsed = HashMap::new();
+        for (abs_path, contents, res) in files {
+            self.prefetched.insert(abs_path.clone(), contents);
+            parsed.insert(abs_path, res);
+        }
+        parsed
+    }
+
+    // Reads a file from the first include directory holding it, as `read_file` would. Files
+    // which are overridden or already read are left alone
+    fn find_first(&self, file_path: &PathBuf) -> Option<(PathBuf, String)> {
+        for dir_path in &self.include_dirs {
+            let abs_path = dir_path.join(file_path);
+            if self.overrides.contains_key(&abs_path) || self.prefetched.contains_key(&abs_path) {
+                return None;
+            }
+            if let Ok(contents) = fs::read_to_string(&abs_path) {
+                return Some((abs_path, contents));
+            }
+        }
+
+        None
+    }
+
     pub fn read_file(
         &mut self,
         file_path: &PathBuf,
@@ -48,29 +169,65 @@ impl FileInclusionCoordinator {
             abs_path.push(&file_path);
 
             // Read this file
-            let contents = match fs::read_to_string(&abs_path) {
-                Ok(cont) => cont,
-                Err(_err) => continue, // Next include directory
+            let contents = match self.overrides.get(&abs_path) {
+                Some(cont) => cont.clone(),
+                None => match self.prefetched.remove(&abs_path) {
+                    Some(cont) => cont,
+                    None => match fs::read_to_string(&abs_path) {
+                        Ok(cont) => cont,
+                        Err(_err) => continue, // Next include directory
+                    },
+                },
             };
 
+            // A file including itself, directly or not, is reported with the whole chain
+            if self.inclusion_chain.contains(&abs_path) {
+                let mut chain = self.inclusion_chain.clone();
+                chain.push(abs_path);
+                return Err(FileInclusionError::Cycle(chain));
+            }
+
             // Check if this file was already included
             if self.already_included.contains(&abs_path) {
                 return Err(FileInclusionError::DoubleInclusion(abs_path.clone()));
             }
 
+            // The outermost file is at depth 0
+            if self.inclusion_chain.len() > self.max_depth {
+                let mut chain = self.inclusion_chain.clone();
+                chain.push(abs_path);
+                return Err(FileInclusionError::DepthExceeded(self.max_depth, chain));
+            }
+
             self.already_included.push(abs_path.clone());
+            self.inclusion_chain.push(abs_path.clone());
 
             return Ok((abs_path, contents));
         }
 
-        Err(FileInclusionError::FileNotFound(file_path.clone()))
+        Err(FileInclusionError::FileNotFound(
+            file_path.clone(),
+            self.include_dirs.clone(),
+        ))
+    }
+
+    /// Marks the last file read as fully processed, including the files it includes
+    pub fn finish_file(&mut self) {
+        self.inclusion_chain.pop();
+    }
+
+    /// Absolute paths of every file read so far, in inclusion order
+    pub fn included_files(&self) -> &[PathBuf] {
+        &self.already_included
     }
 }
 
 #[derive(Debug)]
 pub enum FileInclusionError {
-    FileNotFound(PathBuf),
+    FileNotFound(PathBuf, Vec<PathBuf>),
     DoubleInclusion(PathBuf),
+    Cycle(Vec<PathBuf>),
+    DepthExceeded(usize, Vec<PathBuf>),
 }
 
 // Write file from [u8]
@@ -88,3 +245,101 @@ pub fn write_file(file_path: &PathBuf, data: &[u8]) -> Result<(), io::Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinator(files: &[&str]) -> FileInclusionCoordinator {
+        let mut fi_coord = FileInclusionCoordinator::new();
+        fi_coord.add_include_dirs(&["/virtual".into()]).unwrap();
+        for file in files {
+            fi_coord.add_override(PathBuf::from("/virtual").join(file), "".to_owned());
+        }
+        fi_coord
+    }
+
+    fn abs(file: &str) -> PathBuf {
+        PathBuf::from("/virtual").join(file)
+    }
+
+    #[test]
+    fn read_file_cycle() {
+        let mut fi_coord = coordinator(&["a.l6s", "b.l6s"]);
+        fi_coord.read_file(&"a.l6s".into()).unwrap();
+        fi_coord.read_file(&"b.l6s".into()).unwrap();
+
+        match fi_coord.read_file(&"a.l6s".into()) {
+            Err(FileInclusionError::Cycle(chain)) => {
+                assert_eq!(chain, vec![abs("a.l6s"), abs("b.l6s"), abs("a.l6s")])
+            }
+            res => panic!("{:?}", res),
+        }
+
+        // Once b.l6s is done, including it again is only a double inclusion
+        fi_coord.finish_file();
+        assert!(matches!(
+            fi_coord.read_file(&"b.l6s".into()),
+            Err(FileInclusionError::DoubleInclusion(_))
+        ));
+    }
+
+    #[test]
+    fn read_file_prefetched() {
+        let dir = std::env::temp_dir().join(format!("l6as-prefetch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.l6s"), "HLT").unwrap();
+
+        let mut fi_coord = FileInclusionCoordinator::new();
+        fi_coord
+            .add_include_dirs(std::slice::from_ref(&dir))
+            .unwrap();
+        let parsed = fi_coord.prefetch(&["a.l6s".into(), "missing.l6s".into()], |_, contents| {
+            contents.len()
+        });
+        assert_eq!(parsed, HashMap::from([(dir.join("a.l6s"), 3)]));
+
+        // Contents come from the prefetch, even once the file is gone
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(fi_coord.read_file(&"a.l6s".into()).unwrap().1, "HLT");
+        assert!(matches!(
+            fi_coord.read_file(&"missing.l6s".into()),
+            Err(FileInclusionError::FileNotFound(_, _))
+        ));
+    }
+
+    #[test]
+    fn prefetch_first_include_dir() {
+        let dir = std::env::temp_dir().join(format!("l6as-shadow-{}", std::process::id()));
+        for sub in ["first", "second"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join("a.l6s"), sub).unwrap();
+        }
+
+        let mut fi_coord = FileInclusionCoordinator::new();
+        fi_coord
+            .add_include_dirs(&[dir.join("first"), dir.join("second")])
+            .unwrap();
+        fi_coord.prefetch(&["a.l6s".into()], |_, _| ());
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The shadowed copy is not read
+        assert_eq!(fi_coord.prefetched.len(), 1);
+        assert_eq!(fi_coord.read_file(&"a.l6s".into()).unwrap().1, "first");
+    }
+
+    #[test]
+    fn read_file_depth_exceeded() {
+        let mut fi_coord = coordinator(&["a.l6s", "b.l6s", "c.l6s"]);
+        fi_coord.set_max_depth(1);
+        fi_coord.read_file(&"a.l6s".into()).unwrap();
+        fi_coord.read_file(&"b.l6s".into()).unwrap();
+
+        match fi_coord.read_file(&"c.l6s".into()) {
+            Err(FileInclusionError::DepthExceeded(1, chain)) => {
+                assert_eq!(chain, vec![abs("a.l6s"), abs("b.l6s"), abs("c.l6s")])
+            }
+            res => panic!("{:?}", res),
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{
+    args::FmtArgs,
+    assembler::{parse_label, parse_mnemonic_and_args, parse_statement},
+    file::write_file,
+    logging::{print_read_file_error_msg, print_unformatted_file_msg, print_write_file_error_msg},
+    preprocessor::{is_continued_line, parse_source_line, SourceLineBody},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    pub mnemonic_column: usize,
+    pub operand_column: usize,
+    pub comment_column: usize,
+    pub lowercase: bool,
+}
+
+/// Formats source files in place, or only checks them
+pub fn run_formatter(args: &FmtArgs) -> Result<(), ()> {
+    let opts = FormatOptions {
+        mnemonic_column: args.mnemonic_column,
+        operand_column: args.operand_column,
+        comment_column: args.comment_column,
+        lowercase: args.lowercase,
+    };
+
+    let mut error_encountered = false;
+    for file_path in &args.inputs {
+        if format_file(file_path, &opts, args.check).is_err() {
+            error_encountered = true;
+        }
+    }
+
+    match error_encountered {
+        false => Ok(()),
+        true => Err(()),
+    }
+}
+
+fn format_file(file_path: &PathBuf, opts: &FormatOptions, check: bool) -> Result<(), ()> {
+    let source = match fs::read_to_string(file_path) {
+        Ok(source) => source,
+        Err(err) => {
+            print_read_file_error_msg(file_path, err);
+            return Err(());
+        }
+    };
+
+    let formatted = format_source(&source, opts);
+    if formatted == source {
+        return Ok(());
+    }
+
+    // Report files which aren't formatted
+    if check {
+        print_unformatted_file_msg(file_path);
+        return Err(());
+    }
+
+    match write_file(file_path, formatted.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            print_write_file_error_msg(err);
+            Err(())
+        }
+    }
+}
+
+/// Formats a whole source file, keeping its line endings
+pub fn format_source(source: &str, opts: &FormatOptions) -> String {
+    let line_ending = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    // Lines continued by a backslash are kept as they are
+    let mut output = String::new();
+    let mut continuation = false;
+    for line in source.lines() {
+        let continued = is_continued_line(line);
+        match continued || continuation {
+            true => output.push_str(line.trim_end()),
+            false => output.push_str(&format_line(line, opts)),
+        }
+        output.push_str(line_ending);
+        continuation = continued;
+    }
+
+    output
+}
+
+/// Formats a single source line. Lines which can't be parsed, or whose
+/// meaning would change, are only stripped of trailing whitespace
+pub fn format_line(line: &str, opts: &FormatOptions) -> String {
+    let unchanged = line.trim_end().to_owned();
+
+    let (body, comment) = match parse_source_line(line) {
+        Ok((_, (body, comment, garbage))) if garbage.is_empty() => (body, comment),
+        _ => return unchanged,
+    };
+
+    match body {
+        SourceLineBody::Empty => {
+            // Comments at the start of a line stay there, others are indented
+            if comment.is_empty() || line.starts_with(';') {
+                comment
+            } else {
+                pad_to_column("", opts.mnemonic_column) + &comment
+            }
+        }
+        SourceLineBody::Define(identifier, value) => {
+            let formatted = format!("%define {} = {}", identifier, value);
+            append_comment(formatted, &comment, opts)
+        }
+        SourceLineBody::DefaultDefine(identifier, value) => {
+            let formatted = format!("%define? {} = {}", identifier, value);
+            append_comment(formatted, &comment, opts)
+        }
+        SourceLineBody::Include(file_path) => {
+            let file_path = file_path.display().to_string();
+            let formatted = if file_path.contains([' ', '\t']) {
+                format!("%include \"{}\"", file_path)
+            } else {
+                format!("%include {}", file_path)
+            };
+            append_comment(formatted, &comment, opts)
+        }
+        SourceLineBody::LineMarker(line_n, file_path) => {
+            let formatted = format!("%line {} \"{}\"", line_n, file_path.display());
+            append_comment(formatted, &comment, opts)
+        }
+        SourceLineBody::Code(code) => match format_code(&code, opts) {
+            Some(formatted) if same_meaning(&code, &formatted) => {
+                append_comment(formatted, &comment, opts)
+            }
+            _ => unchanged,
+        },
+    }
+}
+
+// Aligns the label, mnemonic and operands of a code line
+fn format_code(code: &str, opts: &FormatOptions) -> Option<String> {
+    // Label keeps the case it was written in
+    let rest = match parse_label(code) {
+        Ok((rest, _)) => rest,
+        Err(_) => code,
+    };
+    let label = &code[..code.len() - rest.len()];
+    let rest = rest.trim();
+
+    if rest.is_empty() {
+        return Some(label.to_owned());
+    }
+
+    let (garbage, (mnemonic, args)) = parse_mnemonic_and_args(rest).ok()?;
+    if !garbage.is_empty() {
+        return None;
+    }
+
+    let mnemonic = match opts.lowercase {
+        false => mnemonic.to_uppercase(),
+        true => mnemonic.to_lowercase(),
+    };
+    let mut formatted = pad_to_column(label, opts.mnemonic_column) + &mnemonic;
+
+    if !args.is_empty() {
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| normalize_registers(arg.trim(), opts.lowercase))
+            .collect();
+        formatted = pad_to_column(&formatted, opts.operand_column) + &args.join(", ");
+    }
+
+    Some(formatted)
+}
+
+// Checks that a formatted line still defines the same label and statement
+fn same_meaning(original: &str, formatted: &str) -> bool {
+    let split = |code: &str| -> (Option<String>, String) {
+        match parse_label(code) {
+            Ok((rest, label)) => (Some(label), rest.trim().to_owned()),
+            Err(_) => (None, code.trim().to_owned()),
+        }
+    };
+    let (original_label, original_rest) = split(original);
+    let (formatted_label, formatted_rest) = split(formatted);
+
+    // Lines using %defines can't be parsed here, they're compared as failures
+    let statement = |code: &str| parse_statement(code).ok().map(|(_, statement)| statement);
+
+    original_label == formatted_label
+        && (original_rest.is_empty() && formatted_rest.is_empty()
+            || statement(&original_rest) == statement(&formatted_rest))
+}
+
+// Changes the case of register names outside of string literals
+fn normalize_registers(arg: &str, lowercase: bool) -> String {
+    let mut output = String::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut in_register = false;
+
+    for chr in arg.chars() {
+        if in_string {
+            match chr {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            output.push(chr);
+            continue;
+        }
+
+        in_register = (in_register && chr.is_alphanumeric()) || chr == '$';
+        match chr {
+            '"' => in_string = true,
+            _ if in_register && lowercase => output.extend(chr.to_lowercase()),
+            _ if in_register => output.extend(chr.to_uppercase()),
+            _ => {}
+        }
+        if !in_register {
+            output.push(chr);
+        }
+    }
+
+    output
+}
+
+fn append_comment(code: String, comment: &str, opts: &FormatOptions) -> String {
+    if comment.is_empty() {
+        code
+    } else {
+        pad_to_column(&code, opts.comment_column) + comment
+    }
+}
+
+// Pads text with spaces up to a column, or separates it with a single space if it's too long
+fn pad_to_column(text: &str, column: usize) -> String {
+    let length = text.chars().count();
+
+    if length < column {
+        format!("{}{}", text, " ".repeat(column - length))
+    } else if length > 0 {
+        format!("{} ", text)
+    } else {
+        text.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTS: FormatOptions = FormatOptions {
+        mnemonic_column: 8,
+        operand_column: 16,
+        comment_column: 40,
+        lowercase: false,
+    };
+
+    #[test]
+    fn format_line_succ() {
+        let tests = [
+            ("", ""),
+            ("   ", ""),
+            ("; Comment", "; Comment"),
+            ("  ; Comment", "        ; Comment"),
+            (
+                "start:   ldr $r1,=5 ; Load",
+                "start:  LDR     $R1, =5                 ; Load",
+            ),
+            ("  b   start", "        B       start"),
+            ("a_very_long_label: hlt", "a_very_long_label: HLT"),
+            ("loop:", "loop:"),
+            ("\t.dw 1,2,  3", "        .DW     1, 2, 3"),
+            ("  .db \"$r1, b\"", "        .DB     \"$r1, b\""),
+            (" lDR $r1, =%value", "        LDR     $R1, =%value"),
+            (
+                "%DEFINE  value=5  ; Five",
+                "%define value = 5                       ; Five",
+            ),
+            ("%define?  DEBUG=0", "%define? DEBUG = 0"),
+            ("%include   defs.inc", "%include defs.inc"),
+            ("%include \"my defs.inc\"", "%include \"my defs.inc\""),
+            // Unparsable lines are kept
+            ("  ldr $R1,, ", "  ldr $R1,,"),
+        ];
+
+        for (input, exp) in tests {
+            assert_eq!(format_line(input, &OPTS), exp, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn format_line_lowercase_succ() {
+        let opts = FormatOptions {
+            lowercase: true,
+            ..OPTS
+        };
+        assert_eq!(
+            format_line("START: LDR $R1, <$B2", &opts),
+            "START:  ldr     $r1, <$b2"
+        );
+    }
+
+    #[test]
+    fn format_source_continued_lines() {
+        let source = "  .dw 1,  \\\n  2\n  hlt\n";
+        assert_eq!(
+            format_source(source, &OPTS),
+            "  .dw 1,  \\\n  2\n        HLT\n"
+        );
+    }
+
+    #[test]
+    fn format_source_idempotent() {
+        let source = "%include defs.inc\r\n\r\n.ORG 0x100\r\nstart: ldr $r1,=5;Load\r\n  ; Loop\r\nloop: b loop\r\n";
+
+        let formatted = format_source(source, &OPTS);
+        assert!(formatted.ends_with("\r\n"));
+        assert_eq!(format_source(&formatted, &OPTS), formatted);
+    }
+
+    #[test]
+    fn normalize_registers_succ() {
+        let tests = [
+            ("$r1", false, "$R1"),
+            ("<$b2.$r3", false, "<$B2.$R3"),
+            ("$R1", true, "$r1"),
+            ("\"$r1\"", false, "\"$r1\""),
+            ("\"a\\\"$r1\"", false, "\"a\\\"$r1\""),
+            ("=label", false, "=label"),
+        ];
+
+        for (input, lowercase, exp) in tests {
+            assert_eq!(normalize_registers(input, lowercase), exp);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use clap::CommandFactory;
+use std::io;
+use strum::IntoEnumIterator;
+
+use crate::{
+    args::{Args, CompletionsArgs, HelpArgs},
+    assembler::{
+        explain_sample_statement, get_opcode_table_entry, get_operand_specs, get_syntax,
+        is_fixed_field, suggest_mnemonic, Arity, Mnemonic,
+    },
+    logging::{
+        print_assembler_error, print_write_file_error_msg, AssemblerError, AssemblerErrorKind,
+    },
+};
+
+/// Prints the help of a mnemonic or of a subcommand, or the general help if no topic is given
+pub fn run_help(args: &HelpArgs) -> Result<(), ()> {
+    let mut command = Args::command();
+
+    let topic = match &args.topic {
+        Some(topic) => topic,
+        None => {
+            let _ = command.print_help();
+            return Ok(());
+        }
+    };
+
+    // Subcommands take precedence, no mnemonic has the name of one. Their help is rendered
+    // by parsing "--help", which gives it the full usage line
+    if command.find_subcommand(topic).is_some() {
+        let name = command.get_name().to_owned();
+        if let Err(err) = command.try_get_matches_from_mut([name.as_str(), topic, "--help"]) {
+            let _ = err.print();
+        }
+        return Ok(());
+    }
+
+    match find_mnemonic(topic) {
+        Some(mnemonic) => {
+            print!("{}", format_mnemonic_help(&mnemonic));
+            Ok(())
+        }
+        None => {
+            print_assembler_error(AssemblerError {
+                kind: AssemblerErrorKind::UnkownMnemonic(topic.to_owned(), suggest_mnemonic(topic)),
+                location: None,
+            });
+            Err(())
+        }
+    }
+}
+
+/// Prints the shell completion script of the assembler
+pub fn run_completions(args: &CompletionsArgs) -> Result<(), ()> {
+    let mut command = Args::command();
+    let name = command.get_name().to_owned();
+    clap_complete::generate(args.shell, &mut command, name, &mut io::stdout());
+    Ok(())
+}
+
+/// Prints the man page of the assembler
+pub fn run_help_man() -> Result<(), ()> {
+    clap_mangen::Man::new(Args::command())
+        .render(&mut io::stdout())
+        .map_err(print_write_file_error_msg)
+}
+
+fn find_mnemonic(name: &str) -> Option<Mnemonic> {
+    Mnemonic::iter().find(|mnemonic| mnemonic.to_string().eq_ignore_ascii_case(name))
+}
+
+/// Formats the syntax, operands and encoding of a mnemonic
+fn format_mnemonic_help(mnemonic: &Mnemonic) -> String {
+    let mut output = format!("{}\n", get_syntax(mnemonic));
+    output.push_str(&format!("kind: {:?}\n", mnemonic.get_kind()));
+
+    // Operands
+    let specs = get_operand_specs(&mnemonic.get_kind());
+    if !specs.is_empty() {
+        output.push_str("\noperands:\n");
+    }
+    for spec in specs {
+        let arity = match spec.arity {
+            Arity::Required => "",
+            Arity::Optional => " (optional)",
+            Arity::Repeated => " (one or more)",
+        };
+        output.push_str(&format!(
+            "  {:<10}{}{}\n",
+            spec.name,
+            spec.operand_type.description(),
+            arity
+        ));
+        for (syntax, description) in spec.operand_type.addressing_modes() {
+            output.push_str(&format!("    {:<12}{}\n", syntax, description));
+        }
+    }
+
+    // Encoding of the instruction word, directives have none
+    if let (Some(entry), Some(words)) = (
+        get_opcode_table_entry(mnemonic),
+        explain_sample_statement(mnemonic),
+    ) {
+        output.push_str(&format!("\nencoding: {}\n", entry.pattern));
+        for field in words.first().map(|word| &word.fields[..]).unwrap_or(&[]) {
+            let bits = match is_fixed_field(field.name) {
+                true => format!("{:0width$b}", field.value, width = field.width as usize),
+                false => "x".repeat(field.width as usize),
+            };
+            output.push_str(&format!(
+                "  {:<10}{:>2} bits  {}\n",
+                field.name, field.width, bits
+            ));
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap_complete::Shell;
+
+    #[test]
+    fn format_mnemonic_help_succ() {
+        let help = format_mnemonic_help(&find_mnemonic("ldr").unwrap());
+        assert!(help.starts_with("LDR $Rn, operand\nkind: DoubleOperandData\n"));
+        assert!(help.contains("  $Rn       data register, $R1 to $R7\n"));
+        assert!(help.contains("    =$Rn        register addressing\n"));
+        assert!(help.contains("\nencoding: 1xxx10000xxxxxxx\n"));
+        assert!(help.contains("  op         5 bits  10000\n"));
+
+        // Directives have no encoding
+        let help = format_mnemonic_help(&find_mnemonic(".dw").unwrap());
+        assert!(help.contains("(one or more)"));
+        assert!(!help.contains("encoding"));
+
+        assert!(find_mnemonic("XYZ").is_none());
+    }
+
+    #[test]
+    fn test_completions_and_man_page() {
+        let mut script = vec![];
+        clap_complete::generate(Shell::Bash, &mut Args::command(), "l6as", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("l6as__subcmd__build"));
+        assert!(script.contains("--include-dirs"));
+
+        let mut page = vec![];
+        clap_mangen::Man::new(Args::command())
+            .render(&mut page)
+            .unwrap();
+        assert!(String::from_utf8(page).unwrap().contains(".SH NAME"));
+    }
+}
@@ -0,0 +1,490 @@
+mod args;
+mod assembler;
+mod branches;
+mod cycles;
+mod explain;
+mod file;
+mod formatter;
+mod help;
+mod logger;
+mod logging;
+mod lsp;
+mod memory_map;
+mod output;
+mod preprocessor;
+mod profile;
+mod project;
+mod symbols;
+mod table_file;
+use std::{ffi::OsString, path::PathBuf, process::exit};
+
+pub use assembler::{DirectiveHandler, DirectiveOutput, DirectiveRegistry};
+
+use args::Command;
+use assembler::{
+    analyze_stack_usage, assemble_with_labels, build_call_graph, build_program, check,
+    get_opcode_table, AssemblerOptions, CpuFeature, ShortImmediateSignedness,
+};
+use clap::Parser;
+use explain::{run_decode_syllable, run_explain};
+
+use cycles::read_cycle_table;
+use file::FileInclusionCoordinator;
+use formatter::run_formatter;
+use help::{run_completions, run_help, run_help_man};
+use logger::{flush_logger, init_logger};
+use logging::{
+    capture_diagnostics, get_exit_code, print_diagnostic_summary, print_final_error_msg,
+    EXIT_SUCCESS, EXIT_USAGE,
+};
+use lsp::run_lsp_server;
+use memory_map::find_occupied_ranges;
+use output::{
+    generate_opcode_table_output, write_ast_output, write_call_graph_output,
+    write_memory_map_output, write_opcode_table_output, write_preprocessor_output,
+    write_stack_report_output, write_symbol_export_output, write_symbol_map_output,
+    BinaryOutputOptions, ListingOptions, ListingRadix, OutputContext, OutputFormatRegistry,
+    PreprocessorOutputOptions, WordOrder,
+};
+use preprocessor::{preprocess, preprocess_all_lines, source_lines_to_code_lines};
+use profile::read_execution_profile;
+use project::{run_build, write_disk_image};
+use symbols::read_symbol_file;
+
+const DEFAULT_PREPROCESSOR_OUT_FILE: &str = "a.l6s";
+const DEFAULT_ASSEMBLER_OUT_FILE_STEM: &str = "a";
+const DEFAULT_AST_OUT_FILE: &str = "a.json";
+const DEFAULT_CALLGRAPH_OUT_FILE_STEM: &str = "callgraph";
+const DEFAULT_MEMORY_MAP_OUT_FILE_STEM: &str = "memory-map";
+
+// Run the assembler with the given command line arguments, the first being the program name
+pub fn run<I, T>(itr: I) -> !
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    run_with_directives(itr, DirectiveRegistry::new())
+}
+
+// Run the assembler with additional directives, for tools built on top of l6as
+pub fn run_with_directives<I, T>(itr: I, directives: DirectiveRegistry) -> !
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let args = match args::Args::try_parse_from(itr) {
+        Ok(args) => args,
+        // Help and version requests are reported as errors too
+        Err(err) => {
+            let _ = err.print();
+            exit(match err.use_stderr() {
+                true => EXIT_USAGE,
+                false => EXIT_SUCCESS,
+            });
+        }
+    };
+
+    if let Err(err) = args.check_output_options() {
+        let _ = err.print();
+        exit(EXIT_USAGE);
+    }
+
+    // Log records are only collected when asked for
+    if init_logger(args.log_json.as_deref()).is_err() {
+        exit_with::<()>(&Err(()));
+    }
+
+    // Subcommands
+    if let Some(command) = &args.command {
+        let _span = tracing::info_span!("command", subcommand = %command.name()).entered();
+        let res = match command {
+            Command::Lsp(lsp_args) => run_lsp_server(lsp_args),
+            Command::Fmt(fmt_args) => run_formatter(fmt_args),
+            Command::Explain(explain_args) => run_explain(explain_args),
+            Command::DecodeSyllable(decode_args) => run_decode_syllable(decode_args),
+            Command::Build(build_args) => run_build(build_args, &directives),
+            Command::Help(help_args) => run_help(help_args),
+            Command::Completions(completions_args) => run_completions(completions_args),
+        };
+
+        exit_with(&res);
+    }
+
+    // Man page doesn't need any input
+    if args.help_man {
+        exit_with(&run_help_man());
+    }
+
+    // Opcode table doesn't need any input
+    if let Some(format) = args.dump_opcodes {
+        exit_with(&command_dump_opcodes(&args, format));
+    }
+
+    // Setup file inclusion coordinator
+    let mut fi_coord = FileInclusionCoordinator::new();
+    fi_coord.add_current_dir().unwrap();
+    fi_coord.add_include_dirs(&args.include_dirs).unwrap();
+    fi_coord.set_max_depth(args.max_include_depth);
+    fi_coord.set_fail_fast(args.fail_fast);
+    fi_coord.set_strict(args.strict);
+
+    // Preprocess only?
+    let span_guard = tracing::info_span!("command", input = %args.input().display()).entered();
+    let res = if args.preprocess {
+        command_preprocessor_only(&args, &mut fi_coord)
+    } else if args.check {
+        command_check(&args, &mut fi_coord, &directives)
+    } else if args.dump_ast {
+        command_dump_ast(&args, &mut fi_coord, &directives)
+    } else {
+        command_assemble(&args, &mut fi_coord, &directives)
+    };
+    drop(span_guard);
+
+    // Failed runs print the summary before the final error message
+    if res.is_ok() {
+        print_diagnostic_summary();
+    }
+
+    // Files actually included, also useful when the run failed
+    if args.print_include_paths {
+        for file_path in fi_coord.included_files() {
+            println!("{}", file_path.display());
+        }
+    }
+
+    exit_with(&res);
+}
+
+// Exits with the code of the run's result, once the log is written out
+fn exit_with<T>(res: &Result<T, ()>) -> ! {
+    flush_logger();
+    exit(get_exit_code(res))
+}
+
+fn command_preprocessor_only(
+    args: &args::Args,
+    fi_coord: &mut FileInclusionCoordinator,
+) -> Result<(), ()> {
+    // Get output file name
+    let out_file = match &args.output {
+        Some(file) => file.clone(),
+        None => PathBuf::from(DEFAULT_PREPROCESSOR_OUT_FILE),
+    };
+
+    // Get output options
+    let opts = PreprocessorOutputOptions {
+        keep_comments: args.keep_comments,
+        keep_blank_lines: args.keep_blank_lines,
+        keep_directives: args.keep_directives,
+        line_markers: args.line_markers,
+    };
+
+    // Run preprocessor
+    match preprocess_all_lines(args.input(), fi_coord) {
+        Ok(lines) => {
+            // Write output
+            write_preprocessor_output(&out_file, &lines, &opts)
+        }
+        Err(_err) => {
+            print_final_error_msg();
+            Err(())
+        }
+    }
+}
+
+fn command_check(
+    args: &args::Args,
+    fi_coord: &mut FileInclusionCoordinator,
+    directives: &DirectiveRegistry,
+) -> Result<(), ()> {
+    // Preprocess
+    let (code_lines, mut error_encountered) = match preprocess(args.input(), fi_coord) {
+        Ok(lines) => (lines, false),
+        Err(lines) => (lines, true),
+    };
+    if error_encountered && args.fail_fast {
+        print_final_error_msg();
+        return Err(());
+    }
+
+    // Check without writing any output
+    let opts = match get_assembler_options(args, directives) {
+        Ok(opts) => opts,
+        Err(()) => {
+            print_final_error_msg();
+            return Err(());
+        }
+    };
+    if check(&code_lines, &opts).is_err() {
+        error_encountered = true;
+    }
+
+    match error_encountered {
+        false => Ok(()),
+        true => {
+            print_final_error_msg();
+            Err(())
+        }
+    }
+}
+
+fn command_dump_ast(
+    args: &args::Args,
+    fi_coord: &mut FileInclusionCoordinator,
+    directives: &DirectiveRegistry,
+) -> Result<(), ()> {
+    // Preprocess
+    let (code_lines, mut error_encountered) = match preprocess(args.input(), fi_coord) {
+        Ok(lines) => (lines, false),
+        Err(lines) => (lines, true),
+    };
+    if error_encountered && args.fail_fast {
+        print_final_error_msg();
+        return Err(());
+    }
+
+    // Parse
+    let opts = match get_assembler_options(args, directives) {
+        Ok(opts) => opts,
+        Err(()) => {
+            print_final_error_msg();
+            return Err(());
+        }
+    };
+    let program = match build_program(&code_lines, &opts) {
+        Ok(program) => program,
+        Err(program) => {
+            error_encountered = true;
+            program
+        }
+    };
+
+    if !error_encountered {
+        // Get output file name
+        let out_file = match &args.output {
+            Some(file) => file.clone(),
+            None => PathBuf::from(DEFAULT_AST_OUT_FILE),
+        };
+        // Write AST
+        write_ast_output(&out_file, &program)
+    } else {
+        print_final_error_msg();
+        Err(())
+    }
+}
+
+fn command_dump_opcodes(args: &args::Args, format: args::OpcodeTableFormat) -> Result<(), ()> {
+    let table = get_opcode_table();
+
+    // Print to standard output unless an output file is given
+    match &args.output {
+        None => {
+            print!("{}", generate_opcode_table_output(&table, format));
+            Ok(())
+        }
+        Some(file_path) => write_opcode_table_output(file_path, &table, format),
+    }
+}
+
+fn command_assemble(
+    args: &args::Args,
+    fi_coord: &mut FileInclusionCoordinator,
+    directives: &DirectiveRegistry,
+) -> Result<(), ()> {
+    // Has an error happened?
+    let mut error_encountered = false;
+
+    // Preprocess, keeping the lines without code for the listing
+    let source_lines = match preprocess_all_lines(args.input(), fi_coord) {
+        Ok(lines) => lines,
+        Err(lines) => {
+            error_encountered = true;
+            lines
+        }
+    };
+    if error_encountered && args.fail_fast {
+        print_final_error_msg();
+        return Err(());
+    }
+    let code_lines = source_lines_to_code_lines(&source_lines);
+
+    // Assemble
+    let opts = match get_assembler_options(args, directives) {
+        Ok(opts) => opts,
+        Err(()) => {
+            print_final_error_msg();
+            return Err(());
+        }
+    };
+    let (res, labels) = assemble_with_labels(&code_lines, &opts);
+    let assembled_lines = match res {
+        Ok(lines) => lines,
+        Err(lines) => {
+            error_encountered = true;
+            lines
+        }
+    };
+
+    if !error_encountered {
+        // Write symbol map
+        if let Some(map_file) = &args.map {
+            write_symbol_map_output(map_file, &labels)?;
+        }
+        if let Some(export_file) = &args.export_symbols {
+            write_symbol_export_output(export_file, &labels)?;
+        }
+
+        // Write memory map
+        if let Some(format) = args.memory_map {
+            let map_file = match &args.memory_map_output {
+                Some(file) => file.clone(),
+                None => PathBuf::from(DEFAULT_MEMORY_MAP_OUT_FILE_STEM)
+                    .with_extension(format.extension()),
+            };
+            let ranges = find_occupied_ranges(&assembled_lines);
+            write_memory_map_output(&map_file, &ranges, args.max_size, format)?;
+        }
+
+        // Write analyses of the program, already checked by the assembly
+        if args.stack_report.is_some() || args.callgraph.is_some() {
+            let (program, _) = capture_diagnostics(|| build_program(&code_lines, &opts));
+            if let Ok(program) = program {
+                if let Some(report_file) = &args.stack_report {
+                    write_stack_report_output(report_file, &analyze_stack_usage(&program))?;
+                }
+                if let Some(format) = args.callgraph {
+                    let graph_file = match &args.callgraph_output {
+                        Some(file) => file.clone(),
+                        None => PathBuf::from(DEFAULT_CALLGRAPH_OUT_FILE_STEM)
+                            .with_extension(format.extension()),
+                    };
+                    write_call_graph_output(&graph_file, &build_call_graph(&program), format)?;
+                }
+            }
+        }
+
+        // Put program on a disk image
+        if let Some(disk_file) = &args.disk {
+            let profile_file = args.disk_profile_file.as_deref();
+            write_disk_image(
+                disk_file,
+                &args.disk_profile,
+                profile_file,
+                &assembled_lines,
+            )?;
+
+            // The disk image replaces the binary output, unless asked for
+            if args.output.is_none() {
+                return Ok(());
+            }
+        }
+
+        // Read execution trace
+        let profile = match &args.trace {
+            Some(file_path) => match read_execution_profile(file_path) {
+                Ok(profile) => Some(profile),
+                Err(()) => {
+                    print_final_error_msg();
+                    return Err(());
+                }
+            },
+            None => None,
+        };
+
+        // Find output format, only built-in formats can be selected
+        let format_name = match args.listing {
+            true => "listing",
+            false => &args.format,
+        };
+        let formats = OutputFormatRegistry::builtin();
+        let format = formats.get(format_name).unwrap();
+
+        // Get output file name
+        let out_file = match &args.output {
+            Some(file) => file.clone(),
+            None => PathBuf::from(format!(
+                "{}.{}",
+                DEFAULT_ASSEMBLER_OUT_FILE_STEM,
+                format.extension()
+            )),
+        };
+
+        // Write output
+        let ctx = OutputContext {
+            labels: &labels,
+            entry: args.entry.as_deref(),
+            binary: BinaryOutputOptions {
+                pad_to: args.pad_to,
+                pad_byte: args.pad_byte,
+                split: args.split,
+                swap_bytes: args.swap_bytes,
+                word_order: match args.word_order {
+                    args::WordOrder::HighLow => WordOrder::HighLow,
+                    args::WordOrder::LowHigh => WordOrder::LowHigh,
+                },
+            },
+            listing: ListingOptions {
+                cycle_table: opts.cycle_table.as_ref(),
+                profile: profile.as_ref(),
+                radix: match args.listing_radix {
+                    args::ListingRadix::Hex => ListingRadix::Hex,
+                    args::ListingRadix::Octal => ListingRadix::Octal,
+                    args::ListingRadix::Both => ListingRadix::Both,
+                },
+                source: Some(&source_lines),
+            },
+        };
+        format.write(&out_file, &assembled_lines, &ctx)
+    } else {
+        logging::print_final_error_msg();
+        Err(())
+    }
+}
+
+// Builds assembler options from the arguments, reading symbol files and the cycle table
+fn get_assembler_options(
+    args: &args::Args,
+    directives: &DirectiveRegistry,
+) -> Result<AssemblerOptions, ()> {
+    let mut opts = AssemblerOptions {
+        paranoid: args.paranoid,
+        pic: args.pic,
+        max_size: args.max_size,
+        fail_fast: args.fail_fast,
+        directives: directives.clone(),
+        short_immediates: match args.short_immediates {
+            args::ShortImmediates::Any => ShortImmediateSignedness::Any,
+            args::ShortImmediates::Signed => ShortImmediateSignedness::Signed,
+            args::ShortImmediates::Unsigned => ShortImmediateSignedness::Unsigned,
+        },
+        cpu_features: args
+            .cpu_feature
+            .iter()
+            .map(|feature| match feature {
+                args::CpuFeature::Sip => CpuFeature::Sip,
+                args::CpuFeature::Cip => CpuFeature::Cip,
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    let mut error_encountered = false;
+    if let Some(file_path) = &args.cycle_table {
+        match read_cycle_table(file_path) {
+            Ok(table) => opts.cycle_table = Some(table),
+            Err(()) => error_encountered = true,
+        }
+    }
+    for file_path in &args.symbols {
+        match read_symbol_file(file_path) {
+            Ok(symbols) => opts.symbols.extend(symbols),
+            Err(()) => error_encountered = true,
+        }
+    }
+
+    match error_encountered {
+        false => Ok(opts),
+        true => Err(()),
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use tracing_subscriber::{
+    fmt::{format::FmtSpan, time::Uptime},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use super::logging::print_write_file_error_msg;
+
+/// Environment variable selecting the verbosity of the log, as an `EnvFilter` directive,
+/// e.g. "debug" or "info,l6as::preprocessor=trace"
+pub const LOG_ENV_VAR: &str = "L6AS_LOG";
+
+/// Target of the events mirroring the diagnostics printed to the user
+pub const DIAGNOSTICS_TARGET: &str = "l6as::diagnostics";
+
+/// Builds the filter of the stderr log. Diagnostics are left out, as they are already
+/// printed on stdout
+fn stderr_filter(spec: &str) -> Result<EnvFilter, String> {
+    let filter = EnvFilter::try_new(spec).map_err(|err| err.to_string())?;
+    Ok(filter.add_directive(format!("{}=off", DIAGNOSTICS_TARGET).parse().unwrap()))
+}
+
+/// Installs the subscriber. Events are printed on stderr if `L6AS_LOG` is set, and written as
+/// JSON lines to `json_file` if given, at info level unless `L6AS_LOG` says otherwise.
+/// Closing a span logs the time spent in it
+pub fn init_logger(json_file: Option<&Path>) -> Result<(), ()> {
+    let spec = env::var(LOG_ENV_VAR).ok();
+    let stderr = match spec.as_deref().map(stderr_filter) {
+        Some(Ok(filter)) => Some(
+            tracing_subscriber::fmt::layer()
+                .with_writer(io::stderr)
+                .with_timer(Uptime::default())
+                .with_span_events(FmtSpan::CLOSE)
+                .with_filter(filter),
+        ),
+        Some(Err(msg)) => {
+            eprintln!("l6as: ignoring {}: {}", LOG_ENV_VAR, msg);
+            None
+        }
+        None => None,
+    };
+
+    let json = match json_file {
+        Some(file_path) => {
+            let file = match File::create(file_path) {
+                Ok(file) => file,
+                Err(err) => {
+                    print_write_file_error_msg(err);
+                    return Err(());
+                }
+            };
+            let filter = spec
+                .as_deref()
+                .and_then(|spec| EnvFilter::try_new(spec).ok())
+                .unwrap_or_else(|| EnvFilter::new("info"));
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_writer(Mutex::new(file))
+                    .with_timer(Uptime::default())
+                    .with_span_events(FmtSpan::CLOSE)
+                    .with_filter(filter),
+            )
+        }
+        None => None,
+    };
+
+    if stderr.is_none() && json.is_none() {
+        return Ok(());
+    }
+
+    // Only fails if a subscriber is already installed, e.g. by a tool built on l6as
+    let _ = tracing_subscriber::registry()
+        .with(stderr)
+        .with(json)
+        .try_init();
+    Ok(())
+}
+
+/// Writes out buffered events, before the process exits
+pub fn flush_logger() {
+    let _ = io::stderr().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::level_filters::LevelFilter;
+
+    #[test]
+    fn test_stderr_filter() {
+        let filter = stderr_filter("warn,l6as::assembler=debug").unwrap();
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::DEBUG));
+
+        assert!(stderr_filter("l6as=loud").is_err());
+    }
+}
@@ -1,6 +1,11 @@
-use super::assembler::{BaseRegister, DataRegister, Mnemonic};
+use super::assembler::{get_syntax, BaseRegister, DataRegister, Mnemonic, MAX_VECTOR_INDEX};
+use super::logger::DIAGNOSTICS_TARGET;
 use super::preprocessor::LineLocation;
-use std::{io, path::PathBuf};
+use std::{
+    cell::RefCell,
+    io,
+    path::{Path, PathBuf},
+};
 
 use colored::Colorize;
 
@@ -15,6 +20,15 @@ pub struct PreprocessorWarning {
     pub file_name: PathBuf,
     pub line: String,
     pub kind: PreprocessorWarningKind,
+    pub span: Option<ColumnSpan>,
+}
+
+/// Columns of the first line of a location a diagnostic points at, in characters from 0,
+/// the end excluded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnSpan {
+    pub start: usize,
+    pub end: usize,
 }
 
 impl PreprocessorWarning {
@@ -33,14 +47,21 @@ pub enum PreprocessorErrorKind {
     IncludeMissingFilePath,
     DefineMissingIdentifier,
     DefineMissingValue(String),
+    InvalidLineMarker,
 
     // %include processing
-    CannotOpenSourceFile(PathBuf),
+    CannotOpenSourceFile(PathBuf, Vec<PathBuf>),
     DobleInclusion(PathBuf),
+    IncludeCycle(Vec<PathBuf>),
+    IncludeDepthExceeded(usize, Vec<PathBuf>),
 
     // %define processing
     DefineMultipleDefinition(String),
     DefineUndefined(String),
+    DefineCycle(Vec<String>),
+
+    // Lines parsed only in part, an error with --strict
+    GarbageAtEndOfLine(String),
 
     // Unknown
     Nom(nom::error::ErrorKind),
@@ -65,18 +86,48 @@ impl PreprocessorError {
             PreprocessorErrorKind::DefineMissingValue(ident) => {
                 format!("missing value for %define \"{}\"", ident)
             }
-            PreprocessorErrorKind::CannotOpenSourceFile(file_path) => {
-                format!("unable to find source file \"{}\": ", file_path.display())
+            PreprocessorErrorKind::InvalidLineMarker => {
+                "%line needs a line number from 1 and a file path".to_owned()
+            }
+            PreprocessorErrorKind::CannotOpenSourceFile(file_path, searched_dirs) => {
+                let searched_dirs: Vec<String> = searched_dirs
+                    .iter()
+                    .map(|dir| format!("\"{}\"", dir.display()))
+                    .collect();
+                format!(
+                    "unable to find source file \"{}\", searched in: {}",
+                    file_path.display(),
+                    searched_dirs.join(", ")
+                )
             }
             PreprocessorErrorKind::DobleInclusion(file_path) => {
                 format!("double %include for file \"{}\"", file_path.display())
             }
+            PreprocessorErrorKind::IncludeCycle(chain) => {
+                format!(
+                    "%include cycle: {}",
+                    get_inclusion_chain_display_value(chain)
+                )
+            }
+            PreprocessorErrorKind::IncludeDepthExceeded(max_depth, chain) => {
+                format!(
+                    "%include nested more than {} levels deep: {}",
+                    max_depth,
+                    get_inclusion_chain_display_value(chain)
+                )
+            }
             PreprocessorErrorKind::DefineUndefined(identifier) => {
                 format!("no %define for identifier \"{}\"", identifier)
             }
+            PreprocessorErrorKind::DefineCycle(chain) => {
+                format!("%define cycle: {}", chain.join(" -> "))
+            }
             PreprocessorErrorKind::DefineMultipleDefinition(identifier) => {
                 format!("multiple %define for identifier \"{}\"", identifier)
             }
+            PreprocessorErrorKind::GarbageAtEndOfLine(garbage) => {
+                format!("unexpected garbage at end of line: \"{}\"", garbage)
+            }
             PreprocessorErrorKind::Nom(kind) => {
                 format!("unknown nom error: {:?}", kind)
             }
@@ -87,6 +138,15 @@ impl PreprocessorError {
     }
 }
 
+// Files in an inclusion chain, from the outermost one
+fn get_inclusion_chain_display_value(chain: &[PathBuf]) -> String {
+    let files: Vec<String> = chain
+        .iter()
+        .map(|file_path| format!("\"{}\"", file_path.display()))
+        .collect();
+    files.join(" -> ")
+}
+
 #[derive(Debug)]
 pub enum AssemblerErrorKind {
     // Unknown
@@ -97,11 +157,11 @@ pub enum AssemblerErrorKind {
 
     // Statement parsing
     MnemonicRequired,
-    UnkownMnemonic(String),
+    UnkownMnemonic(String, Option<String>),
 
     // Argument parsing
     MalformedArgumentList,
-    WrongNumberOfArguments(Mnemonic, usize, usize),
+    WrongNumberOfArguments(Mnemonic, usize, Option<usize>, usize),
     InvalidAddress(String),
     UnexpectedCharactersAtEndOfArgument(String),
     InvalidBranchLocation(String),
@@ -114,9 +174,29 @@ pub enum AssemblerErrorKind {
     WrongRegisterType(String, Mnemonic),
     RegisterAddressingInvalid(Mnemonic),
     ImmediateAddressingInvalid(Mnemonic),
+    ImmediateOperandWrite(Mnemonic, String),
     InvalidMaskWord(String),
+    MaskWithIndexedAddressing(Mnemonic),
     InvalidShiftDistance(String),
     InvalidChannelExpression(String),
+    InvalidBankNumber(String),
+    InvalidLabel(String),
+    InvalidConstantExpression(String),
+    InvalidSymbolExpression(String),
+    FillCountOutOfRange(i128),
+    SeriesCountOutOfRange(i128),
+    RandomCountOutOfRange(i128),
+    RandomSeedOutOfRange(i128),
+    EquWithoutLabel,
+    WeakWithoutLabel,
+    IoDelayWithoutNopCycles,
+    LabelAddressAssertionFailed(String, u64, u64),
+    VectorIndexOutOfRange(i128),
+    VectorDoubleDefinition(u64),
+    UnknownCpuFeature(String),
+    ElseWithoutIfCpu,
+    EndIfWithoutIfCpu,
+    IfCpuWithoutEndIf,
 
     // Code Generation
     BranchAddressOutOfRange(u64),
@@ -124,10 +204,12 @@ pub enum AssemblerErrorKind {
     BranchShortDisplacementOutOfRange(i128),
     BranchShortDisplacementMustNotBe0Or1,
     WordDisplacementOutOfRange(i128),
-    ShortImmediateValueOutOfRange(i128),
-    UndefinedLabel(String),
+    ShortImmediateValueOutOfRange(i128, i128, i128),
+    UndefinedLabel(String, Option<String>),
+    EquValueOutOfRange(i128),
     DataDefinitionValueOutOfRange(i128),
     ImmediateValueOutOfRange(i128),
+    ByteImmediateValueOutOfRange(i128),
     InvalidIndexRegister(DataRegister),
     ImmediateAddressOutOfRange(u64),
     DisplacementOutOfRange(i128),
@@ -136,6 +218,55 @@ pub enum AssemblerErrorKind {
     ShiftDistanceOutOfRange(u64),
     ChannelOutOfRange(u64),
     FunctionCodeOutOfRange(u64),
+
+    // Banks
+    CrossBankBranch(String, Option<u64>, u64),
+
+    // Position independent code
+    AbsoluteAddressingInPic(String),
+
+    // Size limits
+    ImageSizeExceeded(Option<u64>, u64, u64),
+
+    // Address syllable decoding
+    InvalidAddressSyllableEncoding(u8),
+    AddressSyllableExtraWordMissing,
+    AddressSyllableRoundTripMismatch(String, String),
+
+    // Directive handlers
+    DirectiveFailed(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssemblerWarningKind {
+    UnusedLabel(String),
+    UnreachableCode,
+    PRelativeAcrossRegions(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblerWarning {
+    pub kind: AssemblerWarningKind,
+    pub location: Option<LineLocation>,
+}
+
+impl AssemblerWarning {
+    pub fn message(&self) -> String {
+        match &self.kind {
+            AssemblerWarningKind::UnusedLabel(label) => {
+                format!("label is never referenced: \"{}\"", label)
+            }
+            AssemblerWarningKind::UnreachableCode => {
+                "unreachable code after branch, return or halt".to_owned()
+            }
+            AssemblerWarningKind::PRelativeAcrossRegions(target) => {
+                format!(
+                    "P-relative operand refers to {:#06X} in another .ORG region, only valid if both are loaded at their assembled addresses",
+                    target
+                )
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -156,14 +287,29 @@ impl AssemblerError {
             AssemblerErrorKind::MnemonicRequired => {
                 format!("a mnemonic is required")
             }
-            AssemblerErrorKind::UnkownMnemonic(mnemo) => {
-                format!("unkown mnemonic: \"{}\"", mnemo)
+            AssemblerErrorKind::UnkownMnemonic(mnemo, suggestion) => {
+                format!(
+                    "unkown mnemonic: \"{}\"{}",
+                    mnemo,
+                    get_suggestion_display_value(suggestion)
+                )
             }
             AssemblerErrorKind::MalformedArgumentList => {
                 format!("malformed argument list")
             }
-            AssemblerErrorKind::WrongNumberOfArguments(mnemonic, expected, got) => {
-                format!("{} takes {} arguments, got {}", mnemonic, expected, got)
+            AssemblerErrorKind::WrongNumberOfArguments(mnemonic, min, max, got) => {
+                let expected = match max {
+                    Some(max) if max == min => format!("{}", min),
+                    Some(max) => format!("{} to {}", min, max),
+                    None => format!("at least {}", min),
+                };
+                format!(
+                    "{} takes {} arguments, got {} (syntax: {})",
+                    mnemonic,
+                    expected,
+                    got,
+                    get_syntax(mnemonic)
+                )
             }
             AssemblerErrorKind::InvalidAddress(arg) => {
                 format!("invalid address: \"{}\"", arg)
@@ -195,18 +341,80 @@ impl AssemblerError {
             AssemblerErrorKind::RegisterAddressingInvalid(mnemo) => {
                 format!("register addressing invalid for {}", mnemo)
             }
+            AssemblerErrorKind::ImmediateOperandWrite(mnemo, arg) => {
+                format!("{} can't store into immediate operand \"{}\"", mnemo, arg)
+            }
             AssemblerErrorKind::ImmediateAddressingInvalid(mnemo) => {
                 format!("immediate addressing invalid for {}", mnemo)
             }
             AssemblerErrorKind::InvalidMaskWord(arg) => {
                 format!("invalid mask word: {}", arg)
             }
+            AssemblerErrorKind::MaskWithIndexedAddressing(mnemo) => {
+                format!(
+                    "mask word invalid for {} with indexed addressing, the index register selects the bit",
+                    mnemo
+                )
+            }
             AssemblerErrorKind::InvalidShiftDistance(arg) => {
                 format!("invalid shift distance: {}", arg)
             }
             AssemblerErrorKind::InvalidChannelExpression(arg) => {
                 format!("invalid channel expression: {}", arg)
             }
+            AssemblerErrorKind::InvalidBankNumber(arg) => {
+                format!("invalid bank number: {}", arg)
+            }
+            AssemblerErrorKind::InvalidLabel(arg) => {
+                format!("invalid label: {}", arg)
+            }
+            AssemblerErrorKind::InvalidConstantExpression(arg) => {
+                format!("invalid constant expression: {}", arg)
+            }
+            AssemblerErrorKind::InvalidSymbolExpression(arg) => {
+                format!("invalid label or constant expression: {}", arg)
+            }
+            AssemblerErrorKind::FillCountOutOfRange(count) => {
+                format!("fill count out of range: {}", count)
+            }
+            AssemblerErrorKind::SeriesCountOutOfRange(count) => {
+                format!("series count out of range: {}", count)
+            }
+            AssemblerErrorKind::RandomCountOutOfRange(count) => {
+                format!("random count out of range: {}", count)
+            }
+            AssemblerErrorKind::RandomSeedOutOfRange(seed) => {
+                format!("random seed out of range (1 to 0xFFFF): {}", seed)
+            }
+            AssemblerErrorKind::EquWithoutLabel => "missing label for .EQU".to_owned(),
+            AssemblerErrorKind::WeakWithoutLabel => "missing label for .WEAK".to_owned(),
+            AssemblerErrorKind::IoDelayWithoutNopCycles => {
+                ".IODELAY needs a cycle table with the cycles of NOP".to_owned()
+            }
+            AssemblerErrorKind::VectorIndexOutOfRange(index) => {
+                format!(
+                    "vector index out of range: {} (0 to {})",
+                    index, MAX_VECTOR_INDEX
+                )
+            }
+            AssemblerErrorKind::VectorDoubleDefinition(index) => {
+                format!("vector {} is already defined in this table", index)
+            }
+            AssemblerErrorKind::UnknownCpuFeature(feature) => {
+                format!("unknown CPU feature: \"{}\" (SIP or CIP)", feature)
+            }
+            AssemblerErrorKind::ElseWithoutIfCpu => ".ELSE without .IFCPU".to_owned(),
+            AssemblerErrorKind::EndIfWithoutIfCpu => ".ENDIF without .IFCPU".to_owned(),
+            AssemblerErrorKind::IfCpuWithoutEndIf => ".IFCPU without .ENDIF".to_owned(),
+            AssemblerErrorKind::LabelAddressAssertionFailed(label, address, expected) => {
+                format!(
+                    "label \"{}\" is at {:#06X}, asserted at {:#06X}",
+                    label, address, expected
+                )
+            }
+            AssemblerErrorKind::EquValueOutOfRange(value) => {
+                format!(".EQU value out of range: {}", value)
+            }
             AssemblerErrorKind::UnexpectedCharactersAtEndOfArgument(arg) => {
                 format!("unexpected characters at end of argument: \"{}\"", arg)
             }
@@ -225,11 +433,18 @@ impl AssemblerError {
             AssemblerErrorKind::WordDisplacementOutOfRange(addr) => {
                 format!("word displacement out of range: {}", addr)
             }
-            AssemblerErrorKind::ShortImmediateValueOutOfRange(val) => {
-                format!("short immediate value out of range: ({:#X}) {}", val, val)
+            AssemblerErrorKind::ShortImmediateValueOutOfRange(val, min, max) => {
+                format!(
+                    "short immediate value out of range: ({:#X}) {}, must be {} to {}",
+                    val, val, min, max
+                )
             }
-            AssemblerErrorKind::UndefinedLabel(label) => {
-                format!("undefined label: \"{}\"", label)
+            AssemblerErrorKind::UndefinedLabel(label, suggestion) => {
+                format!(
+                    "undefined label: \"{}\"{}",
+                    label,
+                    get_suggestion_display_value(suggestion)
+                )
             }
             AssemblerErrorKind::DataDefinitionValueOutOfRange(val) => {
                 format!("data definition value out of range: ({:#X}) {}", val, val)
@@ -237,12 +452,15 @@ impl AssemblerError {
             AssemblerErrorKind::ImmediateValueOutOfRange(val) => {
                 format!("immediate value out of range: ({:#X}) {}", val, val)
             }
-            AssemblerErrorKind::InvalidIndexRegister(reg) => {
+            AssemblerErrorKind::ByteImmediateValueOutOfRange(val) => {
                 format!(
-                    "invalid index register: {} ",
-                    get_data_register_display_value(reg)
+                    "immediate value out of range for a byte instruction: ({:#X}) {}, must be -128 to 255",
+                    val, val
                 )
             }
+            AssemblerErrorKind::InvalidIndexRegister(reg) => {
+                format!("invalid index register: {}", reg)
+            }
             AssemblerErrorKind::ImmediateAddressOutOfRange(addr) => {
                 format!("immediate address out of range: ({:#X}) {}", addr, addr)
             }
@@ -250,10 +468,7 @@ impl AssemblerError {
                 format!("displacement out of range: {}", disp)
             }
             AssemblerErrorKind::InvalidBaseRegisterAddrSyl(reg) => {
-                format!(
-                    "invalid base register: {}",
-                    get_base_register_display_value(reg)
-                )
+                format!("invalid base register: {}", reg)
             }
             AssemblerErrorKind::MaskWordOutOfRange(mask) => {
                 format!("mask word out of range: ({:#X}) {}", mask, mask)
@@ -267,54 +482,435 @@ impl AssemblerError {
             AssemblerErrorKind::FunctionCodeOutOfRange(func) => {
                 format!("function code out of range: ({:#X}) {}", func, func)
             }
+            AssemblerErrorKind::CrossBankBranch(label, from_bank, to_bank) => {
+                let from_bank = match from_bank {
+                    Some(bank) => format!("bank {}", bank),
+                    None => "common code".to_owned(),
+                };
+                format!(
+                    "direct branch from {} to \"{}\" in bank {}, branch through a .TRAMPOLINE instead",
+                    from_bank, label, to_bank
+                )
+            }
+            AssemblerErrorKind::AbsoluteAddressingInPic(operand) => {
+                format!(
+                    "absolute address \"{}\" in position independent code, use P-relative or B-relative addressing instead",
+                    operand
+                )
+            }
+            AssemblerErrorKind::ImageSizeExceeded(bank, size, limit) => {
+                let image = match bank {
+                    Some(bank) => format!("bank {} image", bank),
+                    None => "image".to_owned(),
+                };
+                format!(
+                    "{} exceeds maximum size: {} words, limit is {} words",
+                    image, size, limit
+                )
+            }
+            AssemblerErrorKind::InvalidAddressSyllableEncoding(field) => {
+                format!("invalid address syllable encoding: {:#09b}", field)
+            }
+            AssemblerErrorKind::AddressSyllableExtraWordMissing => {
+                "address syllable extra word missing".to_owned()
+            }
+            AssemblerErrorKind::AddressSyllableRoundTripMismatch(original, decoded) => {
+                format!(
+                    "address syllable changed after decoding: {} became {}",
+                    original, decoded
+                )
+            }
+            AssemblerErrorKind::DirectiveFailed(directive, msg) => {
+                format!("{} failed: {}", directive, msg)
+            }
+        }
+    }
+}
+
+/// Errors in the files of entries read alongside the program
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableFileErrorKind {
+    // Symbol files
+    InvalidSymbolDefinition(String),
+
+    // Cycle tables
+    InvalidCycleTableEntry(String),
+
+    // Execution traces
+    InvalidTraceEntry(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableFileError {
+    pub kind: TableFileErrorKind,
+    pub location: LineLocation,
+}
+
+impl TableFileError {
+    pub fn message(&self) -> String {
+        match &self.kind {
+            TableFileErrorKind::InvalidSymbolDefinition(def) => {
+                format!(
+                    "invalid symbol definition, expected label and address: \"{}\"",
+                    def
+                )
+            }
+            TableFileErrorKind::InvalidCycleTableEntry(entry) => {
+                format!(
+                    "invalid cycle table entry, expected mnemonic, cycles and optionally cycles per extra word: \"{}\"",
+                    entry
+                )
+            }
+            TableFileErrorKind::InvalidTraceEntry(entry) => {
+                format!(
+                    "invalid trace entry, expected address and execution count: \"{}\"",
+                    entry
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Error or warning collected instead of being printed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<LineLocation>,
+    pub span: Option<ColumnSpan>,
+}
+
+// Errors and warnings printed for a file
+#[derive(Debug, Clone, PartialEq)]
+struct FileDiagnosticCount {
+    file_name: PathBuf,
+    errors: usize,
+    warnings: usize,
+}
+
+/// Exit code of a successful run
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit code of failures which aren't in any other class, e.g. unformatted files
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code of invalid command lines, the one used by clap, and of invalid project, symbol,
+/// cycle table and trace files
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code of runs which couldn't read or write a file
+pub const EXIT_IO_ERROR: i32 = 3;
+/// Exit code of runs with preprocessor errors
+pub const EXIT_PREPROCESSOR_ERROR: i32 = 4;
+/// Exit code of runs with assembler errors
+pub const EXIT_ASSEMBLER_ERROR: i32 = 5;
+
+// Class of a printed error, in increasing order of precedence for the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ErrorClass {
+    Assembler,
+    Preprocessor,
+    Usage,
+    Io,
+}
+
+thread_local! {
+    // Diagnostics collected while capturing, None when printing normally
+    static CAPTURED_DIAGNOSTICS: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+    // Diagnostics printed so far, by file in order of first diagnostic
+    static DIAGNOSTIC_COUNTS: RefCell<Vec<FileDiagnosticCount>> = const { RefCell::new(vec![]) };
+    // Class of the printed error which determines the exit code
+    static ERROR_CLASS: RefCell<Option<ErrorClass>> = const { RefCell::new(None) };
+}
+
+// Records the class of a printed error, keeping the one with the highest precedence
+fn record_error_class(class: ErrorClass) {
+    ERROR_CLASS.with(|current| {
+        let mut current = current.borrow_mut();
+        *current = (*current).max(Some(class));
+    });
+}
+
+/// Exit code of a run, from the class of the errors printed while it failed. Missing or
+/// unwritable files take precedence over invalid options and input files, then come
+/// preprocessor errors and last assembler errors
+pub fn get_exit_code<T>(res: &Result<T, ()>) -> i32 {
+    if res.is_ok() {
+        return EXIT_SUCCESS;
+    }
+
+    match ERROR_CLASS.with(|class| *class.borrow()) {
+        Some(ErrorClass::Io) => EXIT_IO_ERROR,
+        Some(ErrorClass::Usage) => EXIT_USAGE,
+        Some(ErrorClass::Preprocessor) => EXIT_PREPROCESSOR_ERROR,
+        Some(ErrorClass::Assembler) => EXIT_ASSEMBLER_ERROR,
+        None => EXIT_FAILURE,
+    }
+}
+
+/// Runs `f`, collecting the errors and warnings it reports instead of printing them
+pub fn capture_diagnostics<T>(f: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+    CAPTURED_DIAGNOSTICS.with(|captured| *captured.borrow_mut() = Some(vec![]));
+    let res = f();
+    let diagnostics = CAPTURED_DIAGNOSTICS
+        .with(|captured| captured.borrow_mut().take())
+        .unwrap_or_default();
+
+    (res, diagnostics)
+}
+
+// Collects a diagnostic if capturing is active, returns false otherwise. Printed diagnostics are
+// also logged, so they appear in the JSON log alongside the spans they were reported in
+fn capture_diagnostic(
+    severity: Severity,
+    message: String,
+    location: &Option<LineLocation>,
+    span: Option<ColumnSpan>,
+) -> bool {
+    let text = match location {
+        Some(location) => format!(
+            "{}:{}: {}",
+            location.file_name.display(),
+            location.line_n,
+            message
+        ),
+        None => message.clone(),
+    };
+    match severity {
+        Severity::Error => tracing::error!(target: DIAGNOSTICS_TARGET, "{}", text),
+        Severity::Warning => tracing::warn!(target: DIAGNOSTICS_TARGET, "{}", text),
+    }
+
+    CAPTURED_DIAGNOSTICS.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(diagnostics) => {
+            diagnostics.push(Diagnostic {
+                severity,
+                message,
+                location: location.clone(),
+                span,
+            });
+            true
+        }
+        None => false,
+    })
+}
+
+// Counts a printed diagnostic towards the summary of its file
+fn count_diagnostic(severity: Severity, location: &Option<LineLocation>) {
+    let Some(location) = location else {
+        return;
+    };
+
+    DIAGNOSTIC_COUNTS.with(|counts| {
+        let mut counts = counts.borrow_mut();
+        let i = match counts
+            .iter()
+            .position(|count| count.file_name == location.file_name)
+        {
+            Some(i) => i,
+            None => {
+                counts.push(FileDiagnosticCount {
+                    file_name: location.file_name.clone(),
+                    errors: 0,
+                    warnings: 0,
+                });
+                counts.len() - 1
+            }
+        };
+
+        match severity {
+            Severity::Error => counts[i].errors += 1,
+            Severity::Warning => counts[i].warnings += 1,
         }
+    });
+}
+
+/// Prints how many errors and warnings each file had, if they span more than one file
+pub fn print_diagnostic_summary() {
+    let counts = DIAGNOSTIC_COUNTS.with(|counts| counts.borrow().clone());
+    if counts.len() < 2 {
+        return;
+    }
+
+    println!("l6as: diagnostics by file:");
+    for count in counts {
+        println!(
+            "  {}: {} {}, {} {}",
+            count.file_name.display(),
+            count.errors,
+            match count.errors {
+                1 => "error",
+                _ => "errors",
+            },
+            count.warnings,
+            match count.warnings {
+                1 => "warning",
+                _ => "warnings",
+            }
+        );
     }
 }
 
 pub fn print_preprocessor_warning(msg: PreprocessorWarning) {
+    let location = Some(LineLocation {
+        line_n: msg.line_n,
+        file_name: msg.file_name.clone(),
+        raw_content: msg.line.clone(),
+    });
+    if capture_diagnostic(Severity::Warning, msg.message(), &location, msg.span) {
+        return;
+    }
+    count_diagnostic(Severity::Warning, &location);
+
     println!(
-        "{} [preprocessor]: {}",
+        "{} [preprocessor] {}",
         "warning".bright_yellow(),
         msg.message()
     );
-    println!(
-        "  --> {} {}{} {}",
-        msg.file_name.file_name().unwrap().to_str().unwrap(),
-        msg.line_n.to_string().bold(),
-        "|".bright_blue(),
-        msg.line
-    );
+    if let Some(location) = location {
+        print_location(&location, msg.span);
+    }
 }
 
 pub fn print_preprocessor_error(err: PreprocessorError) {
+    print_preprocessor_error_at(err, None)
+}
+
+/// Prints a preprocessor error pointing at some columns of its line
+pub fn print_preprocessor_error_at(err: PreprocessorError, span: Option<ColumnSpan>) {
+    if capture_diagnostic(Severity::Error, err.message(), &err.location, span) {
+        return;
+    }
+    count_diagnostic(Severity::Error, &err.location);
+    record_error_class(match err.kind {
+        // Missing source files are I/O failures rather than errors in the code
+        PreprocessorErrorKind::CannotOpenSourceFile(..) => ErrorClass::Io,
+        _ => ErrorClass::Preprocessor,
+    });
+
     println!("{} [preprocessor] {}", "error".bright_red(), err.message());
 
     if let Some(location) = err.location {
-        println!(
-            "  --> {} {}{} {}",
-            location.file_name.file_name().unwrap().to_str().unwrap(),
-            location.line_n.to_string().bold(),
-            "|".bright_blue(),
-            location.raw_content.trim()
-        );
+        print_location(&location, span);
+    }
+}
+
+/// Reports preprocessor errors and warnings collected by `capture_diagnostics` on another
+/// thread, as if they had been reported here. They were already logged when collected
+pub fn print_preprocessor_diagnostics(diagnostics: Vec<Diagnostic>) {
+    for diagnostic in diagnostics {
+        let captured = CAPTURED_DIAGNOSTICS.with(|captured| match captured.borrow_mut().as_mut() {
+            Some(diagnostics) => {
+                diagnostics.push(diagnostic.clone());
+                true
+            }
+            None => false,
+        });
+        if captured {
+            continue;
+        }
+        count_diagnostic(diagnostic.severity, &diagnostic.location);
+
+        let severity = match diagnostic.severity {
+            Severity::Error => {
+                record_error_class(ErrorClass::Preprocessor);
+                "error".bright_red()
+            }
+            Severity::Warning => "warning".bright_yellow(),
+        };
+        println!("{} [preprocessor] {}", severity, diagnostic.message);
+        if let Some(location) = &diagnostic.location {
+            print_location(location, diagnostic.span);
+        }
+    }
+}
+
+pub fn print_assembler_warning(msg: AssemblerWarning) {
+    if capture_diagnostic(Severity::Warning, msg.message(), &msg.location, None) {
+        return;
+    }
+    count_diagnostic(Severity::Warning, &msg.location);
+
+    println!(
+        "{} [assembler] {}",
+        "warning".bright_yellow(),
+        msg.message()
+    );
+
+    if let Some(location) = msg.location {
+        print_location(&location, None);
     }
 }
 
 pub fn print_assembler_error(err: AssemblerError) {
+    if capture_diagnostic(Severity::Error, err.message(), &err.location, None) {
+        return;
+    }
+    count_diagnostic(Severity::Error, &err.location);
+    record_error_class(ErrorClass::Assembler);
+
     println!("{} [assembler] {}", "error".bright_red(), err.message());
 
     if let Some(location) = err.location {
+        print_location(&location, None);
+    }
+}
+
+/// Prints an error in a table file, which is a problem with the input of l6as rather than
+/// with the program
+pub fn print_table_file_error(err: TableFileError) {
+    let location = Some(err.location.clone());
+    if capture_diagnostic(Severity::Error, err.message(), &location, None) {
+        return;
+    }
+    count_diagnostic(Severity::Error, &location);
+    record_error_class(ErrorClass::Usage);
+
+    println!("{} [input] {}", "error".bright_red(), err.message());
+    print_location(&err.location, None);
+}
+
+// Prints the source lines a diagnostic refers to, one per physical line, marking the columns
+// of the first one it points at
+fn print_location(location: &LineLocation, span: Option<ColumnSpan>) {
+    let file_name = location.file_name.file_name().unwrap().to_str().unwrap();
+    let width = location.last_line_n().to_string().len();
+    let padding = " ".repeat(file_name.chars().count() + 7);
+
+    for (i, line) in location.raw_content.split('\n').enumerate() {
+        let prefix = match i {
+            0 => format!("  --> {} ", file_name),
+            _ => padding.clone(),
+        };
         println!(
-            "  --> {} {}{} {}",
-            location.file_name.file_name().unwrap().to_str().unwrap(),
-            location.line_n.to_string().bold(),
+            "{}{}{} {}",
+            prefix,
+            format!("{:>width$}", location.line_n + i).bold(),
             "|".bright_blue(),
-            location.raw_content.trim()
+            line.trim()
         );
+
+        if let (0, Some(span)) = (i, span) {
+            // Lines are printed without their indentation
+            let indentation = line.chars().count() - line.trim_start().chars().count();
+            println!(
+                "{}{}{} {}{}",
+                padding,
+                " ".repeat(width),
+                "|".bright_blue(),
+                " ".repeat(span.start.saturating_sub(indentation)),
+                "^".repeat(span.end.saturating_sub(span.start).max(1))
+                    .bright_yellow()
+            );
+        }
     }
 }
 
 pub fn print_final_error_msg() {
+    print_diagnostic_summary();
     println!(
         "l6as: {} encountered during processing, no output generated",
         "errors".bright_red()
@@ -322,6 +918,7 @@ pub fn print_final_error_msg() {
 }
 
 pub fn print_write_file_error_msg(err: io::Error) {
+    record_error_class(ErrorClass::Io);
     println!(
         "{}: Unable to write output file: {}",
         "error".bright_red(),
@@ -329,26 +926,124 @@ pub fn print_write_file_error_msg(err: io::Error) {
     );
 }
 
-fn get_data_register_display_value(reg: &DataRegister) -> &str {
-    match reg {
-        DataRegister::R1 => "$R1",
-        DataRegister::R2 => "$R2",
-        DataRegister::R3 => "$R3",
-        DataRegister::R4 => "$R4",
-        DataRegister::R5 => "$R5",
-        DataRegister::R6 => "$R6",
-        DataRegister::R7 => "$R7",
+pub fn print_pad_to_error_msg(file_path: &Path, size: u64, pad_to: u64) {
+    record_error_class(ErrorClass::Usage);
+    println!(
+        "{}: Output \"{}\" is {} bytes, larger than the {} bytes it should be padded to",
+        "error".bright_red(),
+        file_path.display(),
+        size,
+        pad_to
+    );
+}
+
+pub fn print_module_overlap_error_msg(file_path: &Path, address: u64) {
+    record_error_class(ErrorClass::Assembler);
+    println!(
+        "{}: Module \"{}\" has more than one line at address {:#X}",
+        "error".bright_red(),
+        file_path.display(),
+        address
+    );
+}
+
+pub fn print_module_entry_error_msg(file_path: &Path, label: &str) {
+    record_error_class(ErrorClass::Usage);
+    println!(
+        "{}: Entry point \"{}\" of module \"{}\" isn't a label of its code",
+        "error".bright_red(),
+        label,
+        file_path.display()
+    );
+}
+
+pub fn print_read_file_error_msg(file_path: &Path, err: io::Error) {
+    record_error_class(ErrorClass::Io);
+    println!(
+        "{}: Unable to read file \"{}\": {}",
+        "error".bright_red(),
+        file_path.display(),
+        err
+    );
+}
+
+pub fn print_project_error_msg(msg: &str) {
+    record_error_class(ErrorClass::Usage);
+    println!("{} [build] {}", "error".bright_red(), msg);
+}
+
+pub fn print_unformatted_file_msg(file_path: &Path) {
+    println!("l6as: {} is not formatted", file_path.display());
+}
+
+// Stdout carries the protocol, so the language server reports on stderr
+pub fn print_lsp_error_msg(err: impl std::fmt::Display) {
+    eprintln!("{} [lsp] {}", "error".bright_red(), err);
+}
+
+fn get_suggestion_display_value(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(suggestion) => format!(", did you mean \"{}\"?", suggestion),
+        None => "".to_owned(),
     }
 }
 
-fn get_base_register_display_value(reg: &BaseRegister) -> &str {
-    match reg {
-        BaseRegister::B1 => "$B1",
-        BaseRegister::B2 => "$B2",
-        BaseRegister::B3 => "$B3",
-        BaseRegister::B4 => "$B4",
-        BaseRegister::B5 => "$B5",
-        BaseRegister::B6 => "$B6",
-        BaseRegister::B7 => "$B7",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrong_number_of_arguments_message() {
+        let message = |kind| {
+            AssemblerError {
+                kind,
+                location: None,
+            }
+            .message()
+        };
+        assert!(message(AssemblerErrorKind::WrongNumberOfArguments(
+            Mnemonic::LDR,
+            2,
+            Some(2),
+            1
+        ))
+        .starts_with("LDR takes 2 arguments, got 1"));
+        assert!(message(AssemblerErrorKind::WrongNumberOfArguments(
+            Mnemonic::SRM,
+            2,
+            Some(3),
+            4
+        ))
+        .starts_with("SRM takes 2 to 3 arguments, got 4"));
+        assert!(message(AssemblerErrorKind::WrongNumberOfArguments(
+            Mnemonic::DotDW,
+            1,
+            None,
+            0
+        ))
+        .starts_with(".DW takes at least 1 arguments, got 0"));
+    }
+
+    #[test]
+    fn test_get_exit_code() {
+        assert_eq!(get_exit_code(&Ok(())), EXIT_SUCCESS);
+        assert_eq!(get_exit_code::<()>(&Err(())), EXIT_FAILURE);
+
+        print_assembler_error(AssemblerError {
+            kind: AssemblerErrorKind::MnemonicRequired,
+            location: None,
+        });
+        assert_eq!(get_exit_code::<()>(&Err(())), EXIT_ASSEMBLER_ERROR);
+
+        // Missing files take precedence over errors in the code
+        print_preprocessor_error(PreprocessorError {
+            kind: PreprocessorErrorKind::CannotOpenSourceFile("a.asm".into(), vec![]),
+            location: None,
+        });
+        print_preprocessor_error(PreprocessorError {
+            kind: PreprocessorErrorKind::DefineMissingIdentifier,
+            location: None,
+        });
+        assert_eq!(get_exit_code::<()>(&Err(())), EXIT_IO_ERROR);
     }
 }
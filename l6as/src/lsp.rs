@@ -0,0 +1,349 @@
+mod analysis;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as LspNotification, PublishDiagnostics,
+    },
+    request::{DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as LspRequest},
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, SymbolKind, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use crate::{
+    args::LspArgs,
+    logging::{print_lsp_error_msg, ColumnSpan, Diagnostic, Severity},
+    preprocessor::LineLocation,
+};
+use analysis::{analyze, symbol_at, Analysis};
+
+/// Runs the language server on stdio until the client shuts it down
+pub fn run_lsp_server(args: &LspArgs) -> Result<(), ()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    // Negotiate capabilities
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    if let Err(err) = connection.initialize(serde_json::to_value(capabilities).unwrap()) {
+        print_lsp_error_msg(err);
+        return Err(());
+    }
+
+    // Serve requests
+    let mut server = LanguageServer {
+        connection,
+        include_dirs: args.include_dirs.clone(),
+        documents: HashMap::new(),
+        analyses: HashMap::new(),
+    };
+    let res = server.run();
+
+    // The connection must be closed before the IO threads can finish
+    drop(server);
+    if let Err(err) = io_threads.join() {
+        print_lsp_error_msg(err);
+        return Err(());
+    }
+
+    res
+}
+
+struct LanguageServer {
+    connection: Connection,
+    include_dirs: Vec<PathBuf>,
+    documents: HashMap<PathBuf, String>,
+    analyses: HashMap<PathBuf, Analysis>,
+}
+
+impl LanguageServer {
+    fn run(&mut self) -> Result<(), ()> {
+        while let Ok(msg) = self.connection.receiver.recv() {
+            match msg {
+                Message::Request(req) => match self.connection.handle_shutdown(&req) {
+                    Ok(true) => return Ok(()),
+                    Ok(false) => self.handle_request(req),
+                    Err(err) => {
+                        print_lsp_error_msg(err);
+                        return Err(());
+                    }
+                },
+                Message::Notification(not) => self.handle_notification(not),
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&self, req: Request) {
+        let res = match req.method.as_str() {
+            GotoDefinition::METHOD => self.respond::<GotoDefinition>(req, Self::goto_definition),
+            HoverRequest::METHOD => self.respond::<HoverRequest>(req, Self::hover),
+            DocumentSymbolRequest::METHOD => {
+                self.respond::<DocumentSymbolRequest>(req, Self::document_symbol)
+            }
+            _ => Response::new_err(
+                req.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request: {}", req.method),
+            ),
+        };
+
+        let _ = self.connection.sender.send(Message::Response(res));
+    }
+
+    // Decodes request parameters and runs the handler on them
+    fn respond<R: LspRequest>(
+        &self,
+        req: Request,
+        handler: fn(&Self, R::Params) -> R::Result,
+    ) -> Response {
+        match serde_json::from_value::<R::Params>(req.params) {
+            Ok(params) => Response::new_ok(req.id, handler(self, params)),
+            Err(err) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    fn handle_notification(&mut self, not: Notification) {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<DidOpenTextDocumentParams>(not.params)
+                {
+                    let doc = params.text_document;
+                    self.update_document(&doc.uri, Some(doc.text));
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                // Documents are fully synced, so the last change holds the whole text
+                if let Ok(mut params) =
+                    serde_json::from_value::<DidChangeTextDocumentParams>(not.params)
+                {
+                    if let Some(change) = params.content_changes.pop() {
+                        self.update_document(&params.text_document.uri, Some(change.text));
+                    }
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<DidCloseTextDocumentParams>(not.params)
+                {
+                    self.update_document(&params.text_document.uri, None);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Updates the contents of a document (None if closed) and reanalyzes every
+    // open document, as any of them could include it
+    fn update_document(&mut self, uri: &Url, text: Option<String>) {
+        let Ok(path) = uri.to_file_path() else {
+            return;
+        };
+
+        match text {
+            Some(text) => {
+                self.documents.insert(path, text);
+            }
+            None => {
+                self.documents.remove(&path);
+                self.analyses.remove(&path);
+                self.publish_diagnostics(uri, vec![]);
+            }
+        }
+
+        self.analyses = self
+            .documents
+            .keys()
+            .map(|path| {
+                let analysis = analyze(path, &self.documents, &self.include_dirs);
+                (path.clone(), analysis)
+            })
+            .collect();
+
+        for (path, analysis) in &self.analyses {
+            if let Ok(uri) = Url::from_file_path(path) {
+                let diagnostics = analysis
+                    .diagnostics
+                    .iter()
+                    .map(|diag| convert_diagnostic(diag, path))
+                    .collect();
+                self.publish_diagnostics(&uri, diagnostics);
+            }
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &Url, diagnostics: Vec<lsp_types::Diagnostic>) {
+        let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics, None);
+        let not = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+        let _ = self.connection.sender.send(Message::Notification(not));
+    }
+
+    // Finds the analysis and the line of text a position refers to
+    fn lookup(&self, pos: &TextDocumentPositionParams) -> Option<(&Path, &Analysis, &str)> {
+        let path = pos.text_document.uri.to_file_path().ok()?;
+        let (path, analysis) = self.analyses.get_key_value(&path)?;
+        let line = self
+            .documents
+            .get(path)?
+            .lines()
+            .nth(pos.position.line as usize)?;
+
+        Some((path, analysis, line))
+    }
+
+    fn goto_definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let pos = &params.text_document_position_params;
+        let (_, analysis, line) = self.lookup(pos)?;
+
+        let symbol = symbol_at(line, pos.position.character as usize)?;
+        let location = analysis.find_definition(&symbol)?;
+
+        Some(GotoDefinitionResponse::Scalar(Location {
+            uri: Url::from_file_path(&location.file_name).ok()?,
+            range: line_range(location),
+        }))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let pos = &params.text_document_position_params;
+        let (path, analysis, line) = self.lookup(pos)?;
+
+        // Describe the symbol under the cursor, or else the encoding of the line
+        let description = symbol_at(line, pos.position.character as usize)
+            .and_then(|symbol| analysis.describe_symbol(&symbol))
+            .or_else(|| analysis.describe_encoding(path, pos.position.line as usize + 1))?;
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```\n{}\n```", description),
+            }),
+            range: None,
+        })
+    }
+
+    fn document_symbol(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let path = params.text_document.uri.to_file_path().ok()?;
+        let analysis = self.analyses.get(&path)?;
+
+        let labels = analysis.labels.iter().map(|definition| {
+            (
+                definition.label.clone(),
+                format!("{:0>5X}", definition.address),
+                SymbolKind::FUNCTION,
+                &definition.location,
+            )
+        });
+        let definitions = analysis.definitions.iter().map(|definition| {
+            (
+                definition.identifier.clone(),
+                definition.value.clone(),
+                SymbolKind::CONSTANT,
+                &definition.location,
+            )
+        });
+
+        let symbols = definitions
+            .chain(labels)
+            .filter(|(_, _, _, location)| location.file_name == path)
+            .map(|(name, detail, kind, location)| {
+                // The deprecated field is still required to construct the struct
+                #[allow(deprecated)]
+                DocumentSymbol {
+                    name,
+                    detail: Some(detail),
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    range: line_range(location),
+                    selection_range: line_range(location),
+                    children: None,
+                }
+            })
+            .collect();
+
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+}
+
+// Converts a diagnostic to LSP, attaching ones located in other files to the
+// first line of the document
+fn convert_diagnostic(diag: &Diagnostic, file_path: &Path) -> lsp_types::Diagnostic {
+    let (range, message) = match &diag.location {
+        Some(location) if location.file_name == file_path => {
+            let range = match diag.span {
+                Some(span) => span_range(location, span),
+                None => line_range(location),
+            };
+            (range, diag.message.clone())
+        }
+        Some(location) => (
+            Range::default(),
+            format!(
+                "{}:{}: {}",
+                location.file_name.display(),
+                location.line_n,
+                diag.message
+            ),
+        ),
+        None => (Range::default(), diag.message.clone()),
+    };
+
+    let severity = match diag.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+    };
+
+    lsp_types::Diagnostic {
+        range,
+        severity: Some(severity),
+        source: Some("l6as".to_owned()),
+        message,
+        ..Default::default()
+    }
+}
+
+// Range covering a whole source line, through the last of its physical lines
+fn line_range(location: &LineLocation) -> Range {
+    let first_line = location.line_n.saturating_sub(1) as u32;
+    let last_line = location.last_line_n().saturating_sub(1) as u32;
+    let length = match location.raw_content.lines().last() {
+        Some(line) => line.encode_utf16().count() as u32,
+        None => 0,
+    };
+
+    Range::new(
+        Position::new(first_line, 0),
+        Position::new(last_line, length),
+    )
+}
+
+// Range covering some columns of the first physical line of a location
+fn span_range(location: &LineLocation, span: ColumnSpan) -> Range {
+    let line_n = location.line_n.saturating_sub(1) as u32;
+    let line = location.raw_content.lines().next().unwrap_or("");
+    let utf16_column = |column: usize| {
+        line.chars()
+            .take(column)
+            .map(|character| character.len_utf16())
+            .sum::<usize>() as u32
+    };
+
+    Range::new(
+        Position::new(line_n, utf16_column(span.start)),
+        Position::new(line_n, utf16_column(span.end)),
+    )
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    assembler::{assemble_with_labels, AssembledLine, AssemblerOptions, LabelDefinition},
+    file::FileInclusionCoordinator,
+    logging::{capture_diagnostics, Diagnostic},
+    preprocessor::{preprocess_with_definitions, Definition, LineLocation},
+};
+
+/// Results of assembling a document, used to answer editor requests
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub labels: Vec<LabelDefinition>,
+    pub definitions: Vec<Definition>,
+    pub assembled_lines: Vec<AssembledLine>,
+}
+
+/// Symbol referenced at a position in a document
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolReference {
+    Label(String),
+    Definition(String),
+}
+
+/// Preprocesses and assembles a document, preferring the contents of open
+/// documents over the files on disk
+pub fn analyze(
+    file_path: &Path,
+    documents: &HashMap<PathBuf, String>,
+    include_dirs: &[PathBuf],
+) -> Analysis {
+    // Setup file inclusion coordinator, looking for includes next to the document first.
+    // Directories which can't be resolved only make includes fail, which is reported anyway
+    let mut fi_coord = FileInclusionCoordinator::new();
+    if let Some(dir) = file_path.parent() {
+        let _ = fi_coord.add_include_dirs(&[dir.to_path_buf()]);
+    }
+    let _ = fi_coord.add_current_dir();
+    let _ = fi_coord.add_include_dirs(include_dirs);
+    for (path, contents) in documents {
+        fi_coord.add_override(path.clone(), contents.clone());
+    }
+
+    let ((definitions, labels, assembled_lines), diagnostics) = capture_diagnostics(|| {
+        // Preprocess
+        let (code_lines, definitions) =
+            preprocess_with_definitions(&file_path.to_path_buf(), &mut fi_coord);
+        let code_lines = match code_lines {
+            Ok(lines) | Err(lines) => lines,
+        };
+
+        // Assemble
+        let (assembled_lines, labels) =
+            assemble_with_labels(&code_lines, &AssemblerOptions::default());
+        let assembled_lines = match assembled_lines {
+            Ok(lines) | Err(lines) => lines,
+        };
+
+        (definitions, labels, assembled_lines)
+    });
+
+    Analysis {
+        diagnostics,
+        labels,
+        definitions,
+        assembled_lines,
+    }
+}
+
+/// Finds the symbol under a cursor, `character` being a UTF-16 offset as used by LSP
+pub fn symbol_at(line: &str, character: usize) -> Option<SymbolReference> {
+    let is_identifier_char = |chr: char| chr.is_alphanumeric() || chr == '_';
+
+    // Convert UTF-16 offset to byte offset
+    let mut utf16_offset = 0;
+    let mut cursor = line.len();
+    for (pos, chr) in line.char_indices() {
+        if utf16_offset >= character {
+            cursor = pos;
+            break;
+        }
+        utf16_offset += chr.len_utf16();
+    }
+
+    // Expand to the whole identifier around the cursor
+    let start = line[..cursor]
+        .rfind(|chr: char| !is_identifier_char(chr))
+        .map_or(0, |pos| pos + 1);
+    let end = line[cursor..]
+        .find(|chr: char| !is_identifier_char(chr))
+        .map_or(line.len(), |pos| cursor + pos);
+    let identifier = &line[start..end];
+
+    // Numbers can't be symbols
+    if identifier.is_empty() || identifier.starts_with(|chr: char| chr.is_ascii_digit()) {
+        return None;
+    }
+
+    match line[..start].chars().last() {
+        Some('%') => Some(SymbolReference::Definition(identifier.to_owned())),
+        Some('$') => None, // Register
+        _ => Some(SymbolReference::Label(identifier.to_uppercase())),
+    }
+}
+
+impl Analysis {
+    /// Finds where a symbol is defined
+    pub fn find_definition(&self, symbol: &SymbolReference) -> Option<&LineLocation> {
+        match symbol {
+            SymbolReference::Label(label) => self
+                .labels
+                .iter()
+                .find(|definition| &definition.label == label)
+                .map(|definition| &definition.location),
+            SymbolReference::Definition(identifier) => self
+                .definitions
+                .iter()
+                .find(|definition| definition.identifier.eq_ignore_ascii_case(identifier))
+                .map(|definition| &definition.location),
+        }
+    }
+
+    /// Describes the resolved value of a symbol
+    pub fn describe_symbol(&self, symbol: &SymbolReference) -> Option<String> {
+        match symbol {
+            SymbolReference::Label(label) => self
+                .labels
+                .iter()
+                .find(|definition| &definition.label == label)
+                .map(|definition| format!("{}: {:0>5X}", definition.label, definition.address)),
+            SymbolReference::Definition(identifier) => self
+                .definitions
+                .iter()
+                .find(|definition| definition.identifier.eq_ignore_ascii_case(identifier))
+                .map(|definition| {
+                    format!("%define {} = {}", definition.identifier, definition.value)
+                }),
+        }
+    }
+
+    /// Describes the machine code generated for a source line
+    pub fn describe_encoding(&self, file_path: &Path, line_n: usize) -> Option<String> {
+        let line = self.assembled_lines.iter().find(|line| {
+            line.location.file_name == file_path
+                && (line.location.line_n..=line.location.last_line_n()).contains(&line_n)
+        })?;
+
+        let words: Vec<String> = line
+            .data
+            .iter()
+            .map(|word| format!("{:0>4X}", word))
+            .collect();
+        Some(format!("{:0>5X}: {}", line.address, words.join(" ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze_documents(documents: &[(&str, &str)]) -> Analysis {
+        let documents: HashMap<PathBuf, String> = documents
+            .iter()
+            .map(|(path, contents)| (PathBuf::from(path), contents.to_string()))
+            .collect();
+
+        analyze(Path::new("/l6as_test/main.l6s"), &documents, &[])
+    }
+
+    #[test]
+    fn symbol_at_succ() {
+        let tests = [
+            (
+                "start: LDR $R1, =%VALUE",
+                2,
+                Some(SymbolReference::Label("START".to_owned())),
+            ),
+            (
+                "start: LDR $R1, =%VALUE",
+                5,
+                Some(SymbolReference::Label("START".to_owned())),
+            ),
+            (
+                "start: LDR $R1, =%VALUE",
+                20,
+                Some(SymbolReference::Definition("VALUE".to_owned())),
+            ),
+            (
+                " B loop_1",
+                9,
+                Some(SymbolReference::Label("LOOP_1".to_owned())),
+            ),
+            (" LDR $R1, =5", 6, None),
+            (" LDR $R1, =5", 11, None),
+            (" LDR $R1, =5", 0, None),
+            ("àè: B àè", 7, Some(SymbolReference::Label("ÀÈ".to_owned()))),
+        ];
+
+        for (line, character, exp) in tests {
+            assert_eq!(symbol_at(line, character), exp, "{} @ {}", line, character);
+        }
+    }
+
+    #[test]
+    fn analyze_succ() {
+        let analysis = analyze_documents(&[
+            (
+                "/l6as_test/main.l6s",
+                "%include defs.inc\n.ORG 0x100\nstart: LDR $R1, =%VALUE\n B start\n",
+            ),
+            ("/l6as_test/defs.inc", "%define VALUE = 5\n"),
+        ]);
+
+        assert_eq!(analysis.diagnostics, vec![]);
+        assert_eq!(
+            analysis
+                .find_definition(&SymbolReference::Label("START".to_owned()))
+                .map(|location| location.line_n),
+            Some(3)
+        );
+        assert_eq!(
+            analysis
+                .find_definition(&SymbolReference::Definition("value".to_owned()))
+                .map(|location| location.file_name.clone()),
+            Some(PathBuf::from("/l6as_test/defs.inc"))
+        );
+        assert_eq!(
+            analysis.describe_symbol(&SymbolReference::Label("START".to_owned())),
+            Some("START: 00100".to_owned())
+        );
+        assert_eq!(
+            analysis.describe_symbol(&SymbolReference::Definition("VALUE".to_owned())),
+            Some("%define VALUE = 5".to_owned())
+        );
+        assert_eq!(
+            analysis.describe_encoding(Path::new("/l6as_test/main.l6s"), 3),
+            Some("00100: 9870 0005".to_owned())
+        );
+    }
+
+    #[test]
+    fn analyze_err() {
+        let analysis =
+            analyze_documents(&[("/l6as_test/main.l6s", "start: LRD $R1, =5\n B strat\n")]);
+
+        let messages: Vec<(usize, String)> = analysis
+            .diagnostics
+            .iter()
+            .map(|diag| (diag.location.as_ref().unwrap().line_n, diag.message.clone()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    1,
+                    "unkown mnemonic: \"LRD\", did you mean \"LDR\"?".to_owned()
+                ),
+                (1, "label is never referenced: \"START\"".to_owned()),
+                (
+                    2,
+                    "undefined label: \"STRAT\", did you mean \"START\"?".to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+
+use crate::assembler::AssembledLine;
+
+// Width of the bar of each bank in text maps, in characters
+const TEXT_BAR_WIDTH: u64 = 64;
+
+// Dimensions of SVG maps, in pixels
+const SVG_BAR_WIDTH: u64 = 800;
+const SVG_BAR_HEIGHT: u64 = 24;
+const SVG_MARGIN: u64 = 16;
+const SVG_ROW_HEIGHT: u64 = 56;
+
+// Fill colors of the files, in order of their first range
+const SVG_COLORS: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+];
+
+/// Range of memory filled by the code of a source file, in words
+#[derive(Debug, Clone, PartialEq)]
+pub struct OccupiedRange {
+    pub bank: Option<u64>,
+    pub start: u64,
+    /// Address following the range
+    pub end: u64,
+    pub file_name: PathBuf,
+}
+
+/// Collects the ranges of memory filled by the program, sorted by bank and address. Adjacent
+/// code from the same file is a single range
+pub fn find_occupied_ranges(lines: &[AssembledLine]) -> Vec<OccupiedRange> {
+    let mut lines: Vec<&AssembledLine> =
+        lines.iter().filter(|line| !line.data.is_empty()).collect();
+    lines.sort_by_key(|line| (line.bank, line.address));
+
+    let mut ranges: Vec<OccupiedRange> = vec![];
+    for line in lines {
+        let end = line.address + line.data.len() as u64;
+        match ranges.last_mut() {
+            Some(range)
+                if range.bank == line.bank
+                    && range.end == line.address
+                    && range.file_name == line.location.file_name =>
+            {
+                range.end = end;
+            }
+            _ => ranges.push(OccupiedRange {
+                bank: line.bank,
+                start: line.address,
+                end,
+                file_name: line.location.file_name.clone(),
+            }),
+        }
+    }
+
+    ranges
+}
+
+/// Generates a text map of each bank: a bar of the used memory followed by the used and free
+/// ranges. Banks span `size` words if given, or up to their last range
+pub fn generate_memory_map_text(ranges: &[OccupiedRange], size: Option<u64>) -> String {
+    let mut output = String::new();
+    for bank in get_banks(ranges) {
+        let bank_ranges: Vec<&OccupiedRange> =
+            ranges.iter().filter(|range| range.bank == bank).collect();
+        let bank_size = get_bank_size(&bank_ranges, size);
+        let used: u64 = bank_ranges
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum();
+
+        output.push_str(&format!(
+            "{}: {} words used, {} free\n",
+            format_bank(bank),
+            used,
+            bank_size.saturating_sub(used)
+        ));
+
+        // Each character is used if any of its words is
+        let bar: String = (0..TEXT_BAR_WIDTH)
+            .map(|i| {
+                let start = i * bank_size / TEXT_BAR_WIDTH;
+                let end = ((i + 1) * bank_size / TEXT_BAR_WIDTH).max(start + 1);
+                match bank_ranges
+                    .iter()
+                    .any(|range| range.start < end && start < range.end)
+                {
+                    true => '#',
+                    false => '.',
+                }
+            })
+            .collect();
+        output.push_str(&format!("[{}]\n", bar));
+
+        let mut next_address = 0;
+        for range in &bank_ranges {
+            if range.start > next_address {
+                output.push_str(&format_text_range(next_address, range.start, "free"));
+            }
+            output.push_str(&format_text_range(
+                range.start,
+                range.end,
+                &get_short_file_name(&range.file_name),
+            ));
+            next_address = range.end;
+        }
+        if bank_size > next_address {
+            output.push_str(&format_text_range(next_address, bank_size, "free"));
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Generates an SVG diagram with a bar for each bank, colored by source file
+pub fn generate_memory_map_svg(ranges: &[OccupiedRange], size: Option<u64>) -> String {
+    let banks = get_banks(ranges);
+    let mut files: Vec<&PathBuf> = vec![];
+    for range in ranges {
+        if !files.contains(&&range.file_name) {
+            files.push(&range.file_name);
+        }
+    }
+
+    let width = SVG_BAR_WIDTH + 2 * SVG_MARGIN;
+    let height = SVG_MARGIN + (banks.len() + files.len().div_ceil(4)) as u64 * SVG_ROW_HEIGHT;
+    let mut output = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"monospace\" font-size=\"12\">\n",
+        width, height
+    );
+
+    for (row, bank) in banks.iter().enumerate() {
+        let bank_ranges: Vec<&OccupiedRange> =
+            ranges.iter().filter(|range| range.bank == *bank).collect();
+        let bank_size = get_bank_size(&bank_ranges, size);
+        let y = SVG_MARGIN + row as u64 * SVG_ROW_HEIGHT;
+
+        output.push_str(&format!(
+            "  <text x=\"{}\" y=\"{}\">{} ({} words)</text>\n",
+            SVG_MARGIN,
+            y + 12,
+            format_bank(*bank),
+            bank_size
+        ));
+        output.push_str(&format!(
+            "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#eeeeee\" stroke=\"#999999\"/>\n",
+            SVG_MARGIN,
+            y + 18,
+            SVG_BAR_WIDTH,
+            SVG_BAR_HEIGHT
+        ));
+        for range in bank_ranges {
+            let x = range.start * SVG_BAR_WIDTH / bank_size;
+            let end = range.end * SVG_BAR_WIDTH / bank_size;
+            let color = files
+                .iter()
+                .position(|file| **file == range.file_name)
+                .unwrap_or(0);
+            output.push_str(&format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\">\
+                 <title>{:#06X}-{:#06X} {}</title></rect>\n",
+                SVG_MARGIN + x,
+                y + 18,
+                (end - x).max(1),
+                SVG_BAR_HEIGHT,
+                SVG_COLORS[color % SVG_COLORS.len()],
+                range.start,
+                range.end - 1,
+                escape_xml(&get_short_file_name(&range.file_name))
+            ));
+        }
+    }
+
+    // Legend of the file colors, four to a row
+    let legend_y = SVG_MARGIN + banks.len() as u64 * SVG_ROW_HEIGHT;
+    for (i, file) in files.iter().enumerate() {
+        let x = SVG_MARGIN + (i as u64 % 4) * (SVG_BAR_WIDTH / 4);
+        let y = legend_y + (i as u64 / 4) * SVG_ROW_HEIGHT;
+        output.push_str(&format!(
+            "  <rect x=\"{}\" y=\"{}\" width=\"12\" height=\"12\" fill=\"{}\"/>\
+             <text x=\"{}\" y=\"{}\">{}</text>\n",
+            x,
+            y,
+            SVG_COLORS[i % SVG_COLORS.len()],
+            x + 18,
+            y + 11,
+            escape_xml(&get_short_file_name(file))
+        ));
+    }
+
+    output.push_str("</svg>\n");
+    output
+}
+
+// Banks with code, common code first
+fn get_banks(ranges: &[OccupiedRange]) -> Vec<Option<u64>> {
+    let mut banks: Vec<Option<u64>> = ranges.iter().map(|range| range.bank).collect();
+    banks.dedup();
+    banks
+}
+
+// Words spanned by a bank, never less than its code
+fn get_bank_size(bank_ranges: &[&OccupiedRange], size: Option<u64>) -> u64 {
+    let end = bank_ranges.iter().map(|range| range.end).max().unwrap_or(0);
+    size.unwrap_or(0).max(end).max(1)
+}
+
+fn format_bank(bank: Option<u64>) -> String {
+    match bank {
+        Some(bank) => format!("Bank {}", bank),
+        None => "Common code".to_owned(),
+    }
+}
+
+fn format_text_range(start: u64, end: u64, name: &str) -> String {
+    format!(
+        "  {:#06X}-{:#06X} {:>6} words  {}\n",
+        start,
+        end - 1,
+        end - start,
+        name
+    )
+}
+
+fn get_short_file_name(file_name: &Path) -> String {
+    match file_name.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => file_name.display().to_string(),
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_occupied_ranges_succ() {
+        let mut other_file = AssembledLine::new_test(0x13, None, vec![0], None);
+        other_file.location.file_name = "b.l6s".into();
+        let lines = [
+            AssembledLine::new_test(0x10, None, vec![0; 2], None),
+            AssembledLine::new_test(0x12, None, vec![0], None),
+            other_file,
+            AssembledLine::new_test(0x0, Some(1), vec![0], None),
+            AssembledLine::new_test(0x20, None, vec![], None),
+            AssembledLine::new_test(0x30, None, vec![0], None),
+        ];
+
+        let ranges: Vec<(Option<u64>, u64, u64, &str)> = find_occupied_ranges(&lines)
+            .iter()
+            .map(|range| {
+                let file_name = range.file_name.to_str().unwrap();
+                (
+                    range.bank,
+                    range.start,
+                    range.end,
+                    if file_name == "a.l6s" { "a" } else { "b" },
+                )
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (None, 0x10, 0x13, "a"),
+                (None, 0x13, 0x14, "b"),
+                (None, 0x30, 0x31, "a"),
+                (Some(1), 0x0, 0x1, "a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn generate_memory_map_text_succ() {
+        let ranges =
+            find_occupied_ranges(&[AssembledLine::new_test(0x10, None, vec![0; 0x10], None)]);
+
+        let bar = format!("{}{}{}", ".".repeat(16), "#".repeat(16), ".".repeat(32));
+        let expected = format!(
+            "Common code: 16 words used, 48 free\n\
+             [{}]\n  \
+             0x0000-0x000F     16 words  free\n  \
+             0x0010-0x001F     16 words  a.l6s\n  \
+             0x0020-0x003F     32 words  free\n\n",
+            bar
+        );
+        assert_eq!(generate_memory_map_text(&ranges, Some(0x40)), expected);
+    }
+}
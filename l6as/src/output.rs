@@ -1,18 +1,43 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    assembler::AssembledLine, file::write_file, logging::print_write_file_error_msg,
-    preprocessor::CodeLine,
+    args::{CallGraphFormat, MemoryMapFormat, OpcodeTableFormat},
+    assembler::{
+        generate_call_graph_dot, generate_stack_report, AssembledLine, CallGraph, LabelDefinition,
+        OpcodeTableEntry, Program, RoutineStackUsage,
+    },
+    branches::{format_branch_target, get_line_branch_target, BranchTarget},
+    cycles::{get_label_cycles, get_line_cycles, CycleTable},
+    file::write_file,
+    logging::{
+        print_module_entry_error_msg, print_module_overlap_error_msg, print_pad_to_error_msg,
+        print_write_file_error_msg,
+    },
+    memory_map::{generate_memory_map_svg, generate_memory_map_text, OccupiedRange},
+    preprocessor::{SourceLine, SourceLineBody},
+    profile::{get_hot_threshold, get_line_count, ExecutionProfile},
+    symbols::{generate_symbol_export, generate_symbol_file, SymbolExportFormat},
 };
 
-/// Write preprocessor output to file
-pub fn write_preprocessor_output(file_path: &PathBuf, lines: &[CodeLine]) -> Result<(), ()> {
-    let mut string = String::new();
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PreprocessorOutputOptions {
+    pub keep_comments: bool,
+    pub keep_blank_lines: bool,
+    pub keep_directives: bool,
+    /// Precede lines which don't follow the previous one in the source with a %line marker
+    pub line_markers: bool,
+}
 
-    for code_line in lines {
-        string.push_str(&code_line.body);
-        string.push_str("\r\n");
-    }
+/// Write preprocessor output to file
+pub fn write_preprocessor_output(
+    file_path: &PathBuf,
+    lines: &[SourceLine],
+    opts: &PreprocessorOutputOptions,
+) -> Result<(), ()> {
+    let string = generate_preprocessor_output(lines, opts);
 
     // Write output to file
     match write_file(file_path, &string.as_bytes()) {
@@ -24,25 +49,274 @@ pub fn write_preprocessor_output(file_path: &PathBuf, lines: &[CodeLine]) -> Res
     }
 }
 
-/// Write assembler output to a binary file
+// Convert preprocessed lines to text, only keeping code unless requested otherwise
+fn generate_preprocessor_output(lines: &[SourceLine], opts: &PreprocessorOutputOptions) -> String {
+    let mut string = String::new();
+    let mut last_blank = true;
+    // Source location the next output line has without a %line marker
+    let mut next_location: Option<(&Path, usize)> = None;
+
+    for line in lines {
+        let comment = if opts.keep_comments {
+            line.comment.as_str()
+        } else {
+            ""
+        };
+
+        let output_line = match &line.body {
+            SourceLineBody::Code(code) if comment.is_empty() => code.to_owned(),
+            SourceLineBody::Code(code) => format!("{} {}", code, comment),
+            SourceLineBody::Define(_, _)
+            | SourceLineBody::DefaultDefine(_, _)
+            | SourceLineBody::Include(_)
+                if opts.keep_directives =>
+            {
+                let lines: Vec<String> = line
+                    .location
+                    .raw_content
+                    .lines()
+                    .map(|line| format!("; {}", line.trim()))
+                    .collect();
+                lines.join("\n")
+            }
+            SourceLineBody::Empty if !comment.is_empty() => comment.to_owned(),
+            SourceLineBody::Empty if opts.keep_blank_lines => "".to_owned(),
+            _ => continue,
+        };
+
+        // Collapse consecutive blank lines
+        let blank = output_line.is_empty();
+        if blank && last_blank {
+            continue;
+        }
+        last_blank = blank;
+
+        let location = (line.location.file_name.as_path(), line.location.line_n);
+        if opts.line_markers && next_location != Some(location) {
+            string.push_str(&format!(
+                "%line {} \"{}\"\r\n",
+                location.1,
+                location.0.display()
+            ));
+        }
+        next_location = Some((location.0, location.1 + output_line.lines().count().max(1)));
+
+        string.push_str(&output_line);
+        string.push_str("\r\n");
+    }
+
+    string
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BinaryOutputOptions {
+    /// Size in bytes the output is padded to
+    pub pad_to: Option<u64>,
+    pub pad_byte: u8,
+    /// Splits the output between several PROMs
+    pub split: Option<PromSplit>,
+    /// Write the least significant byte of each word first
+    pub swap_bytes: bool,
+    pub word_order: WordOrder,
+}
+
+/// Order of the two words of each pair in binary output
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum WordOrder {
+    #[default]
+    HighLow,
+    /// Second word of each pair first, for loaders reading 32 bits at a time
+    LowHigh,
+}
+
+/// Arrangement of the PROMs an image is split between
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromSplit {
+    /// Byte-wide PROMs, even bytes on one set and odd bytes on the other. Each PROM holds up
+    /// to a number of bytes, or the whole byte lane
+    ByteLanes(Option<u64>),
+    /// Word-wide PROMs, each holding a number of consecutive words
+    Words(u64),
+}
+
+/// Write assembler output to a binary file. Code placed in banks is written
+/// to a separate file for each bank, named after the output file
 pub fn write_assembler_binary_output(
-    file_path: &PathBuf,
+    file_path: &Path,
     lines: &[AssembledLine],
+    opts: &BinaryOutputOptions,
 ) -> Result<(), ()> {
-    // Convert output to string
-    let mut output: Vec<u8> = vec![];
+    for bank in get_banks(lines) {
+        let mut output = generate_binary_output(lines, bank);
+        reorder_binary_output(&mut output, opts.swap_bytes, opts.word_order);
+
+        let bank_file_path = match bank {
+            Some(bank) => get_bank_file_path(file_path, bank),
+            None => file_path.to_path_buf(),
+        };
+
+        // Pad output to requested size
+        if let Some(pad_to) = opts.pad_to {
+            if output.len() as u64 > pad_to {
+                print_pad_to_error_msg(&bank_file_path, output.len() as u64, pad_to);
+                return Err(());
+            }
+            output.resize(pad_to as usize, opts.pad_byte);
+        }
+
+        // Write output to one file for each PROM, or to a single file
+        let files = match opts.split {
+            Some(split) => split_prom_image(&output, split, opts.pad_byte)
+                .into_iter()
+                .map(|(tag, data)| (insert_file_tag(&bank_file_path, &tag), data))
+                .collect(),
+            None => vec![(bank_file_path, output)],
+        };
+        for (file_path, data) in files {
+            if let Err(err) = write_file(&file_path, &data) {
+                print_write_file_error_msg(err);
+                return Err(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Swaps the bytes of each word and the words of each pair, as requested. A last byte or word
+/// without a pair is left in place
+pub fn reorder_binary_output(output: &mut [u8], swap_bytes: bool, word_order: WordOrder) {
+    if swap_bytes {
+        for word in output.chunks_exact_mut(2) {
+            word.swap(0, 1);
+        }
+    }
+    if word_order == WordOrder::LowHigh {
+        for pair in output.chunks_exact_mut(4) {
+            pair.rotate_left(2);
+        }
+    }
+}
+
+/// Splits an image between PROMs, returning the data of each one with the tag naming its file,
+/// e.g. "even" or "1.odd". The last PROMs are padded to their full size
+pub fn split_prom_image(image: &[u8], split: PromSplit, pad_byte: u8) -> Vec<(String, Vec<u8>)> {
+    match split {
+        PromSplit::ByteLanes(size) => {
+            let even: Vec<u8> = image.iter().step_by(2).copied().collect();
+            let mut odd: Vec<u8> = image.iter().skip(1).step_by(2).copied().collect();
+            odd.resize(even.len(), pad_byte);
+
+            let mut proms = vec![];
+            for (lane, data) in [("even", even), ("odd", odd)] {
+                match size {
+                    Some(size) => {
+                        for (i, chunk) in split_prom_chunks(&data, size, pad_byte) {
+                            proms.push((format!("{}.{}", i, lane), chunk));
+                        }
+                    }
+                    None => proms.push((lane.to_owned(), data)),
+                }
+            }
+            proms
+        }
+        PromSplit::Words(size) => split_prom_chunks(image, size * 2, pad_byte)
+            .into_iter()
+            .map(|(i, chunk)| (i.to_string(), chunk))
+            .collect(),
+    }
+}
+
+// Consecutive chunks of a number of bytes, numbered from 0, the last one padded
+fn split_prom_chunks(data: &[u8], size: u64, pad_byte: u8) -> Vec<(usize, Vec<u8>)> {
+    data.chunks(size as usize)
+        .map(|chunk| {
+            let mut chunk = chunk.to_vec();
+            chunk.resize(size as usize, pad_byte);
+            chunk
+        })
+        .enumerate()
+        .collect()
+}
+
+// Banks used by the program, in the order they first appear, after code outside of banks
+fn get_banks(lines: &[AssembledLine]) -> Vec<Option<u64>> {
+    let mut banks: Vec<Option<u64>> = vec![None];
     for line in lines {
-        for word in line.data.clone() {
+        if !banks.contains(&line.bank) {
+            banks.push(line.bank);
+        }
+    }
+
+    banks
+}
+
+/// Converts the code of a bank (None for code outside of banks) to bytes
+pub fn generate_binary_output(lines: &[AssembledLine], bank: Option<u64>) -> Vec<u8> {
+    let mut output: Vec<u8> = vec![];
+    for line in lines.iter().filter(|line| line.bank == bank) {
+        for word in &line.data {
             output.extend_from_slice(&word.to_be_bytes());
         }
     }
 
-    // Write output to file
-    match write_file(file_path, &output) {
-        Ok(()) => Ok(()),
-        Err(err) => {
-            print_write_file_error_msg(err);
-            Err(())
+    output
+}
+
+// Inserts the bank number before the extension, e.g. a.bin becomes a.bank1.bin
+fn get_bank_file_path(file_path: &Path, bank: u64) -> PathBuf {
+    insert_file_tag(file_path, &format!("bank{}", bank))
+}
+
+// Inserts a tag before the extension, e.g. a.bin becomes a.even.bin
+fn insert_file_tag(file_path: &Path, tag: &str) -> PathBuf {
+    let stem = file_path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match file_path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, tag, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, tag),
+    };
+
+    file_path.with_file_name(file_name)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ListingOptions<'a> {
+    /// Annotate instructions with their cycle estimates
+    pub cycle_table: Option<&'a CycleTable>,
+    /// Annotate instructions with their execution counts, highlighting the hot path
+    pub profile: Option<&'a ExecutionProfile>,
+    /// Radix the words of the listing are shown in
+    pub radix: ListingRadix,
+    /// Preprocessed source of the program, to also list its empty lines, comments and
+    /// preprocessor directives
+    pub source: Option<&'a [SourceLine]>,
+}
+
+/// Radix of the words of listings
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ListingRadix {
+    #[default]
+    Hex,
+    /// As in Honeywell documentation
+    Octal,
+    /// Hexadecimal, then octal
+    Both,
+}
+
+impl ListingRadix {
+    fn format_word(&self, word: u16) -> String {
+        match self {
+            Self::Hex => format!("{:0>4X}", word),
+            Self::Octal => format!("{:0>6o}", word),
+            Self::Both => format!("{:0>4X}/{:0>6o}", word, word),
+        }
+    }
+
+    fn word_width(&self) -> usize {
+        match self {
+            Self::Hex => 4,
+            Self::Octal => 6,
+            Self::Both => 11,
         }
     }
 }
@@ -51,15 +325,13 @@ pub fn write_assembler_binary_output(
 pub fn write_assembler_listing_output(
     file_path: &PathBuf,
     lines: &[AssembledLine],
+    labels: &[LabelDefinition],
+    opts: &ListingOptions,
 ) -> Result<(), ()> {
-    let mut string = String::new();
-
-    for line in lines {
-        string.push_str(&generate_line_listing(line));
-    }
+    let string = generate_listing(lines, labels, opts);
 
     // Write output to file
-    match write_file(file_path, &string.as_bytes()) {
+    match write_file(file_path, string.as_bytes()) {
         Ok(()) => Ok(()),
         Err(err) => {
             print_write_file_error_msg(err);
@@ -68,29 +340,137 @@ pub fn write_assembler_listing_output(
     }
 }
 
+/// Generates the listing of a program, with the words of each line next to its source
+pub fn generate_listing(
+    lines: &[AssembledLine],
+    labels: &[LabelDefinition],
+    opts: &ListingOptions,
+) -> String {
+    let mut string = String::new();
+    let hot_threshold = opts
+        .profile
+        .and_then(|profile| get_hot_threshold(profile, lines));
+
+    // Source lines without code are listed before the next line with code. Code placed out of
+    // source order, e.g. vector tables, doesn't move the position in the source
+    let source = opts.source.unwrap_or(&[]);
+    let code_locations: HashSet<(&Path, usize)> = lines
+        .iter()
+        .map(|line| (line.location.file_name.as_path(), line.location.line_n))
+        .collect();
+    let is_code = |line: &SourceLine| {
+        code_locations.contains(&(line.location.file_name.as_path(), line.location.line_n))
+    };
+    let mut source_positions: HashMap<(&Path, usize), usize> = HashMap::new();
+    for (i, line) in source.iter().enumerate() {
+        let location = (line.location.file_name.as_path(), line.location.line_n);
+        source_positions.entry(location).or_insert(i);
+    }
+    // Label-only lines are listed with the address of their label
+    let label_addresses: HashMap<(&Path, usize), u64> = labels
+        .iter()
+        .map(|label| {
+            let location = (label.location.file_name.as_path(), label.location.line_n);
+            (location, label.address)
+        })
+        .collect();
+    let list_source_line = |string: &mut String, line: &SourceLine| {
+        let location = (line.location.file_name.as_path(), line.location.line_n);
+        let address = label_addresses.get(&location).copied();
+        string.push_str(&generate_source_line_listing(line, address, opts));
+    };
+    let mut next_source_line = 0;
+
+    for line in lines {
+        let location = (line.location.file_name.as_path(), line.location.line_n);
+        let position = source_positions.get(&location).copied();
+        if let Some(position) = position.filter(|position| *position >= next_source_line) {
+            for source_line in &source[next_source_line..position] {
+                if !is_code(source_line) {
+                    list_source_line(&mut string, source_line);
+                }
+            }
+            next_source_line = position + 1;
+        }
+
+        let branch_target = get_line_branch_target(line, labels);
+        string.push_str(&generate_line_listing(
+            line,
+            opts,
+            hot_threshold,
+            branch_target.as_ref(),
+        ));
+    }
+    for source_line in &source[next_source_line.min(source.len())..] {
+        if !is_code(source_line) {
+            list_source_line(&mut string, source_line);
+        }
+    }
+
+    // Summary of cycles per label
+    if let Some(cycle_table) = opts.cycle_table {
+        string.push_str("\r\n; Cycles per label, not following branches\r\n");
+        for (label, cycles) in get_label_cycles(cycle_table, lines, labels) {
+            string.push_str(&format!("; {:<16} {:>6}\r\n", label, cycles));
+        }
+    }
+
+    string
+}
+
 // Generate listing for a single AssembledLine
-fn generate_line_listing(line: &AssembledLine) -> String {
+fn generate_line_listing(
+    line: &AssembledLine,
+    opts: &ListingOptions,
+    hot_threshold: Option<u64>,
+    branch_target: Option<&BranchTarget>,
+) -> String {
     let mut words_written: usize = 0;
     let mut output: String = "".to_owned();
 
-    while words_written < line.data.len() {
+    // Continued lines are listed one physical line per row
+    let source_lines: Vec<&str> = line.location.raw_content.lines().collect();
+    let rows = line.data.len().div_ceil(2).max(source_lines.len());
+
+    for row in 0..rows {
         // Compute address column
-        let (address_column, code_column) = if words_written == 0 {
-            (
-                format!("{:0>5X}:", line.address),
-                line.location.raw_content.clone(),
-            )
-        } else {
-            ("      ".to_owned(), "".to_owned())
+        let address_column = match row {
+            0 => format!("{:0>5X}:", line.address),
+            _ => "      ".to_owned(),
         };
+        let mut code_column = source_lines.get(row).copied().unwrap_or("").to_owned();
+
+        // Resolved target of branches, after the source of the first row
+        if let Some(target) = branch_target.filter(|_| row == 0) {
+            code_column.push_str(&format!("  ; {}", format_branch_target(target)));
+        }
 
         // Calculate instruction words field
         let mut words_column = "".to_owned();
         for i in 0..2 {
             words_column.push_str(&if words_written + i < line.data.len() {
-                format!("{:0>4X} ", line.data[words_written + i])
+                format!("{} ", opts.radix.format_word(line.data[words_written + i]))
             } else {
-                "     ".to_owned()
+                " ".repeat(opts.radix.word_width() + 1)
+            });
+        }
+
+        // Cycles column, only on the first row
+        if let Some(cycle_table) = opts.cycle_table {
+            words_column.push_str(&match get_line_cycles(cycle_table, line) {
+                Some(cycles) if words_written == 0 => format!("{:>5} ", cycles),
+                _ => "      ".to_owned(),
+            });
+        }
+
+        // Execution count column, hot lines are marked with '*'
+        if let Some(profile) = opts.profile {
+            words_column.push_str(&match get_line_count(profile, line) {
+                Some(count) if words_written == 0 => {
+                    let hot = matches!(hot_threshold, Some(threshold) if count >= threshold);
+                    format!("{:>8}{} ", count, if hot { '*' } else { ' ' })
+                }
+                _ => "          ".to_owned(),
             });
         }
 
@@ -104,3 +484,1200 @@ fn generate_line_listing(line: &AssembledLine) -> String {
 
     output
 }
+
+// Generate listing for a source line without code, with empty word columns. The address column
+// is only filled for lines defining a label
+fn generate_source_line_listing(
+    line: &SourceLine,
+    address: Option<u64>,
+    opts: &ListingOptions,
+) -> String {
+    let mut columns_width = 2 + 2 * (opts.radix.word_width() + 1) + 2;
+    if opts.cycle_table.is_some() {
+        columns_width += 6;
+    }
+    if opts.profile.is_some() {
+        columns_width += 10;
+    }
+
+    let mut output = String::new();
+    for (i, row) in line.location.raw_content.split('\n').enumerate() {
+        let address_column = match (i, address) {
+            (0, Some(address)) => format!("{:0>5X}:", address),
+            _ => "      ".to_owned(),
+        };
+        let row = format!(
+            "{}{}{}",
+            address_column,
+            " ".repeat(columns_width),
+            row.trim_end_matches('\r')
+        );
+        output.push_str(row.trim_end());
+        output.push_str("\r\n");
+    }
+
+    output
+}
+
+/// Write labels to a symbol map file
+pub fn write_symbol_map_output(file_path: &PathBuf, labels: &[LabelDefinition]) -> Result<(), ()> {
+    let string = generate_symbol_file(labels);
+
+    // Write output to file
+    match write_file(file_path, string.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            print_write_file_error_msg(err);
+            Err(())
+        }
+    }
+}
+
+/// Write labels as constant declarations or a disassembler script, depending on the file
+/// extension
+pub fn write_symbol_export_output(
+    file_path: &PathBuf,
+    labels: &[LabelDefinition],
+) -> Result<(), ()> {
+    // The extension is checked when parsing the arguments
+    let format = SymbolExportFormat::from_path(file_path).unwrap_or(SymbolExportFormat::CHeader);
+    let string = generate_symbol_export(labels, format);
+
+    // Write output to file
+    match write_file(file_path, string.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            print_write_file_error_msg(err);
+            Err(())
+        }
+    }
+}
+
+/// Write the stack usage of each routine to a report file
+pub fn write_stack_report_output(
+    file_path: &PathBuf,
+    usages: &[RoutineStackUsage],
+) -> Result<(), ()> {
+    let string = generate_stack_report(usages);
+
+    // Write output to file
+    match write_file(file_path, string.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            print_write_file_error_msg(err);
+            Err(())
+        }
+    }
+}
+
+/// Write the call graph of a program to a Graphviz DOT or JSON file
+pub fn write_call_graph_output(
+    file_path: &PathBuf,
+    graph: &CallGraph,
+    format: CallGraphFormat,
+) -> Result<(), ()> {
+    let string = match format {
+        CallGraphFormat::Dot => generate_call_graph_dot(graph),
+        CallGraphFormat::Json => serde_json::to_string_pretty(graph).unwrap(),
+    };
+
+    // Write output to file
+    match write_file(file_path, string.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            print_write_file_error_msg(err);
+            Err(())
+        }
+    }
+}
+
+/// Write a diagram of the memory used by the program, banks spanning `size` words if given
+pub fn write_memory_map_output(
+    file_path: &PathBuf,
+    ranges: &[OccupiedRange],
+    size: Option<u64>,
+    format: MemoryMapFormat,
+) -> Result<(), ()> {
+    let string = match format {
+        MemoryMapFormat::Txt => generate_memory_map_text(ranges, size),
+        MemoryMapFormat::Svg => generate_memory_map_svg(ranges, size),
+    };
+
+    // Write output to file
+    match write_file(file_path, string.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            print_write_file_error_msg(err);
+            Err(())
+        }
+    }
+}
+
+/// Write parsed program to a JSON file, for use by external tools
+pub fn write_ast_output(file_path: &PathBuf, program: &Program) -> Result<(), ()> {
+    let string = serde_json::to_string_pretty(program).unwrap();
+
+    // Write output to file
+    match write_file(file_path, string.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            print_write_file_error_msg(err);
+            Err(())
+        }
+    }
+}
+
+/// Write opcode table to file
+pub fn write_opcode_table_output(
+    file_path: &PathBuf,
+    table: &[OpcodeTableEntry],
+    format: OpcodeTableFormat,
+) -> Result<(), ()> {
+    let string = generate_opcode_table_output(table, format);
+
+    // Write output to file
+    match write_file(file_path, string.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            print_write_file_error_msg(err);
+            Err(())
+        }
+    }
+}
+
+/// Generate opcode table in the requested format
+pub fn generate_opcode_table_output(
+    table: &[OpcodeTableEntry],
+    format: OpcodeTableFormat,
+) -> String {
+    match format {
+        OpcodeTableFormat::Json => serde_json::to_string_pretty(table).unwrap() + "\n",
+        OpcodeTableFormat::Csv => {
+            let mut string = "mnemonic,kind,header,opcode,pattern\n".to_owned();
+            for entry in table {
+                string.push_str(&format!(
+                    "{},{},{},{},{}\n",
+                    entry.mnemonic, entry.kind, entry.header, entry.opcode, entry.pattern
+                ));
+            }
+            string
+        }
+        OpcodeTableFormat::Sleigh => generate_sleigh_constructors(table),
+    }
+}
+
+/// Generates a SLEIGH skeleton for Ghidra, with a constructor matching the fixed bits of
+/// each instruction word. Operands and semantics are left to the processor spec
+pub fn generate_sleigh_constructors(table: &[OpcodeTableEntry]) -> String {
+    // Fields covering each run of fixed bits, as (lsb, msb, value)
+    let constraints: Vec<Vec<(usize, usize, u64)>> = table
+        .iter()
+        .map(|entry| get_fixed_bit_runs(&entry.pattern))
+        .collect();
+    let mut fields: Vec<(usize, usize)> = constraints
+        .iter()
+        .flatten()
+        .map(|(lsb, msb, _)| (*lsb, *msb))
+        .collect();
+    fields.sort_by_key(|(lsb, msb)| (std::cmp::Reverse(*msb), *lsb));
+    fields.dedup();
+
+    let mut string = "# Generated by l6as --dump-opcodes sleigh\n\n\
+                      define endian=big;\n\
+                      define alignment=2;\n\
+                      define space ram type=ram_space size=2 wordsize=2 default;\n\n\
+                      define token instr (16)\n"
+        .to_owned();
+    for (lsb, msb) in &fields {
+        string.push_str(&format!("  b{}_{} = ({},{})\n", msb, lsb, lsb, msb));
+    }
+    string.push_str(";\n\n");
+
+    for (entry, runs) in table.iter().zip(constraints) {
+        let runs: Vec<String> = runs
+            .iter()
+            .map(|(lsb, msb, value)| format!("b{}_{}={:#X}", msb, lsb, value))
+            .collect();
+        string.push_str(&format!(
+            ":{} is {} unimpl\n",
+            entry.mnemonic,
+            runs.join(" & ")
+        ));
+    }
+
+    string
+}
+
+// Runs of 0 and 1 in an opcode pattern, most significant bit first
+fn get_fixed_bit_runs(pattern: &str) -> Vec<(usize, usize, u64)> {
+    let width = pattern.len();
+    let mut runs = vec![];
+    let mut start: Option<usize> = None;
+    for (i, chr) in pattern.chars().chain(std::iter::once('x')).enumerate() {
+        match (chr == 'x', start) {
+            (false, None) => start = Some(i),
+            (true, Some(run_start)) => {
+                let value = u64::from_str_radix(&pattern[run_start..i], 2).unwrap();
+                runs.push((width - i, width - 1 - run_start, value));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    runs
+}
+
+/// Write assembler output to annotated hex files, one for each bank like binary output
+pub fn write_assembler_annotated_hex_output(
+    file_path: &Path,
+    lines: &[AssembledLine],
+    radix: ListingRadix,
+) -> Result<(), ()> {
+    for bank in get_banks(lines) {
+        let bank_file_path = match bank {
+            Some(bank) => get_bank_file_path(file_path, bank),
+            None => file_path.to_path_buf(),
+        };
+
+        let string = generate_annotated_hex_output(lines, bank, radix);
+        if let Err(err) = write_file(&bank_file_path, string.as_bytes()) {
+            print_write_file_error_msg(err);
+            return Err(());
+        }
+    }
+
+    Ok(())
+}
+
+// Words on each line of annotated hex output
+const ANNOTATED_HEX_ROW_LEN: usize = 8;
+
+/// Generates "address: words" lines of the code of a bank, followed by their source line as a
+/// comment, to be typed into a front panel or monitor program
+pub fn generate_annotated_hex_output(
+    lines: &[AssembledLine],
+    bank: Option<u64>,
+    radix: ListingRadix,
+) -> String {
+    let mut string = String::new();
+    for line in lines.iter().filter(|line| line.bank == bank) {
+        // Long data definitions take several rows, only the first one is annotated
+        let mut address = line.address;
+        let mut comment = format!(
+            "  ; {}",
+            line.location
+                .raw_content
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        for row in line.data.chunks(ANNOTATED_HEX_ROW_LEN) {
+            let words: Vec<String> = row.iter().map(|word| radix.format_word(*word)).collect();
+            let address_column = match radix {
+                ListingRadix::Octal => format!("{:0>7o}", address),
+                ListingRadix::Hex | ListingRadix::Both => format!("{:0>5X}", address),
+            };
+            string.push_str(&format!(
+                "{}: {}{}\n",
+                address_column,
+                words.join(" "),
+                comment
+            ));
+            comment.clear();
+            address += row.len() as u64;
+        }
+    }
+
+    string
+}
+
+/// Write assembler output to paper tape images, one for each bank like binary output
+pub fn write_assembler_paper_tape_output(
+    file_path: &Path,
+    lines: &[AssembledLine],
+) -> Result<(), ()> {
+    for bank in get_banks(lines) {
+        let bank_file_path = match bank {
+            Some(bank) => get_bank_file_path(file_path, bank),
+            None => file_path.to_path_buf(),
+        };
+
+        let data = generate_paper_tape_output(lines, bank);
+        if let Err(err) = write_file(&bank_file_path, &data) {
+            print_write_file_error_msg(err);
+            return Err(());
+        }
+    }
+
+    Ok(())
+}
+
+// Blank frames punched before and after the data, for the tape to be threaded
+const PAPER_TAPE_LEADER_LEN: usize = 64;
+// Most words in each block
+const PAPER_TAPE_BLOCK_LEN: usize = 64;
+// Frame starting each block, distinct from the leader
+const PAPER_TAPE_BLOCK_START: u8 = 0x01;
+
+/// Generates a paper tape image of the code of a bank, as read by include/l6ptp.l6s: a leader,
+/// blocks of consecutive words, an empty block ending the data and a trailer. Each block is the
+/// start frame, the word count, the word address of its first word (3 frames), the words
+/// (2 frames each, big endian) and a checksum frame making the frames after the start one sum to 0
+pub fn generate_paper_tape_output(lines: &[AssembledLine], bank: Option<u64>) -> Vec<u8> {
+    // Runs of words at consecutive addresses
+    let mut runs: Vec<(u64, Vec<u16>)> = vec![];
+    for line in lines.iter().filter(|line| line.bank == bank) {
+        match runs.last_mut() {
+            Some((start, run)) if *start + run.len() as u64 == line.address => {
+                run.extend(&line.data)
+            }
+            _ => runs.push((line.address, line.data.clone())),
+        }
+    }
+
+    let mut tape = vec![0; PAPER_TAPE_LEADER_LEN];
+    for (start, run) in runs {
+        for (i, block) in run.chunks(PAPER_TAPE_BLOCK_LEN).enumerate() {
+            let address = start + (i * PAPER_TAPE_BLOCK_LEN) as u64;
+            tape.extend(paper_tape_block(address, block));
+        }
+    }
+
+    // End of data
+    tape.extend(paper_tape_block(0, &[]));
+    tape.extend(vec![0; PAPER_TAPE_LEADER_LEN]);
+    tape
+}
+
+// Frames of a paper tape block, from the start frame to the checksum
+fn paper_tape_block(address: u64, words: &[u16]) -> Vec<u8> {
+    let mut frames = vec![words.len() as u8];
+    frames.extend(&(address as u32).to_be_bytes()[1..]);
+    frames.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+    let sum = frames
+        .iter()
+        .fold(0u8, |sum, frame| sum.wrapping_add(*frame));
+    frames.push(sum.wrapping_neg());
+
+    frames.insert(0, PAPER_TAPE_BLOCK_START);
+    frames
+}
+
+/// Write assembler output to loadable modules, one for each bank like binary output. The entry
+/// point is the address of the label given, or the first word of code
+pub fn write_assembler_module_output(
+    file_path: &Path,
+    lines: &[AssembledLine],
+    labels: &[LabelDefinition],
+    entry: Option<&str>,
+) -> Result<(), ()> {
+    for bank in get_banks(lines) {
+        let bank_file_path = match bank {
+            Some(bank) => get_bank_file_path(file_path, bank),
+            None => file_path.to_path_buf(),
+        };
+
+        let entry = match entry {
+            Some(label) => match get_module_entry(labels, label, bank) {
+                Some(address) => Some(address),
+                None => {
+                    print_module_entry_error_msg(&bank_file_path, label);
+                    return Err(());
+                }
+            },
+            None => None,
+        };
+        let data = match generate_module_output(lines, bank, entry) {
+            Ok(data) => data,
+            Err(address) => {
+                print_module_overlap_error_msg(&bank_file_path, address);
+                return Err(());
+            }
+        };
+        if let Err(err) = write_file(&bank_file_path, &data) {
+            print_write_file_error_msg(err);
+            return Err(());
+        }
+    }
+
+    Ok(())
+}
+
+// First word of modules, "L6"
+const MODULE_MAGIC: u16 = 0x4C36;
+
+/// Address of the entry point label of the module of a bank, defined in the bank or in
+/// common code
+pub fn get_module_entry(labels: &[LabelDefinition], label: &str, bank: Option<u64>) -> Option<u64> {
+    let label = label.to_uppercase();
+    labels
+        .iter()
+        .find(|definition| {
+            definition.label == label && (definition.bank.is_none() || definition.bank == bank)
+        })
+        .map(|definition| definition.address)
+}
+
+/// Generates a loadable module of the code of a bank, as read by include/l6mod.l6s: a header
+/// and the code from its lowest address, gaps filled with zeros. The header is the magic word,
+/// the load address, the length in words and the entry point (2 words each, big endian) and the
+/// CRC-16/CCITT of the code. The entry point is the first word of code when not given. Lines
+/// placed over each other are an error, with the address of the first word written twice
+pub fn generate_module_output(
+    lines: &[AssembledLine],
+    bank: Option<u64>,
+    entry: Option<u64>,
+) -> Result<Vec<u8>, u64> {
+    let lines: Vec<&AssembledLine> = lines
+        .iter()
+        .filter(|line| line.bank == bank && !line.data.is_empty())
+        .collect();
+    let entry = entry.unwrap_or(lines.first().map(|line| line.address).unwrap_or(0));
+    let start = lines.iter().map(|line| line.address).min().unwrap_or(0);
+    let end = lines
+        .iter()
+        .map(|line| line.address + line.data.len() as u64)
+        .max()
+        .unwrap_or(0);
+
+    let mut code = vec![0u16; (end - start) as usize];
+    let mut written = vec![false; code.len()];
+    for line in lines {
+        let offset = (line.address - start) as usize;
+        let range = offset..offset + line.data.len();
+        if let Some(overlap) = written[range.clone()].iter().position(|&w| w) {
+            return Err(line.address + overlap as u64);
+        }
+        written[range.clone()].fill(true);
+        code[range].copy_from_slice(&line.data);
+    }
+
+    let mut words = vec![MODULE_MAGIC];
+    for value in [start, end - start, entry] {
+        words.push((value >> 16) as u16);
+        words.push(value as u16);
+    }
+    words.push(crc16_ccitt(&code));
+    words.extend(code);
+
+    Ok(words.iter().flat_map(|word| word.to_be_bytes()).collect())
+}
+
+// CRC-16/CCITT (polynomial 0x1021, initial value 0xFFFF) of big endian words
+fn crc16_ccitt(words: &[u16]) -> u16 {
+    words.iter().fold(0xFFFF, |crc, word| {
+        (0..16).fold(crc ^ word, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x1021,
+        })
+    })
+}
+
+/// Write assembler output to Intel HEX files, one for each bank like binary output
+pub fn write_assembler_intel_hex_output(
+    file_path: &Path,
+    lines: &[AssembledLine],
+) -> Result<(), ()> {
+    for bank in get_banks(lines) {
+        let bank_file_path = match bank {
+            Some(bank) => get_bank_file_path(file_path, bank),
+            None => file_path.to_path_buf(),
+        };
+
+        let string = generate_intel_hex_output(lines, bank);
+        if let Err(err) = write_file(&bank_file_path, string.as_bytes()) {
+            print_write_file_error_msg(err);
+            return Err(());
+        }
+    }
+
+    Ok(())
+}
+
+// Data bytes in each Intel HEX record
+const INTEL_HEX_RECORD_LEN: usize = 16;
+
+/// Generates Intel HEX records of the code of a bank. Words are stored big endian at twice
+/// their address, as bytes are addressed by programmers
+pub fn generate_intel_hex_output(lines: &[AssembledLine], bank: Option<u64>) -> String {
+    // Runs of bytes at consecutive addresses
+    let mut runs: Vec<(u64, Vec<u8>)> = vec![];
+    for line in lines.iter().filter(|line| line.bank == bank) {
+        let address = line.address * 2;
+        let bytes = line.data.iter().flat_map(|word| word.to_be_bytes());
+        match runs.last_mut() {
+            Some((start, run)) if *start + run.len() as u64 == address => run.extend(bytes),
+            _ => runs.push((address, bytes.collect())),
+        }
+    }
+
+    let mut string = String::new();
+    let mut upper_address: u64 = 0;
+    for (start, run) in runs {
+        let mut address = start;
+        let mut run = run.as_slice();
+        while !run.is_empty() {
+            // Records can't cross 64K boundaries
+            let len = INTEL_HEX_RECORD_LEN
+                .min(run.len())
+                .min((0x10000 - (address & 0xFFFF)) as usize);
+
+            if address >> 16 != upper_address {
+                upper_address = address >> 16;
+                string.push_str(&intel_hex_record(
+                    0,
+                    0x04,
+                    &(upper_address as u16).to_be_bytes(),
+                ));
+            }
+            string.push_str(&intel_hex_record(address as u16, 0x00, &run[..len]));
+
+            address += len as u64;
+            run = &run[len..];
+        }
+    }
+
+    // End of file
+    string.push_str(&intel_hex_record(0, 0x01, &[]));
+    string
+}
+
+fn intel_hex_record(address: u16, record_type: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend_from_slice(data);
+
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(sum.wrapping_neg());
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(":{}\r\n", hex)
+}
+
+/// Everything output formats may need besides the assembled code
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputContext<'a> {
+    pub labels: &'a [LabelDefinition],
+    pub binary: BinaryOutputOptions,
+    pub listing: ListingOptions<'a>,
+    /// Label of the entry point of loadable modules
+    pub entry: Option<&'a str>,
+}
+
+/// Format the assembled program can be written in, selected by name
+pub trait OutputFormat {
+    /// Name used to select the format, e.g. by --format
+    fn name(&self) -> &'static str;
+
+    /// Extension of output files, without the dot
+    fn extension(&self) -> &'static str;
+
+    /// Writes the program to a file, reporting errors itself
+    fn write(
+        &self,
+        file_path: &Path,
+        lines: &[AssembledLine],
+        ctx: &OutputContext,
+    ) -> Result<(), ()>;
+}
+
+struct BinaryFormat;
+
+impl OutputFormat for BinaryFormat {
+    fn name(&self) -> &'static str {
+        "bin"
+    }
+
+    fn extension(&self) -> &'static str {
+        "bin"
+    }
+
+    fn write(
+        &self,
+        file_path: &Path,
+        lines: &[AssembledLine],
+        ctx: &OutputContext,
+    ) -> Result<(), ()> {
+        write_assembler_binary_output(file_path, lines, &ctx.binary)
+    }
+}
+
+struct ListingFormat;
+
+impl OutputFormat for ListingFormat {
+    fn name(&self) -> &'static str {
+        "listing"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn write(
+        &self,
+        file_path: &Path,
+        lines: &[AssembledLine],
+        ctx: &OutputContext,
+    ) -> Result<(), ()> {
+        write_assembler_listing_output(&file_path.to_path_buf(), lines, ctx.labels, &ctx.listing)
+    }
+}
+
+struct SymbolMapFormat;
+
+impl OutputFormat for SymbolMapFormat {
+    fn name(&self) -> &'static str {
+        "map"
+    }
+
+    fn extension(&self) -> &'static str {
+        "map"
+    }
+
+    fn write(
+        &self,
+        file_path: &Path,
+        _lines: &[AssembledLine],
+        ctx: &OutputContext,
+    ) -> Result<(), ()> {
+        write_symbol_map_output(&file_path.to_path_buf(), ctx.labels)
+    }
+}
+
+struct IntelHexFormat;
+
+impl OutputFormat for IntelHexFormat {
+    fn name(&self) -> &'static str {
+        "ihex"
+    }
+
+    fn extension(&self) -> &'static str {
+        "hex"
+    }
+
+    fn write(
+        &self,
+        file_path: &Path,
+        lines: &[AssembledLine],
+        _ctx: &OutputContext,
+    ) -> Result<(), ()> {
+        write_assembler_intel_hex_output(file_path, lines)
+    }
+}
+
+struct AnnotatedHexFormat;
+
+impl OutputFormat for AnnotatedHexFormat {
+    fn name(&self) -> &'static str {
+        "annotated"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ahex"
+    }
+
+    fn write(
+        &self,
+        file_path: &Path,
+        lines: &[AssembledLine],
+        ctx: &OutputContext,
+    ) -> Result<(), ()> {
+        write_assembler_annotated_hex_output(file_path, lines, ctx.listing.radix)
+    }
+}
+
+struct PaperTapeFormat;
+
+impl OutputFormat for PaperTapeFormat {
+    fn name(&self) -> &'static str {
+        "ptp"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ptp"
+    }
+
+    fn write(
+        &self,
+        file_path: &Path,
+        lines: &[AssembledLine],
+        _ctx: &OutputContext,
+    ) -> Result<(), ()> {
+        write_assembler_paper_tape_output(file_path, lines)
+    }
+}
+
+struct ModuleFormat;
+
+impl OutputFormat for ModuleFormat {
+    fn name(&self) -> &'static str {
+        "mod"
+    }
+
+    fn extension(&self) -> &'static str {
+        "l6m"
+    }
+
+    fn write(
+        &self,
+        file_path: &Path,
+        lines: &[AssembledLine],
+        ctx: &OutputContext,
+    ) -> Result<(), ()> {
+        write_assembler_module_output(file_path, lines, ctx.labels, ctx.entry)
+    }
+}
+
+/// Output formats, by name
+pub struct OutputFormatRegistry {
+    formats: Vec<Box<dyn OutputFormat>>,
+}
+
+impl OutputFormatRegistry {
+    /// Registry of the built-in formats
+    pub fn builtin() -> Self {
+        let mut registry = Self { formats: vec![] };
+        registry.register(BinaryFormat);
+        registry.register(ListingFormat);
+        registry.register(SymbolMapFormat);
+        registry.register(IntelHexFormat);
+        registry.register(AnnotatedHexFormat);
+        registry.register(PaperTapeFormat);
+        registry.register(ModuleFormat);
+        registry
+    }
+
+    /// Registers a format, replacing the format with the same name
+    pub fn register(&mut self, format: impl OutputFormat + 'static) {
+        self.formats.retain(|other| other.name() != format.name());
+        self.formats.push(Box::new(format));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn OutputFormat> {
+        self.formats
+            .iter()
+            .find(|format| format.name() == name)
+            .map(|format| format.as_ref())
+    }
+
+    /// Names of the registered formats
+    pub fn names(&self) -> Vec<&'static str> {
+        self.formats.iter().map(|format| format.name()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembler::{assemble_with_labels, AssemblerOptions},
+        file::FileInclusionCoordinator,
+        preprocessor::{preprocess, CodeLine, LineLocation},
+    };
+
+    #[test]
+    fn test_generate_preprocessor_output_line_markers() {
+        let source_line = |file_name: &str, line_n: usize, body: SourceLineBody| SourceLine {
+            body,
+            comment: String::new(),
+            location: LineLocation {
+                line_n,
+                file_name: file_name.into(),
+                raw_content: String::new(),
+            },
+        };
+        let lines = [
+            source_line("main.l6s", 1, SourceLineBody::Include("a.l6s".into())),
+            source_line("a.l6s", 1, SourceLineBody::Code("A: HLT".to_owned())),
+            source_line("a.l6s", 2, SourceLineBody::Code("HLT".to_owned())),
+            source_line("main.l6s", 3, SourceLineBody::Code("B A".to_owned())),
+        ];
+        let opts = PreprocessorOutputOptions {
+            line_markers: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            generate_preprocessor_output(&lines, &opts),
+            "%line 1 \"a.l6s\"\r\nA: HLT\r\nHLT\r\n%line 3 \"main.l6s\"\r\nB A\r\n"
+        );
+    }
+
+    #[test]
+    fn test_listing_radix() {
+        let line = AssembledLine::new_test(0x10, None, vec![0x1C09], None);
+        let tests = [
+            (ListingRadix::Hex, "00010:  1C09        \r\n"),
+            (ListingRadix::Octal, "00010:  016011          \r\n"),
+            (ListingRadix::Both, "00010:  1C09/016011               \r\n"),
+        ];
+        for (radix, exp) in tests {
+            let opts = ListingOptions {
+                radix,
+                ..Default::default()
+            };
+            assert_eq!(generate_line_listing(&line, &opts, None, None), exp);
+        }
+    }
+
+    #[test]
+    fn test_generate_listing_source_lines() {
+        let source_line = |line_n: usize, body: SourceLineBody, raw_content: &str| SourceLine {
+            body,
+            comment: String::new(),
+            location: LineLocation {
+                line_n,
+                file_name: "test.l6s".into(),
+                raw_content: raw_content.to_owned(),
+            },
+        };
+        let source = [
+            source_line(1, SourceLineBody::Empty, "; Start"),
+            source_line(2, SourceLineBody::Code("START:".to_owned()), "START:"),
+            source_line(3, SourceLineBody::Empty, ""),
+            source_line(4, SourceLineBody::Code("HLT".to_owned()), "HLT"),
+        ];
+        let mut code = AssembledLine::new_test(0x10, None, vec![0x0000], None);
+        code.location.line_n = 4;
+        code.location.raw_content = "HLT".to_owned();
+        let labels = [LabelDefinition {
+            label: "START".to_owned(),
+            address: 0x10,
+            bank: None,
+            location: source[1].location.clone(),
+        }];
+        let opts = ListingOptions {
+            source: Some(&source),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            generate_listing(&[code], &labels, &opts),
+            "                    ; Start\r\n\
+             00010:              START:\r\n\
+             \r\n\
+             00010:  0000        HLT\r\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_intel_hex_output() {
+        let lines = [
+            AssembledLine::new_test(0x10, None, vec![0x1C09, 0x2C07], None),
+            AssembledLine::new_test(0x12, None, vec![0x0000], None),
+            AssembledLine::new_test(0x8000, None, vec![0xABCD], None),
+        ];
+
+        assert_eq!(
+            generate_intel_hex_output(&lines, None),
+            ":060020001C092C07000082\r\n\
+             :020000040001F9\r\n\
+             :02000000ABCD86\r\n\
+             :00000001FF\r\n"
+        );
+        assert_eq!(
+            generate_intel_hex_output(&lines, Some(1)),
+            ":00000001FF\r\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_sleigh_constructors() {
+        let entry = |mnemonic: &str, pattern: &str| OpcodeTableEntry {
+            mnemonic: mnemonic.to_owned(),
+            kind: "".to_owned(),
+            header: "".to_owned(),
+            opcode: "".to_owned(),
+            pattern: pattern.to_owned(),
+        };
+        let table = [
+            entry("HLT", "0000000000000000"),
+            entry("BEZ", "0xxx10010xxxxxxx"),
+        ];
+
+        let string = generate_sleigh_constructors(&table);
+        assert!(string.contains(
+            "define token instr (16)\n  b15_0 = (0,15)\n  b15_15 = (15,15)\n  b11_7 = (7,11)\n;\n"
+        ));
+        assert!(
+            string.ends_with(":HLT is b15_0=0x0 unimpl\n:BEZ is b15_15=0x0 & b11_7=0x12 unimpl\n")
+        );
+    }
+
+    #[test]
+    fn test_generate_annotated_hex_output() {
+        let mut lines = [
+            AssembledLine::new_test(0x10, None, vec![0x1C09], None),
+            AssembledLine::new_test(0x11, None, (0..10).collect(), None),
+        ];
+        lines[0].location.raw_content = "START:  LDR $R1, =9   ; Count".to_owned();
+        lines[1].location.raw_content = ".DW 0, 1, 2, 3, 4, 5, 6, 7, 8, 9".to_owned();
+
+        assert_eq!(
+            generate_annotated_hex_output(&lines, None, ListingRadix::Hex),
+            "00010: 1C09  ; START: LDR $R1, =9 ; Count\n\
+             00011: 0000 0001 0002 0003 0004 0005 0006 0007  ; .DW 0, 1, 2, 3, 4, 5, 6, 7, 8, 9\n\
+             00019: 0008 0009\n"
+        );
+        assert_eq!(
+            generate_annotated_hex_output(&lines[..1], None, ListingRadix::Octal),
+            "0000020: 016011  ; START: LDR $R1, =9 ; Count\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_paper_tape_output() {
+        let lines = [
+            AssembledLine::new_test(0x10, None, vec![0x1C09], None),
+            AssembledLine::new_test(0x11, None, vec![0x2C07], None),
+            AssembledLine::new_test(0x10000, None, vec![0xABCD], None),
+        ];
+
+        let tape = generate_paper_tape_output(&lines, None);
+        let leader = vec![0; PAPER_TAPE_LEADER_LEN];
+        assert_eq!(tape[..PAPER_TAPE_LEADER_LEN], leader);
+        assert_eq!(tape[tape.len() - PAPER_TAPE_LEADER_LEN..], leader);
+        assert_eq!(
+            tape[PAPER_TAPE_LEADER_LEN..tape.len() - PAPER_TAPE_LEADER_LEN],
+            [
+                0x01, 0x02, 0x00, 0x00, 0x10, 0x1C, 0x09, 0x2C, 0x07, 0x96, //
+                0x01, 0x01, 0x01, 0x00, 0x00, 0xAB, 0xCD, 0x86, //
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paper_tape_loader() {
+        let mut fi_coord = FileInclusionCoordinator::new();
+        fi_coord
+            .add_include_dirs(&[PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("include")])
+            .unwrap();
+        let mut code_lines = preprocess(&"l6ptp.l6s".into(), &mut fi_coord).unwrap();
+        // Frame reader of the including program
+        code_lines.push(CodeLine {
+            body: "L6PTP_READ: JMP $B4".to_owned(),
+            comment: String::new(),
+            location: LineLocation {
+                line_n: 1,
+                file_name: "reader.l6s".into(),
+                raw_content: "L6PTP_READ: JMP $B4".to_owned(),
+            },
+        });
+        let (res, labels) = assemble_with_labels(&code_lines, &AssemblerOptions::default());
+        let lines = res.unwrap();
+
+        for label in [
+            "L6PTP_LOAD",
+            "L6PTP_BLOCK",
+            "L6PTP_WORD",
+            "L6PTP_CHECK",
+            "L6PTP_DONE",
+            "L6PTP_BAD",
+            "L6PTP_READ",
+        ] {
+            assert!(
+                labels.iter().any(|definition| definition.label == label),
+                "{}",
+                label
+            );
+        }
+
+        // Each line starts where the previous one ends
+        for pair in lines.windows(2) {
+            assert_eq!(
+                pair[0].address + pair[0].data.len() as u64,
+                pair[1].address,
+                "{}",
+                pair[1].location.raw_content
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_module_output() {
+        let lines = [
+            AssembledLine::new_test(0x10, None, vec![0x1C09], None),
+            AssembledLine::new_test(0x11, None, vec![], None),
+            AssembledLine::new_test(0x12, None, vec![0x2C07], None),
+        ];
+
+        assert_eq!(crc16_ccitt(&[0x3132, 0x3334, 0x3536, 0x3738]), 0xA12B);
+        assert_eq!(
+            generate_module_output(&lines, None, None).unwrap(),
+            [
+                0x4C, 0x36, // Magic
+                0x00, 0x00, 0x00, 0x10, // Load address
+                0x00, 0x00, 0x00, 0x03, // Length
+                0x00, 0x00, 0x00, 0x10, // Entry point
+                0x84, 0x67, // CRC
+                0x1C, 0x09, 0x00, 0x00, 0x2C, 0x07,
+            ]
+        );
+
+        // Lines placed over each other
+        let lines = [
+            AssembledLine::new_test(0x10, None, vec![0xC841, 0x0004], None),
+            AssembledLine::new_test(0x11, None, vec![0x0000], None),
+        ];
+        assert_eq!(generate_module_output(&lines, None, None), Err(0x11));
+    }
+
+    #[test]
+    fn test_module_entry() {
+        let code_lines: Vec<CodeLine> = ["HLT", "START: LDV $R1, =1", "HLT"]
+            .iter()
+            .enumerate()
+            .map(|(i, body)| CodeLine {
+                body: body.to_string(),
+                comment: String::new(),
+                location: LineLocation {
+                    line_n: i + 1,
+                    file_name: "test.l6s".into(),
+                    raw_content: body.to_string(),
+                },
+            })
+            .collect();
+        let (res, labels) = assemble_with_labels(&code_lines, &AssemblerOptions::default());
+        let lines = res.unwrap();
+
+        assert_eq!(get_module_entry(&labels, "start", None), Some(0x01));
+        assert_eq!(get_module_entry(&labels, "END", None), None);
+
+        let module = generate_module_output(&lines, None, Some(0x01)).unwrap();
+        assert_eq!(module[10..14], [0x00, 0x00, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn test_module_loader() {
+        let mut fi_coord = FileInclusionCoordinator::new();
+        fi_coord
+            .add_include_dirs(&[PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("include")])
+            .unwrap();
+        let code_lines = preprocess(&"l6mod.l6s".into(), &mut fi_coord).unwrap();
+        let (res, labels) = assemble_with_labels(&code_lines, &AssemblerOptions::default());
+        let lines = res.unwrap();
+
+        let label_address = |label: &str| {
+            labels
+                .iter()
+                .find(|definition| definition.label == label)
+                .map(|definition| definition.address)
+        };
+        let expected = [
+            ("L6MOD_LOAD", 0x00),
+            ("L6MOD_WORD", 0x0A),
+            ("L6MOD_BIT", 0x0E),
+            ("L6MOD_SHIFT", 0x15),
+            ("L6MOD_NEXT", 0x16),
+            ("L6MOD_CHECK", 0x1C),
+            ("L6MOD_COPY", 0x26),
+            ("L6MOD_START", 0x2D),
+            ("L6MOD_BAD", 0x2F),
+        ];
+        for (label, address) in expected {
+            assert_eq!(label_address(label), Some(address), "{}", label);
+        }
+
+        // Each line starts where the previous one ends
+        for pair in lines.windows(2) {
+            assert_eq!(
+                pair[0].address + pair[0].data.len() as u64,
+                pair[1].address,
+                "{}",
+                pair[1].location.raw_content
+            );
+        }
+
+        let module = generate_module_output(&lines, None, None).unwrap();
+        assert_eq!(module.len(), (8 + 0x30) * 2);
+        assert_eq!(
+            module[..14],
+            [
+                0x4C, 0x36, // Magic
+                0x00, 0x00, 0x00, 0x00, // Load address
+                0x00, 0x00, 0x00, 0x30, // Length
+                0x00, 0x00, 0x00, 0x00, // Entry point
+            ]
+        );
+        let code: Vec<u8> = lines
+            .iter()
+            .flat_map(|line| line.data.iter().flat_map(|word| word.to_be_bytes()))
+            .collect();
+        assert_eq!(module[16..], code);
+    }
+
+    #[test]
+    fn test_reorder_binary_output() {
+        let image = [0x1C, 0x09, 0x2C, 0x07, 0xAB, 0xCD];
+        let tests = [
+            (
+                false,
+                WordOrder::HighLow,
+                [0x1C, 0x09, 0x2C, 0x07, 0xAB, 0xCD],
+            ),
+            (
+                true,
+                WordOrder::HighLow,
+                [0x09, 0x1C, 0x07, 0x2C, 0xCD, 0xAB],
+            ),
+            (
+                false,
+                WordOrder::LowHigh,
+                [0x2C, 0x07, 0x1C, 0x09, 0xAB, 0xCD],
+            ),
+            (
+                true,
+                WordOrder::LowHigh,
+                [0x07, 0x2C, 0x09, 0x1C, 0xCD, 0xAB],
+            ),
+        ];
+        for (swap_bytes, word_order, exp) in tests {
+            let mut output = image;
+            reorder_binary_output(&mut output, swap_bytes, word_order);
+            assert_eq!(output, exp, "{:?} {:?}", swap_bytes, word_order);
+        }
+    }
+
+    #[test]
+    fn test_split_prom_image() {
+        let image = [0x1C, 0x09, 0x2C, 0x07, 0xAB];
+
+        assert_eq!(
+            split_prom_image(&image, PromSplit::ByteLanes(None), 0xFF),
+            vec![
+                ("even".to_owned(), vec![0x1C, 0x2C, 0xAB]),
+                ("odd".to_owned(), vec![0x09, 0x07, 0xFF]),
+            ]
+        );
+        assert_eq!(
+            split_prom_image(&image, PromSplit::ByteLanes(Some(2)), 0xFF),
+            vec![
+                ("0.even".to_owned(), vec![0x1C, 0x2C]),
+                ("1.even".to_owned(), vec![0xAB, 0xFF]),
+                ("0.odd".to_owned(), vec![0x09, 0x07]),
+                ("1.odd".to_owned(), vec![0xFF, 0xFF]),
+            ]
+        );
+        assert_eq!(
+            split_prom_image(&image, PromSplit::Words(2), 0),
+            vec![
+                ("0".to_owned(), vec![0x1C, 0x09, 0x2C, 0x07]),
+                ("1".to_owned(), vec![0xAB, 0, 0, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_format_registry() {
+        let registry = OutputFormatRegistry::builtin();
+        assert_eq!(
+            registry.names(),
+            vec!["bin", "listing", "map", "ihex", "annotated", "ptp", "mod"]
+        );
+        assert_eq!(registry.get("ihex").unwrap().extension(), "hex");
+        assert!(registry.get("srec").is_none());
+    }
+}
@@ -1,5 +1,8 @@
 mod parsers;
 mod preprocess;
 
-pub use preprocess::preprocess;
-pub use preprocess::{CodeLine, LineLocation};
+pub use parsers::{is_continued_line, parse_source_line};
+pub use preprocess::{
+    preprocess, preprocess_all_lines, preprocess_with_definitions, source_lines_to_code_lines,
+};
+pub use preprocess::{CodeLine, Definition, LineLocation, SourceLine, SourceLineBody};
@@ -3,7 +3,7 @@ use crate::logging::PreprocessorErrorKind;
 use nom::{
     branch::alt,
     bytes::complete::{is_a, is_not, tag, tag_no_case, take},
-    character::complete::{alphanumeric1, space0, space1},
+    character::complete::{alphanumeric1, digit1, space0, space1},
     combinator::{map, opt, value},
     error::{ErrorKind, ParseError},
     multi::{fold_many0, many0, many1},
@@ -15,7 +15,10 @@ use std::path::PathBuf;
 /////////////// RESERVED KEYWORDS ///////////////
 const PREPRO_CHAR: &str = "%";
 const KEYWORD_DEFINE: &str = "%define";
+const DEFAULT_DEFINE_CHAR: &str = "?";
+const CONTINUATION_CHAR: char = '\\';
 const KEYWORD_INCLUDE: &str = "%include";
+const KEYWORD_LINE: &str = "%line";
 /////////////////////////////////////////////////
 
 #[derive(Debug)]
@@ -61,6 +64,7 @@ fn parse_source_line_body(input: &str) -> IResult<&str, SourceLineBody, Preproce
     alt((
         parse_define_line_body,
         parse_include_line_body,
+        parse_line_marker_line_body,
         parse_code_line_body,
     ))(input)
 }
@@ -90,10 +94,12 @@ fn parse_code_line_body(input: &str) -> IResult<&str, SourceLineBody, Preprocess
     }
 }
 
-/// Parses %define directive
+/// Parses %define directive, and its %define? form which only defines identifiers that aren't
+/// defined elsewhere
 fn parse_define_line_body(input: &str) -> IResult<&str, SourceLineBody, PreprocessorParseError> {
     // Match %define tag
     let (input, _) = preceded(space0, tag_no_case(KEYWORD_DEFINE))(input)?;
+    let (input, default) = opt(tag(DEFAULT_DEFINE_CHAR))(input)?;
 
     // Get identifier
     let (input, identifier) = match preceded(space1, parse_identifier)(input) {
@@ -110,7 +116,7 @@ fn parse_define_line_body(input: &str) -> IResult<&str, SourceLineBody, Preproce
     let (input, value) = match preceded(
         // Type annotation on first space0 is needed but I don't know why
         delimited(space0::<&str, PreprocessorParseError>, tag("="), space0),
-        is_not(";"),
+        parse_define_value,
     )(input)
     {
         Ok(res) => res,
@@ -122,10 +128,23 @@ fn parse_define_line_body(input: &str) -> IResult<&str, SourceLineBody, Preproce
         }
     };
 
-    Ok((
-        input,
-        SourceLineBody::Define(identifier, value.trim().to_owned()),
-    ))
+    let value = value.trim().to_owned();
+    match default {
+        Some(_) => Ok((input, SourceLineBody::DefaultDefine(identifier, value))),
+        None => Ok((input, SourceLineBody::Define(identifier, value))),
+    }
+}
+
+// Value of a %define, up to its comment
+fn parse_define_value(input: &str) -> IResult<&str, &str, PreprocessorParseError<'_>> {
+    let end = find_comment_start(input).unwrap_or(input.len());
+    match end {
+        0 => Err(Err::Error(PreprocessorParseError::from_error_kind(
+            input,
+            ErrorKind::IsNot,
+        ))),
+        _ => Ok((&input[end..], &input[..end])),
+    }
 }
 
 /// Parses %include directive
@@ -148,20 +167,96 @@ fn parse_include_line_body(input: &str) -> IResult<&str, SourceLineBody, Preproc
     Ok((input, SourceLineBody::Include(PathBuf::from(file_path))))
 }
 
+/// Parses %line directive, which gives the original location of the following line in
+/// preprocessor output
+fn parse_line_marker_line_body(
+    input: &str,
+) -> IResult<&str, SourceLineBody, PreprocessorParseError<'_>> {
+    // Match %line tag, which must be followed by its arguments
+    let (input, _) = preceded(space0, tag_no_case(KEYWORD_LINE))(input)?;
+    let (input, _) = space1(input)?;
+
+    // Get line number and file path
+    match tuple((
+        digit1,
+        delimited(space1, alt((parse_string_literal, is_not(" \t;"))), space0),
+    ))(input)
+    {
+        Ok((input, (line_n, file_path))) => match line_n.parse::<usize>() {
+            Ok(line_n) if line_n > 0 => Ok((
+                input,
+                SourceLineBody::LineMarker(line_n, PathBuf::from(file_path)),
+            )),
+            _ => Err(Err::Failure(PreprocessorParseError {
+                _input: input,
+                kind: PreprocessorErrorKind::InvalidLineMarker,
+            })),
+        },
+        Err(_) => Err(Err::Failure(PreprocessorParseError {
+            _input: input,
+            kind: PreprocessorErrorKind::InvalidLineMarker,
+        })),
+    }
+}
+
 /// Parse preprocessor identifier
 fn parse_identifier(input: &str) -> IResult<&str, String> {
     map(many1(alt((alphanumeric1, is_a("_")))), |res| res.join(""))(input)
 }
 
+/// Whether a physical line continues on the next one, ending with a backslash before its comment
+pub fn is_continued_line(input: &str) -> bool {
+    divide_comment(input).0.ends_with(CONTINUATION_CHAR)
+}
+
+/// Joins physical lines continued by a backslash into a single line, keeping their comments
+pub fn join_continued_lines(lines: &[&str]) -> String {
+    let mut code: Vec<&str> = vec![];
+    let mut comments: Vec<&str> = vec![];
+    for line in lines {
+        let (body, comment) = divide_comment(line);
+        let body = body
+            .strip_suffix(CONTINUATION_CHAR)
+            .unwrap_or(body)
+            .trim_end();
+        if !body.is_empty() {
+            code.push(body);
+        }
+        if !comment.is_empty() {
+            comments.push(comment);
+        }
+    }
+
+    match comments.is_empty() {
+        true => code.join(" "),
+        false => format!("{} {}", code.join(" "), comments.join(" ")),
+    }
+}
+
 fn divide_comment(input: &str) -> (&str, &str) {
+    match find_comment_start(input) {
+        // Return (code, comment)
+        Some(pos) => (input[0..pos].trim(), input[pos..].trim()),
+        None => (input.trim(), ""),
+    }
+}
+
+/// Byte position of the semicolon starting the comment of a line, skipping the ones in string
+/// literals
+pub fn find_comment_start(input: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
     for (pos, character) in input.char_indices() {
-        if character == ';' {
-            // Return (code, comment)
-            return (&input[0..pos].trim(), &input[pos..].trim());
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return Some(pos),
+            _ => {}
         }
     }
 
-    (input.trim(), "")
+    None
 }
 
 /// Parse double quote delimited string literal
@@ -228,6 +323,21 @@ fn parse_code_chunk(input: &str) -> IResult<&str, DefinitionChunk> {
 mod tests {
     use super::{divide_comment, *};
 
+    #[test]
+    fn join_continued_lines_succ() {
+        assert!(is_continued_line("  .DW 1, 2, \\  ; First"));
+        assert!(!is_continued_line("  .DW 1, 2 ; Not \\"));
+
+        let tests: [(&[&str], &str); 3] = [
+            (&[".DW 1, 2, \\", "    3, 4"], ".DW 1, 2, 3, 4"),
+            (&[".DW 1, \\ ; One", "  2 ; Two"], ".DW 1, 2 ; One ; Two"),
+            (&["LDR $R1,\\", "\\", "=5"], "LDR $R1, =5"),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(join_continued_lines(input), exp);
+        }
+    }
+
     #[test]
     fn parse_string_literal_succ() {
         let tests = [
@@ -353,6 +463,16 @@ mod tests {
                 ),
                 "",
             ),
+            (
+                "%define? DEBUG = 0",
+                SourceLineBody::DefaultDefine("DEBUG".to_owned(), "0".to_owned()),
+                "",
+            ),
+            (
+                "%define SEP = \"a;b\" ; Comment",
+                SourceLineBody::Define("SEP".to_owned(), "\"a;b\"".to_owned()),
+                "; Comment",
+            ),
         ];
         for (input, exp_output, exp_remaining) in tests {
             let (remaining, output) = parse_define_line_body(input).unwrap();
@@ -369,6 +489,8 @@ mod tests {
             ("%define", true),
             ("%define test", true),
             ("%define test clasdfs= value", true),
+            ("%define?", true),
+            ("%define ? test = value", true),
         ];
         for (input, exp_failure) in tests {
             let err = parse_define_line_body(input).unwrap_err();
@@ -399,6 +521,11 @@ mod tests {
                 SourceLineBody::Include(PathBuf::from("included.l6s")),
                 "",
             ),
+            (
+                "%line 12 \"src/main.l6s\"",
+                SourceLineBody::LineMarker(12, PathBuf::from("src/main.l6s")),
+                "",
+            ),
         ];
         for (input, exp_output, exp_remaining) in tests {
             let (remaining, output) = parse_source_line_body(input).unwrap();
@@ -413,6 +540,9 @@ mod tests {
             ("; comment", "", "; comment"),
             ("code ; comment", "code", "; comment"),
             ("code ", "code", ""),
+            (".DB \"a;b\" ; c", ".DB \"a;b\"", "; c"),
+            (".DB \"a\\\";b\";c", ".DB \"a\\\";b\"", ";c"),
+            (".DB \"a\\\\\";b", ".DB \"a\\\\\"", ";b"),
         ];
         for (input, exp_code, exp_comment) in tests {
             let (code, comment) = divide_comment(input);
@@ -1,35 +1,56 @@
 use nom::Err;
+use serde::Serialize;
 use std::path::PathBuf;
-use std::{collections::HashMap, vec};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    vec,
+};
 
+use crate::assembler::evaluate_constant_expression;
 use crate::file::{FileInclusionCoordinator, FileInclusionError};
 
 use crate::logging::{
-    print_preprocessor_error, print_preprocessor_warning, PreprocessorError, PreprocessorErrorKind,
-    PreprocessorWarning, PreprocessorWarningKind,
+    capture_diagnostics, print_preprocessor_diagnostics, print_preprocessor_error,
+    print_preprocessor_error_at, print_preprocessor_warning, ColumnSpan, Diagnostic,
+    PreprocessorError, PreprocessorErrorKind, PreprocessorWarning, PreprocessorWarningKind,
 };
 
-use super::parsers::{parse_definitions_chunks, parse_source_line};
+use super::parsers::{
+    find_comment_start, is_continued_line, join_continued_lines, parse_definitions_chunks,
+    parse_source_line,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum SourceLineBody {
     Empty,
     Define(String, String),
+    // Only defines the identifier if nothing else does
+    DefaultDefine(String, String),
     Include(PathBuf),
+    // Original line number and file of the following line, in preprocessor output
+    LineMarker(usize, PathBuf),
     Code(String),
 }
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct LineLocation {
     pub line_n: usize,
     pub file_name: PathBuf,
+    /// Source as written, with one line per physical line when continued by a backslash
     pub raw_content: String,
 }
 
+impl LineLocation {
+    /// Number of the last physical line, which differs from `line_n` for continued lines
+    pub fn last_line_n(&self) -> usize {
+        self.line_n + self.raw_content.lines().count().saturating_sub(1)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct SourceLine {
-    body: SourceLineBody,
-    comment: String,
-    location: LineLocation,
+    pub body: SourceLineBody,
+    pub comment: String,
+    pub location: LineLocation,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -39,15 +60,56 @@ pub struct CodeLine {
     pub location: LineLocation,
 }
 
+// Source file parsed ahead of its inclusion, with the diagnostics to report once it's included
+type ParsedSource = (Result<Vec<SourceLine>, Vec<SourceLine>>, Vec<Diagnostic>);
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Definition {
+    pub identifier: String,
+    pub value: String,
+    pub location: LineLocation,
+}
+
 /// Preprocess a program
 pub fn preprocess(
     file_path: &PathBuf,
     fi_coord: &mut FileInclusionCoordinator,
 ) -> Result<Vec<CodeLine>, Vec<CodeLine>> {
+    preprocess_with_definitions(file_path, fi_coord).0
+}
+
+/// Preprocess a program, also returning all of its %defines
+pub fn preprocess_with_definitions(
+    file_path: &PathBuf,
+    fi_coord: &mut FileInclusionCoordinator,
+) -> (Result<Vec<CodeLine>, Vec<CodeLine>>, Vec<Definition>) {
+    let (source_lines, definitions) = preprocess_source_lines(file_path, fi_coord);
+
+    // Flatten all code lines to string
+    let res = match source_lines {
+        Ok(lines) => Ok(source_lines_to_code_lines(&lines)),
+        Err(lines) => Err(source_lines_to_code_lines(&lines)),
+    };
+    (res, definitions)
+}
+
+/// Preprocess a program, keeping empty lines and directives alongside the resolved code
+pub fn preprocess_all_lines(
+    file_path: &PathBuf,
+    fi_coord: &mut FileInclusionCoordinator,
+) -> Result<Vec<SourceLine>, Vec<SourceLine>> {
+    preprocess_source_lines(file_path, fi_coord).0
+}
+
+fn preprocess_source_lines(
+    file_path: &PathBuf,
+    fi_coord: &mut FileInclusionCoordinator,
+) -> (Result<Vec<SourceLine>, Vec<SourceLine>>, Vec<Definition>) {
     let mut error_encountered = false;
+    let _span = tracing::info_span!("preprocess", file = %file_path.display()).entered();
 
     // Parse the source file (resolving all includes)
-    let source_lines = match parse_source_file(file_path, fi_coord, &None) {
+    let source_lines = match parse_source_file(file_path, fi_coord, &None, &mut HashMap::new()) {
         Ok(lines) => lines,
         Err(lines) => {
             error_encountered = true;
@@ -56,7 +118,8 @@ pub fn preprocess(
     };
 
     // Process %defines
-    let source_lines = match process_defines(&source_lines) {
+    let definitions = collect_definitions(&source_lines);
+    let source_lines = match process_defines(&source_lines, fi_coord.definitions()) {
         Ok(lines) => lines,
         Err(lines) => {
             error_encountered = true;
@@ -64,14 +127,18 @@ pub fn preprocess(
         }
     };
 
-    // Flatten all code lines to string
-    let res = source_lines_to_code_lines(&source_lines);
+    tracing::info!(
+        "preprocessed {} lines with {} definitions",
+        source_lines.len(),
+        definitions.len()
+    );
 
     // Return results
-    match error_encountered {
-        false => Ok(res),
-        true => Err(res),
-    }
+    let res = match error_encountered {
+        false => Ok(source_lines),
+        true => Err(source_lines),
+    };
+    (res, definitions)
 }
 
 /// Parses input source file to a vector of `SourceLine`s, resolving includes
@@ -79,8 +146,10 @@ fn parse_source_file(
     file_path: &PathBuf,
     fi_coord: &mut FileInclusionCoordinator,
     include_location: &Option<LineLocation>,
+    prefetched: &mut HashMap<PathBuf, ParsedSource>,
 ) -> Result<Vec<SourceLine>, Vec<SourceLine>> {
     let mut error_encountered = false;
+    let _span = tracing::info_span!("file", path = %file_path.display()).entered();
 
     // Read file
     let (abs_path, code) = match fi_coord.read_file(&file_path) {
@@ -88,9 +157,9 @@ fn parse_source_file(
         Err(err) => {
             // Log error
             match err {
-                FileInclusionError::FileNotFound(file_path) => {
+                FileInclusionError::FileNotFound(file_path, searched_dirs) => {
                     print_preprocessor_error(PreprocessorError {
-                        kind: PreprocessorErrorKind::CannotOpenSourceFile(file_path),
+                        kind: PreprocessorErrorKind::CannotOpenSourceFile(file_path, searched_dirs),
                         location: include_location.clone(),
                     });
                 }
@@ -100,14 +169,35 @@ fn parse_source_file(
                         location: include_location.clone(),
                     });
                 }
+                FileInclusionError::Cycle(chain) => {
+                    print_preprocessor_error(PreprocessorError {
+                        kind: PreprocessorErrorKind::IncludeCycle(chain),
+                        location: include_location.clone(),
+                    });
+                }
+                FileInclusionError::DepthExceeded(max_depth, chain) => {
+                    print_preprocessor_error(PreprocessorError {
+                        kind: PreprocessorErrorKind::IncludeDepthExceeded(max_depth, chain),
+                        location: include_location.clone(),
+                    });
+                }
             }
 
             return Err(vec![]);
         }
     };
 
-    // Parse source lines
-    let lines = match parse_source_string(&code, &abs_path) {
+    tracing::debug!("read {} bytes from {}", code.len(), abs_path.display());
+
+    // Parse source lines, unless they were parsed ahead
+    let parsed = match prefetched.remove(&abs_path) {
+        Some((parsed, diagnostics)) => {
+            print_preprocessor_diagnostics(diagnostics);
+            parsed
+        }
+        None => parse_source_string(&code, &abs_path, fi_coord.strict()),
+    };
+    let lines = match parsed {
         Ok(lines) => lines,
         Err(lines) => {
             error_encountered = true;
@@ -115,7 +205,11 @@ fn parse_source_file(
         }
     };
 
-    // Process %includes
+    // Process %includes, unless this file already has errors and the run should stop
+    if error_encountered && fi_coord.fail_fast() {
+        fi_coord.finish_file();
+        return Err(lines);
+    }
     let lines = match process_includes(&lines, fi_coord) {
         Ok(res) => res,
         Err(lines) => {
@@ -123,6 +217,7 @@ fn parse_source_file(
             lines
         }
     };
+    fi_coord.finish_file();
 
     // Return results
     match error_encountered {
@@ -131,9 +226,9 @@ fn parse_source_file(
     }
 }
 
-/// Converts a Vec of SourceLines (containing only Code) to strings,
-/// fails if vec contains other types of lines
-fn source_lines_to_code_lines(input: &[SourceLine]) -> Vec<CodeLine> {
+/// Converts the Code lines in a Vec of SourceLines to strings, skipping other types of lines
+/// Code lines of preprocessed source lines, dropping empty lines and directives
+pub fn source_lines_to_code_lines(input: &[SourceLine]) -> Vec<CodeLine> {
     let mut strings: Vec<CodeLine> = vec![];
 
     // Go over all lines
@@ -144,8 +239,6 @@ fn source_lines_to_code_lines(input: &[SourceLine]) -> Vec<CodeLine> {
                 comment: line.comment.to_owned(),
                 location: line.location.to_owned(),
             });
-        } else {
-            panic!();
         }
     }
 
@@ -160,34 +253,57 @@ fn source_lines_to_code_lines(input: &[SourceLine]) -> Vec<CodeLine> {
 fn parse_source_string(
     input: &str,
     file_name: &PathBuf,
+    strict: bool,
 ) -> Result<Vec<SourceLine>, Vec<SourceLine>> {
     let mut lines: Vec<SourceLine> = vec![];
 
     // Keep track of if an error has been encountered
     let mut error_encountered = false;
 
-    // Get all lines from file
-    for (line_n, raw_line) in input.lines().enumerate() {
+    // Get all lines from file, joining the ones continued by a backslash
+    let physical_lines: Vec<&str> = input.lines().collect();
+    let mut next_line = 0;
+    // Original file of the lines after a %line, and the difference of their numbers
+    let mut line_marker: Option<(PathBuf, isize)> = None;
+    while next_line < physical_lines.len() {
+        let first_line = next_line;
+        while next_line + 1 < physical_lines.len() && is_continued_line(physical_lines[next_line]) {
+            next_line += 1;
+        }
+        next_line += 1;
+        let group = &physical_lines[first_line..next_line];
+
+        // Locations cover all of the physical lines
+        let raw_line = group.join("\n");
+        let location = match &line_marker {
+            Some((file_name, offset)) => LineLocation {
+                line_n: (first_line as isize + 1 + offset).max(1) as usize,
+                file_name: file_name.clone(),
+                raw_content: raw_line.clone(),
+            },
+            None => LineLocation {
+                line_n: first_line + 1,
+                file_name: file_name.clone(),
+                raw_content: raw_line.clone(),
+            },
+        };
+        let joined_line = match group.len() {
+            1 => group[0].to_owned(),
+            _ => join_continued_lines(group),
+        };
+
         // Parse line
-        let (_, (body, comment, garbage)) = match parse_source_line(raw_line) {
+        let (_, (body, comment, garbage)) = match parse_source_line(&joined_line) {
             Ok(line) => line,
             Err(err) => {
                 match err {
                     Err::Failure(err) => print_preprocessor_error(PreprocessorError {
                         kind: err.kind,
-                        location: Some(LineLocation {
-                            line_n: line_n + 1,
-                            file_name: file_name.clone(),
-                            raw_content: raw_line.to_owned(),
-                        }),
+                        location: Some(location.clone()),
                     }),
                     Err::Error(err) => print_preprocessor_error(PreprocessorError {
                         kind: err.kind,
-                        location: Some(LineLocation {
-                            line_n: line_n + 1,
-                            file_name: file_name.clone(),
-                            raw_content: raw_line.to_owned(),
-                        }),
+                        location: Some(location.clone()),
                     }),
 
                     Err::Incomplete(_) => {}
@@ -202,12 +318,31 @@ fn parse_source_string(
 
         // Check if there is still unparsed stuff
         if garbage.len() > 0 {
-            print_preprocessor_warning(PreprocessorWarning {
-                line_n: line_n + 1,
-                file_name: file_name.clone(),
-                line: raw_line.to_owned(),
-                kind: PreprocessorWarningKind::GarbageAtEndOfLine(garbage.to_owned()),
-            });
+            // Columns are only known when the line wasn't joined with others
+            let span = match group.len() {
+                1 => Some(get_garbage_span(&joined_line, &garbage)),
+                _ => None,
+            };
+
+            match strict {
+                true => {
+                    print_preprocessor_error_at(
+                        PreprocessorError {
+                            kind: PreprocessorErrorKind::GarbageAtEndOfLine(garbage.to_owned()),
+                            location: Some(location.clone()),
+                        },
+                        span,
+                    );
+                    error_encountered = true;
+                }
+                false => print_preprocessor_warning(PreprocessorWarning {
+                    line_n: location.line_n,
+                    file_name: location.file_name.clone(),
+                    line: raw_line.to_owned(),
+                    kind: PreprocessorWarningKind::GarbageAtEndOfLine(garbage.to_owned()),
+                    span,
+                }),
+            }
         }
 
         // If the line is empty, ignore it
@@ -215,15 +350,17 @@ fn parse_source_string(
             // continue;
         }
 
+        // Following lines are numbered from the one given by %line
+        if let SourceLineBody::LineMarker(marker_line_n, marker_file_name) = &body {
+            let offset = *marker_line_n as isize - (next_line as isize + 1);
+            line_marker = Some((marker_file_name.clone(), offset));
+        }
+
         // Add line to list
         lines.push(SourceLine {
             body,
             comment,
-            location: LineLocation {
-                line_n: line_n + 1,
-                file_name: file_name.clone(),
-                raw_content: raw_line.to_owned(),
-            },
+            location,
         });
     }
 
@@ -235,6 +372,19 @@ fn parse_source_string(
     // Ok(lines)
 }
 
+// Columns of a line taken by the garbage left at the end of its code, before the comment
+fn get_garbage_span(line: &str, garbage: &str) -> ColumnSpan {
+    let code = match find_comment_start(line) {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+    let end = code.trim_end().chars().count();
+    ColumnSpan {
+        start: end.saturating_sub(garbage.chars().count()),
+        end,
+    }
+}
+
 fn process_includes(
     input: &Vec<SourceLine>,
     fi_coord: &mut FileInclusionCoordinator,
@@ -242,21 +392,46 @@ fn process_includes(
     let mut output: Vec<SourceLine> = vec![];
     let mut error = false;
 
+    // Read and parse the included files ahead, they are still merged in inclusion order
+    let include_paths: Vec<PathBuf> = input
+        .iter()
+        .filter_map(|line| match &line.body {
+            SourceLineBody::Include(file_path) => Some(file_path.clone()),
+            _ => None,
+        })
+        .collect();
+    let strict = fi_coord.strict();
+    let mut prefetched = match include_paths.len() {
+        0 | 1 => HashMap::new(),
+        _ => fi_coord.prefetch(&include_paths, |abs_path, contents| {
+            capture_diagnostics(|| parse_source_string(contents, abs_path, strict))
+        }),
+    };
+
     for line in input {
-        // If line is include, resolve it. Otherwise copy line
+        // Copy line, keeping the %include itself ahead of the included lines
+        output.push(line.clone());
+
+        // If line is include, resolve it
         if let SourceLineBody::Include(file_path) = &line.body {
             // Process new file
-            let mut included_lines =
-                match parse_source_file(&file_path, fi_coord, &Some(line.location.clone())) {
-                    Ok(lines) => lines,
-                    Err(lines) => {
-                        error = true;
-                        lines
-                    }
-                };
-            output.append(&mut included_lines)
-        } else {
-            output.push(line.clone())
+            let mut included_lines = match parse_source_file(
+                &file_path,
+                fi_coord,
+                &Some(line.location.clone()),
+                &mut prefetched,
+            ) {
+                Ok(lines) => lines,
+                Err(lines) => {
+                    error = true;
+                    lines
+                }
+            };
+            output.append(&mut included_lines);
+
+            if error && fi_coord.fail_fast() {
+                break;
+            }
         }
     }
 
@@ -271,17 +446,37 @@ fn process_includes(
  * Definition processing
  */
 
-fn process_defines(input: &[SourceLine]) -> Result<Vec<SourceLine>, Vec<SourceLine>> {
+/// Lists all %defines in the program
+fn collect_definitions(input: &[SourceLine]) -> Vec<Definition> {
+    input
+        .iter()
+        .filter_map(|line| match &line.body {
+            SourceLineBody::Define(identifier, value)
+            | SourceLineBody::DefaultDefine(identifier, value) => Some(Definition {
+                identifier: identifier.clone(),
+                value: value.clone(),
+                location: line.location.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn process_defines(
+    input: &[SourceLine],
+    predefined: &[(String, String)],
+) -> Result<Vec<SourceLine>, Vec<SourceLine>> {
     let mut res: Vec<SourceLine> = vec![];
     let mut error_encountered = false;
 
-    // Definition table
-    let mut definition_table: HashMap<String, String> = HashMap::new();
+    // Definition table, with values as written
+    let mut raw_table: HashMap<String, RawDefinition> = HashMap::new();
+    let mut identifiers: Vec<String> = vec![];
 
     // Construct definition table
     for line in input {
         if let SourceLineBody::Define(identifier, value) = &line.body {
-            match definition_table.get(identifier) {
+            match raw_table.get(&identifier.to_lowercase()) {
                 // Check if this identifier was already defined
                 Some(_) => {
                     print_preprocessor_error(PreprocessorError {
@@ -291,16 +486,62 @@ fn process_defines(input: &[SourceLine]) -> Result<Vec<SourceLine>, Vec<SourceLi
                     error_encountered = true;
                 }
                 None => {
-                    definition_table.insert(identifier.clone().to_lowercase(), value.clone());
+                    identifiers.push(identifier.to_lowercase());
+                    raw_table.insert(
+                        identifier.to_lowercase(),
+                        RawDefinition {
+                            identifier: identifier.clone(),
+                            value: value.clone(),
+                            location: Some(line.location.clone()),
+                        },
+                    );
                 }
             }
         }
     }
 
+    // Definitions from outside of the program replace its own
+    for (identifier, value) in predefined {
+        if !raw_table.contains_key(&identifier.to_lowercase()) {
+            identifiers.push(identifier.to_lowercase());
+        }
+        raw_table.insert(
+            identifier.to_lowercase(),
+            RawDefinition {
+                identifier: identifier.clone(),
+                value: value.clone(),
+                location: None,
+            },
+        );
+    }
+
+    // Default values of identifiers which aren't defined otherwise, the first one is used
+    for line in input {
+        if let SourceLineBody::DefaultDefine(identifier, value) = &line.body {
+            if let Entry::Vacant(entry) = raw_table.entry(identifier.to_lowercase()) {
+                identifiers.push(identifier.to_lowercase());
+                entry.insert(RawDefinition {
+                    identifier: identifier.clone(),
+                    value: value.clone(),
+                    location: Some(line.location.clone()),
+                });
+            }
+        }
+    }
+
+    // Expand definitions referenced by other definitions, in the order they're defined
+    let mut definition_table: HashMap<String, Option<String>> = HashMap::new();
+    for identifier in &identifiers {
+        let mut chain = vec![];
+        if resolve_definition(identifier, &raw_table, &mut definition_table, &mut chain).is_none() {
+            error_encountered = true;
+        }
+    }
+
     // Resolve definitions
     for line in input {
         if let SourceLineBody::Code(code) = &line.body {
-            match resolve_defines(&code, &definition_table, &line.location) {
+            match resolve_defines(code, &definition_table, &line.location) {
                 Ok(code) => res.push(SourceLine {
                     body: SourceLineBody::Code(code),
                     comment: line.comment.clone(),
@@ -308,6 +549,9 @@ fn process_defines(input: &[SourceLine]) -> Result<Vec<SourceLine>, Vec<SourceLi
                 }),
                 Err(_) => error_encountered = true,
             }
+        } else {
+            // Other lines are kept as they are
+            res.push(line.clone());
         }
     }
 
@@ -317,6 +561,99 @@ fn process_defines(input: &[SourceLine]) -> Result<Vec<SourceLine>, Vec<SourceLi
     }
 }
 
+// Definition before references to other definitions in its value are expanded
+struct RawDefinition {
+    identifier: String,
+    value: String,
+    // None for definitions from outside of the program
+    location: Option<LineLocation>,
+}
+
+/// Expands the references to other definitions in the value of a definition, recursively.
+/// `chain` holds the definitions being expanded, used to detect cycles, which are reported at the
+/// definition closing them. Failed definitions are stored as None, so they're only reported once
+fn resolve_definition(
+    identifier: &str,
+    raw_table: &HashMap<String, RawDefinition>,
+    resolved: &mut HashMap<String, Option<String>>,
+    chain: &mut Vec<String>,
+) -> Option<String> {
+    if let Some(value) = resolved.get(identifier) {
+        return value.clone();
+    }
+    let definition = &raw_table[identifier];
+
+    // Definition values are made of the same chunks as code
+    let chunks = match parse_definitions_chunks(&definition.value) {
+        Ok((_, chunks)) => chunks,
+        Err(_) => vec![DefinitionChunk::Code(definition.value.clone())],
+    };
+
+    chain.push(identifier.to_owned());
+    let mut value: Option<String> = Some("".to_owned());
+    for chunk in chunks {
+        let expanded = match chunk {
+            DefinitionChunk::Code(code) => Some(code),
+            DefinitionChunk::DefinitionReference(reference) => {
+                let key = reference.to_lowercase();
+                if let Some(start) = chain.iter().position(|other| *other == key) {
+                    // Cycle, from the first definition in it back to itself
+                    let mut cycle: Vec<String> = chain[start..]
+                        .iter()
+                        .map(|other| raw_table[other].identifier.clone())
+                        .collect();
+                    cycle.push(raw_table[&key].identifier.clone());
+                    print_preprocessor_error(PreprocessorError {
+                        kind: PreprocessorErrorKind::DefineCycle(cycle),
+                        location: definition.location.clone(),
+                    });
+                    None
+                } else if raw_table.contains_key(&key) {
+                    resolve_definition(&key, raw_table, resolved, chain)
+                } else {
+                    print_preprocessor_error(PreprocessorError {
+                        kind: PreprocessorErrorKind::DefineUndefined(reference),
+                        location: definition.location.clone(),
+                    });
+                    None
+                }
+            }
+        };
+
+        match (&mut value, expanded) {
+            (Some(value), Some(expanded)) => value.push_str(&expanded),
+            _ => value = None,
+        }
+    }
+    chain.pop();
+
+    let value = value.map(|value| fold_constant_expression(&value));
+    resolved.insert(identifier.to_owned(), value.clone());
+    value
+}
+
+/// Replaces a definition value which is an arithmetic expression with its result, so derived
+/// constants are computed once. Hexadecimal results are kept hexadecimal, to be usable as
+/// addresses. Other values, including single numbers, are kept as they are
+fn fold_constant_expression(value: &str) -> String {
+    let value = value.trim();
+    if !value
+        .chars()
+        .skip(1)
+        .any(|ch| matches!(ch, '+' | '-' | '*'))
+    {
+        return value.to_owned();
+    }
+
+    match evaluate_constant_expression(value) {
+        Some(result) if result >= 0 && value.to_lowercase().contains("0x") => {
+            format!("0x{:X}", result)
+        }
+        Some(result) => result.to_string(),
+        None => value.to_owned(),
+    }
+}
+
 #[derive(Debug)]
 pub enum DefinitionChunk {
     Code(String),
@@ -325,7 +662,7 @@ pub enum DefinitionChunk {
 
 fn resolve_defines(
     code: &str,
-    def_table: &HashMap<String, String>,
+    def_table: &HashMap<String, Option<String>>,
     location: &LineLocation,
 ) -> Result<String, ()> {
     let (_, chunks) = match parse_definitions_chunks(code) {
@@ -341,7 +678,9 @@ fn resolve_defines(
             DefinitionChunk::DefinitionReference(identifier) => {
                 // Look for definition in table
                 match def_table.get(&identifier.to_lowercase()) {
-                    Some(value) => result.push_str(&value),
+                    Some(Some(value)) => result.push_str(value),
+                    // Already reported where it's defined
+                    Some(None) => return Err(()),
                     None => {
                         print_preprocessor_error(PreprocessorError {
                             kind: PreprocessorErrorKind::DefineUndefined(identifier),
@@ -356,3 +695,213 @@ fn resolve_defines(
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source_lines(lines: &[SourceLineBody]) -> Vec<SourceLine> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, body)| SourceLine {
+                body: body.clone(),
+                comment: String::new(),
+                location: LineLocation {
+                    line_n: i + 1,
+                    file_name: "test.l6s".into(),
+                    raw_content: String::new(),
+                },
+            })
+            .collect()
+    }
+
+    fn define(identifier: &str, value: &str) -> SourceLineBody {
+        SourceLineBody::Define(identifier.to_owned(), value.to_owned())
+    }
+
+    fn code(lines: &[SourceLine]) -> Vec<String> {
+        lines
+            .iter()
+            .filter_map(|line| match &line.body {
+                SourceLineBody::Code(code) => Some(code.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_source_string_continued_lines() {
+        let input = ".DW 1, \\ ; One\n    2, \\\n    3\nHLT\n";
+        let lines = parse_source_string(input, &"test.l6s".into(), false).unwrap();
+        assert_eq!(code(&lines), vec![".DW 1, 2, 3", "HLT"]);
+        assert_eq!(lines[0].comment, "; One");
+        assert_eq!(lines[0].location.line_n, 1);
+        assert_eq!(lines[0].location.last_line_n(), 3);
+        assert_eq!(
+            lines[0].location.raw_content,
+            ".DW 1, \\ ; One\n    2, \\\n    3"
+        );
+        assert_eq!(lines[1].location.line_n, 4);
+    }
+
+    #[test]
+    fn test_parse_source_string_line_markers() {
+        let input = "HLT\n%line 10 \"src/main.l6s\"\nA: HLT\n\nB: HLT\n";
+        let lines = parse_source_string(input, &"a.l6s".into(), false).unwrap();
+        let locations: Vec<(PathBuf, usize)> = lines
+            .iter()
+            .filter(|line| matches!(line.body, SourceLineBody::Code(_)))
+            .map(|line| (line.location.file_name.clone(), line.location.line_n))
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                ("a.l6s".into(), 1),
+                ("src/main.l6s".into(), 10),
+                ("src/main.l6s".into(), 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_source_string_garbage() {
+        use crate::logging::{capture_diagnostics, Severity};
+
+        let input = "  %include \"a.l6s\" junk  ; Comment\n";
+
+        let (lines, diagnostics) =
+            capture_diagnostics(|| parse_source_string(input, &"test.l6s".into(), false));
+        assert!(lines.is_ok());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].span, Some(ColumnSpan { start: 19, end: 23 }));
+
+        // Strict
+        let (lines, diagnostics) =
+            capture_diagnostics(|| parse_source_string(input, &"test.l6s".into(), true));
+        assert!(lines.is_err());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].span, Some(ColumnSpan { start: 19, end: 23 }));
+    }
+
+    #[test]
+    fn test_process_includes_prefetched() {
+        use crate::logging::capture_diagnostics;
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("l6as-includes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("main.l6s", "%include \"a.l6s\"\n%include \"b.l6s\"\n"),
+            ("a.l6s", "A: HLT\n%include\n"),
+            ("b.l6s", "%include\nB: HLT\n"),
+        ];
+        for (file_name, contents) in files {
+            fs::write(dir.join(file_name), contents).unwrap();
+        }
+        let mut fi_coord = FileInclusionCoordinator::new();
+        fi_coord
+            .add_include_dirs(std::slice::from_ref(&dir))
+            .unwrap();
+
+        // Files parsed concurrently are merged, and their errors reported, in inclusion order
+        let (res, diagnostics) =
+            capture_diagnostics(|| preprocess(&"main.l6s".into(), &mut fi_coord));
+        fs::remove_dir_all(&dir).unwrap();
+        let lines: Vec<String> = res.unwrap_err().into_iter().map(|line| line.body).collect();
+        assert_eq!(lines, vec!["A: HLT", "B: HLT"]);
+        let locations: Vec<(PathBuf, usize)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.location.unwrap())
+            .map(|location| (location.file_name, location.line_n))
+            .collect();
+        assert_eq!(
+            locations,
+            vec![(dir.join("a.l6s"), 2), (dir.join("b.l6s"), 1)]
+        );
+    }
+
+    #[test]
+    fn test_process_defines_recursive() {
+        // References are expanded whatever order definitions are in
+        let input = source_lines(&[
+            define("ADDR", "%BASE+%OFFSET"),
+            define("BASE", "%PAGE*0x100"),
+            define("PAGE", "1"),
+            define("OFFSET", "4"),
+            SourceLineBody::Code("LDR $R1, %addr".to_owned()),
+        ]);
+        let res = process_defines(&input, &[("OFFSET".to_owned(), "8".to_owned())]).unwrap();
+        assert_eq!(code(&res), vec!["LDR $R1, 0x108"]);
+    }
+
+    #[test]
+    fn test_process_defines_default() {
+        let default = |identifier: &str, value: &str| {
+            SourceLineBody::DefaultDefine(identifier.to_owned(), value.to_owned())
+        };
+
+        // Defaults give way to definitions anywhere in the program and to predefined ones
+        let input = source_lines(&[
+            default("WIDTH", "80"),
+            default("HEIGHT", "24"),
+            default("DEBUG", "0"),
+            default("HEIGHT", "25"),
+            define("WIDTH", "132"),
+            SourceLineBody::Code(".DW %WIDTH, %HEIGHT, %DEBUG".to_owned()),
+        ]);
+        let res = process_defines(&input, &[("DEBUG".to_owned(), "1".to_owned())]).unwrap();
+        assert_eq!(code(&res), vec![".DW 132, 24, 1"]);
+    }
+
+    #[test]
+    fn test_fold_constant_expression() {
+        let tests = [
+            ("0x200 - 0x100", "0x100"),
+            ("(2 + 3) * 4", "20"),
+            ("0x10 - 0x20", "-16"),
+            ("0x10", "0x10"),
+            ("-5", "-5"),
+            ("=5+1", "=5+1"),
+            ("<$B1+", "<$B1+"),
+            ("\"A-B\"", "\"A-B\""),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(fold_constant_expression(input), exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_process_defines_cycle() {
+        let input = source_lines(&[
+            define("A", "%B"),
+            define("B", "1+%A"),
+            define("C", "%C"),
+            define("D", "%A"),
+            define("E", "2"),
+            SourceLineBody::Code("LDV $R1, %D".to_owned()),
+            SourceLineBody::Code("LDV $R1, %E".to_owned()),
+        ]);
+        let res = process_defines(&input, &[]).unwrap_err();
+        assert_eq!(code(&res), vec!["LDV $R1, 2"]);
+
+        let raw_table: HashMap<String, RawDefinition> = [("a", "%B"), ("b", "1+%A")]
+            .iter()
+            .map(|(identifier, value)| {
+                (
+                    identifier.to_string(),
+                    RawDefinition {
+                        identifier: identifier.to_uppercase(),
+                        value: value.to_string(),
+                        location: None,
+                    },
+                )
+            })
+            .collect();
+        let mut resolved = HashMap::new();
+        assert!(resolve_definition("a", &raw_table, &mut resolved, &mut vec![]).is_none());
+        assert_eq!(resolved.get("b"), Some(&None));
+    }
+}
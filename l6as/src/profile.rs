@@ -0,0 +1,119 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    assembler::AssembledLine,
+    logging::TableFileErrorKind,
+    symbols::parse_number,
+    table_file::{parse_table_file, read_table_file},
+};
+
+// Lines executed at least 1/HOT_LINE_DIVISOR as many times as the hottest one are highlighted
+const HOT_LINE_DIVISOR: u64 = 4;
+
+/// Execution counts recorded by an emulator, by address
+pub type ExecutionProfile = HashMap<u64, u64>;
+
+/// Reads an execution trace file, with one "ADDRESS COUNT" pair per line
+pub fn read_execution_profile(file_path: &Path) -> Result<ExecutionProfile, ()> {
+    let entries = read_table_file(
+        file_path,
+        parse_trace_entry,
+        TableFileErrorKind::InvalidTraceEntry,
+    )?;
+    Ok(sum_trace_entries(entries))
+}
+
+/// Parses the contents of an execution trace file. Comments start with ';'.
+/// Counts for the same address are added up
+pub fn parse_execution_profile(contents: &str, file_path: &Path) -> Result<ExecutionProfile, ()> {
+    let entries = parse_table_file(
+        contents,
+        file_path,
+        parse_trace_entry,
+        TableFileErrorKind::InvalidTraceEntry,
+    )?;
+    Ok(sum_trace_entries(entries))
+}
+
+fn sum_trace_entries(entries: Vec<(u64, u64)>) -> ExecutionProfile {
+    let mut profile = HashMap::new();
+    for (address, count) in entries {
+        *profile.entry(address).or_insert(0) += count;
+    }
+
+    profile
+}
+
+// Parses "ADDRESS COUNT", both being decimal or 0x prefixed hexadecimal
+fn parse_trace_entry(code: &str) -> Option<(u64, u64)> {
+    let mut parts = code.split_whitespace();
+    let (address, count) = (parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((parse_number(address)?, parse_number(count)?))
+}
+
+/// Number of times an assembled line was executed, None if it was never reached
+pub fn get_line_count(profile: &ExecutionProfile, line: &AssembledLine) -> Option<u64> {
+    match profile.get(&line.address) {
+        Some(count) if !line.data.is_empty() => Some(*count),
+        _ => None,
+    }
+}
+
+/// Minimum execution count of the lines on the hot path, None if nothing was executed
+pub fn get_hot_threshold(profile: &ExecutionProfile, lines: &[AssembledLine]) -> Option<u64> {
+    let max = lines
+        .iter()
+        .filter_map(|line| get_line_count(profile, line))
+        .max()?;
+
+    match max {
+        0 => None,
+        max => Some(max.div_ceil(HOT_LINE_DIVISOR)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_execution_profile_succ() {
+        let contents = "; Trace\n0x100 1\n\n0x102 0x10 ; Loop\n258 4\n";
+
+        let profile = parse_execution_profile(contents, Path::new("a.trace")).unwrap();
+        assert_eq!(profile, HashMap::from([(0x100, 1), (0x102, 20)]));
+    }
+
+    #[test]
+    fn parse_trace_entry_fail() {
+        let tests = ["0x100", "0x100 x", "0x100 1 2", "0x100 -1"];
+
+        for input in tests {
+            assert_eq!(parse_trace_entry(input), None, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn get_hot_threshold_succ() {
+        let lines = [
+            AssembledLine::new_test(0x100, None, vec![0x9870, 5], None),
+            AssembledLine::new_test(0x102, None, vec![0x0F80], None),
+            AssembledLine::new_test(0x103, None, vec![], None),
+        ];
+
+        let tests = [
+            (HashMap::from([(0x100, 1), (0x102, 10)]), Some(3)),
+            (HashMap::from([(0x100, 0)]), None),
+            (HashMap::from([(0x103, 100)]), None),
+            (HashMap::new(), None),
+        ];
+
+        for (profile, exp) in tests {
+            assert_eq!(get_hot_threshold(&profile, &lines), exp, "{:?}", profile);
+        }
+    }
+}
@@ -0,0 +1,507 @@
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use l6disk::disk_image::profiles::{find_profile, read_profile_file};
+use l6disk::disk_image::{
+    convert_image, layout_raw_image, ConvertOpts, InputFormat, OutputFormat, SideOrder,
+};
+
+use crate::{
+    args::BuildArgs,
+    assembler::{
+        assemble_with_labels, AssembledLine, AssemblerOptions, CpuFeature, DirectiveRegistry,
+    },
+    cycles::read_cycle_table,
+    file::{write_file, FileInclusionCoordinator},
+    logging::{
+        print_diagnostic_summary, print_final_error_msg, print_project_error_msg,
+        print_read_file_error_msg, print_write_file_error_msg,
+    },
+    output::{
+        generate_binary_output, ListingOptions, ListingRadix, OutputContext, OutputFormatRegistry,
+    },
+    preprocessor::{preprocess_all_lines, source_lines_to_code_lines},
+};
+
+/// Project file read by `l6as build` when none is given
+pub const DEFAULT_PROJECT_FILE: &str = "level6.toml";
+
+/// l6disk profile of disk images, when none is given
+pub const DEFAULT_DISK_PROFILE: &str = "level6-sssd";
+
+const DEFAULT_OUTPUT_DIR: &str = "build";
+
+/// Description of how to build a project, read from a project file.
+/// Paths are relative to the directory of the project file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    /// Programs to assemble, each to its own outputs
+    pub sources: Vec<PathBuf>,
+    pub include_dirs: Vec<PathBuf>,
+    /// Identifiers defined as if by %define
+    pub defines: Vec<(String, String)>,
+    pub output_dir: PathBuf,
+    /// Names of the output formats
+    pub formats: Vec<String>,
+    /// Cycle table of the target CPU model, used by listings
+    pub cycle_table: Option<PathBuf>,
+    /// Optional processors of the target CPU model, tested by .IFCPU
+    pub cpu_features: Vec<CpuFeature>,
+    pub disks: Vec<DiskStep>,
+}
+
+/// Disk image holding one of the programs, starting at its first sector
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskStep {
+    /// Image file, in the output directory. The extension selects the image format
+    pub output: PathBuf,
+    /// l6disk profile of the disk
+    pub profile: String,
+    /// File with user defined l6disk profiles
+    pub profile_file: Option<PathBuf>,
+    /// Name of the program, the file name of its source without extension.
+    /// The first source when not given
+    pub program: Option<String>,
+}
+
+/// Builds the project described by a project file: assembles every source and packages
+/// the programs into disk images
+pub fn run_build(args: &BuildArgs, directives: &DirectiveRegistry) -> Result<(), ()> {
+    let project = read_project_file(&args.project)?;
+
+    let res = build_project(&project, directives);
+    if res.is_ok() {
+        print_diagnostic_summary();
+    }
+    res
+}
+
+fn build_project(project: &Project, directives: &DirectiveRegistry) -> Result<(), ()> {
+    if let Err(err) = fs::create_dir_all(&project.output_dir) {
+        print_write_file_error_msg(err);
+        return Err(());
+    }
+
+    // Cycle table is needed by listings and .IODELAY
+    let cycle_table = match &project.cycle_table {
+        Some(file_path) => match read_cycle_table(file_path) {
+            Ok(table) => Some(table),
+            Err(()) => {
+                print_final_error_msg();
+                return Err(());
+            }
+        },
+        None => None,
+    };
+
+    let opts = AssemblerOptions {
+        directives: directives.clone(),
+        cycle_table,
+        cpu_features: project.cpu_features.clone(),
+        ..Default::default()
+    };
+
+    let formats = OutputFormatRegistry::builtin();
+
+    // Assemble all sources, keeping them for the disk images
+    let mut programs: Vec<(String, Vec<AssembledLine>)> = vec![];
+    for source in &project.sources {
+        let name = program_name(source);
+        let _span = tracing::info_span!("build", program = %name).entered();
+
+        let mut fi_coord = FileInclusionCoordinator::new();
+        fi_coord.add_current_dir().unwrap();
+        fi_coord.add_include_dirs(&project.include_dirs).unwrap();
+        fi_coord.add_definitions(&project.defines);
+
+        let source_lines = match preprocess_all_lines(source, &mut fi_coord) {
+            Ok(source_lines) => source_lines,
+            Err(_) => {
+                print_final_error_msg();
+                return Err(());
+            }
+        };
+        let code_lines = source_lines_to_code_lines(&source_lines);
+        let (lines, labels) = match assemble_with_labels(&code_lines, &opts) {
+            (Ok(lines), labels) => (lines, labels),
+            (Err(_), _) => {
+                print_final_error_msg();
+                return Err(());
+            }
+        };
+
+        // Write outputs
+        let ctx = OutputContext {
+            labels: &labels,
+            listing: ListingOptions {
+                cycle_table: opts.cycle_table.as_ref(),
+                profile: None,
+                radix: ListingRadix::Hex,
+                source: Some(&source_lines),
+            },
+            ..Default::default()
+        };
+        for format_name in &project.formats {
+            let format = formats.get(format_name).unwrap();
+            let out_file = project
+                .output_dir
+                .join(format!("{}.{}", name, format.extension()));
+            format.write(&out_file, &lines, &ctx)?;
+        }
+
+        programs.push((name, lines));
+    }
+
+    // Package programs into disk images
+    for disk in &project.disks {
+        let lines = match &disk.program {
+            Some(name) => match programs.iter().find(|(program, _)| program == name) {
+                Some((_, lines)) => lines,
+                None => {
+                    print_project_error_msg(&format!("Unknown program \"{}\"", name));
+                    return Err(());
+                }
+            },
+            None => &programs[0].1,
+        };
+
+        let out_file = project.output_dir.join(&disk.output);
+        let profile_file = disk.profile_file.as_deref();
+        write_disk_image(&out_file, &disk.profile, profile_file, lines)?;
+    }
+
+    Ok(())
+}
+
+/// Lays out a program on a disk, from its first sector, and writes the disk image.
+/// The image format is chosen by the extension of the image file
+pub fn write_disk_image(
+    out_file: &Path,
+    profile: &str,
+    profile_file: Option<&Path>,
+    lines: &[AssembledLine],
+) -> Result<(), ()> {
+    let image = match generate_disk_image(out_file, profile, profile_file, lines) {
+        Ok(image) => image,
+        Err(msg) => {
+            print_project_error_msg(&msg);
+            return Err(());
+        }
+    };
+
+    if let Err(err) = write_file(&out_file.to_path_buf(), &image) {
+        print_write_file_error_msg(err);
+        return Err(());
+    }
+
+    Ok(())
+}
+
+// Lays out a program on a disk, returning the data of the image file
+fn generate_disk_image(
+    out_file: &Path,
+    profile: &str,
+    profile_file: Option<&Path>,
+    lines: &[AssembledLine],
+) -> Result<Vec<u8>, String> {
+    if lines.iter().any(|line| line.bank.is_some()) {
+        return Err(format!(
+            "{}: programs with banks can't be put on a disk",
+            out_file.display()
+        ));
+    }
+
+    let output_format = match OutputFormat::from_path(out_file) {
+        Some(format) => format,
+        None => return Err(format!("{}: unknown disk image format", out_file.display())),
+    };
+
+    let user_profiles = match profile_file {
+        Some(file_path) => read_profile_file(file_path)?,
+        None => vec![],
+    };
+    let disk_parameters = find_profile(profile, &user_profiles)?.disk_parameters;
+
+    let data = generate_binary_output(lines, None);
+    let image = layout_raw_image(&data, &disk_parameters)
+        .and_then(|image| {
+            convert_image(
+                image,
+                ConvertOpts {
+                    ignore_errors: false,
+                    disk_parameters,
+                    input_format: InputFormat::Raw,
+                    output_format,
+                    side_order: SideOrder::Cylinder,
+                    side1_data: None,
+                    boot_loader: None,
+                    verbose: false,
+                },
+            )
+        })
+        .map_err(|err| format!("{}: {}", out_file.display(), err))?;
+
+    Ok(image.data)
+}
+
+// Programs are named after their source file
+fn program_name(source: &Path) -> String {
+    source
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reads a project file
+pub fn read_project_file(file_path: &Path) -> Result<Project, ()> {
+    let contents = match fs::read_to_string(file_path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            print_read_file_error_msg(file_path, err);
+            return Err(());
+        }
+    };
+
+    // Paths in the project are relative to its directory
+    let base_dir = match file_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    match parse_project(&contents, &base_dir) {
+        Ok(project) => Ok(project),
+        Err(msg) => {
+            print_project_error_msg(&format!("{}: {}", file_path.display(), msg));
+            Err(())
+        }
+    }
+}
+
+// Tables of a project file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProjectFile {
+    project: ProjectTable,
+    #[serde(default)]
+    disk: Vec<DiskTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ProjectTable {
+    sources: Vec<String>,
+    #[serde(default)]
+    include_dirs: Vec<String>,
+    #[serde(default)]
+    defines: Vec<String>,
+    output_dir: Option<String>,
+    formats: Option<Vec<String>>,
+    cycle_table: Option<String>,
+    #[serde(default)]
+    cpu_features: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct DiskTable {
+    output: String,
+    profile: String,
+    profile_file: Option<String>,
+    program: Option<String>,
+}
+
+/// Parses a project file, written in TOML:
+///
+/// [project]
+/// sources = ["monitor.asm"]
+/// include-dirs = ["include"]
+/// defines = ["DEBUG=1"]
+/// output-dir = "build"
+/// formats = ["bin", "listing", "map", "ihex"]
+/// cycle-table = "cycles.txt"
+/// cpu-features = ["SIP"]
+///
+/// [[disk]]
+/// output = "monitor.hfe"
+/// profile = "level6-sssd"
+pub fn parse_project(contents: &str, base_dir: &Path) -> Result<Project, String> {
+    let file: ProjectFile =
+        toml::from_str(contents).map_err(|err| err.to_string().trim_end().to_owned())?;
+    let table = file.project;
+    let paths = |strings: &[String]| -> Vec<PathBuf> {
+        strings.iter().map(|string| base_dir.join(string)).collect()
+    };
+
+    let defines = table
+        .defines
+        .iter()
+        .map(|define| match define.split_once('=') {
+            Some((identifier, value)) => {
+                Ok((identifier.trim().to_owned(), value.trim().to_owned()))
+            }
+            None => Err(format!(
+                "expected IDENTIFIER=VALUE definitions: \"{}\"",
+                define
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let cpu_features = table
+        .cpu_features
+        .iter()
+        .map(|name| {
+            CpuFeature::from_name(name).ok_or_else(|| format!("unknown CPU feature \"{}\"", name))
+        })
+        .collect::<Result<_, _>>()?;
+
+    let formats = table.formats.unwrap_or_else(|| vec!["bin".to_owned()]);
+    let registry = OutputFormatRegistry::builtin();
+    if let Some(format) = formats.iter().find(|f| registry.get(f).is_none()) {
+        return Err(format!("unknown output format \"{}\"", format));
+    }
+
+    let project = Project {
+        sources: paths(&table.sources),
+        // %includes are also searched for next to the project file
+        include_dirs: [vec![base_dir.to_path_buf()], paths(&table.include_dirs)].concat(),
+        defines,
+        output_dir: base_dir.join(table.output_dir.as_deref().unwrap_or(DEFAULT_OUTPUT_DIR)),
+        formats,
+        cycle_table: table.cycle_table.map(|path| base_dir.join(path)),
+        cpu_features,
+        disks: file
+            .disk
+            .into_iter()
+            .map(|disk| DiskStep {
+                // Disk images go in the output directory
+                output: PathBuf::from(disk.output),
+                profile: disk.profile,
+                profile_file: disk.profile_file.map(|path| base_dir.join(path)),
+                program: disk.program,
+            })
+            .collect(),
+    };
+
+    if project.sources.is_empty() {
+        return Err("no sources".to_owned());
+    }
+    for disk in &project.disks {
+        if disk.output.as_os_str().is_empty() || disk.profile.is_empty() {
+            return Err("disks need an output and a profile".to_owned());
+        }
+    }
+
+    Ok(project)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{get_exit_code, EXIT_IO_ERROR, EXIT_USAGE};
+
+    #[test]
+    fn test_parse_project() {
+        let project = parse_project(
+            "# Monitor\n[project]\nsources = [\"monitor.asm\", \"test.asm\",]\n\
+             include-dirs = [\"include\"]\ndefines = [\"DEBUG=1\", \"VERSION = 3\"]\n\
+             formats = [\"bin\", \"map\"]\ncpu-features = [\"sip\", \"CIP\"]\n\n\
+             [[disk]]\noutput = \"monitor.hfe\"\nprofile = \"level6-sssd\" # Boot disk\n\
+             [[disk]]\noutput = \"test.imd\"\nprofile = \"level6-dssd\"\nprogram = \"test\"\n",
+            Path::new("proj"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            project.sources,
+            vec![
+                PathBuf::from("proj/monitor.asm"),
+                PathBuf::from("proj/test.asm")
+            ]
+        );
+        assert_eq!(
+            project.include_dirs,
+            vec![PathBuf::from("proj"), PathBuf::from("proj/include")]
+        );
+        assert_eq!(
+            project.defines,
+            vec![
+                ("DEBUG".to_owned(), "1".to_owned()),
+                ("VERSION".to_owned(), "3".to_owned())
+            ]
+        );
+        assert_eq!(project.output_dir, PathBuf::from("proj/build"));
+        assert_eq!(project.formats, vec!["bin".to_owned(), "map".to_owned()]);
+        assert_eq!(project.cpu_features, vec![CpuFeature::Sip, CpuFeature::Cip]);
+        assert_eq!(project.disks.len(), 2);
+        assert_eq!(project.disks[0].output, PathBuf::from("monitor.hfe"));
+        assert_eq!(project.disks[0].program, None);
+        assert_eq!(project.disks[1].program, Some("test".to_owned()));
+
+        // Comment characters and commas in strings, arrays over several lines
+        let project = parse_project(
+            "[project]\nsources = [\n    \"a,b.asm\",\n    \"c.asm\", # Test\n]\n\
+             defines = [\"C='#'\"]\n",
+            Path::new("."),
+        )
+        .unwrap();
+        assert_eq!(
+            project.sources,
+            vec![PathBuf::from("./a,b.asm"), PathBuf::from("./c.asm")]
+        );
+        assert_eq!(project.defines, vec![("C".to_owned(), "'#'".to_owned())]);
+
+        let tests = [
+            "sources = [\"a.asm\"]",
+            "[project]",
+            "[project]\nsources = \"a.asm\"",
+            "[project]\nsources = [a.asm]",
+            "[project]\nsources = [\"a.asm\"]\nformats = [\"hex\"]",
+            "[project]\nsources = [\"a.asm\"]\ndefines = [\"DEBUG\"]",
+            "[project]\nsources = [\"a.asm\"]\ncpu-features = [\"FPU\"]",
+            "[project]\nsources = [\"a.asm\"]\n[[disk]]\noutput = \"a.hfe\"",
+            "[project]\nsources = [\"a.asm\"]\n[disk]",
+        ];
+        for contents in tests {
+            assert!(
+                parse_project(contents, Path::new(".")).is_err(),
+                "{:?}",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_disk_image_exit_code() {
+        let line = AssembledLine::new_test(0, None, vec![0x0000], Some("HLT"));
+
+        // Programs which can't be put on the disk are a usage problem
+        let banked = [AssembledLine {
+            bank: Some(1),
+            ..line.clone()
+        }];
+        let res = write_disk_image(Path::new("a.img"), DEFAULT_DISK_PROFILE, None, &banked);
+        assert_eq!(get_exit_code(&res), EXIT_USAGE);
+
+        // Image files which can't be written take precedence
+        let out_file = std::env::temp_dir()
+            .join(format!("l6as-missing-{}", std::process::id()))
+            .join("a.img");
+        let res = write_disk_image(&out_file, DEFAULT_DISK_PROFILE, None, &[line]);
+        assert_eq!(get_exit_code(&res), EXIT_IO_ERROR);
+    }
+
+    #[test]
+    fn test_read_project_file_exit_code() {
+        let file_path =
+            std::env::temp_dir().join(format!("l6as-project-{}.toml", std::process::id()));
+        fs::write(&file_path, "[project]\nsources = \"a.asm\"\n").unwrap();
+
+        let res = read_project_file(&file_path);
+        fs::remove_file(&file_path).unwrap();
+        assert_eq!(get_exit_code(&res), EXIT_USAGE);
+    }
+}
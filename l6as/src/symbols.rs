@@ -0,0 +1,246 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    assembler::LabelDefinition,
+    logging::TableFileErrorKind,
+    table_file::{parse_table_file, read_table_file},
+};
+
+/// Reads a symbol map file, with one "LABEL ADDRESS" pair per line
+pub fn read_symbol_file(file_path: &Path) -> Result<HashMap<String, u64>, ()> {
+    let symbols = read_table_file(
+        file_path,
+        parse_symbol_definition,
+        TableFileErrorKind::InvalidSymbolDefinition,
+    )?;
+    Ok(symbols.into_iter().collect())
+}
+
+/// Parses the contents of a symbol map file. Comments start with ';'
+pub fn parse_symbol_file(contents: &str, file_path: &Path) -> Result<HashMap<String, u64>, ()> {
+    let symbols = parse_table_file(
+        contents,
+        file_path,
+        parse_symbol_definition,
+        TableFileErrorKind::InvalidSymbolDefinition,
+    )?;
+    Ok(symbols.into_iter().collect())
+}
+
+// Parses "LABEL ADDRESS", the address being decimal or 0x prefixed hexadecimal
+fn parse_symbol_definition(code: &str) -> Option<(String, u64)> {
+    let mut parts = code.split_whitespace();
+    let (label, address) = (parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    // Labels are case insensitive
+    Some((label.to_uppercase(), parse_number(address)?))
+}
+
+/// Parses a decimal or 0x prefixed hexadecimal number
+pub fn parse_number(number: &str) -> Option<u64> {
+    match number.strip_prefix("0x").or(number.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => number.parse().ok(),
+    }
+}
+
+/// Generates a symbol map file, sorted by address
+pub fn generate_symbol_file(labels: &[LabelDefinition]) -> String {
+    let mut labels: Vec<&LabelDefinition> = labels.iter().collect();
+    labels.sort_by_key(|label| (label.address, label.label.clone()));
+
+    let mut output = String::new();
+    for label in labels {
+        output.push_str(&format!("{} {:#06X}\n", label.label, label.address));
+    }
+
+    output
+}
+
+/// Language of the symbol declarations written by --export-symbols
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolExportFormat {
+    CHeader,
+    Rust,
+    GhidraScript,
+    Idc,
+}
+
+impl SymbolExportFormat {
+    /// Chooses the format from the extension of the output file
+    pub fn from_path(file_path: &Path) -> Option<Self> {
+        match file_path.extension()?.to_str()? {
+            "h" => Some(Self::CHeader),
+            "rs" => Some(Self::Rust),
+            "py" => Some(Self::GhidraScript),
+            "idc" => Some(Self::Idc),
+            _ => None,
+        }
+    }
+}
+
+/// Generates declarations of the labels, sorted by address: #define or pub const
+/// declarations, or a Ghidra or IDA script naming the addresses of a disassembled image
+pub fn generate_symbol_export(labels: &[LabelDefinition], format: SymbolExportFormat) -> String {
+    let mut labels: Vec<&LabelDefinition> = labels.iter().collect();
+    labels.sort_by_key(|label| (label.address, label.label.clone()));
+
+    let mut output = String::new();
+    match format {
+        SymbolExportFormat::CHeader => output.push_str("/* Generated by l6as */\n#pragma once\n\n"),
+        SymbolExportFormat::Rust => output.push_str("// Generated by l6as\n\n"),
+        SymbolExportFormat::GhidraScript => output.push_str(
+            "# Generated by l6as, run from the Ghidra Script Manager\n\
+             from ghidra.program.model.symbol import SourceType\n\n",
+        ),
+        SymbolExportFormat::Idc => {
+            output.push_str("// Generated by l6as\n#include <idc.idc>\n\nstatic main()\n{\n")
+        }
+    }
+
+    // Label identifiers are valid identifiers in all languages
+    for label in labels {
+        output.push_str(&match format {
+            SymbolExportFormat::CHeader => {
+                format!("#define {} {:#06X}\n", label.label, label.address)
+            }
+            SymbolExportFormat::Rust => {
+                format!("pub const {}: u64 = {:#06X};\n", label.label, label.address)
+            }
+            SymbolExportFormat::GhidraScript => format!(
+                "createLabel(toAddr({:#06X}), \"{}\", True, SourceType.IMPORTED)\n",
+                label.address, label.label
+            ),
+            SymbolExportFormat::Idc => {
+                format!(
+                    "    set_name({:#06X}, \"{}\");\n",
+                    label.address, label.label
+                )
+            }
+        });
+    }
+
+    if format == SymbolExportFormat::Idc {
+        output.push_str("}\n");
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessor::LineLocation;
+
+    #[test]
+    fn parse_symbol_file_succ() {
+        let contents = "; Symbols\nstart 0x100\n\n  PRINT 4096 ; Routine\nEND 0X1A\n";
+
+        let symbols = parse_symbol_file(contents, Path::new("a.map")).unwrap();
+        assert_eq!(
+            symbols,
+            HashMap::from([
+                ("START".to_owned(), 0x100),
+                ("PRINT".to_owned(), 4096),
+                ("END".to_owned(), 0x1A),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_symbol_definition_fail() {
+        let tests = ["start", "start 0x", "start 12 13", "start -1"];
+
+        for input in tests {
+            assert_eq!(parse_symbol_definition(input), None, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn generate_symbol_file_round_trip() {
+        let location = LineLocation {
+            line_n: 1,
+            file_name: "a.l6s".into(),
+            raw_content: "".to_owned(),
+        };
+        let labels = [
+            LabelDefinition {
+                label: "LOOP".to_owned(),
+                address: 0x104,
+                bank: None,
+                location: location.clone(),
+            },
+            LabelDefinition {
+                label: "START".to_owned(),
+                address: 0x100,
+                bank: None,
+                location,
+            },
+        ];
+
+        let contents = generate_symbol_file(&labels);
+        assert_eq!(contents, "START 0x0100\nLOOP 0x0104\n");
+        assert_eq!(
+            parse_symbol_file(&contents, Path::new("a.map")).unwrap(),
+            HashMap::from([("START".to_owned(), 0x100), ("LOOP".to_owned(), 0x104)])
+        );
+    }
+
+    #[test]
+    fn generate_symbol_export_succ() {
+        let location = LineLocation {
+            line_n: 1,
+            file_name: "a.l6s".into(),
+            raw_content: "".to_owned(),
+        };
+        let labels = [
+            LabelDefinition {
+                label: "UART".to_owned(),
+                address: 0x1F00,
+                bank: None,
+                location: location.clone(),
+            },
+            LabelDefinition {
+                label: "START".to_owned(),
+                address: 0x100,
+                bank: None,
+                location,
+            },
+        ];
+
+        assert_eq!(
+            generate_symbol_export(&labels, SymbolExportFormat::CHeader),
+            "/* Generated by l6as */\n#pragma once\n\n#define START 0x0100\n#define UART 0x1F00\n"
+        );
+        assert_eq!(
+            generate_symbol_export(&labels, SymbolExportFormat::Rust),
+            "// Generated by l6as\n\npub const START: u64 = 0x0100;\npub const UART: u64 = 0x1F00;\n"
+        );
+        assert_eq!(
+            generate_symbol_export(&labels, SymbolExportFormat::Idc),
+            "// Generated by l6as\n#include <idc.idc>\n\nstatic main()\n{\n    \
+             set_name(0x0100, \"START\");\n    set_name(0x1F00, \"UART\");\n}\n"
+        );
+    }
+
+    #[test]
+    fn symbol_export_format_from_path() {
+        assert_eq!(
+            SymbolExportFormat::from_path(Path::new("out/fw.h")),
+            Some(SymbolExportFormat::CHeader)
+        );
+        assert_eq!(
+            SymbolExportFormat::from_path(Path::new("fw.rs")),
+            Some(SymbolExportFormat::Rust)
+        );
+        assert_eq!(
+            SymbolExportFormat::from_path(Path::new("fw.idc")),
+            Some(SymbolExportFormat::Idc)
+        );
+        assert_eq!(SymbolExportFormat::from_path(Path::new("fw.txt")), None);
+        assert_eq!(SymbolExportFormat::from_path(Path::new("fw")), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{fs, path::Path};
+
+use crate::{
+    logging::{
+        print_read_file_error_msg, print_table_file_error, TableFileError, TableFileErrorKind,
+    },
+    preprocessor::LineLocation,
+};
+
+/// Reads a file with one entry per line, such as a symbol map, cycle table or execution trace.
+/// Lines which `parse_entry` rejects are reported as errors of the kind made by `error_kind`
+pub fn read_table_file<T>(
+    file_path: &Path,
+    parse_entry: impl Fn(&str) -> Option<T>,
+    error_kind: impl Fn(String) -> TableFileErrorKind,
+) -> Result<Vec<T>, ()> {
+    let contents = match fs::read_to_string(file_path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            print_read_file_error_msg(file_path, err);
+            return Err(());
+        }
+    };
+
+    parse_table_file(&contents, file_path, parse_entry, error_kind)
+}
+
+/// Parses the contents of a file with one entry per line. Comments start with ';' and
+/// blank lines are skipped
+pub fn parse_table_file<T>(
+    contents: &str,
+    file_path: &Path,
+    parse_entry: impl Fn(&str) -> Option<T>,
+    error_kind: impl Fn(String) -> TableFileErrorKind,
+) -> Result<Vec<T>, ()> {
+    let mut entries = vec![];
+    let mut error_occurred = false;
+
+    for (i, line) in contents.lines().enumerate() {
+        let code = match line.split_once(';') {
+            Some((code, _)) => code,
+            None => line,
+        };
+        if code.trim().is_empty() {
+            continue;
+        }
+
+        match parse_entry(code) {
+            Some(entry) => entries.push(entry),
+            None => {
+                print_table_file_error(TableFileError {
+                    kind: error_kind(code.trim().to_owned()),
+                    location: LineLocation {
+                        line_n: i + 1,
+                        file_name: file_path.to_path_buf(),
+                        raw_content: line.to_owned(),
+                    },
+                });
+                error_occurred = true;
+            }
+        }
+    }
+
+    match error_occurred {
+        false => Ok(entries),
+        true => Err(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{capture_diagnostics, get_exit_code, EXIT_USAGE};
+
+    fn parse_entry(code: &str) -> Option<u64> {
+        code.trim().parse().ok()
+    }
+
+    #[test]
+    fn parse_table_file_succ() {
+        let contents = "; Entries\n1\n\n  2 ; Second\n";
+
+        let entries = parse_table_file(
+            contents,
+            Path::new("a.txt"),
+            parse_entry,
+            TableFileErrorKind::InvalidTraceEntry,
+        );
+        assert_eq!(entries, Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn parse_table_file_fail() {
+        let contents = "1\nx ; Not a number\n3\n";
+
+        let (entries, diagnostics) = capture_diagnostics(|| {
+            parse_table_file(
+                contents,
+                Path::new("a.txt"),
+                parse_entry,
+                TableFileErrorKind::InvalidTraceEntry,
+            )
+        });
+        assert_eq!(entries, Err(()));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location.as_ref().unwrap().line_n, 2);
+
+        // Invalid entries are a problem with the input, not with the program
+        let entries = parse_table_file(
+            contents,
+            Path::new("a.txt"),
+            parse_entry,
+            TableFileErrorKind::InvalidTraceEntry,
+        );
+        assert_eq!(get_exit_code(&entries), EXIT_USAGE);
+    }
+}
@@ -1,24 +1,92 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
-use crate::disk_image::disk_parameters::DiskFormat;
+use crate::disk_image::disk_parameters::DiskParameters;
+use crate::disk_image::disk_parameters::{
+    parse_byte, parse_bytes, parse_gap_lengths, parse_sector_numbers, parse_zone, GeometryZone,
+};
+use crate::disk_image::disk_parameters::{read_bad_sector_file, read_layout_file};
+use crate::disk_image::patch::{parse_chs, parse_offset};
+use crate::disk_image::profiles::{find_profile, read_profile_file, DEFAULT_PROFILE};
+use crate::disk_image::{InputFormat, OutputFormat, PatchLocation, SideOrder};
+use clap_complete::Shell;
 
 #[derive(Debug, Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
-    /// Input data disk image
-    pub input: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    /// Output raw disk image
-    pub output: PathBuf,
+    /// Input disk image, in the format given by --input-format or the file extension
+    #[arg(required_unless_present = "help_man")]
+    pub input: Option<PathBuf>,
+
+    /// Output disk image, in the format given by --output-format or the file extension
+    #[arg(required_unless_present = "help_man")]
+    pub output: Option<PathBuf>,
 
     /// Ignore image conversion errors
     #[arg(short = 'l', long, action)]
     pub ignore_errors: bool,
 
-    /// Disk format preset
-    #[arg(value_enum, short = 'p', long, default_value_t = DiskFormat::LEVEL6)]
-    pub disk_format: DiskFormat,
+    /// Print statistics of each track
+    #[arg(short = 'v', long, action)]
+    pub verbose: bool,
+
+    /// Input image format
+    #[arg(value_enum, short = 'g', long, default_value_t = InputFormat::Raw)]
+    pub input_format: InputFormat,
+
+    /// Order of the tracks in the input image
+    #[arg(value_enum, long, default_value_t = SideOrder::Cylinder)]
+    pub side_order: SideOrder,
+
+    /// Side 1 data disk image, the input image then holds side 0 only
+    #[arg(long, default_value = None, value_name = "FILE")]
+    pub side1: Option<PathBuf>,
+
+    /// Output image format
+    #[arg(value_enum, short = 'f', long, default_value_t = OutputFormat::Hfe)]
+    pub output_format: OutputFormat,
+
+    #[command(flatten)]
+    pub disk: DiskArgs,
+
+    /// Bootstrap loader binary, placed at the start of the boot track
+    #[arg(long, default_value = None, value_name = "FILE")]
+    pub boot: Option<PathBuf>,
+
+    /// Write a JSON manifest with checksums of the image and its sectors
+    #[arg(long, default_value = None, value_name = "FILE")]
+    pub manifest: Option<PathBuf>,
+
+    /// Print the man page, in roff format
+    #[arg(long, action)]
+    pub help_man: bool,
+}
+
+// Comma separated list parsed as a single value. Clap would read an Option<Vec<u8>> as
+// separate values of type u8
+type ByteList = Vec<u8>;
+
+// Options describing the disk to create
+#[derive(Debug, clap::Args)]
+pub struct DiskArgs {
+    /// Disk profile, by name (see the profiles command)
+    ///
+    /// The former --disk-format presets level6, ibm8sssd and ibm8dssd name the matching
+    /// built-in profiles
+    #[arg(short = 'p', long, visible_alias = "disk-format", default_value = DEFAULT_PROFILE)]
+    pub profile: String,
+
+    /// User disk profile file
+    #[arg(long, default_value = None, value_name = "FILE")]
+    pub profile_file: Option<PathBuf>,
 
     /// Number of cylinders
     #[arg(short = 'c', long, default_value = None, value_parser=clap::value_parser!(u16).range(0..))]
@@ -47,4 +115,320 @@ pub struct Args {
     /// Disk sector interleave
     #[arg(short = 'i', long, default_value = None, value_parser=clap::value_parser!(u16).range(1..))]
     pub interleave: Option<u16>,
+
+    /// Track to track skew, in sectors
+    #[arg(short = 'k', long, default_value = None)]
+    pub skew: Option<u16>,
+
+    /// Number of the first sector of each track
+    #[arg(long, default_value = None)]
+    pub first_sector: Option<u8>,
+
+    /// Sector numbers in logical order (e.g. 1,3,5,2,4,6)
+    #[arg(long, default_value = None, value_name = "NUMBERS", value_parser = parse_sector_numbers)]
+    pub sector_numbers: Option<ByteList>,
+
+    /// Number of sector holes of hard sectored media, one sector is written after each hole
+    #[arg(long, default_value = None, value_name = "HOLES")]
+    pub hard_sectors: Option<u16>,
+
+    /// Gap byte value (e.g. 0x4E)
+    #[arg(long, default_value = None, value_name = "BYTE", value_parser = parse_byte)]
+    pub gap_byte: Option<u8>,
+
+    /// Lengths of GAP1 to GAP4, in bytes (e.g. 40,26,11,27)
+    #[arg(long, default_value = None, value_name = "LENGTHS", value_parser = parse_gap_lengths)]
+    pub gaps: Option<[u16; 4]>,
+
+    /// Pattern filling missing sectors (e.g. 0xE5 or 0xDE,0xAD)
+    #[arg(long, default_value = None, value_name = "BYTES", value_parser = parse_bytes)]
+    pub fill: Option<ByteList>,
+
+    /// Sector layout file
+    #[arg(long, default_value = None)]
+    pub layout: Option<PathBuf>,
+
+    /// Geometry of a cylinder range, as CYLINDERS:SECTORSxSIZE (e.g. 0:26x128)
+    #[arg(short = 'z', long = "zone", value_name = "ZONE", value_parser = parse_zone)]
+    pub zones: Vec<GeometryZone>,
+
+    /// Bad sector map file, with "CYLINDER SIDE SECTOR DEFECT" lines (no-data, data-crc, id-crc)
+    #[arg(long, default_value = None, value_name = "FILE")]
+    pub bad_sectors: Option<PathBuf>,
+}
+
+impl DiskArgs {
+    // Construct DiskParameters based on CLI args
+    pub fn disk_parameters(&self) -> Result<DiskParameters, String> {
+        // Get default parameters for specified profile
+        let user_profiles = match &self.profile_file {
+            Some(file_path) => read_profile_file(file_path)?,
+            None => vec![],
+        };
+        let mut disk_pars = find_profile(&self.profile, &user_profiles)?.disk_parameters;
+
+        // Number of cylinders
+        if let Some(cylinders) = self.cylinders {
+            disk_pars.n_cylinders = cylinders;
+        }
+
+        // Number of heads
+        if let Some(heads) = self.heads {
+            disk_pars.n_sides = heads;
+        }
+
+        // Number of sectors
+        if let Some(sectors) = self.sectors {
+            disk_pars.sectors_per_track = sectors;
+        }
+
+        // Sector size
+        if let Some(sector_size) = self.sector_size {
+            disk_pars.bytes_per_sector = sector_size;
+        }
+
+        // Cell rate
+        if let Some(cell_rate) = self.cell_rate {
+            disk_pars.cell_rate = cell_rate;
+        }
+
+        // Spindle RPM
+        if let Some(spindle_rpm) = self.spindle_rpm {
+            disk_pars.rpm = spindle_rpm;
+        }
+
+        // Hard sectors
+        if let Some(hard_sectors) = self.hard_sectors {
+            disk_pars.hard_sectors = hard_sectors;
+        }
+
+        // Sector layout file, overridden by single options
+        if let Some(file_path) = &self.layout {
+            let layout = read_layout_file(file_path)?;
+            if let Some(interleave) = layout.interleave {
+                disk_pars.sector_interleave = interleave;
+            }
+            if let Some(skew) = layout.skew {
+                disk_pars.track_skew = skew;
+            }
+            if let Some(first_sector) = layout.first_sector {
+                disk_pars.first_sector_n = first_sector;
+            }
+            disk_pars.sector_numbers = layout.sector_numbers;
+            disk_pars.zones = layout.zones;
+            if let Some(gap_byte) = layout.gap_byte {
+                disk_pars.gaps.gap_byte = gap_byte;
+            }
+            if let Some(gap_lengths) = layout.gap_lengths {
+                disk_pars.gaps.set_lengths(gap_lengths);
+            }
+        }
+
+        // Interleave
+        if let Some(interleave) = self.interleave {
+            disk_pars.sector_interleave = interleave;
+        }
+
+        // Track skew
+        if let Some(skew) = self.skew {
+            disk_pars.track_skew = skew;
+        }
+
+        // First sector number
+        if let Some(first_sector) = self.first_sector {
+            disk_pars.first_sector_n = first_sector;
+        }
+
+        // Sector numbering map
+        if let Some(sector_numbers) = &self.sector_numbers {
+            disk_pars.sector_numbers = Some(sector_numbers.clone());
+        }
+
+        // Gap byte and lengths
+        if let Some(gap_byte) = self.gap_byte {
+            disk_pars.gaps.gap_byte = gap_byte;
+        }
+        if let Some(gap_lengths) = self.gaps {
+            disk_pars.gaps.set_lengths(gap_lengths);
+        }
+
+        // Fill pattern
+        if let Some(fill_pattern) = &self.fill {
+            disk_pars.fill_pattern = fill_pattern.clone();
+        }
+
+        // Geometry zones, added after the ones from the layout file
+        disk_pars.zones.extend(self.zones.iter().cloned());
+
+        // Bad sector map
+        if let Some(file_path) = &self.bad_sectors {
+            disk_pars.bad_sectors = read_bad_sector_file(file_path)?;
+        }
+
+        Ok(disk_pars)
+    }
+}
+
+impl Args {
+    // Input path, always present when no subcommand is given
+    pub fn input(&self) -> &PathBuf {
+        self.input.as_ref().unwrap()
+    }
+
+    // Output path, always present when no subcommand is given
+    pub fn output(&self) -> &PathBuf {
+        self.output.as_ref().unwrap()
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Convert a disk image to another format, taking the disk geometry from the input image
+    Convert(Box<ConvertArgs>),
+
+    /// Extract logical sector contents from an HFE image
+    Extract(ExtractArgs),
+
+    /// Decode an HFE image and check it for errors, printing its geometry
+    Verify(VerifyArgs),
+
+    /// Replace sector contents in an HFE image, re-encoding only the affected tracks
+    Patch(PatchArgs),
+
+    /// Print the ID fields, gaps and sector data of the tracks of an HFE image
+    Dump(DumpArgs),
+
+    /// List the available disk profiles
+    Profiles(ProfilesArgs),
+
+    /// Print the shell completion script
+    Completions(CompletionsArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ConvertArgs {
+    /// Input disk image
+    pub input: PathBuf,
+
+    /// Output disk image
+    pub output: PathBuf,
+
+    /// Ignore image conversion errors
+    #[arg(short = 'l', long, action)]
+    pub ignore_errors: bool,
+
+    /// Print statistics of each track
+    #[arg(short = 'v', long, action)]
+    pub verbose: bool,
+
+    /// Input image format [default: from the input file extension]
+    #[arg(value_enum, short = 'g', long)]
+    pub input_format: Option<InputFormat>,
+
+    /// Output image format [default: from the output file extension]
+    #[arg(value_enum, short = 'f', long)]
+    pub output_format: Option<OutputFormat>,
+
+    #[command(flatten)]
+    pub disk: DiskArgs,
+
+    /// Write a JSON manifest with checksums of the image and its sectors
+    #[arg(long, default_value = None, value_name = "FILE")]
+    pub manifest: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ExtractArgs {
+    /// Input HFE disk image
+    pub input: PathBuf,
+
+    /// Output data disk image
+    pub output: PathBuf,
+
+    /// Ignore missing sectors and CRC errors, filling missing sectors with 0x00
+    #[arg(short = 'l', long, action)]
+    pub ignore_errors: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct VerifyArgs {
+    /// Input HFE disk image
+    pub input: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DumpArgs {
+    /// Input HFE disk image
+    pub input: PathBuf,
+
+    /// Cylinder to dump [default: all]
+    #[arg(short = 't', long)]
+    pub track: Option<u16>,
+
+    /// Side to dump [default: all]
+    #[arg(short = 'e', long)]
+    pub side: Option<u16>,
+}
+
+#[derive(Debug, clap::Args)]
+#[command(group(clap::ArgGroup::new("location").required(true).args(["chs", "offset"])))]
+pub struct PatchArgs {
+    /// HFE disk image to patch
+    pub image: PathBuf,
+
+    /// First sector to patch, as CYLINDER,SIDE,SECTOR (e.g. 0,0,1)
+    #[arg(long, value_parser = parse_chs)]
+    pub chs: Option<PatchLocation>,
+
+    /// Offset in the logical sector data to patch at, as written by extract (e.g. 0x1A00)
+    #[arg(long, value_parser = parse_offset)]
+    pub offset: Option<PatchLocation>,
+
+    /// Data to write
+    #[arg(long, value_name = "FILE")]
+    pub data: PathBuf,
+
+    /// Output disk image [default: patch the image in place]
+    #[arg(short = 'o', long, default_value = None)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CompletionsArgs {
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ProfilesArgs {
+    /// User disk profile file
+    #[arg(long, default_value = None, value_name = "FILE")]
+    pub profile_file: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sector_numbers_arg() {
+        let args = Args::try_parse_from([
+            "l6disk",
+            "--sector-numbers",
+            "1,3,5,2,4,6",
+            "in.img",
+            "out.hfe",
+        ])
+        .unwrap();
+        assert_eq!(args.disk.sector_numbers, Some(vec![1, 3, 5, 2, 4, 6]));
+        assert_eq!(
+            args.disk.disk_parameters().unwrap().sector_numbers,
+            Some(vec![1, 3, 5, 2, 4, 6])
+        );
+
+        assert!(
+            Args::try_parse_from(["l6disk", "--sector-numbers", "1,x", "in.img", "out.hfe"])
+                .is_err()
+        );
+    }
 }
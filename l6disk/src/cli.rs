@@ -0,0 +1,420 @@
+use clap::{CommandFactory, Parser};
+use colored::Colorize;
+use std::ffi::OsString;
+use std::io;
+use std::process::exit;
+
+use crate::args;
+use crate::disk_image::profiles::{builtin_profiles, read_profile_file};
+use crate::disk_image::{
+    convert_image, dump_hfe, extract_from_hfe, make_manifest, manifest_to_json, patch_hfe,
+    verify_hfe,
+};
+use crate::disk_image::{
+    ConvertOpts, ConvertedImage, DumpOpts, ExtractOpts, InputFormat, OutputFormat, SideOrder,
+};
+use crate::file;
+
+// Run the utility with the given command line arguments, the first being the program name.
+// Exits with status 1 if the command failed, so scripts can check the result
+pub fn run<I, T>(itr: I) -> !
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let args = args::Args::parse_from(itr);
+
+    // Decide what command to run
+    let res = match &args.command {
+        Some(args::Command::Convert(convert_args)) => run_convert_command(convert_args),
+        Some(args::Command::Extract(extract_args)) => run_extract_command(extract_args),
+        Some(args::Command::Verify(verify_args)) => run_verify_command(verify_args),
+        Some(args::Command::Patch(patch_args)) => run_patch_command(patch_args),
+        Some(args::Command::Dump(dump_args)) => run_dump_command(dump_args),
+        Some(args::Command::Profiles(profiles_args)) => run_profiles_command(profiles_args),
+        Some(args::Command::Completions(completions_args)) => {
+            let mut command = args::Args::command();
+            let name = command.get_name().to_owned();
+            clap_complete::generate(
+                completions_args.shell,
+                &mut command,
+                name,
+                &mut io::stdout(),
+            );
+            Ok(())
+        }
+        None if args.help_man => clap_mangen::Man::new(args::Args::command())
+            .render(&mut io::stdout())
+            .map_err(|err| format!("Unable to write man page: {}", err)),
+        None => run_create_command(args),
+    };
+
+    match res {
+        Err(msg) => {
+            println!("{} {}", "error: ".bright_red().bold(), msg);
+            exit(1)
+        }
+        Ok(()) => exit(0),
+    }
+}
+
+fn run_create_command(args: args::Args) -> Result<(), String> {
+    // Read input file
+    let input_data = match file::read_file(args.input()) {
+        Ok(data) => data,
+        Err(err) => {
+            return Err(format!(
+                "Unable to open \"{}\": {}",
+                args.input().display(),
+                err
+            ))
+        }
+    };
+
+    // Read side 1 input file
+    let side1_data = match &args.side1 {
+        Some(file_path) => match file::read_file(file_path) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                return Err(format!(
+                    "Unable to open \"{}\": {}",
+                    file_path.display(),
+                    err
+                ))
+            }
+        },
+        None => None,
+    };
+
+    // Get disk parameters
+    let disk_parameters = args.disk.disk_parameters()?;
+
+    // Read boot loader
+    let boot_loader = match &args.boot {
+        Some(file_path) => match file::read_file(file_path) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                return Err(format!(
+                    "Unable to open \"{}\": {}",
+                    file_path.display(),
+                    err
+                ))
+            }
+        },
+        None => None,
+    };
+
+    // Convert image
+    let generated_image = match convert_image(
+        input_data,
+        ConvertOpts {
+            ignore_errors: args.ignore_errors,
+            disk_parameters,
+            input_format: args.input_format,
+            output_format: args.output_format,
+            side_order: args.side_order,
+            side1_data,
+            boot_loader,
+            verbose: args.verbose,
+        },
+    ) {
+        Ok(data) => data,
+        Err(err) => return Err(format!("Image conversion error: {}", err)),
+    };
+
+    print_summary(&generated_image);
+
+    // Write manifest
+    if let Some(file_path) = &args.manifest {
+        write_manifest(file_path, &generated_image)?;
+    }
+
+    // Write data to output file
+    match file::write_file(args.output(), generated_image.data) {
+        Ok(()) => {}
+        Err(err) => {
+            return Err(format!(
+                "Unable to write to \"{}\": {}",
+                args.output().display(),
+                err
+            ))
+        }
+    };
+
+    Ok(())
+}
+
+fn run_convert_command(args: &args::ConvertArgs) -> Result<(), String> {
+    // Image formats, from the file extensions unless given
+    let input_format = match args.input_format.or(InputFormat::from_path(&args.input)) {
+        Some(format) => format,
+        None => {
+            return Err(format!(
+                "Unknown format of \"{}\", use --input-format",
+                args.input.display()
+            ))
+        }
+    };
+    let output_format = match args.output_format.or(OutputFormat::from_path(&args.output)) {
+        Some(format) => format,
+        None => {
+            return Err(format!(
+                "Unknown format of \"{}\", use --output-format",
+                args.output.display()
+            ))
+        }
+    };
+
+    // Read input file
+    let input_data = match file::read_file(&args.input) {
+        Ok(data) => data,
+        Err(err) => {
+            return Err(format!(
+                "Unable to open \"{}\": {}",
+                args.input.display(),
+                err
+            ))
+        }
+    };
+
+    // Get disk parameters
+    let disk_parameters = args.disk.disk_parameters()?;
+
+    // Convert image
+    let converted_image = match convert_image(
+        input_data,
+        ConvertOpts {
+            ignore_errors: args.ignore_errors,
+            disk_parameters,
+            input_format,
+            output_format,
+            side_order: SideOrder::Cylinder,
+            side1_data: None,
+            boot_loader: None,
+            verbose: args.verbose,
+        },
+    ) {
+        Ok(data) => data,
+        Err(err) => return Err(format!("Image conversion error: {}", err)),
+    };
+
+    print_summary(&converted_image);
+
+    // Write manifest
+    if let Some(file_path) = &args.manifest {
+        write_manifest(file_path, &converted_image)?;
+    }
+
+    // Write data to output file
+    match file::write_file(&args.output, converted_image.data) {
+        Ok(()) => {}
+        Err(err) => {
+            return Err(format!(
+                "Unable to write to \"{}\": {}",
+                args.output.display(),
+                err
+            ))
+        }
+    };
+
+    Ok(())
+}
+
+// Print the size of a converted image
+fn print_summary(image: &ConvertedImage) {
+    match image.track_bitcells.len() {
+        0 => println!(
+            "{} sectors, {} bytes",
+            image.sectors.len(),
+            image.data.len()
+        ),
+        n_tracks => println!(
+            "{} tracks, {} bitcells, {} bytes",
+            n_tracks,
+            image.track_bitcells.iter().sum::<usize>(),
+            image.data.len()
+        ),
+    }
+}
+
+fn write_manifest(file_path: &std::path::Path, image: &ConvertedImage) -> Result<(), String> {
+    let manifest = make_manifest(&image.data, &image.sectors, &image.disk_parameters);
+    match file::write_file(
+        &file_path.to_path_buf(),
+        manifest_to_json(&manifest).into_bytes(),
+    ) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(format!(
+            "Unable to write to \"{}\": {}",
+            file_path.display(),
+            err
+        )),
+    }
+}
+
+fn run_extract_command(args: &args::ExtractArgs) -> Result<(), String> {
+    // Read input file
+    let input_data = match file::read_file(&args.input) {
+        Ok(data) => data,
+        Err(err) => {
+            return Err(format!(
+                "Unable to open \"{}\": {}",
+                args.input.display(),
+                err
+            ))
+        }
+    };
+
+    // Extract sectors
+    let extracted_data = match extract_from_hfe(
+        &input_data,
+        ExtractOpts {
+            ignore_errors: args.ignore_errors,
+        },
+    ) {
+        Ok(data) => data,
+        Err(err) => return Err(format!("Image extraction error: {}", err)),
+    };
+
+    // Write data to output file
+    match file::write_file(&args.output, extracted_data) {
+        Ok(()) => {}
+        Err(err) => {
+            return Err(format!(
+                "Unable to write to \"{}\": {}",
+                args.output.display(),
+                err
+            ))
+        }
+    };
+
+    Ok(())
+}
+
+fn run_verify_command(args: &args::VerifyArgs) -> Result<(), String> {
+    // Read input file
+    let input_data = match file::read_file(&args.input) {
+        Ok(data) => data,
+        Err(err) => {
+            return Err(format!(
+                "Unable to open \"{}\": {}",
+                args.input.display(),
+                err
+            ))
+        }
+    };
+
+    // Decode and check image
+    let report = match verify_hfe(&input_data) {
+        Ok(report) => report,
+        Err(msg) => return Err(format!("Image verification error: {}", msg)),
+    };
+
+    // Print geometry report and problems found
+    print!("{}", report);
+    for problem in &report.problems {
+        println!("{} {}", "problem:".yellow().bold(), problem);
+    }
+
+    match report.problems.len() {
+        0 => {
+            println!("No problems found");
+            Ok(())
+        }
+        1 => Err("1 problem found".to_owned()),
+        n => Err(format!("{} problems found", n)),
+    }
+}
+
+fn run_patch_command(args: &args::PatchArgs) -> Result<(), String> {
+    // Read image and patch data
+    let mut files: Vec<Vec<u8>> = vec![];
+    for file_path in [&args.image, &args.data] {
+        match file::read_file(file_path) {
+            Ok(data) => files.push(data),
+            Err(err) => {
+                return Err(format!(
+                    "Unable to open \"{}\": {}",
+                    file_path.display(),
+                    err
+                ))
+            }
+        }
+    }
+
+    // Patch sectors
+    let location = args.chs.or(args.offset).unwrap();
+    let patched_data = match patch_hfe(&files[0], location, &files[1]) {
+        Ok(data) => data,
+        Err(err) => return Err(format!("Image patching error: {}", err)),
+    };
+
+    // Write data to output file, or back to the image
+    let output = args.output.as_ref().unwrap_or(&args.image);
+    match file::write_file(output, patched_data) {
+        Ok(()) => {}
+        Err(err) => {
+            return Err(format!(
+                "Unable to write to \"{}\": {}",
+                output.display(),
+                err
+            ))
+        }
+    };
+
+    Ok(())
+}
+
+fn run_dump_command(args: &args::DumpArgs) -> Result<(), String> {
+    // Read input file
+    let input_data = match file::read_file(&args.input) {
+        Ok(data) => data,
+        Err(err) => {
+            return Err(format!(
+                "Unable to open \"{}\": {}",
+                args.input.display(),
+                err
+            ))
+        }
+    };
+
+    // Decode and print tracks
+    let dump = match dump_hfe(
+        &input_data,
+        DumpOpts {
+            cylinder: args.track,
+            side: args.side,
+        },
+    ) {
+        Ok(dump) => dump,
+        Err(err) => return Err(format!("Image dump error: {}", err)),
+    };
+    print!("{}", dump);
+
+    Ok(())
+}
+
+fn run_profiles_command(args: &args::ProfilesArgs) -> Result<(), String> {
+    let mut profiles = match &args.profile_file {
+        Some(file_path) => read_profile_file(file_path)?,
+        None => vec![],
+    };
+    profiles.append(&mut builtin_profiles());
+
+    for profile in profiles {
+        let pars = &profile.disk_parameters;
+        println!(
+            "{:<16} {}x{}x{}x{} {} kbps {} RPM  {}",
+            profile.name,
+            pars.n_cylinders,
+            pars.n_sides,
+            pars.sectors_per_track,
+            pars.bytes_per_sector,
+            pars.cell_rate,
+            pars.rpm,
+            profile.description
+        );
+    }
+
+    Ok(())
+}
@@ -1,11 +1,30 @@
+pub mod boot;
 pub mod convert;
+pub mod d86f;
 pub mod disk_parameters;
+pub mod dump;
 pub mod encode;
 pub mod errors;
+pub mod extract;
 pub mod fm;
 pub mod hfe;
 pub mod ibm3470;
+pub mod imd;
+pub mod lzhuf;
+pub mod manifest;
+pub mod patch;
+pub mod profiles;
+pub mod scp;
+pub mod sector_image;
+pub mod td0;
+pub mod verify;
 
 // Public exports
-pub use convert::{convert_to_raw, ConvertOpts};
+pub use convert::{convert_image, layout_raw_image, ConvertOpts, ConvertedImage};
+pub use convert::{InputFormat, OutputFormat, SideOrder};
 pub use disk_parameters::DiskParameters;
+pub use dump::{dump_hfe, DumpOpts};
+pub use extract::{extract_from_hfe, ExtractOpts};
+pub use manifest::{make_manifest, manifest_to_json};
+pub use patch::{patch_hfe, PatchLocation};
+pub use verify::verify_hfe;
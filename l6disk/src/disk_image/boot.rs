@@ -0,0 +1,63 @@
+// In-module imports
+use super::convert::Sector;
+use super::disk_parameters::DiskParameters;
+
+// Place a bootstrap loader into the sectors of the boot track (cylinder 0, side 0), starting
+// from its first logical sector. The rest of the last sector used is filled with 0x00
+pub fn install_boot_loader(
+    sectors: &mut [Sector],
+    loader: &[u8],
+    disk_parameters: &DiskParameters,
+) -> Result<(), String> {
+    let boot_pars = disk_parameters.cylinder_parameters(0);
+    let sector_size = boot_pars.bytes_per_sector as usize;
+    let track_size = boot_pars.sectors_per_track as usize * sector_size;
+
+    // Check loader size
+    if loader.is_empty() {
+        return Err("empty file".to_string());
+    }
+    if !loader.len().is_multiple_of(2) {
+        return Err(format!(
+            "not a whole number of words: {} bytes",
+            loader.len()
+        ));
+    }
+    if loader.len() > track_size {
+        return Err(format!(
+            "too large for the boot track ({} bytes, maximum is {})",
+            loader.len(),
+            track_size
+        ));
+    }
+
+    for (sector, chunk) in sectors.iter_mut().zip(loader.chunks(sector_size)) {
+        sector.fill(0);
+        sector[0..chunk.len()].copy_from_slice(chunk);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::*;
+
+    #[test]
+    fn test_install_boot_loader() {
+        let disk_parameters = DiskFormatDefaults::LEVEL6;
+        let mut sectors: Vec<Sector> = vec![vec![0xE5; 128]; 26 * 2];
+
+        let loader: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        install_boot_loader(&mut sectors, &loader, &disk_parameters).unwrap();
+        assert_eq!(sectors[0], loader[0..128]);
+        assert_eq!(sectors[1][0..72], loader[128..200]);
+        assert_eq!(sectors[1][72..], [0; 56]);
+        assert_eq!(sectors[2], [0xE5; 128]);
+
+        for size in [0, 201, 26 * 128 + 2] {
+            assert!(install_boot_loader(&mut sectors, &vec![0; size], &disk_parameters).is_err());
+        }
+    }
+}
@@ -1,14 +1,84 @@
 // In-module imports
+use super::boot::install_boot_loader;
 use super::disk_parameters::DiskParameters;
 use super::encode::encode_disk;
 use super::errors::{ConvertError, ConvertErrorType};
+use super::extract::{read_86f_sectors, read_hfe_sectors, read_scp_sectors};
 use super::hfe::make_hfe_file;
+use super::imd::{make_imd_file, read_imd_file};
+use super::scp::make_scp_file;
+use super::sector_image::SectorImage;
+use super::td0::read_td0_file;
 
 #[derive(Debug)]
 // Encoding options
 pub struct ConvertOpts {
     pub ignore_errors: bool,
     pub disk_parameters: DiskParameters,
+    pub input_format: InputFormat,
+    pub output_format: OutputFormat,
+    pub side_order: SideOrder,
+    pub side1_data: Option<Vec<u8>>, // Side 1 data, in a separate image
+    pub boot_loader: Option<Vec<u8>>,
+    pub verbose: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum InputFormat {
+    Raw,
+    Td0,
+    Hfe,
+    Scp,
+    Imd,
+    #[value(name = "86f")]
+    D86f,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Hfe,
+    Scp,
+    Raw,
+    Imd,
+}
+
+impl InputFormat {
+    // Format of an image file, from its extension
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        match extension(path)?.as_str() {
+            "img" | "bin" | "raw" => Some(Self::Raw),
+            "td0" => Some(Self::Td0),
+            "hfe" => Some(Self::Hfe),
+            "scp" => Some(Self::Scp),
+            "imd" => Some(Self::Imd),
+            "86f" => Some(Self::D86f),
+            _ => None,
+        }
+    }
+}
+
+impl OutputFormat {
+    // Format of an image file, from its extension
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        match extension(path)?.as_str() {
+            "img" | "bin" | "raw" => Some(Self::Raw),
+            "hfe" => Some(Self::Hfe),
+            "scp" => Some(Self::Scp),
+            "imd" => Some(Self::Imd),
+            _ => None,
+        }
+    }
+}
+
+fn extension(path: &std::path::Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_ascii_lowercase())
+}
+
+// Order of the tracks in the input image
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum SideOrder {
+    Cylinder, // Both sides of each cylinder in turn
+    Side,     // All cylinders of side 0, then all cylinders of side 1
 }
 
 pub type ConvertResult = Result<Vec<u8>, ConvertError>;
@@ -18,35 +88,212 @@ pub type Sector = Vec<u8>;
 pub type Track = Vec<u8>;
 pub type Cylinder = Vec<Track>;
 
-// Convert data image to raw floppy image
-pub fn convert_to_raw(data_img: Vec<u8>, opts: ConvertOpts) -> ConvertResult {
-    // Divide disk image into sectors
-    let mut sectors = match divide_data_image_sectors(
-        data_img,
-        opts.disk_parameters.bytes_per_sector,
-        opts.ignore_errors,
-    ) {
-        Ok(sectors) => sectors,
-        Err(()) => return Err(ConvertError::new(ConvertErrorType::SectorDivision)),
+// Converted image, with the sectors and geometry it holds
+#[derive(Debug)]
+pub struct ConvertedImage {
+    pub data: Vec<u8>,
+    pub sectors: Vec<Sector>, // In logical order
+    pub disk_parameters: DiskParameters,
+    pub track_bitcells: Vec<usize>, // Bitcells of each encoded track, empty for sector images
+}
+
+// Convert data image to raw floppy image, keeping the sectors written
+pub fn convert_image(
+    data_img: Vec<u8>,
+    mut opts: ConvertOpts,
+) -> Result<ConvertedImage, ConvertError> {
+    // Read sectors out of input image. Images which record their geometry replace the
+    // geometry of the disk parameters
+    let (mut sectors, disk_parameters) = match opts.input_format {
+        InputFormat::Raw => (
+            read_raw_image(data_img, opts.side1_data.take(), &opts)?,
+            opts.disk_parameters.clone(),
+        ),
+        format => {
+            let image = read_sector_image(&data_img, format, opts.ignore_errors)?;
+            let disk_parameters = match image.disk_parameters(&opts.disk_parameters) {
+                Ok(disk_parameters) => disk_parameters,
+                Err(msg) => return Err(ConvertError::new(ConvertErrorType::ImageReading(msg))),
+            };
+            (image.sectors(), disk_parameters)
+        }
+    };
+    // Place boot loader into the boot track
+    if let Some(loader) = &opts.boot_loader {
+        if let Err(msg) = install_boot_loader(&mut sectors, loader, &disk_parameters) {
+            return Err(ConvertError::new(ConvertErrorType::BootLoader(msg)));
+        }
+    }
+
+    // Sector images need no encoding
+    let out_data = match opts.output_format {
+        OutputFormat::Raw => Some(Ok(sectors.concat())),
+        OutputFormat::Imd => Some(make_imd_file(&sectors, &disk_parameters)),
+        OutputFormat::Hfe | OutputFormat::Scp => None,
+    };
+    if let Some(out_data) = out_data {
+        let out_data = match out_data {
+            Ok(data) => data,
+            Err(msg) => return Err(ConvertError::new(ConvertErrorType::RawImageCreation(msg))),
+        };
+        return Ok(ConvertedImage {
+            data: out_data,
+            sectors,
+            disk_parameters,
+            track_bitcells: vec![],
+        });
+    }
+
+    // Encode disk image to correct format
+    let encoded_cylinders = match encode_disk(&sectors, &disk_parameters, opts.verbose) {
+        Ok(disk) => disk,
+        Err(msg) => return Err(ConvertError::new(ConvertErrorType::DiskEncoding(msg))),
+    };
+
+    // Create output raw disk image image
+    let out_data = match opts.output_format {
+        OutputFormat::Hfe => make_hfe_file(&encoded_cylinders, &disk_parameters),
+        OutputFormat::Scp => make_scp_file(&encoded_cylinders, &disk_parameters),
+        OutputFormat::Raw | OutputFormat::Imd => unreachable!(),
+    };
+    let out_data: Vec<u8> = match out_data {
+        Ok(data) => data,
+        Err(msg) => return Err(ConvertError::new(ConvertErrorType::RawImageCreation(msg))),
+    };
+
+    Ok(ConvertedImage {
+        data: out_data,
+        sectors,
+        disk_parameters,
+        track_bitcells: encoded_cylinders
+            .iter()
+            .flatten()
+            .map(|track| track.len() * 8)
+            .collect(),
+    })
+}
+
+// Lay out data from the first sector of the disk, filling the sectors past its end, so it
+// can be read as a raw image
+pub fn layout_raw_image(
+    data: &[u8],
+    disk_parameters: &DiskParameters,
+) -> Result<Vec<u8>, ConvertError> {
+    let sector_sizes = disk_parameters.sector_sizes();
+    let capacity: usize = sector_sizes.iter().map(|size| *size as usize).sum();
+    if data.len() > capacity {
+        return Err(ConvertError::new(ConvertErrorType::ImageTooLarge(
+            data.len(),
+            capacity,
+        )));
+    }
+
+    let mut image: Vec<u8> = Vec::with_capacity(capacity);
+    let mut data = data;
+    for size in sector_sizes {
+        let sector = disk_parameters.fill_sector(size as usize);
+        let (used, rest) = data.split_at(data.len().min(sector.len()));
+        image.extend_from_slice(used);
+        image.extend_from_slice(&sector[used.len()..]);
+        data = rest;
+    }
+
+    Ok(image)
+}
+
+// Divide raw data images into sectors, in cylinder order
+fn read_raw_image(
+    data_img: Vec<u8>,
+    side1_data: Option<Vec<u8>>,
+    opts: &ConvertOpts,
+) -> Result<Vec<Sector>, ConvertError> {
+    // Sector sizes of each input image, in the order their sectors are stored
+    let disk_parameters = &opts.disk_parameters;
+    let inputs = match (side1_data, opts.side_order) {
+        (None, SideOrder::Cylinder) => vec![(data_img, disk_parameters.sector_sizes())],
+        (None, SideOrder::Side) => {
+            let sizes = disk_parameters.side_sector_sizes();
+            vec![(data_img, sizes.repeat(disk_parameters.n_sides as usize))]
+        }
+        (Some(side1_data), _) => {
+            if disk_parameters.n_sides != 2 {
+                return Err(ConvertError::new(ConvertErrorType::SideNumber(
+                    disk_parameters.n_sides,
+                )));
+            }
+            vec![
+                (data_img, disk_parameters.side_sector_sizes()),
+                (side1_data, disk_parameters.side_sector_sizes()),
+            ]
+        }
     };
+    let side_major = inputs.len() > 1 || opts.side_order == SideOrder::Side;
+
+    // Divide input images into sectors
+    let mut sectors: Vec<Sector> = vec![];
+    for (data_img, sector_sizes) in inputs {
+        sectors.append(&mut read_sectors(
+            data_img,
+            &sector_sizes,
+            disk_parameters,
+            opts.ignore_errors,
+        )?);
+    }
+
+    // Encoding expects sectors in cylinder order
+    if side_major {
+        sectors = side_to_cylinder_order(sectors, disk_parameters);
+    }
+
+    Ok(sectors)
+}
+
+// Read the sectors of an image which records its own geometry
+fn read_sector_image(
+    data_img: &[u8],
+    format: InputFormat,
+    ignore_errors: bool,
+) -> Result<SectorImage, ConvertError> {
+    let image = match format {
+        InputFormat::Td0 => read_td0_file(data_img),
+        InputFormat::Imd => read_imd_file(data_img, ignore_errors),
+        InputFormat::Hfe => return read_hfe_sectors(data_img, ignore_errors),
+        InputFormat::Scp => return read_scp_sectors(data_img, ignore_errors),
+        InputFormat::D86f => return read_86f_sectors(data_img, ignore_errors),
+        InputFormat::Raw => unreachable!(),
+    };
+
+    image.map_err(|msg| ConvertError::new(ConvertErrorType::ImageReading(msg)))
+}
+
+// Divide data image into exactly one sector for each of the given sizes. Missing sectors
+// are filled with the fill pattern
+fn read_sectors(
+    data_img: Vec<u8>,
+    sector_sizes: &[u16],
+    disk_parameters: &DiskParameters,
+    ignore_errors: bool,
+) -> Result<Vec<Sector>, ConvertError> {
+    let mut sectors =
+        match divide_data_image_sectors(data_img, sector_sizes, disk_parameters, ignore_errors) {
+            Ok(sectors) => sectors,
+            Err(()) => return Err(ConvertError::new(ConvertErrorType::SectorDivision)),
+        };
 
     // Check number of sectors
-    let expected_sectors: usize = (opts.disk_parameters.sectors_per_track
-        * opts.disk_parameters.n_sides
-        * opts.disk_parameters.n_cylinders) as usize;
+    let expected_sectors: usize = sector_sizes.len();
 
     if sectors.len() != expected_sectors {
         // Wrong number of sectors
-        if opts.ignore_errors {
+        if ignore_errors {
             if sectors.len() < expected_sectors {
                 // Add sectors to fill remaining tracks
-                sectors.append(&mut vec![
-                    vec![
-                        0;
-                        opts.disk_parameters.bytes_per_sector as usize
-                    ];
-                    expected_sectors - sectors.len()
-                ]);
+                for size in &sector_sizes[sectors.len()..] {
+                    sectors.push(disk_parameters.fill_sector(*size as usize));
+                }
+            } else {
+                // Drop sectors past the end of the disk
+                sectors.truncate(expected_sectors);
             }
         } else {
             return Err(ConvertError::new(ConvertErrorType::SectorNumber(
@@ -56,33 +303,49 @@ pub fn convert_to_raw(data_img: Vec<u8>, opts: ConvertOpts) -> ConvertResult {
         }
     }
 
-    // Encode disk image to correct format
-    let encoded_cylinders = match encode_disk(&sectors, &opts.disk_parameters) {
-        Ok(disk) => disk,
-        Err(msg) => return Err(ConvertError::new(ConvertErrorType::DiskEncoding(msg))),
-    };
+    Ok(sectors)
+}
 
-    // Create output raw disk image image
-    let out_data: Vec<u8> = match make_hfe_file(&encoded_cylinders, &opts.disk_parameters) {
-        Ok(data) => data,
-        Err(msg) => return Err(ConvertError::new(ConvertErrorType::RawImageCreation(msg))),
-    };
+// Reorder sectors stored one side after the other (all of side 0, then all of side 1)
+// to cylinder order (cylinder 0 side 0, cylinder 0 side 1, cylinder 1 side 0...)
+fn side_to_cylinder_order(sectors: Vec<Sector>, disk_parameters: &DiskParameters) -> Vec<Sector> {
+    let sectors_per_side = sectors.len() / disk_parameters.n_sides as usize;
+    let mut sides: Vec<_> = sectors
+        .chunks(sectors_per_side.max(1))
+        .map(|side| side.iter())
+        .collect();
+
+    let mut ordered: Vec<Sector> = vec![];
+    for cyl_n in 0..disk_parameters.n_cylinders {
+        let sectors_per_track = disk_parameters.cylinder_parameters(cyl_n).sectors_per_track;
+        for side in sides.iter_mut() {
+            ordered.extend(side.by_ref().take(sectors_per_track as usize).cloned());
+        }
+    }
 
-    Ok(out_data)
+    ordered
 }
 
-// Divide data image into vector of sectors with given size
+// Divide data image into vector of sectors with given sizes. Data past the last sector is
+// divided into sectors of the default size
 fn divide_data_image_sectors(
     data_img: Vec<u8>,
-    sector_size: u16,
+    sector_sizes: &[u16],
+    disk_parameters: &DiskParameters,
     ignore_errors: bool,
 ) -> Result<Vec<Sector>, ()> {
+    let default_sector_size = disk_parameters.bytes_per_sector;
     let mut sectors: Vec<Sector> = vec![];
+    let mut sector_sizes = sector_sizes
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(default_sector_size));
 
     // Convert image to sectors
     let mut bytes_consumed: usize = 0;
     while bytes_consumed < data_img.len() {
         let remaining_bytes = data_img.len() - bytes_consumed;
+        let sector_size = sector_sizes.next().unwrap_or(default_sector_size);
 
         // Create new sector
         if remaining_bytes >= sector_size as usize {
@@ -91,8 +354,8 @@ fn divide_data_image_sectors(
         } else if ignore_errors {
             // Invalid sector boundary but we ignore errors
 
-            // Fill remaining bytes with zeroes
-            let mut this_sector: Sector = vec![0; sector_size as usize];
+            // Fill remaining bytes with the fill pattern
+            let mut this_sector: Sector = disk_parameters.fill_sector(sector_size as usize);
             this_sector[0..remaining_bytes]
                 .copy_from_slice(&data_img[bytes_consumed..data_img.len()]);
             sectors.push(this_sector);
@@ -106,3 +369,45 @@ fn divide_data_image_sectors(
 
     Ok(sectors)
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::*;
+
+    #[test]
+    fn test_side_to_cylinder_order() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8DSSD;
+        disk_parameters.n_cylinders = 2;
+        disk_parameters.sectors_per_track = 2;
+
+        // Sectors named by cylinder, side and sector
+        let sectors: Vec<Sector> = [0x000, 0x001, 0x100, 0x101, 0x010, 0x011, 0x110, 0x111]
+            .iter()
+            .map(|id: &u16| id.to_be_bytes().to_vec())
+            .collect();
+        let ordered: Vec<u16> = side_to_cylinder_order(sectors, &disk_parameters)
+            .iter()
+            .map(|sector| u16::from_be_bytes([sector[0], sector[1]]))
+            .collect();
+        assert_eq!(
+            ordered,
+            vec![0x000, 0x001, 0x010, 0x011, 0x100, 0x101, 0x110, 0x111]
+        );
+    }
+
+    #[test]
+    fn test_layout_raw_image() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8SSSD;
+        disk_parameters.n_cylinders = 1;
+        disk_parameters.fill_pattern = vec![0xE5];
+
+        let image = layout_raw_image(&[1; 200], &disk_parameters).unwrap();
+        assert_eq!(image.len(), 26 * 128);
+        assert_eq!(image[..200], [1; 200]);
+        assert!(image[200..].iter().all(|byte| *byte == 0xE5));
+
+        assert!(layout_raw_image(&[1; 26 * 128], &disk_parameters).is_ok());
+        assert!(layout_raw_image(&[1; 26 * 128 + 1], &disk_parameters).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+// In-module imports
+use super::convert::{Cylinder, Track};
+
+const D86F_HEADER_LEN: usize = 8;
+const D86F_MAJOR_VERSION: u16 = 2;
+
+// Disk flags
+const D86F_FLAG_SURFACE: u16 = 0x0001; // Surface description follows the bitcells of each track
+const D86F_FLAG_SIDES: u16 = 0x0008; // Two sides
+const D86F_FLAG_BITCELL_MODE: u16 = 0x0080; // Tracks give their extra bitcell count
+const D86F_FLAG_REVERSE_ENDIAN: u16 = 0x0800; // Bitcell words are stored high byte first
+
+// Track flags
+const D86F_TRACK_ENCODING: u16 = 0x0018; // 0 for FM
+
+// Tracks read back from an 86F file
+#[derive(Debug, Clone)]
+pub struct D86FImage {
+    pub n_sides: u8,
+    pub cylinders: Vec<Cylinder>, // FM encoded tracks, from the index hole
+}
+
+// Read the tracks of an 86Box 86F file. Tracks are listed by the offset table after the
+// header, both sides of each cylinder in turn, and hold 16 bit words of bitcells starting
+// anywhere on the track. They are turned to start at the index hole and read twice, so
+// sectors across the index hole are whole. Surface description data is ignored
+pub fn read_86f_file(data: &[u8]) -> Result<D86FImage, String> {
+    // Check header
+    if data.len() < D86F_HEADER_LEN || data[0..4] != *b"86BF" {
+        return Err("Not an 86F file".to_string());
+    }
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    let disk_flags = u16::from_le_bytes([data[6], data[7]]);
+
+    if version >> 8 != D86F_MAJOR_VERSION {
+        return Err(format!(
+            "Unsupported 86F version: {}.{}",
+            version >> 8,
+            version & 0xFF
+        ));
+    }
+    let n_sides: u8 = match disk_flags & D86F_FLAG_SIDES {
+        0 => 1,
+        _ => 2,
+    };
+
+    // Track offset table, ending at the first unused entry or the first track
+    let mut offsets: Vec<usize> = vec![];
+    let mut entry = D86F_HEADER_LEN;
+    while let Some(bytes) = data.get(entry..entry + 4) {
+        let offset = u32::from_le_bytes(bytes.try_into().unwrap()) as usize;
+        if offset == 0 || offsets.first().is_some_and(|first| entry >= *first) {
+            break;
+        }
+        offsets.push(offset);
+        entry += 4;
+    }
+
+    // Each track runs up to the next one
+    let mut tracks: Vec<Track> = vec![];
+    for (track_n, offset) in offsets.iter().enumerate() {
+        let end = offsets.get(track_n + 1).copied().unwrap_or(data.len());
+        tracks.push(read_track(data, *offset, end, disk_flags, track_n)?);
+    }
+
+    let cylinders = tracks
+        .chunks(n_sides as usize)
+        .map(|cylinder| {
+            let mut cylinder = cylinder.to_vec();
+            cylinder.resize(n_sides as usize, vec![]);
+            cylinder
+        })
+        .collect();
+
+    Ok(D86FImage { n_sides, cylinders })
+}
+
+// Read the bitcells of a track, from its index hole and twice over
+fn read_track(
+    data: &[u8],
+    offset: usize,
+    end: usize,
+    disk_flags: u16,
+    track_n: usize,
+) -> Result<Track, String> {
+    // Flags, extra bitcell count in bitcell mode, and index hole position
+    let header_len = match disk_flags & D86F_FLAG_BITCELL_MODE {
+        0 => 6,
+        _ => 10,
+    };
+    let header = match data.get(offset..offset + header_len) {
+        Some(header) if offset + header_len <= end => header,
+        _ => return Err(format!("Track {} truncated", track_n)),
+    };
+    let track_flags = u16::from_le_bytes([header[0], header[1]]);
+    let index = u32::from_le_bytes(header[header_len - 4..].try_into().unwrap()) as usize;
+
+    if track_flags & D86F_TRACK_ENCODING != 0 {
+        return Err(format!("Track {} is not FM encoded", track_n));
+    }
+
+    // Bitcells fill the track, followed by as many surface bits when present
+    let mut data_len = end - offset - header_len;
+    if disk_flags & D86F_FLAG_SURFACE != 0 {
+        data_len /= 2;
+    }
+    data_len -= data_len % 2;
+    let words = match data.get(offset + header_len..offset + header_len + data_len) {
+        Some(words) => words,
+        None => return Err(format!("Track {} truncated", track_n)),
+    };
+
+    // Most significant bit of each word first
+    let mut cells: Vec<bool> = words
+        .chunks(2)
+        .map(|word| match disk_flags & D86F_FLAG_REVERSE_ENDIAN {
+            0 => u16::from_le_bytes([word[0], word[1]]),
+            _ => u16::from_be_bytes([word[0], word[1]]),
+        })
+        .flat_map(|word| (0..16).map(move |bit_n| word & (0x8000 >> bit_n) != 0))
+        .collect();
+    if cells.is_empty() {
+        return Ok(vec![]);
+    }
+    cells.rotate_left(index % cells.len());
+    cells.extend_from_within(..);
+
+    Ok(cells
+        .chunks(8)
+        .map(|byte| {
+            byte.iter()
+                .enumerate()
+                .fold(0, |acc, (bit_n, cell)| acc | ((*cell as u8) << (7 - bit_n)))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::convert::Sector;
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::super::encode::encode_disk;
+    use super::super::extract::read_86f_sectors;
+    use super::*;
+
+    // 86F file of FM encoded tracks, with no surface data and the index hole at `index`
+    fn make_86f(cylinders: &[Cylinder], n_sides: u8, index: u32) -> Vec<u8> {
+        let mut data = b"86BF".to_vec();
+        data.extend_from_slice(&0x020Cu16.to_le_bytes());
+        let disk_flags = match n_sides {
+            1 => 0,
+            _ => D86F_FLAG_SIDES,
+        };
+        data.extend_from_slice(&disk_flags.to_le_bytes());
+
+        let tracks: Vec<&Track> = cylinders.iter().flatten().collect();
+        let mut offset = D86F_HEADER_LEN + 512 * 4;
+        let mut track_data: Vec<u8> = vec![];
+        for track in &tracks {
+            data.extend_from_slice(&(offset as u32).to_le_bytes());
+            track_data.extend_from_slice(&0u16.to_le_bytes());
+            track_data.extend_from_slice(&index.to_le_bytes());
+            for word in track.chunks(2) {
+                let word = u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]);
+                track_data.extend_from_slice(&word.to_le_bytes());
+            }
+            offset = D86F_HEADER_LEN + 512 * 4 + track_data.len();
+        }
+        data.resize(D86F_HEADER_LEN + 512 * 4, 0);
+        data.append(&mut track_data);
+
+        data
+    }
+
+    #[test]
+    fn test_read_86f_file() {
+        let cylinders = vec![vec![vec![0xF0, 0x0F, 0x12, 0x34]]];
+
+        let image = read_86f_file(&make_86f(&cylinders, 1, 0)).unwrap();
+        assert_eq!(image.n_sides, 1);
+        assert_eq!(
+            image.cylinders,
+            vec![vec![vec![0xF0, 0x0F, 0x12, 0x34, 0xF0, 0x0F, 0x12, 0x34]]]
+        );
+
+        // Tracks start at the index hole
+        let image = read_86f_file(&make_86f(&cylinders, 1, 8)).unwrap();
+        assert_eq!(
+            image.cylinders,
+            vec![vec![vec![0x0F, 0x12, 0x34, 0xF0, 0x0F, 0x12, 0x34, 0xF0]]]
+        );
+
+        // MFM tracks aren't read
+        let mut data = make_86f(&cylinders, 1, 0);
+        data[D86F_HEADER_LEN + 512 * 4] = 0x08;
+        assert!(read_86f_file(&data).is_err());
+        assert!(read_86f_file(b"HXCPICFE").is_err());
+    }
+
+    #[test]
+    fn test_read_86f_sectors() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8DSSD;
+        disk_parameters.n_cylinders = 2;
+
+        // Every sector filled with a different pattern
+        let data: Sector = (0..2 * 2 * 26 * 128).map(|i| (i / 128 + i) as u8).collect();
+        let sectors: Vec<Sector> = data.chunks(128).map(|sector| sector.to_vec()).collect();
+        let cylinders = encode_disk(&sectors, &disk_parameters, false).unwrap();
+
+        let image = read_86f_sectors(&make_86f(&cylinders, 2, 1000), false).unwrap();
+        assert_eq!(image.to_data(), data);
+    }
+}
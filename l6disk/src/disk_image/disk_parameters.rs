@@ -1,11 +1,14 @@
-use crate::args;
+use std::path::Path;
 
-#[derive(Debug)]
+use super::convert::Sector;
+use crate::file;
+
+#[derive(Debug, Clone)]
 pub enum DiskTrackFormat {
     IBM3470,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DiskParameters {
     pub track_format: DiskTrackFormat,
     pub n_sides: u16,
@@ -15,60 +18,313 @@ pub struct DiskParameters {
     pub cell_rate: u16, // In kbps
     pub rpm: u16,
     pub sector_interleave: u16,
+    pub track_skew: u16,                 // Sectors each track is rotated by
+    pub first_sector_n: u8,              // Number of the first logical sector
+    pub sector_numbers: Option<Vec<u8>>, // Number of each logical sector, overrides first_sector_n
+    pub zones: Vec<GeometryZone>,        // Cylinders with a different geometry
+    pub bad_sectors: Vec<BadSector>,     // Sectors written with deliberate errors
+    pub hard_sectors: u16,               // Number of sector holes, 0 if soft sectored
+    pub gaps: TrackGaps,
+    pub fill_pattern: Vec<u8>, // Repeated to fill sectors missing from the input, zeroes if empty
+}
+
+// Gap lengths (in bytes) and gap byte value of the track format
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackGaps {
+    pub gap_byte: u8,
+    pub gap1_len: u16, // Before the index address mark
+    pub gap2_len: u16, // After the index address mark
+    pub gap3_len: u16, // Between sector ID and data fields
+    pub gap4_len: u16, // Between sectors
+}
+
+impl TrackGaps {
+    pub const IBM3470: TrackGaps = TrackGaps {
+        gap_byte: 0xFF,
+        gap1_len: 40,
+        gap2_len: 26,
+        gap3_len: 11,
+        gap4_len: 27,
+    };
+
+    // Set the lengths of GAP1 to GAP4
+    pub fn set_lengths(&mut self, lengths: [u16; 4]) {
+        [self.gap1_len, self.gap2_len, self.gap3_len, self.gap4_len] = lengths;
+    }
+}
+
+// Geometry of a range of cylinders, overriding the disk-wide one
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeometryZone {
+    pub first_cylinder: u16,
+    pub last_cylinder: u16,
+    pub sectors_per_track: u16,
+    pub bytes_per_sector: u16,
+}
+
+// Sector written with a deliberate error
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadSector {
+    pub cyl_n: u16,
+    pub side_n: u16,
+    pub sector_n: u8, // As written in the ID field
+    pub defect: SectorDefect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SectorDefect {
+    NoData,  // Data field omitted
+    DataCrc, // Wrong data field CRC
+    IdCrc,   // Wrong ID field CRC
+}
+
+// Sector layout options read from a layout file
+#[derive(Debug, Default, PartialEq)]
+pub struct SectorLayout {
+    pub interleave: Option<u16>,
+    pub skew: Option<u16>,
+    pub first_sector: Option<u8>,
+    pub sector_numbers: Option<Vec<u8>>,
+    pub zones: Vec<GeometryZone>,
+    pub gap_byte: Option<u8>,
+    pub gap_lengths: Option<[u16; 4]>,
 }
 
 impl DiskParameters {
-    // Construct DiskParameters based on CLI args
-    pub fn from_args(args: &args::Args) -> DiskParameters {
-        // Get default parameters for specified format
-        let mut disk_pars = match args.disk_format {
-            DiskFormat::LEVEL6 => DiskFormatDefaults::LEVEL6,
-            DiskFormat::IBM8DSSD => DiskFormatDefaults::IBM8DSSD,
-        };
+    // Parameters of one cylinder, with the geometry of the first zone containing it
+    pub fn cylinder_parameters(&self, cyl_n: u16) -> DiskParameters {
+        let mut cyl_pars = self.clone();
+        cyl_pars.zones = vec![];
 
-        // Number of cylinders
-        if let Some(cylinders) = args.cylinders {
-            disk_pars.n_cylinders = cylinders;
+        if let Some(zone) = self
+            .zones
+            .iter()
+            .find(|zone| (zone.first_cylinder..=zone.last_cylinder).contains(&cyl_n))
+        {
+            cyl_pars.sectors_per_track = zone.sectors_per_track;
+            cyl_pars.bytes_per_sector = zone.bytes_per_sector;
         }
 
-        // Number of heads
-        if let Some(heads) = args.heads {
-            disk_pars.n_sides = heads;
+        cyl_pars
+    }
+
+    // Deliberate error to write in a sector, if any
+    pub fn sector_defect(&self, cyl_n: u16, side_n: u16, sector_n: u8) -> Option<SectorDefect> {
+        self.bad_sectors
+            .iter()
+            .find(|bad| (bad.cyl_n, bad.side_n, bad.sector_n) == (cyl_n, side_n, sector_n))
+            .map(|bad| bad.defect)
+    }
+
+    // Sector of the given size filled with the fill pattern
+    pub fn fill_sector(&self, size: usize) -> Sector {
+        match self.fill_pattern.is_empty() {
+            true => vec![0; size],
+            false => self
+                .fill_pattern
+                .iter()
+                .copied()
+                .cycle()
+                .take(size)
+                .collect(),
         }
+    }
 
-        // Number of sectors
-        if let Some(sectors) = args.sectors {
-            disk_pars.sectors_per_track = sectors;
+    // Size of every sector on one side of the disk, in logical order
+    pub fn side_sector_sizes(&self) -> Vec<u16> {
+        let mut sizes: Vec<u16> = vec![];
+        for cyl_n in 0..self.n_cylinders {
+            let cyl_pars = self.cylinder_parameters(cyl_n);
+            sizes.extend(std::iter::repeat_n(
+                cyl_pars.bytes_per_sector,
+                cyl_pars.sectors_per_track as usize,
+            ));
         }
 
-        // Sector size
-        if let Some(sector_size) = args.sector_size {
-            disk_pars.bytes_per_sector = sector_size;
+        sizes
+    }
+
+    // Size of every sector on the disk, in logical order
+    pub fn sector_sizes(&self) -> Vec<u16> {
+        let mut sizes: Vec<u16> = vec![];
+        for cyl_n in 0..self.n_cylinders {
+            let cyl_pars = self.cylinder_parameters(cyl_n);
+            let n_sectors = cyl_pars.sectors_per_track * self.n_sides;
+            sizes.extend(std::iter::repeat_n(
+                cyl_pars.bytes_per_sector,
+                n_sectors as usize,
+            ));
         }
 
-        // Cell rate
-        if let Some(cell_rate) = args.cell_rate {
-            disk_pars.cell_rate = cell_rate;
+        sizes
+    }
+}
+
+// Read a sector layout file, with one "key = value" option per line. Comments start with '#'
+pub fn read_layout_file(file_path: &Path) -> Result<SectorLayout, String> {
+    let data = match file::read_file(&file_path.to_path_buf()) {
+        Ok(data) => data,
+        Err(err) => {
+            return Err(format!(
+                "Unable to open \"{}\": {}",
+                file_path.display(),
+                err
+            ))
         }
+    };
 
-        // Spindle RPM
-        if let Some(spindle_rpm) = args.spindle_rpm {
-            disk_pars.rpm = spindle_rpm;
+    match parse_layout(&String::from_utf8_lossy(&data)) {
+        Ok(layout) => Ok(layout),
+        Err(msg) => Err(format!("{}: {}", file_path.display(), msg)),
+    }
+}
+
+fn parse_layout(contents: &str) -> Result<SectorLayout, String> {
+    let mut layout = SectorLayout::default();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
         }
 
-        // Interleave
-        if let Some(interleave) = args.interleave {
-            disk_pars.sector_interleave = interleave;
+        let invalid = || format!("line {}: invalid option \"{}\"", i + 1, line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(invalid()),
+        };
+
+        match key {
+            "interleave" => layout.interleave = Some(value.parse().map_err(|_| invalid())?),
+            "skew" => layout.skew = Some(value.parse().map_err(|_| invalid())?),
+            "first-sector" => layout.first_sector = Some(value.parse().map_err(|_| invalid())?),
+            "sector-numbers" => {
+                layout.sector_numbers = Some(parse_sector_numbers(value).map_err(|_| invalid())?)
+            }
+            "zone" => layout.zones.push(parse_zone(value).map_err(|_| invalid())?),
+            "gap-byte" => layout.gap_byte = Some(parse_byte(value).map_err(|_| invalid())?),
+            "gaps" => layout.gap_lengths = Some(parse_gap_lengths(value).map_err(|_| invalid())?),
+            _ => return Err(invalid()),
         }
+    }
+
+    Ok(layout)
+}
+
+// Read a bad sector map file, with one "CYLINDER SIDE SECTOR DEFECT" entry per line.
+// DEFECT is one of no-data, data-crc and id-crc. Comments start with '#'
+pub fn read_bad_sector_file(file_path: &Path) -> Result<Vec<BadSector>, String> {
+    let data = match file::read_file(&file_path.to_path_buf()) {
+        Ok(data) => data,
+        Err(err) => {
+            return Err(format!(
+                "Unable to open \"{}\": {}",
+                file_path.display(),
+                err
+            ))
+        }
+    };
 
-        disk_pars
+    match parse_bad_sectors(&String::from_utf8_lossy(&data)) {
+        Ok(bad_sectors) => Ok(bad_sectors),
+        Err(msg) => Err(format!("{}: {}", file_path.display(), msg)),
     }
 }
 
-#[derive(Debug, Clone, clap::ValueEnum)]
-pub enum DiskFormat {
-    LEVEL6,
-    IBM8DSSD,
+fn parse_bad_sectors(contents: &str) -> Result<Vec<BadSector>, String> {
+    let mut bad_sectors: Vec<BadSector> = vec![];
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let invalid = || format!("line {}: invalid bad sector \"{}\"", i + 1, line);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 4 {
+            return Err(invalid());
+        }
+
+        bad_sectors.push(BadSector {
+            cyl_n: fields[0].parse().map_err(|_| invalid())?,
+            side_n: fields[1].parse().map_err(|_| invalid())?,
+            sector_n: fields[2].parse().map_err(|_| invalid())?,
+            defect: match fields[3] {
+                "no-data" => SectorDefect::NoData,
+                "data-crc" => SectorDefect::DataCrc,
+                "id-crc" => SectorDefect::IdCrc,
+                _ => return Err(invalid()),
+            },
+        });
+    }
+
+    Ok(bad_sectors)
+}
+
+// Parse a comma separated list of sector numbers
+pub fn parse_sector_numbers(value: &str) -> Result<Vec<u8>, String> {
+    value
+        .split(',')
+        .map(|number| {
+            number
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid sector number: \"{}\"", number.trim()))
+        })
+        .collect()
+}
+
+// Parse a byte value, in decimal or in hexadecimal with a 0x prefix
+pub fn parse_byte(value: &str) -> Result<u8, String> {
+    let value = value.trim();
+    let byte = match value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    byte.map_err(|_| format!("invalid byte: \"{}\"", value))
+}
+
+// Parse a comma separated list of bytes
+pub fn parse_bytes(value: &str) -> Result<Vec<u8>, String> {
+    value.split(',').map(parse_byte).collect()
+}
+
+// Parse the lengths of GAP1 to GAP4, comma separated (e.g. "40,26,11,27")
+pub fn parse_gap_lengths(value: &str) -> Result<[u16; 4], String> {
+    let invalid = || format!("invalid gap lengths: \"{}\"", value);
+    let lengths = value
+        .split(',')
+        .map(|length| length.trim().parse::<u16>().map_err(|_| invalid()))
+        .collect::<Result<Vec<u16>, String>>()?;
+
+    lengths.try_into().map_err(|_| invalid())
+}
+
+// Parse a geometry zone: "CYLINDERS:SECTORSxSIZE", where CYLINDERS is a cylinder or a
+// range of cylinders (e.g. "0:26x128" or "1-76:8x255")
+pub fn parse_zone(value: &str) -> Result<GeometryZone, String> {
+    let invalid = || format!("invalid geometry zone: \"{}\"", value);
+    let number = |n: &str| n.trim().parse::<u16>().map_err(|_| invalid());
+
+    let (cylinders, geometry) = value.split_once(':').ok_or_else(invalid)?;
+    let (first_cylinder, last_cylinder) = match cylinders.split_once('-') {
+        Some((first, last)) => (number(first)?, number(last)?),
+        None => (number(cylinders)?, number(cylinders)?),
+    };
+    let (sectors, size) = geometry.split_once('x').ok_or_else(invalid)?;
+
+    if first_cylinder > last_cylinder {
+        return Err(invalid());
+    }
+
+    Ok(GeometryZone {
+        first_cylinder,
+        last_cylinder,
+        sectors_per_track: number(sectors)?,
+        bytes_per_sector: number(size)?,
+    })
 }
 
 #[non_exhaustive]
@@ -83,6 +339,33 @@ impl DiskFormatDefaults {
         sectors_per_track: 26,
         bytes_per_sector: 128,
         sector_interleave: 1,
+        track_skew: 0,
+        first_sector_n: 1,
+        sector_numbers: None,
+        zones: vec![],
+        bad_sectors: vec![],
+        hard_sectors: 0,
+        gaps: TrackGaps::IBM3470,
+        fill_pattern: vec![],
+        cell_rate: 500,
+        rpm: 360,
+    };
+    // IBM 8 inch single sided - single density format
+    pub const IBM8SSSD: DiskParameters = DiskParameters {
+        track_format: DiskTrackFormat::IBM3470,
+        n_sides: 1,
+        n_cylinders: 77,
+        sectors_per_track: 26,
+        bytes_per_sector: 128,
+        sector_interleave: 1,
+        track_skew: 0,
+        first_sector_n: 1,
+        sector_numbers: None,
+        zones: vec![],
+        bad_sectors: vec![],
+        hard_sectors: 0,
+        gaps: TrackGaps::IBM3470,
+        fill_pattern: vec![],
         cell_rate: 500,
         rpm: 360,
     };
@@ -94,7 +377,128 @@ impl DiskFormatDefaults {
         sectors_per_track: 26,
         bytes_per_sector: 128,
         sector_interleave: 1,
+        track_skew: 0,
+        first_sector_n: 1,
+        sector_numbers: None,
+        zones: vec![],
+        bad_sectors: vec![],
+        hard_sectors: 0,
+        gaps: TrackGaps::IBM3470,
+        fill_pattern: vec![],
         cell_rate: 500,
         rpm: 360,
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_layout() {
+        let layout = parse_layout(
+            "# System diskette\ninterleave = 3\n\nskew=6 # Track to track\nsector-numbers = 1, 3, 2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            layout,
+            SectorLayout {
+                interleave: Some(3),
+                skew: Some(6),
+                first_sector: None,
+                sector_numbers: Some(vec![1, 3, 2]),
+                zones: vec![],
+                gap_byte: None,
+                gap_lengths: None,
+            }
+        );
+
+        let layout = parse_layout(
+            "gap-byte = 0x4E
+gaps = 16, 11, 11, 27
+",
+        )
+        .unwrap();
+        assert_eq!(layout.gap_byte, Some(0x4E));
+        assert_eq!(layout.gap_lengths, Some([16, 11, 11, 27]));
+        assert!(parse_layout("gaps = 16, 11, 11\n").is_err());
+
+        let layout = parse_layout("zone = 0:26x128\nzone = 1-76:8x255\n").unwrap();
+        assert_eq!(
+            layout.zones,
+            vec![
+                GeometryZone {
+                    first_cylinder: 0,
+                    last_cylinder: 0,
+                    sectors_per_track: 26,
+                    bytes_per_sector: 128,
+                },
+                GeometryZone {
+                    first_cylinder: 1,
+                    last_cylinder: 76,
+                    sectors_per_track: 8,
+                    bytes_per_sector: 255,
+                },
+            ]
+        );
+
+        for contents in [
+            "interleave 3",
+            "skew = -1",
+            "sector-numbers = 1,,2",
+            "side = 1",
+        ] {
+            assert!(parse_layout(contents).is_err(), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn test_parse_bad_sectors() {
+        let bad_sectors =
+            parse_bad_sectors("# Protection\n0 0 5 no-data\n\n76 1 26 id-crc # Last\n").unwrap();
+        assert_eq!(
+            bad_sectors,
+            vec![
+                BadSector {
+                    cyl_n: 0,
+                    side_n: 0,
+                    sector_n: 5,
+                    defect: SectorDefect::NoData,
+                },
+                BadSector {
+                    cyl_n: 76,
+                    side_n: 1,
+                    sector_n: 26,
+                    defect: SectorDefect::IdCrc,
+                },
+            ]
+        );
+
+        for contents in ["0 0 5", "0 0 5 bad", "0 0 256 no-data", "0 0 5 no-data 1"] {
+            assert!(parse_bad_sectors(contents).is_err(), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn test_cylinder_parameters() {
+        let mut disk_parameters = DiskFormatDefaults::LEVEL6;
+        disk_parameters.n_cylinders = 4;
+        disk_parameters.zones = vec![
+            parse_zone("1-2:8x255").unwrap(),
+            parse_zone("2:4x64").unwrap(),
+        ];
+
+        let geometry = |cyl_n| {
+            let cyl_pars = disk_parameters.cylinder_parameters(cyl_n);
+            (cyl_pars.sectors_per_track, cyl_pars.bytes_per_sector)
+        };
+        assert_eq!(geometry(0), (26, 128));
+        assert_eq!(geometry(1), (8, 255));
+        assert_eq!(geometry(2), (8, 255));
+        assert_eq!(geometry(3), (26, 128));
+
+        let sizes = disk_parameters.sector_sizes();
+        assert_eq!(sizes.len(), 26 + 8 + 8 + 26);
+        assert_eq!(sizes[26..34], [255; 8]);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt::Write;
+
+// In-module imports
+use super::errors::{ConvertError, ConvertErrorType};
+use super::hfe::read_hfe_file;
+use super::ibm3470::{decode_track, DecodedSector};
+
+const DUMP_ROW_LEN: usize = 16;
+
+// Tracks to dump, all if not given
+#[derive(Debug, Default)]
+pub struct DumpOpts {
+    pub cylinder: Option<u16>,
+    pub side: Option<u16>,
+}
+
+// Describe the tracks of an HFE image: ID fields, gap lengths and a hex view of the data of
+// each sector. Data rows are annotated with their offset in the logical sector data (as
+// written by extract) and in the sector
+pub fn dump_hfe(hfe_img: &[u8], opts: DumpOpts) -> Result<String, ConvertError> {
+    let image = match read_hfe_file(hfe_img) {
+        Ok(image) => image,
+        Err(msg) => return Err(ConvertError::new(ConvertErrorType::ImageReading(msg))),
+    };
+
+    let mut out = String::new();
+    let mut logical_offset: usize = 0;
+    for (cyl_n, cylinder) in image.cylinders.iter().enumerate() {
+        for (side_n, track) in cylinder.iter().enumerate() {
+            let sectors = decode_track(track);
+
+            // Logical offset of each sector number, in sector number order
+            let mut logical: Vec<(u8, usize)> = vec![];
+            let mut numbers: Vec<&DecodedSector> = sectors
+                .iter()
+                .filter(|sector| sector.id_crc_ok && sector.data.is_some())
+                .collect();
+            numbers.sort_by_key(|sector| sector.sector_n);
+            numbers.dedup_by_key(|sector| sector.sector_n);
+            for sector in numbers {
+                logical.push((sector.sector_n, logical_offset));
+                logical_offset += sector.data.as_ref().map_or(0, |data| data.len());
+            }
+
+            if opts.cylinder.is_some_and(|n| n as usize != cyl_n)
+                || opts.side.is_some_and(|n| n as usize != side_n)
+            {
+                continue;
+            }
+
+            dump_track(&mut out, cyl_n, side_n, track.len() * 8, &sectors, &logical);
+        }
+    }
+
+    Ok(out)
+}
+
+fn dump_track(
+    out: &mut String,
+    cyl_n: usize,
+    side_n: usize,
+    n_cells: usize,
+    sectors: &[DecodedSector],
+    logical: &[(u8, usize)],
+) {
+    let ok = |crc_ok: bool| match crc_ok {
+        true => "CRC ok",
+        false => "CRC error",
+    };
+
+    writeln!(
+        out,
+        "cylinder {} side {}: {} sectors, {} bytes",
+        cyl_n,
+        side_n,
+        sectors.len(),
+        n_cells / 16
+    )
+    .unwrap();
+
+    // Cells are counted from the start of the track, gaps include sync fields
+    let mut pos: usize = 0;
+    for sector in sectors {
+        let id_start = sector.id_cell - 16;
+        writeln!(out, "  gap: {} bytes", (id_start - pos) / 16).unwrap();
+        writeln!(
+            out,
+            "  sector {} at byte {}: ID cylinder {} side {} sector {} size {}, {}",
+            sector.sector_n,
+            id_start / 16,
+            sector.track_n,
+            sector.side_n,
+            sector.sector_n,
+            sector.sector_len,
+            ok(sector.id_crc_ok)
+        )
+        .unwrap();
+        pos = sector.id_cell + 6 * 16;
+
+        let (data, data_cell) = match (&sector.data, sector.data_cell) {
+            (Some(data), Some(data_cell)) => (data, data_cell),
+            _ => {
+                writeln!(out, "    no data field").unwrap();
+                continue;
+            }
+        };
+        writeln!(out, "    gap: {} bytes", (data_cell - 16 - pos) / 16).unwrap();
+        writeln!(
+            out,
+            "    data: {} bytes, {}",
+            data.len(),
+            ok(sector.data_crc_ok)
+        )
+        .unwrap();
+        pos = data_cell + (data.len() + 2) * 16;
+
+        // Hex view, rows start with their logical offset when the sector is extracted
+        let logical_offset = logical
+            .iter()
+            .find(|(sector_n, _)| *sector_n == sector.sector_n)
+            .map(|(_, offset)| *offset);
+        for (row_n, row) in data.chunks(DUMP_ROW_LEN).enumerate() {
+            let offset = row_n * DUMP_ROW_LEN;
+            match logical_offset {
+                Some(logical_offset) => write!(out, "    {:06X} ", logical_offset + offset),
+                None => write!(out, "    {:6} ", ""),
+            }
+            .unwrap();
+            write!(out, "+{:03X} ", offset).unwrap();
+            for byte in row {
+                write!(out, " {:02X}", byte).unwrap();
+            }
+            let ascii: String = row
+                .iter()
+                .map(|byte| match byte {
+                    0x20..=0x7E => *byte as char,
+                    _ => '.',
+                })
+                .collect();
+            let padding = (DUMP_ROW_LEN - row.len()) * 3;
+            writeln!(out, "{:padding$}  |{}|", "", ascii, padding = padding).unwrap();
+        }
+    }
+    writeln!(out, "  gap: {} bytes", n_cells.saturating_sub(pos) / 16).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::convert::{convert_image, ConvertOpts, InputFormat, OutputFormat, SideOrder};
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::*;
+
+    #[test]
+    fn test_dump_hfe() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8DSSD;
+        disk_parameters.n_cylinders = 2;
+        let data: Vec<u8> = (0..2 * 2 * 26 * 128).map(|i| (i / 128) as u8).collect();
+
+        let hfe_img = convert_image(
+            data,
+            ConvertOpts {
+                ignore_errors: false,
+                disk_parameters,
+                input_format: InputFormat::Raw,
+                output_format: OutputFormat::Hfe,
+                side_order: SideOrder::Cylinder,
+                side1_data: None,
+                boot_loader: None,
+                verbose: false,
+            },
+        )
+        .unwrap()
+        .data;
+
+        let dump = dump_hfe(
+            &hfe_img,
+            DumpOpts {
+                cylinder: Some(1),
+                side: Some(1),
+            },
+        )
+        .unwrap();
+        let lines: Vec<&str> = dump.lines().collect();
+
+        assert_eq!(lines[0], "cylinder 1 side 1: 26 sectors, 5158 bytes");
+        assert_eq!(lines[1], "  gap: 79 bytes");
+        assert_eq!(
+            lines[2],
+            "  sector 1 at byte 79: ID cylinder 1 side 1 sector 1 size 128, CRC ok"
+        );
+        assert_eq!(lines[3], "    gap: 17 bytes");
+        assert_eq!(lines[4], "    data: 128 bytes, CRC ok");
+        assert!(lines[5].starts_with("    002700 +000  4E 4E 4E"));
+        assert!(lines[5].ends_with("|NNNNNNNNNNNNNNNN|"));
+        assert_eq!(lines[13], "  gap: 33 bytes");
+        assert_eq!(dump.matches("cylinder ").count(), 1 + 26);
+    }
+}
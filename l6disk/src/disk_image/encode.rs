@@ -1,3 +1,4 @@
+use std::io::{self, IsTerminal, Write};
 use std::vec;
 
 // In-module imports
@@ -5,24 +6,57 @@ use super::convert::{Cylinder, Sector, Track};
 pub use super::disk_parameters::{DiskParameters, DiskTrackFormat};
 use super::ibm3470;
 
-// Encode all cylinders in the disk
+// Encode all cylinders in the disk. Statistics of each track are printed in verbose mode,
+// otherwise progress is shown when stderr is a terminal
 pub fn encode_disk(
-    sectors: &Vec<Sector>,
+    sectors: &[Sector],
     disk_parameters: &DiskParameters,
+    verbose: bool,
 ) -> Result<Vec<Cylinder>, String> {
     let mut cylinders: Vec<Cylinder> = vec![];
-
-    // println!("Encoding disk: n_sectors = {}", sectors.len());
+    let show_progress = !verbose && io::stderr().is_terminal();
 
     // Encode each cylinder of the disk
+    let mut start_sector: usize = 0;
     for cyl_n in 0..disk_parameters.n_cylinders {
+        // Geometry may be different on each cylinder
+        let cyl_pars = disk_parameters.cylinder_parameters(cyl_n);
+        let end_sector = start_sector + (cyl_pars.sectors_per_track * cyl_pars.n_sides) as usize;
+
         // Encode cylinder
-        let cylinder = match encode_cylinder(sectors, disk_parameters, cyl_n) {
+        let cylinder = match encode_cylinder(&sectors[start_sector..end_sector], &cyl_pars, cyl_n) {
             Ok(cylinder) => cylinder,
             Err(msg) => return Err(msg),
         };
 
+        // Report progress
+        if verbose {
+            for (side_n, track) in cylinder.iter().enumerate() {
+                println!(
+                    "cylinder {:>2} side {}: {} sectors of {} bytes, {} bitcells",
+                    cyl_n,
+                    side_n,
+                    cyl_pars.sectors_per_track,
+                    cyl_pars.bytes_per_sector,
+                    track.len() * 8
+                );
+            }
+        } else if show_progress {
+            eprint!(
+                "\rEncoding cylinder {}/{}",
+                cyl_n + 1,
+                disk_parameters.n_cylinders
+            );
+            let _ = io::stderr().flush();
+        }
+
         cylinders.push(cylinder);
+        start_sector = end_sector;
+    }
+
+    // Clear progress line
+    if show_progress {
+        eprint!("\r\x1b[K");
     }
 
     Ok(cylinders)
@@ -30,17 +64,16 @@ pub fn encode_disk(
 
 // Encode one cylinder in the disk
 fn encode_cylinder(
-    sectors: &Vec<Sector>,
+    sectors: &[Sector],
     disk_parameters: &DiskParameters,
     cyl_n: u16,
 ) -> Result<Cylinder, String> {
     let mut cylinder: Cylinder = vec![];
-    // println!("Encoding cylinder: {}", cyl_n);
 
     // Encode each side of this cylinder
     for side_n in 0..disk_parameters.n_sides {
         // Encode this side
-        let track = match encode_track(&sectors, &disk_parameters, cyl_n, side_n) {
+        let track = match encode_track(sectors, disk_parameters, cyl_n, side_n) {
             Ok(track) => track,
             Err(msg) => return Err(msg),
         };
@@ -54,32 +87,60 @@ fn encode_cylinder(
 
 // Encode one track
 fn encode_track(
-    sectors: &Vec<Sector>,
+    sectors: &[Sector],
     disk_parameters: &DiskParameters,
     cyl_n: u16,
     side_n: u16,
 ) -> Result<Track, String> {
-    // Compute start  and end sector of this track
-    let start_sector: usize =
-        (disk_parameters.sectors_per_track * (side_n + disk_parameters.n_sides * cyl_n)) as usize;
+    // Compute start and end sector of this track within the cylinder
+    let start_sector: usize = (disk_parameters.sectors_per_track * side_n) as usize;
     let end_sector: usize = start_sector + disk_parameters.sectors_per_track as usize;
 
-    // println!(
-    //     "Encoding track cyl_n={}, side_n={}: start_sector={}",
-    //     cyl_n, side_n, start_sector
-    // );
-
     // Encode track to appropriate format
     match disk_parameters.track_format {
         DiskTrackFormat::IBM3470 => ibm3470::encode_track(
             &sectors[start_sector..end_sector],
-            &disk_parameters,
+            disk_parameters,
             cyl_n,
             side_n,
         ),
     }
 }
 
+// Logical sector index and sector number of each sector of a track, in the order they are
+// written. Skew rotates the sectors of each track relative to the previous one
+pub fn physical_sector_order(
+    disk_parameters: &DiskParameters,
+    cyl_n: u16,
+    side_n: u16,
+) -> Vec<(usize, u8)> {
+    let interleave_map = calc_interleave_map(
+        disk_parameters.sectors_per_track,
+        disk_parameters.sector_interleave,
+    );
+
+    let n_sectors = disk_parameters.sectors_per_track as usize;
+    if n_sectors == 0 {
+        return vec![];
+    }
+    let track_index = (cyl_n * disk_parameters.n_sides + side_n) as usize;
+    let rotation = (disk_parameters.track_skew as usize * track_index) % n_sectors;
+
+    (0..n_sectors)
+        .map(|phys_sec_n| {
+            let logical_sec_n = interleave_map[(phys_sec_n + n_sectors - rotation) % n_sectors];
+
+            // Sector number written in the ID field
+            let sector_n = match &disk_parameters.sector_numbers {
+                Some(sector_numbers) => sector_numbers[logical_sec_n as usize],
+                None => disk_parameters.first_sector_n + logical_sec_n as u8,
+            };
+
+            (logical_sec_n as usize, sector_n)
+        })
+        .collect()
+}
+
 pub fn calc_interleave_map(n_sectors: u16, interleave: u16) -> Vec<u16> {
     let mut res: Vec<u16> = vec![0; n_sectors as usize];
     let mut used: Vec<bool> = vec![false; n_sectors as usize];
@@ -7,6 +7,13 @@ pub enum ConvertErrorType {
     SectorNumber(usize, usize),
     DiskEncoding(String),
     RawImageCreation(String),
+    ImageReading(String),
+    SectorMissing(u16, u16, u16),
+    SectorCrc(u16, u16, u16),
+    BootLoader(String),
+    SideNumber(u16),
+    PatchPastEnd,
+    ImageTooLarge(usize, usize),
 }
 
 #[derive(Debug)]
@@ -32,6 +39,25 @@ impl fmt::Display for ConvertError {
             ),
             ConvertErrorType::DiskEncoding(msg) => format!("Disk encoding error: {}", msg),
             ConvertErrorType::RawImageCreation(msg) => format!("Raw image creation error: {}", msg),
+            ConvertErrorType::ImageReading(msg) => format!("Image reading error: {}", msg),
+            ConvertErrorType::SectorMissing(cyl_n, side_n, sector_n) => format!(
+                "Sector not found (cylinder {}, side {}, sector {})",
+                cyl_n, side_n, sector_n
+            ),
+            ConvertErrorType::SectorCrc(cyl_n, side_n, sector_n) => format!(
+                "CRC error (cylinder {}, side {}, sector {})",
+                cyl_n, side_n, sector_n
+            ),
+            ConvertErrorType::BootLoader(msg) => format!("Boot loader error: {}", msg),
+            ConvertErrorType::SideNumber(n_sides) => format!(
+                "Separate side 1 image needs a double sided disk (disk has {} sides)",
+                n_sides
+            ),
+            ConvertErrorType::PatchPastEnd => "Patch data past the end of the disk".to_string(),
+            ConvertErrorType::ImageTooLarge(size, capacity) => format!(
+                "Image is {} bytes, larger than the {} bytes the disk holds",
+                size, capacity
+            ),
         };
 
         write!(f, "{}", string)
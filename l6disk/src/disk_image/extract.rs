@@ -0,0 +1,254 @@
+// In-module imports
+use super::convert::{ConvertResult, Cylinder};
+use super::d86f::read_86f_file;
+use super::errors::{ConvertError, ConvertErrorType};
+use super::hfe::read_hfe_file;
+use super::ibm3470::{decode_track, DecodedSector};
+use super::scp::read_scp_file;
+use super::sector_image::SectorImage;
+
+#[derive(Debug)]
+// Extraction options
+pub struct ExtractOpts {
+    pub ignore_errors: bool,
+}
+
+// Extract logical sector contents from an HFE floppy image
+pub fn extract_from_hfe(hfe_img: &[u8], opts: ExtractOpts) -> ConvertResult {
+    Ok(read_hfe_sectors(hfe_img, opts.ignore_errors)?.to_data())
+}
+
+// Read the sectors of an HFE floppy image. Tracks with the same sector size are expected to
+// hold the same sectors
+pub fn read_hfe_sectors(hfe_img: &[u8], ignore_errors: bool) -> Result<SectorImage, ConvertError> {
+    let image = match read_hfe_file(hfe_img) {
+        Ok(image) => image,
+        Err(msg) => return Err(ConvertError::new(ConvertErrorType::ImageReading(msg))),
+    };
+
+    decode_sectors(&image.cylinders, image.n_sides, ignore_errors)
+}
+
+// Read the sectors of an SCP flux image, as for HFE images
+pub fn read_scp_sectors(scp_img: &[u8], ignore_errors: bool) -> Result<SectorImage, ConvertError> {
+    let image = match read_scp_file(scp_img) {
+        Ok(image) => image,
+        Err(msg) => return Err(ConvertError::new(ConvertErrorType::ImageReading(msg))),
+    };
+
+    decode_sectors(&image.cylinders, image.n_sides, ignore_errors)
+}
+
+// Read the sectors of an 86Box 86F bitcell image, as for HFE images
+pub fn read_86f_sectors(d86f_img: &[u8], ignore_errors: bool) -> Result<SectorImage, ConvertError> {
+    let image = match read_86f_file(d86f_img) {
+        Ok(image) => image,
+        Err(msg) => return Err(ConvertError::new(ConvertErrorType::ImageReading(msg))),
+    };
+
+    decode_sectors(&image.cylinders, image.n_sides, ignore_errors)
+}
+
+// Decode the sectors of FM encoded tracks
+fn decode_sectors(
+    cylinders: &[Cylinder],
+    n_sides: u8,
+    ignore_errors: bool,
+) -> Result<SectorImage, ConvertError> {
+    // Decode all tracks
+    let tracks: Vec<Vec<DecodedSector>> = cylinders
+        .iter()
+        .flat_map(|cylinder| cylinder.iter().map(|track| decode_track(track)))
+        .collect();
+
+    // Sector size of each track, taken from the sectors found
+    let first_sector_size = tracks
+        .iter()
+        .flatten()
+        .find_map(|sector| sector.data.as_ref().map(|data| data.len()))
+        .unwrap_or(0);
+    let sector_sizes: Vec<usize> = tracks
+        .iter()
+        .map(|track| {
+            track
+                .iter()
+                .find_map(|sector| sector.data.as_ref().map(|data| data.len()))
+                .unwrap_or(first_sector_size)
+        })
+        .collect();
+
+    // Collect sectors of each track
+    let mut sector_image = SectorImage::new(cylinders.len(), n_sides as u16);
+    for (track_i, track) in tracks.iter().enumerate() {
+        let cyl_n = (track_i / n_sides as usize) as u16;
+        let side_n = (track_i % n_sides as usize) as u16;
+
+        // Tracks with the same sector size share the geometry of the sectors found on them,
+        // in sector number order
+        let sector_size = sector_sizes[track_i];
+        let mut sector_numbers: Vec<u8> = tracks
+            .iter()
+            .zip(&sector_sizes)
+            .filter(|(_, size)| **size == sector_size)
+            .flat_map(|(track, _)| track.iter())
+            .filter(|sector| sector.id_crc_ok)
+            .map(|sector| sector.sector_n)
+            .collect();
+        sector_numbers.sort();
+        sector_numbers.dedup();
+
+        for &sector_n in &sector_numbers {
+            let sector = find_sector(track, sector_n);
+            let data = match sector.and_then(|sector| sector.data.clone()) {
+                Some(data) => data,
+                None if ignore_errors => vec![0; sector_size],
+                None => {
+                    return Err(ConvertError::new(ConvertErrorType::SectorMissing(
+                        cyl_n,
+                        side_n,
+                        sector_n as u16,
+                    )))
+                }
+            };
+
+            // Keep data with a wrong CRC only when ignoring errors
+            if !sector.is_none_or(|sector| sector.data_crc_ok) && !ignore_errors {
+                return Err(ConvertError::new(ConvertErrorType::SectorCrc(
+                    cyl_n,
+                    side_n,
+                    sector_n as u16,
+                )));
+            }
+
+            sector_image.cylinders[cyl_n as usize][side_n as usize].push((sector_n, data));
+        }
+    }
+
+    Ok(sector_image)
+}
+
+// Find a sector by number, preferring copies which were read correctly
+fn find_sector(track: &[DecodedSector], sector_n: u8) -> Option<&DecodedSector> {
+    let mut candidates = track
+        .iter()
+        .filter(|sector| sector.id_crc_ok && sector.sector_n == sector_n);
+
+    candidates
+        .clone()
+        .find(|sector| sector.data.is_some() && sector.data_crc_ok)
+        .or_else(|| candidates.find(|sector| sector.data.is_some()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::convert::{
+        convert_image, ConvertOpts, InputFormat, OutputFormat, Sector, SideOrder,
+    };
+    use super::super::disk_parameters::{DiskFormatDefaults, GeometryZone};
+    use super::*;
+
+    #[test]
+    fn test_extract_round_trip() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8DSSD;
+        disk_parameters.n_cylinders = 2;
+        disk_parameters.sector_interleave = 3;
+
+        // Every sector filled with a different pattern
+        let data: Sector = (0..2 * 2 * 26 * 128).map(|i| (i / 128 + i) as u8).collect();
+
+        let hfe_img = convert_image(
+            data.clone(),
+            ConvertOpts {
+                ignore_errors: false,
+                disk_parameters,
+                input_format: InputFormat::Raw,
+                output_format: OutputFormat::Hfe,
+                side_order: SideOrder::Cylinder,
+                side1_data: None,
+                boot_loader: None,
+                verbose: false,
+            },
+        )
+        .unwrap()
+        .data;
+
+        let extracted = extract_from_hfe(
+            &hfe_img,
+            ExtractOpts {
+                ignore_errors: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(extracted, data);
+    }
+
+    #[test]
+    fn test_extract_zones() {
+        let mut disk_parameters = DiskFormatDefaults::LEVEL6;
+        disk_parameters.n_cylinders = 3;
+        disk_parameters.zones = vec![GeometryZone {
+            first_cylinder: 1,
+            last_cylinder: 2,
+            sectors_per_track: 8,
+            bytes_per_sector: 255,
+        }];
+
+        let data: Sector = (0..26 * 128 + 2 * 8 * 255).map(|i| (i / 7) as u8).collect();
+
+        let hfe_img = convert_image(
+            data.clone(),
+            ConvertOpts {
+                ignore_errors: false,
+                disk_parameters,
+                input_format: InputFormat::Raw,
+                output_format: OutputFormat::Hfe,
+                side_order: SideOrder::Cylinder,
+                side1_data: None,
+                boot_loader: None,
+                verbose: false,
+            },
+        )
+        .unwrap()
+        .data;
+
+        let extracted = extract_from_hfe(
+            &hfe_img,
+            ExtractOpts {
+                ignore_errors: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(extracted, data);
+    }
+
+    #[test]
+    fn test_read_scp_round_trip() {
+        let data: Sector = (0..2 * 2 * 26 * 128).map(|i| (i / 128 + i) as u8).collect();
+
+        // Hard sectored tracks have a sector across the index hole
+        for hard_sectors in [0, 26] {
+            let mut disk_parameters = DiskFormatDefaults::IBM8DSSD;
+            disk_parameters.n_cylinders = 2;
+            disk_parameters.hard_sectors = hard_sectors;
+
+            let scp_img = convert_image(
+                data.clone(),
+                ConvertOpts {
+                    ignore_errors: false,
+                    disk_parameters,
+                    input_format: InputFormat::Raw,
+                    output_format: OutputFormat::Scp,
+                    side_order: SideOrder::Cylinder,
+                    side1_data: None,
+                    boot_loader: None,
+                    verbose: false,
+                },
+            )
+            .unwrap()
+            .data;
+
+            let extracted = read_scp_sectors(&scp_img, false).unwrap().to_data();
+            assert_eq!(extracted, data);
+        }
+    }
+}
@@ -6,7 +6,7 @@ pub struct FMByte {
     pub clock: u8,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct FMBytes {
     bytes: Vec<FMByte>,
 }
@@ -73,11 +73,77 @@ impl FMBytes {
         self.bytes.append(&mut other.bytes);
     }
 
+    pub fn truncate(&mut self, len: usize) {
+        self.bytes.truncate(len);
+    }
+
     pub fn fm_len(&self) -> usize {
         self.bytes.len() * 2 // FM length is double byte length
     }
 }
 
+// Split FM encoded bytes into single cells, most significant bit first
+pub fn encoded_to_cells(encoded: &[u8]) -> Vec<bool> {
+    let mut cells: Vec<bool> = vec![];
+
+    for byte in encoded {
+        for bit in (0..8).rev() {
+            cells.push((byte >> bit) & 1 == 1);
+        }
+    }
+
+    cells
+}
+
+// Join single cells back into FM encoded bytes, most significant bit first
+pub fn cells_to_encoded(cells: &[bool]) -> Vec<u8> {
+    cells
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, cell| (acc << 1) | *cell as u8))
+        .collect()
+}
+
+// Find an FM byte (data and clock) in a cell stream, at any cell offset.
+// Returns the index of the cell following it
+pub fn find_fm_byte(cells: &[bool], start: usize, fm_byte: &FMByte) -> Option<usize> {
+    let mut pattern = FMBytes::new();
+    pattern.add_fm_byte(fm_byte);
+    let pattern = u16::from_be_bytes(pattern.encode().try_into().unwrap());
+
+    let mut window: u16 = 0;
+    for (i, cell) in cells.iter().enumerate().skip(start) {
+        window = (window << 1) | *cell as u16;
+
+        if i + 1 - start >= 16 && window == pattern {
+            return Some(i + 1);
+        }
+    }
+
+    None
+}
+
+// Read data bytes from a cell stream, ignoring clock cells
+pub fn read_fm_bytes(cells: &[bool], start: usize, n_bytes: usize) -> Option<Vec<u8>> {
+    if start + n_bytes * 16 > cells.len() {
+        return None;
+    }
+
+    let mut data: Vec<u8> = vec![];
+    for byte_n in 0..n_bytes {
+        let mut byte: u8 = 0;
+
+        // Data cells follow each clock cell
+        for bit_n in 0..8 {
+            byte <<= 1;
+            byte |= cells[start + byte_n * 16 + bit_n * 2 + 1] as u8;
+        }
+
+        data.push(byte);
+    }
+
+    Some(data)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -95,4 +161,27 @@ mod test {
 
         assert_eq!(data.encode(), [0b11110111, 0b01111010]);
     }
+
+    #[test]
+    fn test_fm_dec() {
+        let mark = FMByte {
+            data: 0xFE,
+            clock: 0xC7,
+        };
+
+        let mut data = FMBytes::new();
+        data.add_bytes(&[0xFF, 0x00]);
+        data.add_fm_byte(&mark);
+        data.add_bytes(&[0x12, 0x34]);
+
+        // Misalign the stream by a few cells
+        let mut cells = vec![true, false, true];
+        cells.append(&mut encoded_to_cells(&data.encode()));
+
+        let start = find_fm_byte(&cells, 0, &mark).unwrap();
+        assert_eq!(start, 3 + 16 * 3);
+        assert_eq!(read_fm_bytes(&cells, start, 2), Some(vec![0x12, 0x34]));
+        assert_eq!(read_fm_bytes(&cells, start, 3), None);
+        assert_eq!(find_fm_byte(&cells, start, &mark), None);
+    }
 }
@@ -4,6 +4,7 @@ use super::disk_parameters::{DiskParameters, DiskTrackFormat};
 
 const HFE_BLOCK_SIZE: usize = 512;
 const HFE_PAD_VALUE: u8 = 0x00;
+const HFE_LUT_N_BLOCKS: usize = 2; // Blocks reserved for the track offset LUT
 
 // Create HFE file from arary of encoded cylinders
 pub fn make_hfe_file(
@@ -18,6 +19,11 @@ pub fn make_hfe_file(
         ));
     }
 
+    // Sector holes cannot be represented
+    if disk_parameters.hard_sectors != 0 {
+        return Err("HFE files cannot hold hard sector pulses, use SCP output".to_string());
+    }
+
     // Encode track data and track LUT
     let mut track_offset_lut = HFETrackOffsetLUT::new();
     let mut track_data: Vec<u8> = vec![];
@@ -48,13 +54,183 @@ pub fn make_hfe_file(
     hfe_data.append(&mut pad_to_block(&header.as_bytes(), HFE_BLOCK_SIZE));
     hfe_data.append(&mut pad_to_block(
         &track_offset_lut.as_bytes(),
-        HFE_BLOCK_SIZE * 2,
+        HFE_BLOCK_SIZE * HFE_LUT_N_BLOCKS,
     ));
     hfe_data.append(&mut track_data);
 
     Ok(hfe_data)
 }
 
+// Tracks read back from an HFE file
+#[derive(Debug, Clone)]
+pub struct HFEImage {
+    pub n_sides: u8,
+    pub bit_rate: u16, // In kbps
+    pub rpm: u16,
+    pub cylinders: Vec<Cylinder>, // FM encoded tracks, as passed to make_hfe_file
+}
+
+// Read the tracks of an HFE file
+pub fn read_hfe_file(data: &[u8]) -> Result<HFEImage, String> {
+    // Check header
+    if data.len() < HFE_BLOCK_SIZE || data[0..8] != *b"HXCPICFE" {
+        return Err("Not an HFE file".to_string());
+    }
+    let n_cylinders = data[0x09];
+    let n_sides = data[0x0A];
+    let track_list_offset = u16::from_le_bytes([data[0x12], data[0x13]]) as usize * HFE_BLOCK_SIZE;
+
+    if n_sides == 0 || n_sides > 2 {
+        return Err(format!("Invalid number of sides: {}", n_sides));
+    }
+
+    let mut cylinders: Vec<Cylinder> = vec![];
+    for cyl_n in 0..n_cylinders as usize {
+        // Track offset LUT entry
+        let entry_offset = track_list_offset + cyl_n * 4;
+        let entry = match data.get(entry_offset..entry_offset + 4) {
+            Some(entry) => entry,
+            None => return Err("Track offset LUT truncated".to_string()),
+        };
+        let offset = u16::from_le_bytes([entry[0], entry[1]]) as usize * HFE_BLOCK_SIZE;
+        let track_len = u16::from_le_bytes([entry[2], entry[3]]) as usize;
+
+        // Split blocks into sides
+        let side_len = track_len / 2;
+        let mut sides: Vec<Vec<u8>> = vec![vec![]; 2];
+        let mut block_offset = offset;
+        while sides[0].len() < side_len {
+            let part_len = usize::min(HFE_BLOCK_SIZE / 2, side_len - sides[0].len());
+            for (side_n, side) in sides.iter_mut().enumerate() {
+                let part_offset = block_offset + side_n * HFE_BLOCK_SIZE / 2;
+                match data.get(part_offset..part_offset + part_len) {
+                    Some(part) => side.extend_from_slice(part),
+                    None => return Err(format!("Track {} truncated", cyl_n)),
+                }
+            }
+            block_offset += HFE_BLOCK_SIZE;
+        }
+
+        cylinders.push(
+            sides
+                .iter()
+                .take(n_sides as usize)
+                .map(|side| undo_weird_hfe_track_encoding(side))
+                .collect(),
+        );
+    }
+
+    Ok(HFEImage {
+        n_sides,
+        bit_rate: u16::from_le_bytes([data[0x0C], data[0x0D]]),
+        rpm: u16::from_le_bytes([data[0x0E], data[0x0F]]),
+        cylinders,
+    })
+}
+
+// Replace one track of an HFE file, leaving the rest of the file as it is
+pub fn write_hfe_track(
+    data: &mut [u8],
+    cyl_n: usize,
+    side_n: usize,
+    track: &[u8],
+) -> Result<(), String> {
+    let track_list_offset = u16::from_le_bytes([data[0x12], data[0x13]]) as usize * HFE_BLOCK_SIZE;
+    let entry_offset = track_list_offset + cyl_n * 4;
+    let entry = match data.get(entry_offset..entry_offset + 4) {
+        Some(entry) => entry,
+        None => return Err("Track offset LUT truncated".to_string()),
+    };
+    let offset = u16::from_le_bytes([entry[0], entry[1]]) as usize * HFE_BLOCK_SIZE;
+    let track_len = u16::from_le_bytes([entry[2], entry[3]]) as usize;
+
+    // The new track must have the same length as the old one
+    let encoded = do_weird_hfe_track_encoding(&track.to_vec());
+    if encoded.len() != track_len / 2 {
+        return Err(format!("Track {} changed length", cyl_n));
+    }
+
+    // Sides are interleaved in half blocks
+    let mut block_offset = offset;
+    for part in encoded.chunks(HFE_BLOCK_SIZE / 2) {
+        let part_offset = block_offset + side_n * HFE_BLOCK_SIZE / 2;
+        match data.get_mut(part_offset..part_offset + part.len()) {
+            Some(dest) => dest.copy_from_slice(part),
+            None => return Err(format!("Track {} truncated", cyl_n)),
+        }
+        block_offset += HFE_BLOCK_SIZE;
+    }
+
+    Ok(())
+}
+
+// Check the header and track offset LUT of an HFE file, returning the problems found
+pub fn check_hfe_file(data: &[u8]) -> Vec<String> {
+    let mut problems: Vec<String> = vec![];
+    if data.len() < HFE_BLOCK_SIZE || data[0..8] != *b"HXCPICFE" {
+        return vec!["Not an HFE file".to_string()];
+    }
+
+    // Header fields
+    let n_cylinders = data[0x09] as usize;
+    let track_list_offset = u16::from_le_bytes([data[0x12], data[0x13]]) as usize;
+    if data[0x08] != 0 {
+        problems.push(format!("Unsupported format revision: {}", data[0x08]));
+    }
+    if n_cylinders == 0 {
+        problems.push("No tracks in file".to_string());
+    }
+    if data[0x0B] != TrackEncoding::IsoIbmFmEncoding as u8 {
+        problems.push(format!("Track encoding is not FM: 0x{:02X}", data[0x0B]));
+    }
+    if u16::from_le_bytes([data[0x0C], data[0x0D]]) == 0 {
+        problems.push("Bit rate is 0".to_string());
+    }
+    if track_list_offset == 0 {
+        problems.push("Track offset LUT overlaps the header".to_string());
+    }
+
+    // Track offset LUT entries, tracks must lie after the LUT, within the file, without overlapping
+    let lut_end = (track_list_offset + HFE_LUT_N_BLOCKS) * HFE_BLOCK_SIZE;
+    let mut last_end = lut_end;
+    let mut first_track_len: Option<usize> = None;
+    for cyl_n in 0..n_cylinders {
+        let entry_offset = track_list_offset * HFE_BLOCK_SIZE + cyl_n * 4;
+        let entry = match data.get(entry_offset..entry_offset + 4) {
+            Some(entry) => entry,
+            None => {
+                problems.push("Track offset LUT truncated".to_string());
+                break;
+            }
+        };
+        let offset = u16::from_le_bytes([entry[0], entry[1]]) as usize * HFE_BLOCK_SIZE;
+        let track_len = u16::from_le_bytes([entry[2], entry[3]]) as usize;
+        let end = offset + track_len.div_ceil(HFE_BLOCK_SIZE) * HFE_BLOCK_SIZE;
+
+        if track_len == 0 || !track_len.is_multiple_of(4) {
+            problems.push(format!("Track {}: invalid length {}", cyl_n, track_len));
+        }
+        if offset < last_end {
+            problems.push(format!("Track {}: data overlaps the previous block", cyl_n));
+        }
+        if end > data.len() {
+            problems.push(format!("Track {}: data past the end of the file", cyl_n));
+        }
+        if *first_track_len.get_or_insert(track_len) != track_len {
+            problems.push(format!(
+                "Track {}: length {} differs from track 0 ({})",
+                cyl_n,
+                track_len,
+                first_track_len.unwrap()
+            ));
+        }
+
+        last_end = usize::max(last_end, end);
+    }
+
+    problems
+}
+
 // Pad array of u8 to block size
 fn pad_to_block(data: &Vec<u8>, block_size: usize) -> Vec<u8> {
     if data.len() > block_size {
@@ -103,6 +279,26 @@ fn do_weird_hfe_track_encoding(track: &Vec<u8>) -> Vec<u8> {
     encoded
 }
 
+// Undo weird HFE format encoding. Example: 10001010... -> 1011...
+fn undo_weird_hfe_track_encoding(track: &[u8]) -> Vec<u8> {
+    let mut decoded: Vec<u8> = vec![];
+
+    for pair in reverse_bits(track).chunks_exact(2) {
+        let byte_encoded = u16::from_be_bytes([pair[0], pair[1]]);
+        let mut byte: u8 = 0;
+
+        // Keep every second bit
+        for bit in (0..8).rev() {
+            byte <<= 1;
+            byte |= ((byte_encoded >> (bit * 2)) & 1) as u8;
+        }
+
+        decoded.push(byte);
+    }
+
+    decoded
+}
+
 fn reverse_bits(track: &[u8]) -> Vec<u8> {
     let mut encoded: Vec<u8> = vec![];
 
@@ -2,13 +2,13 @@ use crc::{Crc, CRC_16_IBM_3740};
 
 // In-module imports
 use super::convert::{Sector, Track};
-use super::disk_parameters::DiskParameters;
-use super::encode::calc_interleave_map;
-use super::fm::{FMByte, FMBytes};
+use super::disk_parameters::{DiskParameters, SectorDefect, TrackGaps};
+use super::encode::physical_sector_order;
+use super::fm::{cells_to_encoded, encoded_to_cells, find_fm_byte, read_fm_bytes, FMByte, FMBytes};
 
 // Level6 Disk format Address Marks
 #[non_exhaustive]
-struct IBM3470AddressMark;
+pub struct IBM3470AddressMark;
 impl IBM3470AddressMark {
     pub const IAM: FMByte = FMByte {
         data: 0xFC,
@@ -24,16 +24,26 @@ impl IBM3470AddressMark {
     };
 }
 
+// Gap lengths and gap byte value come from the disk parameters
 struct IBM3470Gaps;
 impl IBM3470Gaps {
-    pub const FILL_BYTE: u8 = 0xFF;
-    pub const GAP1_LEN: usize = 40;
-    pub const GAP2_LEN: usize = 26;
-    pub const GAP3_LEN: usize = 11;
-    pub const GAP4_LEN: usize = 27;
     pub const GAP5_LESS: usize = 100; // Bytes of GAP5 to omit
 }
 
+// Sector read back from an encoded track
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedSector {
+    pub track_n: u8,
+    pub side_n: u8,
+    pub sector_n: u8,
+    pub sector_len: usize, // From the ID field
+    pub id_crc_ok: bool,
+    pub data: Option<Vec<u8>>, // None if the data field is missing
+    pub data_crc_ok: bool,
+    pub id_cell: usize, // Cell at which the ID field follows its address mark
+    pub data_cell: Option<usize>, // Cell at which the data follows its address mark
+}
+
 // Encode one track to Level6 format
 pub fn encode_track(
     sectors: &[Sector],
@@ -44,37 +54,57 @@ pub fn encode_track(
     // Validate disk parameters
     check_disk_parameters(disk_parameters)?;
 
-    // Compute sector interleave map
-    let interleave_map = calc_interleave_map(
-        disk_parameters.sectors_per_track,
-        disk_parameters.sector_interleave,
-    );
-
     // Track data buffer
     let mut track = FMBytes::new();
+    let gaps = &disk_parameters.gaps;
 
-    // Encode track header
-    track.append(&mut encode_track_header());
+    // Encode track header, hard sectored tracks start at the first sector hole
+    if disk_parameters.hard_sectors == 0 {
+        track.append(&mut encode_track_header(gaps));
+    }
 
-    // Encode sectors
-    for phys_sec_n in 0..disk_parameters.sectors_per_track {
-        // Compute interleave
-        let logical_sec_n = interleave_map[phys_sec_n as usize];
+    // Track length, in encoded bytes
+    let track_fm_len =
+        (60000 * disk_parameters.cell_rate as usize) / (disk_parameters.rpm as usize * 8);
 
+    // Encode sectors
+    let n_sectors = disk_parameters.sectors_per_track as usize;
+    for (logical_sec_n, sector_n) in physical_sector_order(disk_parameters, cyl_n, side_n) {
         // Encode single sector
-        track.append(&mut encode_sector(
-            &sectors[logical_sec_n as usize],
+        let mut sector = encode_sector(
+            &sectors[logical_sec_n],
             cyl_n as u8,
-            logical_sec_n as u8 + 1, // Sector numbers start from 1
+            sector_n,
             side_n as u8,
-        ));
+            disk_parameters.sector_defect(cyl_n, side_n, sector_n),
+            gaps,
+        );
+
+        // On hard sectored tracks each sector fills the space between two sector holes,
+        // the trailing gap is shortened or lengthened to fit
+        if disk_parameters.hard_sectors != 0 {
+            let slot_len = track_fm_len / 2 / n_sectors;
+            let sector_len = sector.fm_len() / 2 - gaps.gap4_len as usize;
+            if sector_len > slot_len {
+                return Err(format!(
+                    "Sector too large for hard sector ({} bytes, maximum is {})",
+                    sector_len, slot_len
+                ));
+            }
+            sector.truncate(sector_len);
+            sector.add_bytes(&vec![gaps.gap_byte; slot_len - sector_len]);
+        }
+
+        track.append(&mut sector);
     }
 
     // Fill remaining part of track
-    let remaining_bytes: isize = (((60000) * disk_parameters.cell_rate as u64)
-        / (disk_parameters.rpm as u64 * 8)) as isize
-        - track.fm_len() as isize
-        - IBM3470Gaps::GAP5_LESS as isize;
+    let gap5_less = match disk_parameters.hard_sectors {
+        0 => IBM3470Gaps::GAP5_LESS,
+        _ => 0,
+    };
+    let remaining_bytes: isize =
+        track_fm_len as isize - track.fm_len() as isize - gap5_less as isize;
 
     // Check if sectors can fit on disk
     if remaining_bytes < 0 {
@@ -82,7 +112,7 @@ pub fn encode_track(
     }
 
     // GAP5
-    track.add_bytes(&vec![IBM3470Gaps::FILL_BYTE; remaining_bytes as usize / 2]); // divided by 2 because 1 data byte = 2 fm bytes
+    track.add_bytes(&vec![gaps.gap_byte; remaining_bytes as usize / 2]); // divided by 2 because 1 data byte = 2 fm bytes
 
     Ok(track.encode())
 }
@@ -117,14 +147,44 @@ fn check_disk_parameters(disk_parameters: &DiskParameters) -> Result<(), String>
         ));
     };
 
+    // Check hard sectors
+    if disk_parameters.hard_sectors != 0
+        && disk_parameters.hard_sectors != disk_parameters.sectors_per_track
+    {
+        return Err(format!(
+            "Sectors per track ({}) must match the number of hard sectors ({})",
+            disk_parameters.sectors_per_track, disk_parameters.hard_sectors
+        ));
+    }
+
+    // Check sector numbering
+    match &disk_parameters.sector_numbers {
+        Some(sector_numbers)
+            if sector_numbers.len() != disk_parameters.sectors_per_track as usize =>
+        {
+            return Err(format!(
+                "Wrong number of sector numbers (should be {}, is {})",
+                disk_parameters.sectors_per_track,
+                sector_numbers.len()
+            ));
+        }
+        None if disk_parameters.first_sector_n as u16 + disk_parameters.sectors_per_track > 256 => {
+            return Err(format!(
+                "Sector numbers past 255: first sector is {}",
+                disk_parameters.first_sector_n
+            ));
+        }
+        _ => {}
+    }
+
     Ok(())
 }
 
-fn encode_track_header() -> FMBytes {
+fn encode_track_header(gaps: &TrackGaps) -> FMBytes {
     let mut data = FMBytes::new();
 
     // Pre-Index Gap (GAP1)
-    data.add_bytes(&[IBM3470Gaps::FILL_BYTE; IBM3470Gaps::GAP1_LEN]);
+    data.add_bytes(&vec![gaps.gap_byte; gaps.gap1_len as usize]);
 
     // AM4 Sync field (6 bytes)
     data.add_bytes(&[0x00; 6]);
@@ -133,12 +193,19 @@ fn encode_track_header() -> FMBytes {
     data.add_fm_byte(&IBM3470AddressMark::IAM);
 
     // Post-Index address mark (GAP2)
-    data.add_bytes(&[IBM3470Gaps::FILL_BYTE; IBM3470Gaps::GAP2_LEN]);
+    data.add_bytes(&vec![gaps.gap_byte; gaps.gap2_len as usize]);
 
     data
 }
 
-fn encode_sector(sector: &Sector, track_n: u8, sector_n: u8, side_n: u8) -> FMBytes {
+fn encode_sector(
+    sector: &Sector,
+    track_n: u8,
+    sector_n: u8,
+    side_n: u8,
+    defect: Option<SectorDefect>,
+    gaps: &TrackGaps,
+) -> FMBytes {
     let mut data = FMBytes::new();
 
     // Sector ID Sync field (6 bytes)
@@ -150,24 +217,37 @@ fn encode_sector(sector: &Sector, track_n: u8, sector_n: u8, side_n: u8) -> FMBy
         track_n,
         sector_n,
         side_n,
+        defect == Some(SectorDefect::IdCrc),
     ));
 
     // Identifier to Data Gap (GAP3)
-    data.add_bytes(&[IBM3470Gaps::FILL_BYTE; IBM3470Gaps::GAP3_LEN]);
+    data.add_bytes(&vec![gaps.gap_byte; gaps.gap3_len as usize]);
 
     // AM2 Sync field (6 bytes)
     data.add_bytes(&[0x00; 6]);
 
     // Sector data field
-    data.append(&mut encode_sector_data(sector));
+    let mut data_field = encode_sector_data(sector, defect == Some(SectorDefect::DataCrc));
+    if defect == Some(SectorDefect::NoData) {
+        // Omitted data field, replaced by gap to keep the track length
+        data_field = FMBytes::new();
+        data_field.add_bytes(&vec![gaps.gap_byte; sector.len() + 3]);
+    }
+    data.append(&mut data_field);
 
     // Intrer-sector Gap (GAP4)
-    data.add_bytes(&[IBM3470Gaps::FILL_BYTE; IBM3470Gaps::GAP4_LEN]);
+    data.add_bytes(&vec![gaps.gap_byte; gaps.gap4_len as usize]);
 
     data
 }
 
-fn encode_sector_header(sector_len: u8, track_n: u8, sector_n: u8, side_n: u8) -> FMBytes {
+fn encode_sector_header(
+    sector_len: u8,
+    track_n: u8,
+    sector_n: u8,
+    side_n: u8,
+    bad_crc: bool,
+) -> FMBytes {
     let mut header = FMBytes::new();
 
     // Sector ID address mark (AM1)
@@ -183,14 +263,18 @@ fn encode_sector_header(sector_len: u8, track_n: u8, sector_n: u8, side_n: u8) -
     let mut digest = crc.digest();
     digest.update(&header.get_data_bytes());
 
-    // Sector ID field EDC (CRC)
-    header.add_bytes(&digest.finalize().to_be_bytes());
+    // Sector ID field EDC (CRC), inverted to make it wrong
+    let mut crc = digest.finalize();
+    if bad_crc {
+        crc = !crc;
+    }
+    header.add_bytes(&crc.to_be_bytes());
     // header.add_bytes(&[0x00, 0x00]);
 
     header
 }
 
-fn encode_sector_data(sector: &Sector) -> FMBytes {
+fn encode_sector_data(sector: &Sector, bad_crc: bool) -> FMBytes {
     let mut data = FMBytes::new();
 
     // Sector Data field address mark (AM1)
@@ -204,8 +288,233 @@ fn encode_sector_data(sector: &Sector) -> FMBytes {
     let mut digest = crc.digest();
     digest.update(&data.get_data_bytes());
 
-    // Sector Data field EDC (CRC)
-    data.add_bytes(&digest.finalize().to_be_bytes());
+    // Sector Data field EDC (CRC), inverted to make it wrong
+    let mut crc = digest.finalize();
+    if bad_crc {
+        crc = !crc;
+    }
+    data.add_bytes(&crc.to_be_bytes());
 
     data
 }
+
+// Decode the sectors of one track, in the order they appear on the track
+pub fn decode_track(encoded: &[u8]) -> Vec<DecodedSector> {
+    let cells = encoded_to_cells(encoded);
+    let crc = Crc::<u16>::new(&CRC_16_IBM_3740);
+    let mut sectors: Vec<DecodedSector> = vec![];
+
+    let mut pos: usize = 0;
+    while let Some(id_start) = find_fm_byte(&cells, pos, &IBM3470AddressMark::IDAM) {
+        // Sector ID field, followed by its CRC
+        let id = match read_fm_bytes(&cells, id_start, 6) {
+            Some(id) => id,
+            None => break,
+        };
+        let mut digest = crc.digest();
+        digest.update(&[IBM3470AddressMark::IDAM.data]);
+        digest.update(&id[0..4]);
+        let id_crc_ok = digest.finalize().to_be_bytes() == id[4..6];
+        pos = id_start + 6 * 16;
+
+        // The data field belongs to this sector only if it comes before the next ID field
+        let next_id = find_fm_byte(&cells, pos, &IBM3470AddressMark::IDAM);
+        let data_start = match find_fm_byte(&cells, pos, &IBM3470AddressMark::DAM) {
+            Some(data_start) if next_id.is_none_or(|next_id| data_start < next_id) => {
+                Some(data_start)
+            }
+            _ => None,
+        };
+
+        // Sector data, followed by its CRC. A sector length of 0 means 256 bytes
+        let sector_len = match id[3] {
+            0 => 256,
+            len => len as usize,
+        };
+        let (data, data_crc_ok) =
+            match data_start.and_then(|start| read_fm_bytes(&cells, start, sector_len + 2)) {
+                Some(field) => {
+                    let mut digest = crc.digest();
+                    digest.update(&[IBM3470AddressMark::DAM.data]);
+                    digest.update(&field[0..sector_len]);
+                    let data_crc_ok = digest.finalize().to_be_bytes() == field[sector_len..];
+                    pos = data_start.unwrap() + (sector_len + 2) * 16;
+
+                    (Some(field[0..sector_len].to_vec()), data_crc_ok)
+                }
+                None => (None, false),
+            };
+
+        sectors.push(DecodedSector {
+            track_n: id[0],
+            side_n: id[1],
+            sector_n: id[2],
+            sector_len,
+            id_crc_ok,
+            data,
+            data_crc_ok,
+            id_cell: id_start,
+            data_cell: data_start,
+        });
+    }
+
+    sectors
+}
+
+// Replace the data of a sector in an encoded track, with a new data field CRC. Every copy of
+// the sector is replaced, the rest of the track is left as it is
+pub fn patch_sector(encoded: &[u8], sector_n: u8, data: &[u8]) -> Result<Track, String> {
+    let mut cells = encoded_to_cells(encoded);
+    let mut found = false;
+
+    for sector in decode_track(encoded) {
+        let data_cell = match (sector.data_cell, &sector.data) {
+            (Some(data_cell), Some(old_data))
+                if sector.id_crc_ok && sector.sector_n == sector_n =>
+            {
+                if old_data.len() != data.len() {
+                    return Err(format!(
+                        "Wrong data size for sector {} (should be {}, is {})",
+                        sector_n,
+                        old_data.len(),
+                        data.len()
+                    ));
+                }
+                data_cell
+            }
+            _ => continue,
+        };
+
+        // Re-encode the data field from its address mark on
+        let field_cells = encoded_to_cells(&encode_sector_data(&data.to_vec(), false).encode());
+        let field_start = data_cell - 16;
+        cells[field_start..field_start + field_cells.len()].copy_from_slice(&field_cells);
+        found = true;
+    }
+
+    match found {
+        true => Ok(cells_to_encoded(&cells)),
+        false => Err(format!("Sector {} not found", sector_n)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::disk_parameters::{BadSector, DiskFormatDefaults};
+    use super::*;
+
+    #[test]
+    fn test_sector_layout() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8DSSD;
+        disk_parameters.sectors_per_track = 6;
+        disk_parameters.sector_interleave = 2;
+        disk_parameters.track_skew = 1;
+        let sectors: Vec<Sector> = (0..6).map(|i| vec![i; 128]).collect();
+
+        let sector_ns = |disk_parameters: &DiskParameters, cyl_n, side_n| -> Vec<u8> {
+            let track = encode_track(&sectors, disk_parameters, cyl_n, side_n).unwrap();
+            decode_track(&track).iter().map(|s| s.sector_n).collect()
+        };
+
+        // Interleave 2: 0 3 1 4 2 5, rotated by one sector per track
+        assert_eq!(sector_ns(&disk_parameters, 0, 0), vec![1, 4, 2, 5, 3, 6]);
+        assert_eq!(sector_ns(&disk_parameters, 0, 1), vec![6, 1, 4, 2, 5, 3]);
+        assert_eq!(sector_ns(&disk_parameters, 1, 0), vec![3, 6, 1, 4, 2, 5]);
+
+        disk_parameters.first_sector_n = 0;
+        assert_eq!(sector_ns(&disk_parameters, 0, 0), vec![0, 3, 1, 4, 2, 5]);
+
+        disk_parameters.sector_numbers = Some(vec![10, 20, 30, 40, 50, 60]);
+        assert_eq!(
+            sector_ns(&disk_parameters, 0, 0),
+            vec![10, 40, 20, 50, 30, 60]
+        );
+
+        disk_parameters.sector_numbers = Some(vec![1, 2]);
+        assert!(encode_track(&sectors, &disk_parameters, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_track_gaps() {
+        let mut disk_parameters = DiskFormatDefaults::LEVEL6;
+        let sectors: Vec<Sector> = (0..26).map(|i| vec![i; 128]).collect();
+        let first_id = |disk_parameters: &DiskParameters| {
+            let track = encode_track(&sectors, disk_parameters, 0, 0).unwrap();
+            let cells = encoded_to_cells(&track);
+            let gap_byte = read_fm_bytes(&cells, 0, 1).unwrap()[0];
+            let id_start = find_fm_byte(&cells, 0, &IBM3470AddressMark::IDAM).unwrap();
+            (gap_byte, id_start / 16, decode_track(&track).len())
+        };
+
+        // Output is the same on every run
+        assert_eq!(
+            encode_track(&sectors, &disk_parameters, 0, 0),
+            encode_track(&sectors, &disk_parameters, 0, 0)
+        );
+        assert_eq!(first_id(&disk_parameters), (0xFF, 40 + 7 + 26 + 7, 26));
+
+        disk_parameters.gaps.gap_byte = 0x4E;
+        disk_parameters.gaps.set_lengths([16, 11, 11, 27]);
+        assert_eq!(first_id(&disk_parameters), (0x4E, 16 + 7 + 11 + 7, 26));
+    }
+
+    #[test]
+    fn test_hard_sectors() {
+        let mut disk_parameters = DiskFormatDefaults::LEVEL6;
+        disk_parameters.sectors_per_track = 16;
+        disk_parameters.hard_sectors = 16;
+        let sectors: Vec<Sector> = (0..16).map(|i| vec![i; 128]).collect();
+
+        // Each sector starts right after its hole, 325 bytes apart
+        let track = encode_track(&sectors, &disk_parameters, 0, 0).unwrap();
+        let cells = encoded_to_cells(&track);
+        let mut pos = 0;
+        for hole in 0..16 {
+            let id_start = find_fm_byte(&cells, pos, &IBM3470AddressMark::IDAM).unwrap();
+            assert_eq!(id_start, (hole * 325 + 7) * 16);
+            pos = id_start;
+        }
+        assert!(decode_track(&track)
+            .iter()
+            .all(|sector| sector.id_crc_ok && sector.data_crc_ok));
+
+        disk_parameters.hard_sectors = 32;
+        assert!(encode_track(&sectors, &disk_parameters, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_sector_defects() {
+        let mut disk_parameters = DiskFormatDefaults::LEVEL6;
+        disk_parameters.sectors_per_track = 4;
+        disk_parameters.bad_sectors = [
+            (1, SectorDefect::NoData),
+            (2, SectorDefect::DataCrc),
+            (3, SectorDefect::IdCrc),
+        ]
+        .into_iter()
+        .map(|(sector_n, defect)| BadSector {
+            cyl_n: 0,
+            side_n: 0,
+            sector_n,
+            defect,
+        })
+        .collect();
+        let sectors: Vec<Sector> = (0..4).map(|i| vec![i; 128]).collect();
+
+        let track = encode_track(&sectors, &disk_parameters, 0, 0).unwrap();
+        let decoded = decode_track(&track);
+        let status: Vec<(bool, bool, bool)> = decoded
+            .iter()
+            .map(|sector| (sector.id_crc_ok, sector.data.is_some(), sector.data_crc_ok))
+            .collect();
+        assert_eq!(
+            status,
+            vec![
+                (true, false, false),
+                (true, true, false),
+                (false, true, true),
+                (true, true, true),
+            ]
+        );
+    }
+}
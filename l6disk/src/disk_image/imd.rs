@@ -0,0 +1,210 @@
+// In-module imports
+use super::convert::Sector;
+use super::disk_parameters::DiskParameters;
+use super::encode::physical_sector_order;
+use super::sector_image::SectorImage;
+
+// Fixed creation date, so that the same input always gives the same image
+const IMD_HEADER: &[u8] = b"IMD 1.18: 01/01/1980 00:00:00\r\nCreated by l6disk\r\n\x1A";
+const IMD_COMMENT_END: u8 = 0x1A;
+
+// Track flags, in the head byte
+const IMD_CYLINDER_MAP: u8 = 0x80;
+const IMD_HEAD_MAP: u8 = 0x40;
+
+// Sector data record types
+const IMD_SECTOR_UNAVAILABLE: u8 = 0;
+const IMD_SECTOR_NORMAL: u8 = 1;
+const IMD_SECTOR_COMPRESSED: u8 = 2;
+
+// Recording modes: 500, 300 and 250 kbps FM
+const IMD_FM_MODES: [(u8, u16); 3] = [(0, 500), (1, 300), (2, 250)];
+
+// Read the sectors of an ImageDisk image
+pub fn read_imd_file(data: &[u8], ignore_errors: bool) -> Result<SectorImage, String> {
+    if !data.starts_with(b"IMD ") {
+        return Err("Not an IMD file".to_string());
+    }
+    let mut pos = match data.iter().position(|byte| *byte == IMD_COMMENT_END) {
+        Some(end) => end + 1,
+        None => return Err("IMD comment not terminated".to_string()),
+    };
+    let truncated = || "Unexpected end of IMD file".to_string();
+
+    let mut image = SectorImage::default();
+    while pos < data.len() {
+        // Track header
+        let header = data.get(pos..pos + 5).ok_or_else(truncated)?;
+        let (cyl_n, head) = (header[1] as usize, header[2]);
+        let (n_sectors, size_code) = (header[3] as usize, header[4]);
+        let side_n = (head & 0x01) as usize;
+        if size_code > 6 {
+            return Err(format!("Unsupported IMD sector size code: {}", size_code));
+        }
+        let sector_size = 128usize << size_code;
+        pos += 5;
+
+        // Sector numbering map, then optional cylinder and head maps
+        let sector_map = data
+            .get(pos..pos + n_sectors)
+            .ok_or_else(truncated)?
+            .to_vec();
+        pos += n_sectors;
+        if head & IMD_CYLINDER_MAP != 0 {
+            pos += n_sectors;
+        }
+        if head & IMD_HEAD_MAP != 0 {
+            pos += n_sectors;
+        }
+
+        // Tracks may be missing from the image
+        image.track_mut(cyl_n, side_n);
+
+        // Sector data records
+        for sector_n in sector_map {
+            let record_type = *data.get(pos).ok_or_else(truncated)?;
+            pos += 1;
+
+            let sector: Sector = match record_type {
+                IMD_SECTOR_UNAVAILABLE => match ignore_errors {
+                    true => vec![0; sector_size],
+                    false => {
+                        return Err(format!(
+                            "Sector data unavailable (cylinder {}, side {}, sector {})",
+                            cyl_n, side_n, sector_n
+                        ))
+                    }
+                },
+                1..=8 => {
+                    // Even types hold a single fill byte
+                    let sector = match record_type % 2 {
+                        1 => data
+                            .get(pos..pos + sector_size)
+                            .ok_or_else(truncated)?
+                            .to_vec(),
+                        _ => vec![*data.get(pos).ok_or_else(truncated)?; sector_size],
+                    };
+                    pos += match record_type % 2 {
+                        1 => sector_size,
+                        _ => 1,
+                    };
+
+                    // Types 5 to 8 were read with a data error
+                    if record_type >= 5 && !ignore_errors {
+                        return Err(format!(
+                            "Sector read with data error (cylinder {}, side {}, sector {})",
+                            cyl_n, side_n, sector_n
+                        ));
+                    }
+                    sector
+                }
+                _ => return Err(format!("Invalid IMD sector record type: {}", record_type)),
+            };
+
+            image.cylinders[cyl_n][side_n].push((sector_n, sector));
+        }
+    }
+
+    Ok(image)
+}
+
+// Create an ImageDisk image from sectors in logical order, with sectors listed in the order they
+// are written on each track
+pub fn make_imd_file(
+    sectors: &[Sector],
+    disk_parameters: &DiskParameters,
+) -> Result<Vec<u8>, String> {
+    let mode = match IMD_FM_MODES
+        .iter()
+        .find(|(_, cell_rate)| *cell_rate == disk_parameters.cell_rate)
+    {
+        Some((mode, _)) => *mode,
+        None => {
+            return Err(format!(
+                "Unsupported cell rate for IMD file: {}",
+                disk_parameters.cell_rate
+            ))
+        }
+    };
+
+    let mut imd_data: Vec<u8> = IMD_HEADER.to_vec();
+    let mut start_sector: usize = 0;
+    for cyl_n in 0..disk_parameters.n_cylinders {
+        let cyl_pars = disk_parameters.cylinder_parameters(cyl_n);
+
+        // Sector size must be a power of 2, from 128 bytes
+        let size_code = match (0..=6).find(|code| 128 << code == cyl_pars.bytes_per_sector) {
+            Some(size_code) => size_code,
+            None => {
+                return Err(format!(
+                    "Unsupported sector size for IMD file: {}",
+                    cyl_pars.bytes_per_sector
+                ))
+            }
+        };
+
+        for side_n in 0..cyl_pars.n_sides {
+            let order = physical_sector_order(&cyl_pars, cyl_n, side_n);
+
+            // Track header and sector numbering map
+            imd_data.extend_from_slice(&[
+                mode,
+                cyl_n as u8,
+                side_n as u8,
+                order.len() as u8,
+                size_code as u8,
+            ]);
+            imd_data.extend(order.iter().map(|(_, sector_n)| *sector_n));
+
+            // Sector data, filled sectors are compressed
+            for (logical_sec_n, _) in &order {
+                let sector = &sectors[start_sector + logical_sec_n];
+                if sector.iter().all(|byte| *byte == sector[0]) {
+                    imd_data.extend_from_slice(&[IMD_SECTOR_COMPRESSED, sector[0]]);
+                } else {
+                    imd_data.push(IMD_SECTOR_NORMAL);
+                    imd_data.extend_from_slice(sector);
+                }
+            }
+            start_sector += order.len();
+        }
+    }
+
+    Ok(imd_data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::*;
+
+    #[test]
+    fn test_imd_round_trip() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8DSSD;
+        disk_parameters.n_cylinders = 2;
+        disk_parameters.sectors_per_track = 4;
+        disk_parameters.sector_interleave = 2;
+
+        // Odd sectors filled with a single value
+        let sectors: Vec<Sector> = (0..16u8)
+            .map(|i| {
+                (0..128u8)
+                    .map(|j| if i % 2 == 1 { i } else { i ^ j })
+                    .collect()
+            })
+            .collect();
+
+        let imd_data = make_imd_file(&sectors, &disk_parameters).unwrap();
+        assert_eq!(
+            imd_data[IMD_HEADER.len()..IMD_HEADER.len() + 9],
+            [0, 0, 0, 4, 0, 1, 3, 2, 4]
+        );
+
+        let image = read_imd_file(&imd_data, false).unwrap();
+        assert_eq!(image.n_sides, 2);
+        assert_eq!(image.sectors(), sectors);
+
+        // Truncated file
+        assert!(read_imd_file(&imd_data[..imd_data.len() - 1], false).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+// LZSS with adaptive Huffman coding (LZHUF), as used by TeleDisk advanced compression
+
+const N: usize = 4096; // Ring buffer size
+const F: usize = 60; // Maximum match length
+const THRESHOLD: usize = 2; // Matches this long or shorter are coded as literals
+const N_CHAR: usize = 256 - THRESHOLD + F; // Literals and match lengths
+const T: usize = N_CHAR * 2 - 1; // Huffman tree size
+const R: usize = T - 1; // Huffman tree root
+const MAX_FREQ: u16 = 0x8000; // Tree is rebuilt when the root reaches this frequency
+
+// Code lengths of the upper 6 bits of match positions
+const POSITION_CODE_LENGTHS: [(usize, u8); 6] = [(1, 3), (3, 4), (8, 5), (12, 6), (24, 7), (16, 8)];
+
+// Reads bits from a byte slice, most significant bit first
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    bit_pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, bit_pos: 0 }
+    }
+
+    fn get_bit(&mut self) -> Option<usize> {
+        let byte = self.data.get(self.bit_pos / 8)?;
+        let bit = (byte >> (7 - self.bit_pos % 8)) & 1;
+        self.bit_pos += 1;
+        Some(bit as usize)
+    }
+
+    fn get_byte(&mut self) -> Option<usize> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = (byte << 1) | self.get_bit()?;
+        }
+        Some(byte)
+    }
+}
+
+// Adaptive Huffman tree
+pub struct HuffmanTree {
+    freq: Vec<u16>,
+    parent: Vec<usize>, // Parents of nodes, and of leaves at T + c
+    son: Vec<usize>,    // Children of nodes, leaves are T + c
+}
+
+impl Default for HuffmanTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HuffmanTree {
+    pub fn new() -> Self {
+        let mut tree = Self {
+            freq: vec![0; T + 1],
+            parent: vec![0; T + N_CHAR],
+            son: vec![0; T],
+        };
+
+        // Leaves
+        for i in 0..N_CHAR {
+            tree.freq[i] = 1;
+            tree.son[i] = i + T;
+            tree.parent[i + T] = i;
+        }
+
+        // Nodes
+        let mut i = 0;
+        for j in N_CHAR..=R {
+            tree.freq[j] = tree.freq[i] + tree.freq[i + 1];
+            tree.son[j] = i;
+            tree.parent[i] = j;
+            tree.parent[i + 1] = j;
+            i += 2;
+        }
+        tree.freq[T] = 0xFFFF;
+        tree.parent[R] = 0;
+
+        tree
+    }
+
+    // Rebuild the tree, halving frequencies
+    fn reconstruct(&mut self) {
+        // Collect leaves in the first half
+        let mut j = 0;
+        for i in 0..T {
+            if self.son[i] >= T {
+                self.freq[j] = self.freq[i].div_ceil(2);
+                self.son[j] = self.son[i];
+                j += 1;
+            }
+        }
+
+        // Connect nodes, keeping frequencies sorted
+        let mut i = 0;
+        for j in N_CHAR..T {
+            let f = self.freq[i] + self.freq[i + 1];
+            let mut k = j - 1;
+            while f < self.freq[k] {
+                k -= 1;
+            }
+            k += 1;
+
+            self.freq.copy_within(k..j, k + 1);
+            self.freq[k] = f;
+            self.son.copy_within(k..j, k + 1);
+            self.son[k] = i;
+            i += 2;
+        }
+
+        // Connect parents
+        for i in 0..T {
+            let k = self.son[i];
+            self.parent[k] = i;
+            if k < T {
+                self.parent[k + 1] = i;
+            }
+        }
+    }
+
+    // Increment the frequency of a character, keeping the tree sorted
+    pub fn update(&mut self, c: usize) {
+        if self.freq[R] == MAX_FREQ {
+            self.reconstruct();
+        }
+
+        let mut c = self.parent[c + T];
+        loop {
+            self.freq[c] += 1;
+            let k = self.freq[c];
+
+            // Swap nodes if the order is disturbed
+            let mut l = c + 1;
+            if k > self.freq[l] {
+                while k > self.freq[l + 1] {
+                    l += 1;
+                }
+                self.freq[c] = self.freq[l];
+                self.freq[l] = k;
+
+                let i = self.son[c];
+                self.parent[i] = l;
+                if i < T {
+                    self.parent[i + 1] = l;
+                }
+
+                let j = self.son[l];
+                self.son[l] = i;
+                self.parent[j] = c;
+                if j < T {
+                    self.parent[j + 1] = c;
+                }
+                self.son[c] = j;
+
+                c = l;
+            }
+
+            c = self.parent[c];
+            if c == 0 {
+                break;
+            }
+        }
+    }
+
+    fn decode_char(&mut self, reader: &mut BitReader) -> Option<usize> {
+        let mut c = self.son[R];
+        while c < T {
+            c = self.son[c + reader.get_bit()?];
+        }
+        c -= T;
+        self.update(c);
+
+        Some(c)
+    }
+
+    // Bit path from the root to a character, used to encode it
+    #[cfg(test)]
+    pub fn char_code(&self, c: usize) -> Vec<usize> {
+        let mut bits = vec![];
+        let mut k = self.parent[c + T];
+        loop {
+            bits.push(k & 1);
+            k = self.parent[k];
+            if k == R {
+                break;
+            }
+        }
+        bits.reverse();
+        bits
+    }
+}
+
+// Upper 6 bits and code length of match positions, indexed by the first 8 coded bits
+fn position_tables() -> (Vec<usize>, Vec<usize>) {
+    let mut codes: Vec<usize> = vec![];
+    let mut lengths: Vec<usize> = vec![];
+
+    let mut code = 0;
+    for (n_codes, length) in POSITION_CODE_LENGTHS {
+        for _ in 0..n_codes {
+            for _ in 0..1 << (8 - length) {
+                codes.push(code);
+                lengths.push(length as usize);
+            }
+            code += 1;
+        }
+    }
+
+    (codes, lengths)
+}
+
+fn decode_position(reader: &mut BitReader, codes: &[usize], lengths: &[usize]) -> Option<usize> {
+    let mut i = reader.get_byte()?;
+    let c = codes[i] << 6;
+
+    // Read the rest of the lower 6 bits
+    for _ in 0..lengths[i] - 2 {
+        i = (i << 1) | reader.get_bit()?;
+    }
+
+    Some(c | (i & 0x3F))
+}
+
+// Decompress data until the input runs out
+pub fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut reader = BitReader::new(data);
+    let mut tree = HuffmanTree::new();
+    let (codes, lengths) = position_tables();
+
+    let mut ring = vec![b' '; N];
+    let mut r = N - F;
+    let mut output: Vec<u8> = vec![];
+
+    while let Some(c) = tree.decode_char(&mut reader) {
+        if c < 256 {
+            // Literal
+            output.push(c as u8);
+            ring[r] = c as u8;
+            r = (r + 1) & (N - 1);
+        } else {
+            // Match, copied from the ring buffer
+            let position = match decode_position(&mut reader, &codes, &lengths) {
+                Some(position) => position,
+                None => break,
+            };
+            let start = (r + N - position - 1) & (N - 1);
+            for k in 0..c - 255 + THRESHOLD {
+                let byte = ring[(start + k) & (N - 1)];
+                output.push(byte);
+                ring[r] = byte;
+                r = (r + 1) & (N - 1);
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Minimal encoder, emitting literals and explicit matches
+    enum Token {
+        Literal(u8),
+        Match(usize, usize), // Length, distance back from the next byte - 1
+    }
+
+    fn compress(tokens: &[Token]) -> Vec<u8> {
+        let mut tree = HuffmanTree::new();
+        let (codes, lengths) = position_tables();
+        let mut bits: Vec<usize> = vec![];
+
+        for token in tokens {
+            let c = match token {
+                Token::Literal(byte) => *byte as usize,
+                Token::Match(length, _) => length + 255 - THRESHOLD,
+            };
+            bits.extend(tree.char_code(c));
+            tree.update(c);
+
+            if let Token::Match(_, position) = token {
+                // First byte index of the upper 6 bits' code, then the lower 6 bits
+                let first = codes
+                    .iter()
+                    .position(|code| *code == position >> 6)
+                    .unwrap();
+                let length = lengths[first];
+                for bit in (8 - length..8).rev() {
+                    bits.push((first >> bit) & 1);
+                }
+                for bit in (0..6).rev() {
+                    bits.push((position >> bit) & 1);
+                }
+            }
+        }
+
+        // Pad with zeros, which would decode to a partial character
+        bits.chunks(8)
+            .map(|byte| {
+                byte.iter()
+                    .chain([0; 8].iter())
+                    .take(8)
+                    .fold(0u8, |acc, bit| (acc << 1) | *bit as u8)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decompress() {
+        let mut tokens = vec![
+            Token::Literal(b'A'),
+            Token::Literal(b'B'),
+            Token::Literal(b'C'),
+            Token::Match(6, 2),
+            Token::Match(3, 100), // Reaches back into the initial spaces
+        ];
+
+        // Enough characters to rebuild the tree a few times
+        let mut exp = b"ABCABCABC   ".to_vec();
+        for i in 0..100000usize {
+            let byte = (i * 7 % 251) as u8;
+            tokens.push(Token::Literal(byte));
+            exp.push(byte);
+        }
+
+        let output = decompress(&compress(&tokens));
+        assert_eq!(output[..exp.len()], exp);
+    }
+}
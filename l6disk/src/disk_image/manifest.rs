@@ -0,0 +1,193 @@
+use crc::{Crc, CRC_16_IBM_3740, CRC_32_ISO_HDLC};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// In-module imports
+use super::convert::Sector;
+use super::disk_parameters::DiskParameters;
+use super::encode::physical_sector_order;
+use super::ibm3470::IBM3470AddressMark;
+
+// Archival manifest of a disk image, for validating and deduplicating images
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub image: ImageDigest,
+    pub data: ImageDigest,
+    pub geometry: ManifestGeometry,
+    pub tracks: Vec<TrackManifest>,
+}
+
+// Size and SHA-256 of a file or of the logical data
+#[derive(Debug, Serialize)]
+pub struct ImageDigest {
+    pub size: usize,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestGeometry {
+    pub cylinders: u16,
+    pub sides: u16,
+    pub sectors_per_track: u16,
+    pub sector_size: u16,
+    pub cell_rate: u16,
+    pub rpm: u16,
+}
+
+// Track data, CRC-32 of the sectors in logical order
+#[derive(Debug, Serialize)]
+pub struct TrackManifest {
+    pub cylinder: u16,
+    pub side: u16,
+    pub crc32: String,
+    pub sectors: Vec<SectorManifest>,
+}
+
+// Sector data, CRC-16/IBM-3740 as stored in the data field, which covers the address mark too
+#[derive(Debug, Serialize)]
+pub struct SectorManifest {
+    pub sector: u8,
+    pub size: usize,
+    pub crc16: String,
+}
+
+// Build the manifest of an image, given its sectors in logical order
+pub fn make_manifest(
+    image: &[u8],
+    sectors: &[Sector],
+    disk_parameters: &DiskParameters,
+) -> Manifest {
+    let crc16 = Crc::<u16>::new(&CRC_16_IBM_3740);
+    let crc32 = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+    let data = sectors.concat();
+    let mut tracks: Vec<TrackManifest> = vec![];
+    let mut sectors = sectors.iter();
+    for cyl_n in 0..disk_parameters.n_cylinders {
+        let cyl_pars = disk_parameters.cylinder_parameters(cyl_n);
+        for side_n in 0..disk_parameters.n_sides {
+            // Sector numbers in logical order
+            let mut order = physical_sector_order(&cyl_pars, cyl_n, side_n);
+            order.sort();
+
+            let track_sectors: Vec<&Sector> = sectors.by_ref().take(order.len()).collect();
+            let mut digest = crc32.digest();
+            for sector in &track_sectors {
+                digest.update(sector);
+            }
+
+            tracks.push(TrackManifest {
+                cylinder: cyl_n,
+                side: side_n,
+                crc32: format!("{:08x}", digest.finalize()),
+                sectors: track_sectors
+                    .iter()
+                    .zip(&order)
+                    .map(|(sector, (_, sector_n))| SectorManifest {
+                        sector: *sector_n,
+                        size: sector.len(),
+                        crc16: format!("{:04x}", sector_data_crc(&crc16, sector)),
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    Manifest {
+        image: ImageDigest {
+            size: image.len(),
+            sha256: hex(&Sha256::digest(image)),
+        },
+        data: ImageDigest {
+            size: data.len(),
+            sha256: hex(&Sha256::digest(&data)),
+        },
+        geometry: ManifestGeometry {
+            cylinders: disk_parameters.n_cylinders,
+            sides: disk_parameters.n_sides,
+            sectors_per_track: disk_parameters.sectors_per_track,
+            sector_size: disk_parameters.bytes_per_sector,
+            cell_rate: disk_parameters.cell_rate,
+            rpm: disk_parameters.rpm,
+        },
+        tracks,
+    }
+}
+
+// CRC of the data field of a sector, from its address mark
+fn sector_data_crc(crc16: &Crc<u16>, sector: &[u8]) -> u16 {
+    let mut digest = crc16.digest();
+    digest.update(&[IBM3470AddressMark::DAM.data]);
+    digest.update(sector);
+    digest.finalize()
+}
+
+// Manifest as pretty printed JSON
+pub fn manifest_to_json(manifest: &Manifest) -> String {
+    serde_json::to_string_pretty(manifest).unwrap() + "\n"
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::super::fm::{encoded_to_cells, read_fm_bytes};
+    use super::super::ibm3470::{decode_track, encode_track};
+    use super::*;
+
+    #[test]
+    fn test_make_manifest() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8SSSD;
+        disk_parameters.n_cylinders = 2;
+        disk_parameters.sector_interleave = 3;
+        let sectors: Vec<Sector> = (0..2 * 26).map(|i| vec![i as u8; 128]).collect();
+
+        let manifest = make_manifest(b"image", &sectors, &disk_parameters);
+        assert_eq!(manifest.image.size, 5);
+        assert_eq!(manifest.data.size, 2 * 26 * 128);
+        assert_eq!(manifest.data.sha256, hex(&Sha256::digest(sectors.concat())));
+        assert_eq!(manifest.tracks.len(), 2);
+
+        // Sectors are listed in logical order
+        let track = &manifest.tracks[1];
+        assert_eq!((track.cylinder, track.side), (1, 0));
+        assert_eq!(track.sectors.len(), 26);
+        assert_eq!(track.sectors[0].sector, 1);
+        assert_eq!(track.sectors[25].sector, 26);
+        assert_eq!(
+            track.sectors[3].crc16,
+            format!(
+                "{:04x}",
+                Crc::<u16>::new(&CRC_16_IBM_3740)
+                    .checksum(&[[0xFB].as_slice(), &[29; 128]].concat())
+            )
+        );
+    }
+
+    #[test]
+    fn test_manifest_crc16_matches_track() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8SSSD;
+        disk_parameters.n_cylinders = 1;
+        let sectors: Vec<Sector> = (0..26).map(|i| vec![i as u8 * 7; 128]).collect();
+        let manifest = make_manifest(b"image", &sectors, &disk_parameters);
+
+        // CRCs as stored on the encoded track
+        let track = encode_track(&sectors, &disk_parameters, 0, 0).unwrap();
+        let cells = encoded_to_cells(&track);
+        let decoded = decode_track(&track);
+        assert_eq!(decoded.len(), 26);
+        for sector in decoded {
+            let crc_cell = sector.data_cell.unwrap() + sector.sector_len * 16;
+            let crc = read_fm_bytes(&cells, crc_cell, 2).unwrap();
+            let entry = manifest.tracks[0]
+                .sectors
+                .iter()
+                .find(|entry| entry.sector == sector.sector_n)
+                .unwrap();
+            assert_eq!(entry.crc16, format!("{:02x}{:02x}", crc[0], crc[1]));
+        }
+    }
+}
@@ -0,0 +1,217 @@
+// In-module imports
+use super::errors::{ConvertError, ConvertErrorType};
+use super::hfe::{read_hfe_file, write_hfe_track};
+use super::ibm3470::{decode_track, patch_sector};
+
+// Where patch data is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchLocation {
+    Chs(u16, u16, u8), // Start of a sector, by cylinder, side and sector number
+    Offset(usize),     // Offset in the logical sector data, as written by extract
+}
+
+// Sector found on the disk, in logical order
+struct PatchSector {
+    cyl_n: usize,
+    side_n: usize,
+    sector_n: u8,
+    data: Vec<u8>,
+}
+
+// Write data over the sectors of an HFE image, starting at the given location. Data longer
+// than a sector continues into the following sectors, in logical order. Only the tracks
+// holding patched sectors are re-encoded
+pub fn patch_hfe(
+    hfe_img: &[u8],
+    location: PatchLocation,
+    data: &[u8],
+) -> Result<Vec<u8>, ConvertError> {
+    let image = match read_hfe_file(hfe_img) {
+        Ok(image) => image,
+        Err(msg) => return Err(ConvertError::new(ConvertErrorType::ImageReading(msg))),
+    };
+
+    // Sectors of every track, in logical order (cylinder, side, sector number)
+    let mut sectors: Vec<PatchSector> = vec![];
+    for (cyl_n, cylinder) in image.cylinders.iter().enumerate() {
+        for (side_n, track) in cylinder.iter().enumerate() {
+            let mut decoded: Vec<_> = decode_track(track)
+                .into_iter()
+                .filter(|sector| sector.id_crc_ok && sector.data.is_some())
+                .collect();
+            decoded.sort_by_key(|sector| sector.sector_n);
+            decoded.dedup_by_key(|sector| sector.sector_n);
+
+            sectors.extend(decoded.into_iter().map(|sector| PatchSector {
+                cyl_n,
+                side_n,
+                sector_n: sector.sector_n,
+                data: sector.data.unwrap(),
+            }));
+        }
+    }
+
+    // First sector to patch, and offset in it
+    let (mut sector_i, mut offset) = match location {
+        PatchLocation::Chs(cyl_n, side_n, sector_n) => {
+            let found = sectors.iter().position(|sector| {
+                (sector.cyl_n, sector.side_n, sector.sector_n)
+                    == (cyl_n as usize, side_n as usize, sector_n)
+            });
+            match found {
+                Some(sector_i) => (sector_i, 0),
+                None => {
+                    return Err(ConvertError::new(ConvertErrorType::SectorMissing(
+                        cyl_n,
+                        side_n,
+                        sector_n as u16,
+                    )))
+                }
+            }
+        }
+        PatchLocation::Offset(mut offset) => {
+            let mut sector_i = 0;
+            while sector_i < sectors.len() && offset >= sectors[sector_i].data.len() {
+                offset -= sectors[sector_i].data.len();
+                sector_i += 1;
+            }
+            (sector_i, offset)
+        }
+    };
+
+    // Overlay data on the sectors
+    let mut patched: Vec<usize> = vec![];
+    let mut remaining = data;
+    while !remaining.is_empty() {
+        let sector = match sectors.get_mut(sector_i) {
+            Some(sector) => sector,
+            None => return Err(ConvertError::new(ConvertErrorType::PatchPastEnd)),
+        };
+        let len = remaining.len().min(sector.data.len() - offset);
+        sector.data[offset..offset + len].copy_from_slice(&remaining[..len]);
+        patched.push(sector_i);
+
+        remaining = &remaining[len..];
+        sector_i += 1;
+        offset = 0;
+    }
+
+    // Re-encode the patched sectors, one track at a time
+    let mut out_img = hfe_img.to_vec();
+    let mut tracks: Vec<(usize, usize)> = patched
+        .iter()
+        .map(|sector_i| (sectors[*sector_i].cyl_n, sectors[*sector_i].side_n))
+        .collect();
+    tracks.dedup();
+    for (cyl_n, side_n) in tracks {
+        let mut track = image.cylinders[cyl_n][side_n].clone();
+        for sector in patched
+            .iter()
+            .map(|sector_i| &sectors[*sector_i])
+            .filter(|sector| (sector.cyl_n, sector.side_n) == (cyl_n, side_n))
+        {
+            track = match patch_sector(&track, sector.sector_n, &sector.data) {
+                Ok(track) => track,
+                Err(msg) => return Err(ConvertError::new(ConvertErrorType::DiskEncoding(msg))),
+            };
+        }
+
+        if let Err(msg) = write_hfe_track(&mut out_img, cyl_n, side_n, &track) {
+            return Err(ConvertError::new(ConvertErrorType::RawImageCreation(msg)));
+        }
+    }
+
+    Ok(out_img)
+}
+
+// Parse a sector location: "CYLINDER,SIDE,SECTOR"
+pub fn parse_chs(value: &str) -> Result<PatchLocation, String> {
+    let invalid = || format!("invalid sector: \"{}\"", value);
+    let fields: Vec<&str> = value.split(',').map(|field| field.trim()).collect();
+    if fields.len() != 3 {
+        return Err(invalid());
+    }
+
+    Ok(PatchLocation::Chs(
+        fields[0].parse().map_err(|_| invalid())?,
+        fields[1].parse().map_err(|_| invalid())?,
+        fields[2].parse().map_err(|_| invalid())?,
+    ))
+}
+
+// Parse a logical data offset, in decimal or in hexadecimal with a 0x prefix
+pub fn parse_offset(value: &str) -> Result<PatchLocation, String> {
+    let offset = match value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    match offset {
+        Ok(offset) => Ok(PatchLocation::Offset(offset)),
+        Err(_) => Err(format!("invalid offset: \"{}\"", value)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::convert::{convert_image, ConvertOpts, InputFormat, OutputFormat, SideOrder};
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::super::extract::{extract_from_hfe, ExtractOpts};
+    use super::super::verify::verify_hfe;
+    use super::*;
+
+    #[test]
+    fn test_patch_hfe() {
+        let mut disk_parameters = DiskFormatDefaults::IBM8DSSD;
+        disk_parameters.n_cylinders = 2;
+        disk_parameters.sector_interleave = 3;
+        let data: Vec<u8> = (0..2 * 2 * 26 * 128).map(|i| (i / 128 + i) as u8).collect();
+
+        let hfe_img = convert_image(
+            data.clone(),
+            ConvertOpts {
+                ignore_errors: false,
+                disk_parameters,
+                input_format: InputFormat::Raw,
+                output_format: OutputFormat::Hfe,
+                side_order: SideOrder::Cylinder,
+                side1_data: None,
+                boot_loader: None,
+                verbose: false,
+            },
+        )
+        .unwrap()
+        .data;
+        let extract = |hfe_img: &[u8]| {
+            extract_from_hfe(
+                hfe_img,
+                ExtractOpts {
+                    ignore_errors: false,
+                },
+            )
+            .unwrap()
+        };
+
+        // Patch across the last sectors of cylinder 0 side 1 and the first of cylinder 1
+        let offset = 2 * 26 * 128 - 200;
+        let patch = vec![0xA5; 300];
+        let patched = patch_hfe(&hfe_img, PatchLocation::Offset(offset), &patch).unwrap();
+        let mut expected = data.clone();
+        expected[offset..offset + 300].copy_from_slice(&patch);
+        assert_eq!(extract(&patched), expected);
+        assert!(verify_hfe(&patched).unwrap().problems.is_empty());
+
+        // Side 0 of cylinder 0 is untouched
+        let unchanged = patch_hfe(&patched, PatchLocation::Chs(0, 0, 1), &data[0..128]).unwrap();
+        assert_eq!(unchanged, patched);
+
+        let patched = patch_hfe(&hfe_img, PatchLocation::Chs(1, 0, 26), b"abc").unwrap();
+        let offset = (2 * 26 + 25) * 128;
+        expected = data.clone();
+        expected[offset..offset + 3].copy_from_slice(b"abc");
+        assert_eq!(extract(&patched), expected);
+
+        assert!(patch_hfe(&hfe_img, PatchLocation::Chs(2, 0, 1), b"abc").is_err());
+        assert!(patch_hfe(&hfe_img, PatchLocation::Offset(data.len() - 1), b"abc").is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use serde::Deserialize;
+use std::path::Path;
+
+// In-module imports
+use super::disk_parameters::{DiskFormatDefaults, DiskParameters};
+use crate::file;
+
+// Named set of disk parameters
+#[derive(Debug, Clone)]
+pub struct DiskProfile {
+    pub name: String,
+    pub description: String,
+    pub disk_parameters: DiskParameters,
+}
+
+impl DiskProfile {
+    fn new(name: &str, description: &str, disk_parameters: DiskParameters) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            disk_parameters,
+        }
+    }
+}
+
+// Profile used when none is given
+pub const DEFAULT_PROFILE: &str = "level6-sssd";
+
+// Names of the former disk format presets, and the built-in profiles they stand for
+const PRESET_ALIASES: [(&str, &str); 3] = [
+    ("level6", "level6-sssd"),
+    ("ibm8sssd", "ibm8-sssd"),
+    ("ibm8dssd", "ibm8-dssd"),
+];
+
+// Built-in profiles
+pub fn builtin_profiles() -> Vec<DiskProfile> {
+    vec![
+        DiskProfile::new(
+            "level6-sssd",
+            "Level 6 8-inch diskette, single sided, single density",
+            DiskFormatDefaults::LEVEL6,
+        ),
+        DiskProfile::new(
+            "level6-dssd",
+            "Level 6 8-inch diskette, double sided, single density",
+            DiskFormatDefaults::IBM8DSSD,
+        ),
+        DiskProfile::new(
+            "ibm8-sssd",
+            "IBM 3740 8-inch diskette, single sided, single density",
+            DiskFormatDefaults::IBM8SSSD,
+        ),
+        DiskProfile::new(
+            "ibm8-dssd",
+            "IBM 8-inch diskette, double sided, single density",
+            DiskFormatDefaults::IBM8DSSD,
+        ),
+    ]
+}
+
+// Find a profile by name, user profiles take precedence over built-in ones. Built-in profiles
+// can also be named after the former disk format presets
+pub fn find_profile(name: &str, user_profiles: &[DiskProfile]) -> Result<DiskProfile, String> {
+    let builtin_name = PRESET_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, profile)| profile);
+
+    user_profiles
+        .iter()
+        .find(|profile| profile.name == name)
+        .cloned()
+        .or_else(|| {
+            builtin_profiles()
+                .into_iter()
+                .find(|profile| profile.name == builtin_name)
+        })
+        .ok_or_else(|| format!("Unknown disk profile: \"{}\"", name))
+}
+
+// Read a user profile file
+pub fn read_profile_file(file_path: &Path) -> Result<Vec<DiskProfile>, String> {
+    let data = match file::read_file(&file_path.to_path_buf()) {
+        Ok(data) => data,
+        Err(err) => {
+            return Err(format!(
+                "Unable to open \"{}\": {}",
+                file_path.display(),
+                err
+            ))
+        }
+    };
+
+    match parse_profiles(&String::from_utf8_lossy(&data)) {
+        Ok(profiles) => Ok(profiles),
+        Err(msg) => Err(format!("{}: {}", file_path.display(), msg)),
+    }
+}
+
+// Options of a profile in a user profile file, unset ones are taken from its base profile
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ProfileEntry {
+    base: Option<String>,
+    description: Option<String>,
+    cylinders: Option<u16>,
+    heads: Option<u16>,
+    sectors: Option<u16>,
+    sector_size: Option<u16>,
+    cell_rate: Option<u16>,
+    spindle_rpm: Option<u16>,
+    interleave: Option<u16>,
+    skew: Option<u16>,
+    first_sector: Option<u8>,
+    hard_sectors: Option<u16>,
+    gap_byte: Option<u8>,
+    gaps: Option<[u16; 4]>,
+    fill: Option<Vec<u8>>,
+}
+
+// Parse user profiles, written as TOML tables:
+//
+// [name]
+// base = "level6-sssd"
+// description = "..."
+// cylinders = 40
+//
+// Profiles start from their base profile (level6-sssd by default), which may be a profile
+// defined earlier in the file
+fn parse_profiles(contents: &str) -> Result<Vec<DiskProfile>, String> {
+    let table: toml::Table =
+        toml::from_str(contents).map_err(|err| err.to_string().trim_end().to_owned())?;
+    let mut profiles: Vec<DiskProfile> = vec![];
+
+    // Tables are kept in the order of the file
+    for (name, value) in table {
+        let entry: ProfileEntry = value
+            .try_into()
+            .map_err(|err| format!("profile \"{}\": {}", name, err))?;
+
+        // Base profiles may be defined earlier in the file
+        let mut pars = match &entry.base {
+            Some(base) => find_profile(base, &profiles)?.disk_parameters,
+            None => DiskFormatDefaults::LEVEL6,
+        };
+        let set = |field: &mut u16, value: Option<u16>| {
+            if let Some(value) = value {
+                *field = value;
+            }
+        };
+        set(&mut pars.n_cylinders, entry.cylinders);
+        set(&mut pars.n_sides, entry.heads);
+        set(&mut pars.sectors_per_track, entry.sectors);
+        set(&mut pars.bytes_per_sector, entry.sector_size);
+        set(&mut pars.cell_rate, entry.cell_rate);
+        set(&mut pars.rpm, entry.spindle_rpm);
+        set(&mut pars.sector_interleave, entry.interleave);
+        set(&mut pars.track_skew, entry.skew);
+        set(&mut pars.hard_sectors, entry.hard_sectors);
+        if let Some(first_sector) = entry.first_sector {
+            pars.first_sector_n = first_sector;
+        }
+        if let Some(gap_byte) = entry.gap_byte {
+            pars.gaps.gap_byte = gap_byte;
+        }
+        if let Some(gaps) = entry.gaps {
+            pars.gaps.set_lengths(gaps);
+        }
+        if let Some(fill) = entry.fill {
+            pars.fill_pattern = fill;
+        }
+
+        profiles.push(DiskProfile::new(
+            &name,
+            entry.description.as_deref().unwrap_or(""),
+            pars,
+        ));
+    }
+
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_profiles() {
+        let profiles = parse_profiles(
+            "# Work disks\n[work]\ndescription = \"Work disk #1\"\ncylinders = 40\n\n\
+             [work-ds]\nbase = \"work\" # Same as work\nheads = 2\ninterleave = 3\n",
+        )
+        .unwrap();
+
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].name, "work");
+        assert_eq!(profiles[0].description, "Work disk #1");
+        assert_eq!(profiles[0].disk_parameters.n_cylinders, 40);
+
+        let work_ds = find_profile("work-ds", &profiles).unwrap();
+        assert_eq!(work_ds.disk_parameters.n_cylinders, 40);
+        assert_eq!(work_ds.disk_parameters.n_sides, 2);
+        assert_eq!(work_ds.disk_parameters.sector_interleave, 3);
+
+        let profiles =
+            parse_profiles("[blank]\ngap-byte = 0x4E\ngaps = [16, 11, 11, 27]\nfill = [0xE5]\n")
+                .unwrap();
+        let pars = &profiles[0].disk_parameters;
+        assert_eq!(pars.gaps.gap_byte, 0x4E);
+        assert_eq!(pars.gaps.gap1_len, 16);
+        assert_eq!(pars.fill_sector(3), vec![0xE5; 3]);
+
+        let tests = [
+            "cylinders = 40",
+            "[a]\ncylinders = forty",
+            "[a]\ndescription = Work",
+            "[a]\nbase = \"missing\"",
+            "[a]\nsides = 2",
+            "[a]\ngaps = 16, 11, 11, 27",
+            "[a]\ngaps = [16, 11, 11]",
+            "[a]\ngap-byte = 0x100",
+        ];
+        for contents in tests {
+            assert!(parse_profiles(contents).is_err(), "{:?}", contents);
+        }
+
+        assert!(find_profile("level6-dssd", &[]).is_ok());
+        assert!(find_profile("level6-dd", &[]).is_err());
+
+        // Former presets
+        let profile = find_profile("ibm8sssd", &[]).unwrap();
+        assert_eq!(profile.name, "ibm8-sssd");
+    }
+}
@@ -0,0 +1,417 @@
+// In-module imports
+use super::convert::{Cylinder, Track};
+use super::disk_parameters::DiskParameters;
+use super::fm::cells_to_encoded;
+
+const SCP_VERSION: u8 = 0x22; // Version 2.2
+const SCP_DISK_TYPE_OTHER: u8 = 0x80;
+const SCP_N_TRACK_OFFSETS: usize = 168;
+const SCP_HEADER_LEN: usize = 0x10 + SCP_N_TRACK_OFFSETS * 4;
+const SCP_TICK_NS: u64 = 25; // Sampling resolution
+
+// Header flags
+const SCP_FLAG_INDEX: u8 = 0x01; // Tracks start at the index hole
+const SCP_FLAG_RPM_360: u8 = 0x04;
+
+// Create SuperCard Pro flux image from array of encoded cylinders
+pub fn make_scp_file(
+    encoded_cylinders: &[Cylinder],
+    disk_parameters: &DiskParameters,
+) -> Result<Vec<u8>, String> {
+    // Check number of sides
+    if disk_parameters.n_sides > 2 {
+        return Err(format!(
+            "Too many sides for SCP file: {}",
+            disk_parameters.n_sides
+        ));
+    }
+
+    // Each interval between holes is a revolution, their number is a byte
+    let n_revolutions = match u8::try_from(disk_parameters.hard_sectors as u32 + 1) {
+        Ok(n_revolutions) => n_revolutions,
+        Err(_) => {
+            return Err(format!(
+                "Too many hard sectors for SCP file: {}",
+                disk_parameters.hard_sectors
+            ))
+        }
+    };
+
+    // Track numbers interleave sides: cylinder * 2 + side
+    let n_tracks = encoded_cylinders.len() * 2;
+    if n_tracks > SCP_N_TRACK_OFFSETS {
+        return Err(format!(
+            "Too many cylinders for SCP file: {}",
+            encoded_cylinders.len()
+        ));
+    }
+
+    // One revolution, in ticks
+    let index_time = 60_000_000_000 / (disk_parameters.rpm as u64 * SCP_TICK_NS);
+
+    // Encode track data and track offsets
+    let mut track_offsets: Vec<u32> = vec![0; SCP_N_TRACK_OFFSETS];
+    let mut track_data: Vec<u8> = vec![];
+    let mut last_track: usize = 0;
+    for (cyl_n, cylinder) in encoded_cylinders.iter().enumerate() {
+        for (side_n, track) in cylinder.iter().enumerate() {
+            let track_n = cyl_n * 2 + side_n;
+            track_offsets[track_n] = (SCP_HEADER_LEN + track_data.len()) as u32;
+            last_track = track_n;
+
+            let revolutions = match disk_parameters.hard_sectors {
+                0 => vec![(
+                    index_time as u32,
+                    track_to_flux(track, disk_parameters.cell_rate),
+                )],
+                hard_sectors => {
+                    hard_sector_revolutions(track, disk_parameters.cell_rate, hard_sectors)
+                }
+            };
+            track_data.append(&mut make_track(track_n as u8, &revolutions));
+        }
+    }
+
+    // Everything after the header is covered by the checksum
+    let mut data: Vec<u8> = vec![];
+    for offset in track_offsets {
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+    data.append(&mut track_data);
+    let checksum = data
+        .iter()
+        .fold(0u32, |sum, byte| sum.wrapping_add(*byte as u32));
+
+    // Construct final SCP file
+    let mut flags = SCP_FLAG_INDEX;
+    if disk_parameters.rpm == 360 {
+        flags |= SCP_FLAG_RPM_360;
+    }
+
+    let mut scp_data: Vec<u8> = vec![];
+    scp_data.extend_from_slice(b"SCP");
+    scp_data.push(SCP_VERSION);
+    scp_data.push(SCP_DISK_TYPE_OTHER);
+    scp_data.push(n_revolutions);
+    scp_data.push(0); // Start track
+    scp_data.push(last_track as u8); // End track
+    scp_data.push(flags);
+    scp_data.push(0); // 16 bit flux values
+    scp_data.push(match disk_parameters.n_sides {
+        1 => 1, // Side 0 only
+        _ => 0, // Both sides
+    });
+    scp_data.push(0); // 25ns resolution
+    scp_data.extend_from_slice(&checksum.to_le_bytes());
+    scp_data.append(&mut data);
+
+    Ok(scp_data)
+}
+
+// Convert encoded bitcells to flux transition times in ticks. Each 1 cell is a transition
+fn track_to_flux(track: &[u8], cell_rate: u16) -> Vec<u64> {
+    let mut flux: Vec<u64> = vec![];
+    let mut last_transition: u64 = 0;
+
+    for (byte_n, byte) in track.iter().enumerate() {
+        for bit_n in 0..8 {
+            if (byte << bit_n) & 0x80 == 0 {
+                continue;
+            }
+
+            // Time of this transition from the index hole, cell rate is in kbps
+            let cell_n = (byte_n * 8 + bit_n) as u64 + 1;
+            let time = cell_n * 1_000_000 / (cell_rate as u64 * SCP_TICK_NS);
+
+            flux.push(time - last_transition);
+            last_transition = time;
+        }
+    }
+
+    flux
+}
+
+// Split the flux of a hard sectored track at its holes. The track starts at the first sector
+// hole, the index hole lies halfway between the last sector hole and the end of the track.
+// Each interval between holes is stored as a separate revolution, starting at the index hole.
+// The last one ends at the end of the track, so the flux runs on across the index hole
+fn hard_sector_revolutions(
+    track: &[u8],
+    cell_rate: u16,
+    hard_sectors: u16,
+) -> Vec<(u32, Vec<u64>)> {
+    let n_cells = (track.len() * 8) as u64;
+    let cell_time = |cell_n: u64| cell_n * 1_000_000 / (cell_rate as u64 * SCP_TICK_NS);
+
+    // Hole positions, in cells from the start of the track
+    let slot_cells = (track.len() as u64 / 2 / hard_sectors as u64) * 16;
+    let last_hole = slot_cells * (hard_sectors as u64 - 1);
+    let index = last_hole + (n_cells - last_hole) / 2;
+
+    // Revolution boundaries, in cells from the index hole
+    let mut boundaries: Vec<u64> = (0..hard_sectors as u64)
+        .map(|hole| (hole * slot_cells + n_cells - index) % n_cells)
+        .collect();
+    boundaries.sort();
+    boundaries.insert(0, 0);
+
+    // Transitions, in cells from the index hole
+    let mut transitions: Vec<u64> = vec![];
+    for (byte_n, byte) in track.iter().enumerate() {
+        for bit_n in 0..8 {
+            if (byte << bit_n) & 0x80 != 0 {
+                let cell_n = (byte_n * 8 + bit_n) as u64 + 1;
+                transitions.push((cell_n + n_cells - index - 1) % n_cells + 1);
+            }
+        }
+    }
+    transitions.sort();
+
+    // Split flux between revolutions
+    let mut revolutions: Vec<(u32, Vec<u64>)> = vec![];
+    let mut last_transition: u64 = 0;
+    for (rev_n, start) in boundaries.iter().enumerate() {
+        let end = boundaries.get(rev_n + 1).copied();
+        let duration = cell_time(end.unwrap_or(n_cells)) - cell_time(*start);
+
+        let mut flux: Vec<u64> = vec![];
+        for cell_n in transitions
+            .iter()
+            .filter(|cell_n| **cell_n > *start && end.is_none_or(|end| **cell_n <= end))
+        {
+            let time = cell_time(*cell_n);
+            flux.push(time - last_transition);
+            last_transition = time;
+        }
+
+        revolutions.push((duration as u32, flux));
+    }
+
+    revolutions
+}
+
+// Track data header followed by flux data for each revolution, given by duration and flux
+fn make_track(track_n: u8, revolutions: &[(u32, Vec<u64>)]) -> Vec<u8> {
+    let header_len = 4 + revolutions.len() * 12;
+
+    let mut header: Vec<u8> = vec![];
+    header.extend_from_slice(b"TRK");
+    header.push(track_n);
+
+    // Flux values are 16 bits, each 0 adds 65536 to the next value
+    let mut flux_data: Vec<u8> = vec![];
+    for (duration, flux) in revolutions {
+        let offset = (header_len + flux_data.len()) as u32;
+        let mut n_values: u32 = 0;
+        for value in flux {
+            let mut value = *value;
+            while value > 0xFFFF {
+                flux_data.extend_from_slice(&0u16.to_be_bytes());
+                n_values += 1;
+                value -= 0x10000;
+            }
+            flux_data.extend_from_slice(&(value as u16).to_be_bytes());
+            n_values += 1;
+        }
+
+        header.extend_from_slice(&duration.to_le_bytes());
+        header.extend_from_slice(&n_values.to_le_bytes());
+        header.extend_from_slice(&offset.to_le_bytes()); // Flux data follows the header
+    }
+
+    header.append(&mut flux_data);
+    header
+}
+
+// Tracks read back from an SCP file
+#[derive(Debug, Clone)]
+pub struct SCPImage {
+    pub n_sides: u8,
+    pub cylinders: Vec<Cylinder>, // FM encoded tracks, as passed to make_scp_file
+}
+
+// Read the tracks of an SCP file. The revolutions of each track are joined and read twice, so
+// sectors across the index hole are whole. Flux is turned back into cells at the cell rate
+// found from the shortest intervals
+pub fn read_scp_file(data: &[u8]) -> Result<SCPImage, String> {
+    // Check header
+    if data.len() < SCP_HEADER_LEN || data[0..3] != *b"SCP" {
+        return Err("Not an SCP file".to_string());
+    }
+    let n_revolutions = data[0x05] as usize;
+    let end_track = data[0x07] as usize;
+    let bit_cell_width = data[0x09];
+    let sides = data[0x0A];
+
+    if bit_cell_width != 0 && bit_cell_width != 16 {
+        return Err(format!("Unsupported flux value width: {}", bit_cell_width));
+    }
+    let n_sides: u8 = match sides {
+        0 => 2,
+        1 => 1,
+        _ => return Err(format!("Unsupported sides: {}", sides)),
+    };
+    if end_track >= SCP_N_TRACK_OFFSETS {
+        return Err(format!("Invalid end track: {}", end_track));
+    }
+
+    // Track numbers interleave sides: cylinder * 2 + side
+    let n_cylinders = end_track / 2 + 1;
+    let mut cylinders: Vec<Cylinder> = vec![];
+    for cyl_n in 0..n_cylinders {
+        let mut cylinder: Cylinder = vec![];
+        for side_n in 0..n_sides as usize {
+            let track_n = cyl_n * 2 + side_n;
+            let entry = 0x10 + track_n * 4;
+            let offset = u32::from_le_bytes(data[entry..entry + 4].try_into().unwrap()) as usize;
+
+            let (mut flux, duration) = match offset {
+                0 => (vec![], 0),
+                offset => read_track(data, offset, track_n, n_revolutions)?,
+            };
+
+            // The second turn starts with the time left before the index hole
+            if let Some(first) = flux.first().copied() {
+                let tail = duration.saturating_sub(flux.iter().sum());
+                flux.extend_from_within(..);
+                let second = flux.len() / 2;
+                flux[second] = first + tail;
+            }
+            cylinder.push(flux_to_track(&flux));
+        }
+        cylinders.push(cylinder);
+    }
+
+    Ok(SCPImage { n_sides, cylinders })
+}
+
+// Flux of all revolutions of a track and their total duration, in ticks
+fn read_track(
+    data: &[u8],
+    offset: usize,
+    track_n: usize,
+    n_revolutions: usize,
+) -> Result<(Vec<u64>, u64), String> {
+    let truncated = || format!("Track {} truncated", track_n);
+    let header = data
+        .get(offset..offset + 4 + n_revolutions * 12)
+        .ok_or_else(truncated)?;
+    if header[0..3] != *b"TRK" || header[3] as usize != track_n {
+        return Err(format!("Invalid header of track {}", track_n));
+    }
+
+    let mut flux: Vec<u64> = vec![];
+    let mut duration: u64 = 0;
+    for rev_n in 0..n_revolutions {
+        let entry = &header[4 + rev_n * 12..16 + rev_n * 12];
+        duration += u32::from_le_bytes(entry[0..4].try_into().unwrap()) as u64;
+        let n_values = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as usize;
+        let values_offset = offset + u32::from_le_bytes(entry[8..12].try_into().unwrap()) as usize;
+        let values = data
+            .get(values_offset..values_offset + n_values * 2)
+            .ok_or_else(truncated)?;
+
+        // Each 0 adds 65536 to the next value
+        let mut carry: u64 = 0;
+        for value in values.chunks(2) {
+            match u16::from_be_bytes([value[0], value[1]]) {
+                0 => carry += 0x10000,
+                value => {
+                    flux.push(carry + value as u64);
+                    carry = 0;
+                }
+            }
+        }
+    }
+
+    Ok((flux, duration))
+}
+
+// Convert flux transition times in ticks to encoded bitcells. The cell time is the average
+// of the intervals close to the shortest one, which is a single cell in FM
+fn flux_to_track(flux: &[u64]) -> Track {
+    let shortest = match flux.iter().min() {
+        Some(shortest) => *shortest,
+        None => return vec![],
+    };
+    let short: Vec<u64> = flux
+        .iter()
+        .copied()
+        .filter(|interval| *interval * 2 < shortest * 3)
+        .collect();
+    let cell_time = short.iter().sum::<u64>() as f64 / short.len() as f64;
+
+    let mut cells: Vec<bool> = vec![];
+    for interval in flux {
+        let n_cells = (*interval as f64 / cell_time).round().max(1.0) as usize;
+        cells.extend(std::iter::repeat_n(false, n_cells - 1));
+        cells.push(true);
+    }
+
+    cells_to_encoded(&cells)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disk_image::disk_parameters::DiskFormatDefaults;
+
+    #[test]
+    fn test_make_scp_file_revolutions() {
+        let disk_parameters = |hard_sectors| DiskParameters {
+            hard_sectors,
+            ..DiskFormatDefaults::LEVEL6
+        };
+
+        let scp = make_scp_file(&[], &disk_parameters(32)).unwrap();
+        assert_eq!(scp[5], 33);
+
+        // The number of revolutions doesn't fit in its byte
+        assert!(make_scp_file(&[], &disk_parameters(254)).is_ok());
+        assert!(make_scp_file(&[], &disk_parameters(255)).is_err());
+        assert!(make_scp_file(&[], &disk_parameters(u16::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_track_to_flux() {
+        // 500 kbps cells are 2us apart, 80 ticks
+        assert_eq!(
+            track_to_flux(&[0b10010000, 0b00000001], 500),
+            vec![80, 240, 960]
+        );
+
+        // Intervals longer than 16 bits are split
+        let track = make_track(3, &[(1000, vec![0x10005])]);
+        assert_eq!(track[0..4], *b"TRK\x03");
+        assert_eq!(track[8..12], 2u32.to_le_bytes());
+        assert_eq!(track[16..], [0x00, 0x00, 0x00, 0x05]);
+    }
+
+    #[test]
+    fn test_hard_sector_revolutions() {
+        // 4 holes, 2 encoded bytes (16 cells) apart, one transition at the start of each slot
+        let track = [0x80, 0x00].repeat(4);
+        let revolutions = hard_sector_revolutions(&track, 500, 4);
+
+        // Index hole halfway into the last slot: half slot, 3 slots, half slot
+        let durations: Vec<u32> = revolutions.iter().map(|rev| rev.0).collect();
+        assert_eq!(durations, vec![640, 1280, 1280, 1280, 640]);
+
+        let flux: Vec<Vec<u64>> = revolutions.into_iter().map(|rev| rev.1).collect();
+        assert_eq!(
+            flux,
+            vec![vec![], vec![720], vec![1280], vec![1280], vec![1280]]
+        );
+    }
+
+    #[test]
+    fn test_flux_to_track() {
+        assert_eq!(
+            flux_to_track(&track_to_flux(&[0b10010000, 0b00000001], 500)),
+            vec![0b10010000, 0b00000001]
+        );
+
+        // Intervals are rounded to whole cells
+        assert_eq!(flux_to_track(&[78, 243, 958]), vec![0b10010000, 0b00000001]);
+    }
+}
@@ -0,0 +1,178 @@
+// In-module imports
+use super::convert::Sector;
+use super::disk_parameters::{DiskParameters, GeometryZone};
+
+// Sectors read from a disk image, independent of the image format
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SectorImage {
+    pub n_sides: u16,
+    pub cylinders: Vec<Vec<ImageTrack>>, // Indexed by cylinder and side
+}
+
+// Sectors of one track, by sector number
+pub type ImageTrack = Vec<(u8, Sector)>;
+
+impl SectorImage {
+    // Empty image with the given number of cylinders and sides
+    pub fn new(n_cylinders: usize, n_sides: u16) -> Self {
+        Self {
+            n_sides,
+            cylinders: vec![vec![vec![]; n_sides as usize]; n_cylinders],
+        }
+    }
+
+    // Track of the image, adding cylinders and sides as needed
+    pub fn track_mut(&mut self, cyl_n: usize, side_n: usize) -> &mut ImageTrack {
+        self.n_sides = self.n_sides.max(side_n as u16 + 1);
+        if self.cylinders.len() <= cyl_n {
+            self.cylinders.resize(cyl_n + 1, vec![]);
+        }
+        for cylinder in self.cylinders.iter_mut() {
+            cylinder.resize(self.n_sides as usize, vec![]);
+        }
+
+        &mut self.cylinders[cyl_n][side_n]
+    }
+
+    // Sectors in logical order (cylinder, side, sector number)
+    pub fn sectors(&self) -> Vec<Sector> {
+        let mut sectors: Vec<Sector> = vec![];
+        for track in self.cylinders.iter().flatten() {
+            let mut track = track.clone();
+            track.sort_by_key(|(sector_n, _)| *sector_n);
+            sectors.extend(track.into_iter().map(|(_, data)| data));
+        }
+
+        sectors
+    }
+
+    // Sector data in logical order
+    pub fn to_data(&self) -> Vec<u8> {
+        self.sectors().concat()
+    }
+
+    // Disk parameters matching the geometry of this image. Timing and sector layout are
+    // taken from the base parameters
+    pub fn disk_parameters(&self, base: &DiskParameters) -> Result<DiskParameters, String> {
+        let mut disk_pars = base.clone();
+        disk_pars.n_cylinders = self.cylinders.len() as u16;
+        disk_pars.n_sides = self.n_sides;
+        disk_pars.zones = vec![];
+        disk_pars.sector_numbers = None;
+
+        // Geometry and sector numbers of each cylinder, every side must be the same
+        let mut geometries: Vec<(u16, u16)> = vec![];
+        let mut numberings: Vec<Vec<u8>> = vec![];
+        for (cyl_n, cylinder) in self.cylinders.iter().enumerate() {
+            let mut numbering: Option<Vec<u8>> = None;
+            let mut geometry: Option<(u16, u16)> = None;
+            for track in cylinder {
+                let mut sector_ns: Vec<u8> = track.iter().map(|(sector_n, _)| *sector_n).collect();
+                sector_ns.sort();
+                let size = track.first().map_or(0, |(_, data)| data.len()) as u16;
+
+                if track.iter().any(|(_, data)| data.len() as u16 != size)
+                    || numbering
+                        .as_ref()
+                        .is_some_and(|numbering| *numbering != sector_ns)
+                    || geometry.is_some_and(|geometry| geometry.1 != size)
+                {
+                    return Err(format!("Mixed geometry in cylinder {}", cyl_n));
+                }
+                geometry = Some((sector_ns.len() as u16, size));
+                numbering = Some(sector_ns);
+            }
+            geometries.push(geometry.unwrap_or((0, 0)));
+            numberings.push(numbering.unwrap_or_default());
+        }
+
+        // The most common geometry is used for the whole disk, other cylinders become zones
+        let main_geometry = geometries
+            .iter()
+            .max_by_key(|geometry| geometries.iter().filter(|other| other == geometry).count())
+            .copied()
+            .unwrap_or((base.sectors_per_track, base.bytes_per_sector));
+        (disk_pars.sectors_per_track, disk_pars.bytes_per_sector) = main_geometry;
+
+        for (cyl_n, geometry) in geometries.iter().enumerate() {
+            if *geometry == main_geometry {
+                continue;
+            }
+            let cyl_n = cyl_n as u16;
+            match disk_pars.zones.last_mut() {
+                Some(zone)
+                    if zone.last_cylinder + 1 == cyl_n
+                        && (zone.sectors_per_track, zone.bytes_per_sector) == *geometry =>
+                {
+                    zone.last_cylinder = cyl_n
+                }
+                _ => disk_pars.zones.push(GeometryZone {
+                    first_cylinder: cyl_n,
+                    last_cylinder: cyl_n,
+                    sectors_per_track: geometry.0,
+                    bytes_per_sector: geometry.1,
+                }),
+            }
+        }
+
+        // Sector numbers: consecutive from the same first sector, or the same on every track
+        let first_sector_n = numberings.first().and_then(|numbering| numbering.first());
+        let consecutive = numberings.iter().all(|numbering| {
+            numbering.iter().enumerate().all(|(i, sector_n)| {
+                Some(*sector_n as usize) == first_sector_n.map(|n| *n as usize + i)
+            })
+        });
+        if consecutive {
+            disk_pars.first_sector_n = first_sector_n.copied().unwrap_or(base.first_sector_n);
+        } else if numberings
+            .iter()
+            .all(|numbering| *numbering == numberings[0])
+        {
+            disk_pars.sector_numbers = Some(numberings[0].clone());
+        } else {
+            return Err("Sector numbering differs between tracks".to_string());
+        }
+
+        Ok(disk_pars)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::*;
+
+    #[test]
+    fn test_disk_parameters() {
+        // Cylinder 0: 4 sectors of 128 bytes, cylinders 1 and 2: 2 sectors of 64 bytes
+        let mut image = SectorImage::new(3, 1);
+        image.cylinders[0][0] = (0..4).rev().map(|n| (n, vec![n; 128])).collect();
+        image.cylinders[1][0] = (0..2).map(|n| (n, vec![n; 64])).collect();
+        image.cylinders[2][0] = (0..2).map(|n| (n, vec![n; 64])).collect();
+
+        let pars = image.disk_parameters(&DiskFormatDefaults::LEVEL6).unwrap();
+        assert_eq!((pars.n_cylinders, pars.n_sides), (3, 1));
+        assert_eq!((pars.sectors_per_track, pars.bytes_per_sector), (2, 64));
+        assert_eq!(pars.first_sector_n, 0);
+        assert_eq!(
+            pars.zones,
+            vec![GeometryZone {
+                first_cylinder: 0,
+                last_cylinder: 0,
+                sectors_per_track: 4,
+                bytes_per_sector: 128,
+            }]
+        );
+        assert_eq!(image.sectors()[0], vec![0; 128]);
+
+        // Same non consecutive numbering on every track
+        for cylinder in image.cylinders.iter_mut() {
+            cylinder[0] = vec![(1, vec![0; 128]), (3, vec![0; 128])];
+        }
+        let pars = image.disk_parameters(&DiskFormatDefaults::LEVEL6).unwrap();
+        assert_eq!(pars.sector_numbers, Some(vec![1, 3]));
+
+        image.cylinders[2][0][1].0 = 4;
+        assert!(image.disk_parameters(&DiskFormatDefaults::LEVEL6).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// In-module imports
+use super::convert::Sector;
+use super::lzhuf::decompress;
+use super::sector_image::SectorImage;
+
+const TD0_HEADER_LEN: usize = 12;
+const TD0_COMMENT_HEADER_LEN: usize = 10;
+const TD0_END_OF_TRACKS: u8 = 0xFF;
+
+// Sector flags
+const TD0_SECTOR_NO_DATA: u8 = 0x30; // Unallocated or ID field only
+
+// Reads bytes from a TeleDisk image
+struct TD0Reader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl TD0Reader {
+    fn bytes(&mut self, n: usize) -> Result<&[u8], String> {
+        match self.data.get(self.pos..self.pos + n) {
+            Some(bytes) => {
+                self.pos += n;
+                Ok(bytes)
+            }
+            None => Err("Unexpected end of TD0 file".to_string()),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn word(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+// Read the sectors of a TeleDisk image
+pub fn read_td0_file(data: &[u8]) -> Result<SectorImage, String> {
+    if data.len() < TD0_HEADER_LEN {
+        return Err("Not a TD0 file".to_string());
+    }
+    let header = &data[0..TD0_HEADER_LEN];
+    let version = header[4];
+    let has_comment = header[7] & 0x80 != 0;
+
+    // Everything after the header may be compressed
+    let body = match &header[0..2] {
+        b"TD" => data[TD0_HEADER_LEN..].to_vec(),
+        b"td" if version >= 20 => decompress(&data[TD0_HEADER_LEN..]),
+        b"td" => return Err(format!("Unsupported TD0 compression (version {})", version)),
+        _ => return Err("Not a TD0 file".to_string()),
+    };
+    let mut reader = TD0Reader { data: body, pos: 0 };
+
+    // Skip comment block
+    if has_comment {
+        reader.bytes(2)?; // CRC
+        let comment_len = reader.word()? as usize;
+        reader.bytes(TD0_COMMENT_HEADER_LEN - 4 + comment_len)?;
+    }
+
+    // Collect sectors, keeping the first copy of each
+    let mut image = SectorImage::default();
+    loop {
+        // Track header
+        let n_sectors = reader.byte()?;
+        if n_sectors == TD0_END_OF_TRACKS {
+            break;
+        }
+        reader.bytes(3)?; // Cylinder, head, CRC
+
+        for _ in 0..n_sectors {
+            // Sector header
+            let id = reader.bytes(6)?.to_vec();
+            let (cyl_n, side_n, sector_n, size_code, flags) = (id[0], id[1], id[2], id[3], id[4]);
+            let sector_len = 128usize << (size_code & 0x07);
+
+            let data = match flags & TD0_SECTOR_NO_DATA {
+                0 => decode_sector_data(&mut reader, sector_len)?,
+                _ => vec![0; sector_len],
+            };
+
+            let track = image.track_mut(cyl_n as usize, (side_n & 0x01) as usize);
+            if !track.iter().any(|(other_n, _)| *other_n == sector_n) {
+                track.push((sector_n, data));
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+// Decode a sector data block
+fn decode_sector_data(reader: &mut TD0Reader, sector_len: usize) -> Result<Sector, String> {
+    let block_len = reader.word()? as usize;
+    if block_len == 0 {
+        return Err("Invalid TD0 sector data block".to_string());
+    }
+    let encoding = reader.byte()?;
+    let block = reader.bytes(block_len - 1)?.to_vec();
+
+    let mut data: Sector = vec![];
+    match encoding {
+        // Raw sector data
+        0 => data = block,
+
+        // Repeated 2 byte pattern
+        1 => {
+            for chunk in block.chunks_exact(4) {
+                let count = u16::from_le_bytes([chunk[0], chunk[1]]);
+                for _ in 0..count {
+                    data.extend_from_slice(&chunk[2..4]);
+                }
+            }
+        }
+
+        // Run length encoding: literal runs and repeated patterns
+        2 => {
+            let mut pos = 0;
+            while pos + 1 < block.len() {
+                let (kind, len) = (block[pos] as usize, block[pos + 1] as usize);
+                pos += 2;
+
+                let run = match kind {
+                    0 => block.get(pos..pos + len).map(|run| (run, 1)),
+                    _ => block.get(pos..pos + kind * 2).map(|run| (run, len)),
+                };
+                let (run, repeat) = match run {
+                    Some(run) => run,
+                    None => return Err("Invalid TD0 sector data block".to_string()),
+                };
+                for _ in 0..repeat {
+                    data.extend_from_slice(run);
+                }
+                pos += run.len();
+            }
+        }
+
+        _ => return Err(format!("Unknown TD0 sector encoding: {}", encoding)),
+    }
+
+    data.resize(sector_len, 0);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_td0() {
+        let mut data: Vec<u8> = vec![];
+        data.extend_from_slice(b"TD\x00\x00\x15\x00\x00\x80\x00\x01\x00\x00");
+
+        // Comment block with a 3 byte comment
+        data.extend_from_slice(&[0, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(b"abc");
+
+        // Track 0: sector 2 as repeated pattern, sector 1 as raw data
+        data.extend_from_slice(&[2, 0, 0, 0]);
+        data.extend_from_slice(&[0, 0, 2, 0, 0, 0, 5, 0, 1, 64, 0, 0xAB, 0xCD]);
+        data.extend_from_slice(&[0, 0, 1, 0, 0, 0, 129, 0, 0]);
+        data.extend_from_slice(&[0x11; 128]);
+
+        // Track 1: run length encoded sector, and sector without data
+        data.extend_from_slice(&[2, 1, 0, 0]);
+        data.extend_from_slice(&[
+            1, 0, 1, 0, 0, 0, 9, 0, 2, 0, 2, 0x01, 0x02, 1, 63, 0x03, 0x04,
+        ]);
+        data.extend_from_slice(&[1, 0, 2, 0, 0x20, 0]);
+        data.push(TD0_END_OF_TRACKS);
+
+        let mut exp: Vec<u8> = vec![0x11; 128];
+        exp.extend([0xAB, 0xCD].repeat(64));
+        exp.extend_from_slice(&[0x01, 0x02]);
+        exp.extend([0x03, 0x04].repeat(63));
+        exp.extend_from_slice(&[0; 128]);
+
+        assert_eq!(read_td0_file(&data).unwrap().to_data(), exp);
+    }
+}
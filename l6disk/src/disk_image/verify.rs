@@ -0,0 +1,244 @@
+use std::fmt;
+
+// In-module imports
+use super::hfe::{check_hfe_file, read_hfe_file};
+use super::ibm3470::{decode_track, DecodedSector};
+
+// Result of verifying an HFE floppy image
+#[derive(Debug)]
+pub struct VerifyReport {
+    pub n_cylinders: usize,
+    pub n_sides: u8,
+    pub bit_rate: u16,
+    pub rpm: u16,
+    pub tracks: Vec<TrackGeometry>,
+    pub problems: Vec<String>,
+}
+
+// Geometry of one track, as found on the disk
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackGeometry {
+    pub cyl_n: u16,
+    pub side_n: u16,
+    pub n_sectors: usize,
+    pub sector_size: usize,
+    pub first_sector_n: u8,
+    pub last_sector_n: u8,
+}
+
+// Decode every track of an HFE floppy image and check it for problems
+pub fn verify_hfe(hfe_img: &[u8]) -> Result<VerifyReport, String> {
+    let image = read_hfe_file(hfe_img)?;
+
+    let mut report = VerifyReport {
+        n_cylinders: image.cylinders.len(),
+        n_sides: image.n_sides,
+        bit_rate: image.bit_rate,
+        rpm: image.rpm,
+        tracks: vec![],
+        problems: check_hfe_file(hfe_img),
+    };
+
+    for (cyl_n, cylinder) in image.cylinders.iter().enumerate() {
+        for (side_n, track) in cylinder.iter().enumerate() {
+            let sectors = decode_track(track);
+            let (geometry, mut problems) = verify_track(&sectors, cyl_n as u16, side_n as u16);
+
+            report.tracks.push(geometry);
+            report.problems.append(&mut problems);
+        }
+    }
+
+    // Tracks with the same sector size are expected to have the same number of sectors
+    for track in &report.tracks {
+        let expected = report
+            .tracks
+            .iter()
+            .filter(|other| other.sector_size == track.sector_size)
+            .map(|other| other.n_sectors)
+            .max()
+            .unwrap_or(0);
+        if track.n_sectors < expected {
+            report.problems.push(format!(
+                "Cylinder {}, side {}: {} sectors, other tracks have {}",
+                track.cyl_n, track.side_n, track.n_sectors, expected
+            ));
+        }
+    }
+
+    Ok(report)
+}
+
+// Check the sectors decoded from one track
+fn verify_track(
+    sectors: &[DecodedSector],
+    cyl_n: u16,
+    side_n: u16,
+) -> (TrackGeometry, Vec<String>) {
+    let mut problems: Vec<String> = vec![];
+    let location = format!("Cylinder {}, side {}", cyl_n, side_n);
+
+    if sectors.is_empty() {
+        problems.push(format!("{}: no sectors found", location));
+    }
+
+    let mut sector_numbers: Vec<u8> = vec![];
+    for sector in sectors {
+        let location = format!("{}, sector {}", location, sector.sector_n);
+
+        // ID field
+        if !sector.id_crc_ok {
+            problems.push(format!("{}: ID field CRC error", location));
+            continue;
+        }
+        if sector.track_n as u16 != cyl_n {
+            problems.push(format!(
+                "{}: ID field has cylinder {}",
+                location, sector.track_n
+            ));
+        }
+        if sector.side_n as u16 != side_n {
+            problems.push(format!("{}: ID field has side {}", location, sector.side_n));
+        }
+        if sector_numbers.contains(&sector.sector_n) {
+            problems.push(format!("{}: duplicate sector", location));
+        }
+        sector_numbers.push(sector.sector_n);
+
+        // Data field
+        match sector.data {
+            None => problems.push(format!("{}: data field missing", location)),
+            Some(_) if !sector.data_crc_ok => {
+                problems.push(format!("{}: data field CRC error", location))
+            }
+            Some(_) => {}
+        }
+    }
+
+    // Sector numbers should have no gaps
+    sector_numbers.sort();
+    sector_numbers.dedup();
+    let first_sector_n = sector_numbers.first().copied().unwrap_or(0);
+    let last_sector_n = sector_numbers.last().copied().unwrap_or(0);
+    for sector_n in first_sector_n..last_sector_n {
+        if !sector_numbers.contains(&sector_n) {
+            problems.push(format!("{}, sector {}: not found", location, sector_n));
+        }
+    }
+
+    let geometry = TrackGeometry {
+        cyl_n,
+        side_n,
+        n_sectors: sector_numbers.len(),
+        sector_size: sectors
+            .iter()
+            .find_map(|sector| sector.data.as_ref().map(|data| data.len()))
+            .unwrap_or(0),
+        first_sector_n,
+        last_sector_n,
+    };
+
+    (geometry, problems)
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} cylinders, {} sides, {} kbps, {} RPM",
+            self.n_cylinders, self.n_sides, self.bit_rate, self.rpm
+        )?;
+
+        // Group consecutive cylinders with the same geometry on every side
+        let same_geometry = |a: &TrackGeometry, b: &TrackGeometry| {
+            (
+                a.n_sectors,
+                a.sector_size,
+                a.first_sector_n,
+                a.last_sector_n,
+            ) == (
+                b.n_sectors,
+                b.sector_size,
+                b.first_sector_n,
+                b.last_sector_n,
+            )
+        };
+        let mut start = 0;
+        while start < self.tracks.len() {
+            let mut end = start + 1;
+            while end < self.tracks.len() && same_geometry(&self.tracks[start], &self.tracks[end]) {
+                end += 1;
+            }
+
+            let (first, last) = (&self.tracks[start], &self.tracks[end - 1]);
+            write!(
+                f,
+                "cylinder {} side {} to cylinder {} side {}: ",
+                first.cyl_n, first.side_n, last.cyl_n, last.side_n
+            )?;
+            match first.n_sectors {
+                0 => writeln!(f, "no sectors")?,
+                n_sectors => writeln!(
+                    f,
+                    "{} sectors of {} bytes, numbered {} to {}",
+                    n_sectors, first.sector_size, first.first_sector_n, first.last_sector_n
+                )?,
+            }
+
+            start = end;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::convert::{convert_image, ConvertOpts, InputFormat, OutputFormat, SideOrder};
+    use super::super::disk_parameters::DiskFormatDefaults;
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let mut disk_parameters = DiskFormatDefaults::LEVEL6;
+        disk_parameters.n_cylinders = 3;
+
+        let mut hfe_img = convert_image(
+            vec![0xE5; 3 * 26 * 128],
+            ConvertOpts {
+                ignore_errors: false,
+                disk_parameters,
+                input_format: InputFormat::Raw,
+                output_format: OutputFormat::Hfe,
+                side_order: SideOrder::Cylinder,
+                side1_data: None,
+                boot_loader: None,
+                verbose: false,
+            },
+        )
+        .unwrap()
+        .data;
+
+        let report = verify_hfe(&hfe_img).unwrap();
+        assert_eq!(report.problems, Vec::<String>::new());
+        assert_eq!(report.tracks.len(), 3);
+        assert_eq!(
+            report.to_string().lines().nth(1),
+            Some(
+                "cylinder 0 side 0 to cylinder 2 side 0: 26 sectors of 128 bytes, numbered 1 to 26"
+            )
+        );
+
+        // Corrupt the track offset LUT, and the data of cylinder 1
+        hfe_img[0x200 + 4 + 2] = 0x02;
+        let offset = u16::from_le_bytes([hfe_img[0x200 + 4], hfe_img[0x200 + 5]]) as usize * 512;
+        hfe_img[offset + 2048] ^= 0xFF;
+
+        let report = verify_hfe(&hfe_img).unwrap();
+        assert!(!report.problems.is_empty());
+        assert!(report
+            .problems
+            .iter()
+            .any(|problem| problem.starts_with("Track 1: length")));
+    }
+}
@@ -0,0 +1,26 @@
+//! Level6 diskette image library
+//!
+//! Generation, conversion and decoding of Level6 and IBM 3740 diskette images, used by the
+//! `l6disk` command line utility. The main entry points are:
+//!
+//! - [`disk_image::DiskParameters`]: disk geometry, timing, sector layout and track gaps,
+//!   starting from the presets in [`disk_image::disk_parameters::DiskFormatDefaults`] or the
+//!   profiles in [`disk_image::profiles`]
+//! - [`disk_image::convert_image`]: conversion of sector images to HFE, SCP, IMD or raw images
+//! - [`disk_image::encode::encode_disk`] and [`disk_image::ibm3470`]: encoding of sectors to
+//!   FM tracks, and decoding of tracks back to sectors
+//! - [`disk_image::extract_from_hfe`] and [`disk_image::verify_hfe`]: reading HFE images back
+//!
+//! ```no_run
+//! use l6disk::disk_image::disk_parameters::DiskFormatDefaults;
+//! use l6disk::disk_image::encode::encode_disk;
+//!
+//! let disk_parameters = DiskFormatDefaults::LEVEL6;
+//! let sectors = vec![vec![0xE5; 128]; 77 * 26];
+//! let cylinders = encode_disk(&sectors, &disk_parameters, false).unwrap();
+//! ```
+
+mod args;
+pub mod cli;
+pub mod disk_image;
+pub mod file;
@@ -0,0 +1,61 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use std::ffi::OsString;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Level6 toolchain", long_about = None)]
+struct Args {
+    /// When to color the output
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Level6 assembler, same as l6as
+    Asm(ToolArgs),
+
+    /// Level6 diskette image utility, same as l6disk
+    Disk(ToolArgs),
+}
+
+// Arguments passed on to a tool, as they are
+#[derive(clap::Args, Debug)]
+#[command(disable_help_flag = true, disable_version_flag = true)]
+struct ToolArgs {
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<OsString>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum ColorChoice {
+    Auto, // Color when the terminal supports it, unless NO_COLOR is set
+    Always,
+    Never,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    // Color handling is shared by all tools
+    match args.color {
+        ColorChoice::Auto => {}
+        ColorChoice::Always => colored::control::set_override(true),
+        ColorChoice::Never => colored::control::set_override(false),
+    }
+
+    // Each tool sees its own command line, named after the subcommand
+    match args.command {
+        Command::Asm(tool_args) => l6as::run(tool_command_line("level6 asm", tool_args)),
+        Command::Disk(tool_args) => l6disk::cli::run(tool_command_line("level6 disk", tool_args)),
+    }
+}
+
+fn tool_command_line(name: &str, tool_args: ToolArgs) -> Vec<OsString> {
+    let mut command_line = vec![OsString::from(name)];
+    command_line.extend(tool_args.args);
+
+    command_line
+}